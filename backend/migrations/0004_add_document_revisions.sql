@@ -0,0 +1,18 @@
+-- Create document revisions table
+CREATE TABLE document_revisions (
+    id TEXT PRIMARY KEY NOT NULL,
+    document_id TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+    UNIQUE (document_id, revision)
+);
+
+CREATE INDEX idx_document_revisions_document_id ON document_revisions(document_id);
+
+-- Seed the history with the current state of every existing document
+INSERT INTO document_revisions (id, document_id, revision, title, content, created_at)
+SELECT randomblob(16), id, 1, title, content, updated_at
+FROM documents;
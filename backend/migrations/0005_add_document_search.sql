@@ -0,0 +1,27 @@
+-- Full-text index over document titles and content. The index keeps its own
+-- copy of the text and refers to documents by id, which unlike the rowid
+-- stays the same across a VACUUM.
+CREATE VIRTUAL TABLE documents_fts USING fts5(
+    document_id UNINDEXED,
+    title,
+    content,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER documents_fts_insert AFTER INSERT ON documents BEGIN
+    INSERT INTO documents_fts (document_id, title, content)
+    VALUES (new.id, new.title, new.content);
+END;
+
+CREATE TRIGGER documents_fts_update AFTER UPDATE OF title, content ON documents BEGIN
+    UPDATE documents_fts SET title = new.title, content = new.content
+    WHERE document_id = old.id;
+END;
+
+CREATE TRIGGER documents_fts_delete AFTER DELETE ON documents BEGIN
+    DELETE FROM documents_fts WHERE document_id = old.id;
+END;
+
+-- Index the documents that already exist
+INSERT INTO documents_fts (document_id, title, content)
+SELECT id, title, content FROM documents;
@@ -0,0 +1,19 @@
+-- Every request to the LLM, for enforcing the quotas
+CREATE TABLE llm_usage (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    tokens INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_llm_usage_user_id_created_at ON llm_usage(user_id, created_at);
+
+-- Limits for single users that differ from the server defaults. NULL keeps
+-- the default, 0 means unlimited.
+CREATE TABLE llm_quotas (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    request_limit INTEGER,
+    token_limit INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
@@ -0,0 +1,25 @@
+-- Conversations with the AI assistant about a document
+CREATE TABLE chat_threads (
+    id TEXT PRIMARY KEY NOT NULL,
+    document_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_chat_threads_document_id ON chat_threads(document_id, updated_at);
+
+-- The turns of a conversation, role is 'user' or 'assistant'
+CREATE TABLE chat_messages (
+    id TEXT PRIMARY KEY NOT NULL,
+    thread_id TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('user', 'assistant')),
+    content TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (thread_id) REFERENCES chat_threads (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_chat_messages_thread_id ON chat_messages(thread_id, created_at);
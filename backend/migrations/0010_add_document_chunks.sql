@@ -0,0 +1,35 @@
+-- Pieces of the documents with their embeddings, to find the parts of a
+-- user's documents that fit a question. Embeddings are little-endian f32s.
+CREATE TABLE document_chunks (
+    id TEXT PRIMARY KEY NOT NULL,
+    document_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_document_chunks_user_id ON document_chunks(user_id);
+CREATE INDEX idx_document_chunks_document_id ON document_chunks(document_id);
+
+-- Which version of a document is in document_chunks, and with which model
+CREATE TABLE document_index (
+    document_id TEXT PRIMARY KEY NOT NULL,
+    model TEXT NOT NULL,
+    indexed_at TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
+
+-- Versions of documents that could not be indexed, which are not tried
+-- again until they change
+CREATE TABLE document_index_failures (
+    document_id TEXT PRIMARY KEY NOT NULL,
+    model TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
+
+-- The documents an answer drew on, as JSON
+ALTER TABLE chat_messages ADD COLUMN sources TEXT;
@@ -0,0 +1,18 @@
+-- System prompts for the AI chat. Prompts without a user are shared with
+-- everyone and managed by admins, one of them can replace the built-in
+-- default prompt.
+CREATE TABLE prompt_templates (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT,
+    name TEXT NOT NULL,
+    template TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_prompt_templates_user_id ON prompt_templates(user_id);
+
+-- Admins are made with an explicit UPDATE, never by registering a name
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,11 @@
+-- Rendered diagrams, by the SHA-256 of their language and source, so that
+-- each diagram is only drawn or fetched from Kroki once. SVG and PNG images
+-- of the same diagram are cached apart.
+CREATE TABLE diagram_cache (
+    hash TEXT NOT NULL,
+    format TEXT NOT NULL,
+    diagram_type TEXT NOT NULL,
+    image BLOB NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (hash, format)
+);
@@ -0,0 +1,11 @@
+-- Other users who may edit a document together with its owner
+CREATE TABLE document_collaborators (
+    document_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (document_id, user_id),
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_document_collaborators_user_id ON document_collaborators(user_id);
@@ -0,0 +1,186 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use dr_markdown_llm::ChatMessage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    edit,
+    handlers::AppError,
+    llm,
+    models::{AiActionRequest, AiActionResponse},
+    prompts, AppState,
+};
+
+/// Where the answer of an action goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    /// The answer takes the place of the selection.
+    #[default]
+    Replace,
+    /// The answer is inserted after the selection.
+    After,
+}
+
+/// Something the AI does with the selected text, e.g. fixing its grammar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiAction {
+    pub id: String,
+    pub label: String,
+    /// The instruction for the model. `{text}` stands for the selection and
+    /// `{argument}` for what the user entered.
+    pub prompt: String,
+    /// What to ask the user for before running the action, e.g. "Language".
+    #[serde(default)]
+    pub argument: Option<String>,
+    #[serde(default)]
+    pub placement: Placement,
+}
+
+impl AiAction {
+    fn new(id: &str, label: &str, prompt: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            prompt: prompt.to_string(),
+            argument: None,
+            placement: Placement::Replace,
+        }
+    }
+
+    fn render(&self, text: &str, argument: &str) -> String {
+        // The selection is added at the end if the template doesn't say where
+        let prompt = if self.prompt.contains("{text}") {
+            self.prompt.clone()
+        } else {
+            format!("{}\n\n{{text}}", self.prompt)
+        };
+        // In one pass, so that an argument containing `{text}` stays as it is
+        prompts::fill_placeholders(&prompt, |name| match name {
+            "argument" => Some(argument),
+            "text" => Some(text),
+            _ => None,
+        })
+    }
+}
+
+const ACTION_INSTRUCTIONS: &str = "You are a writing assistant working on a Markdown document. Do what the user asks with the text they give you. Reply only with the text that goes into the document: no explanations, no introduction, and do not wrap it in a code block.";
+
+/// The actions offered when no actions file is configured.
+pub fn default_actions() -> Vec<AiAction> {
+    vec![
+        AiAction::new(
+            "rewrite",
+            "Rewrite",
+            "Rewrite the following text so it reads better. Keep its meaning, language and Markdown formatting:\n\n{text}",
+        ),
+        AiAction::new(
+            "summarize",
+            "Summarize",
+            "Summarize the following text in a few sentences, in the same language:\n\n{text}",
+        ),
+        AiAction::new(
+            "fix_grammar",
+            "Fix grammar",
+            "Fix spelling, grammar and punctuation of the following text. Change nothing else:\n\n{text}",
+        ),
+        AiAction {
+            argument: Some("Language".to_string()),
+            ..AiAction::new(
+                "translate",
+                "Translate to…",
+                "Translate the following text to {argument}. Keep its Markdown formatting:\n\n{text}",
+            )
+        },
+        AiAction {
+            placement: Placement::After,
+            ..AiAction::new(
+                "continue",
+                "Continue writing",
+                "Continue writing after the following text, in the same style and language. Reply with the continuation only, without repeating the text:\n\n{text}",
+            )
+        },
+        AiAction::new(
+            "table",
+            "Convert to table",
+            "Convert the following text into a Markdown table:\n\n{text}",
+        ),
+    ]
+}
+
+/// Reads the actions from a JSON file with a list of actions, which takes
+/// the place of the default actions.
+pub fn load_actions(path: &str) -> anyhow::Result<Vec<AiAction>> {
+    let file = std::fs::read_to_string(path).with_context(|| format!("Cannot read AI actions from {}", path))?;
+    let actions: Vec<AiAction> =
+        serde_json::from_str(&file).with_context(|| format!("Invalid AI actions in {}", path))?;
+
+    if let Some(action) = actions.iter().find(|action| action.id.is_empty() || action.prompt.is_empty()) {
+        anyhow::bail!("AI action {:?} in {} needs an id and a prompt", action.label, path);
+    }
+    Ok(actions)
+}
+
+pub async fn get_ai_actions(_auth_user: AuthUser, State(state): State<AppState>) -> impl IntoResponse {
+    let actions: Vec<AiActionResponse> = state.config.llm_actions.iter().map(AiActionResponse::from).collect();
+    Json(actions)
+}
+
+/// Runs an action on the selected text and streams the text that goes into
+/// the document, in the same format as chat answers.
+pub async fn run_ai_action(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(action_id): Path<String>,
+    Json(request): Json<AiActionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let action = state
+        .config
+        .llm_actions
+        .iter()
+        .find(|action| action.id == action_id)
+        .ok_or(AppError::AiActionNotFound)?;
+
+    let text = edit::select(&request.content, request.selection)?;
+
+    let argument = request.argument.as_deref().map(str::trim).unwrap_or_default();
+    if let Some(name) = &action.argument {
+        if argument.is_empty() {
+            return Err(AppError::MissingActionArgument(name.clone()));
+        }
+    }
+
+    let messages = vec![
+        ChatMessage::system(format!(
+            "{}\n\nThe text is part of this document:\n\n---\n{}\n---",
+            ACTION_INSTRUCTIONS, request.content
+        )),
+        ChatMessage::user(action.render(&text, argument)),
+    ];
+    llm::stream_answer(&state, auth_user.user_id, messages, Vec::new(), |_| {}).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_in_the_argument_are_not_filled_in() {
+        let action = AiAction::new("translate", "Translate", "Translate to {argument}:\n\n{text}");
+
+        let prompt = action.render("Hello {argument}", "{text} and French");
+        assert_eq!(prompt, "Translate to {text} and French:\n\nHello {argument}");
+    }
+
+    #[test]
+    fn selection_is_appended_without_placeholder() {
+        let action = AiAction::new("shorten", "Shorten", "Make this shorter");
+
+        assert_eq!(action.render("Some text", ""), "Make this shorter\n\nSome text");
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use axum::{
     extract::FromRequestParts,
-    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -74,8 +74,14 @@ impl FromRequestParts<AppState> for AuthUser {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let authorization = parts
-            .headers
+        AuthUser::from_headers(&parts.headers, state).await
+    }
+}
+
+impl AuthUser {
+    /// Authenticates the `Authorization: Bearer` header of a request.
+    pub async fn from_headers(headers: &HeaderMap, state: &AppState) -> Result<Self, AuthError> {
+        let authorization = headers
             .get(AUTHORIZATION)
             .ok_or(AuthError::MissingToken)?;
 
@@ -89,6 +95,12 @@ impl FromRequestParts<AppState> for AuthUser {
 
         let token = authorization.trim_start_matches("Bearer ");
 
+        AuthUser::from_token(token, state).await
+    }
+
+    /// Authenticates a raw JWT, for requests that cannot send an Authorization
+    /// header (such as browser WebSocket connections).
+    pub async fn from_token(token: &str, state: &AppState) -> Result<Self, AuthError> {
         let claims = AuthService::verify_token(token, &state.config.jwt_secret)
             .map_err(|_| AuthError::InvalidToken)?;
 
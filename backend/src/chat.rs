@@ -0,0 +1,184 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use dr_markdown_llm::ChatMessage;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::AuthUser,
+    embeddings,
+    handlers::AppError,
+    llm,
+    models::{
+        ChatMessageRequest, ChatThread, ChatThreadResponse, ChatThreadSummary, CreateChatThreadRequest,
+    },
+    prompts::{self, PromptVariables},
+    AppState,
+};
+
+/// Threads without a title are named after the first question, cut to
+/// this many characters.
+const TITLE_LENGTH: usize = 60;
+
+pub async fn get_chat_threads(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    // Make sure the document exists and belongs to the user
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let threads = state
+        .db
+        .find_chat_threads_by_document(document_id, auth_user.user_id)
+        .await?;
+
+    let summaries: Vec<ChatThreadSummary> = threads.into_iter().map(Into::into).collect();
+    Ok(Json(summaries))
+}
+
+pub async fn create_chat_thread(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Json(request): Json<CreateChatThreadRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let title = request.title.as_deref().map(str::trim).unwrap_or_default();
+    let thread = state
+        .db
+        .create_chat_thread(document_id, auth_user.user_id, title)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(ChatThreadResponse::new(thread, Vec::new()))))
+}
+
+pub async fn get_chat_thread(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, thread_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    let thread = find_thread(&state, document_id, thread_id, auth_user.user_id).await?;
+    let messages = state.db.find_chat_messages(thread.id).await?;
+
+    Ok(Json(ChatThreadResponse::new(thread, messages)))
+}
+
+pub async fn delete_chat_thread(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, thread_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = state
+        .db
+        .delete_chat_thread(thread_id, document_id, auth_user.user_id)
+        .await?;
+
+    if !deleted {
+        return Err(AppError::ChatThreadNotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Continues a thread: the question is stored, the whole conversation is
+/// sent to the model and its answer streamed back and stored once complete.
+pub async fn post_chat_message(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, thread_id)): Path<(Uuid, Uuid)>,
+    Json(request): Json<ChatMessageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let thread = find_thread(&state, document_id, thread_id, auth_user.user_id).await?;
+    let document = state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let variables = PromptVariables {
+        title: &document.title,
+        content: request.context.as_deref().unwrap_or(&document.content),
+        selection: request.selection.as_deref().unwrap_or_default(),
+        question: &request.message,
+    };
+    let system = prompts::system_prompt(&state, auth_user.user_id, request.prompt_id, &variables).await?;
+
+    let history = state.db.find_chat_messages(thread.id).await?;
+    let mut messages = vec![system];
+    messages.extend(history.into_iter().map(|message| match message.role.as_str() {
+        "assistant" => ChatMessage::assistant(message.content),
+        _ => ChatMessage::user(message.content),
+    }));
+
+    let mut sources = Vec::new();
+    if request.search_documents {
+        if let Some((found, prompt)) =
+            embeddings::find_sources(&state, auth_user.user_id, &request.message, document_id).await?
+        {
+            sources = found;
+            messages.push(prompt);
+        }
+    }
+    messages.push(ChatMessage::user(request.message.clone()));
+
+    // Checked before anything is stored, so that a rejected question does
+    // not end up in the thread
+    let db = state.db.clone();
+    let answer_sources = sources.clone();
+    let response = llm::stream_answer(&state, auth_user.user_id, messages, sources, move |answer| {
+        tokio::spawn(async move {
+            if let Err(e) = db.add_chat_message(thread.id, "assistant", &answer, &answer_sources).await {
+                println!("Failed to store chat answer: {}", e);
+            }
+        });
+    })
+    .await?;
+
+    state.db.add_chat_message(thread.id, "user", &request.message, &[]).await?;
+    if thread.title.is_empty() {
+        state.db.rename_chat_thread(thread.id, &thread_title(&request.message)).await?;
+    }
+
+    Ok(response)
+}
+
+async fn find_thread(
+    state: &AppState,
+    document_id: Uuid,
+    thread_id: Uuid,
+    user_id: Uuid,
+) -> Result<ChatThread, AppError> {
+    state
+        .db
+        .find_chat_thread(thread_id, document_id, user_id)
+        .await?
+        .ok_or(AppError::ChatThreadNotFound)
+}
+
+fn thread_title(question: &str) -> String {
+    let line = question.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    if line.chars().count() <= TITLE_LENGTH {
+        return line.to_string();
+    }
+
+    let cut: String = line.chars().take(TITLE_LENGTH).collect();
+    format!("{}…", cut.trim_end())
+}
@@ -1,12 +1,42 @@
 use anyhow::Result;
+use dr_markdown_llm::ProviderKind;
+use dr_markdown_render::{DiagramFormat, DiagramLanguages};
 use serde::{Deserialize, Serialize};
 
+use crate::actions::{self, AiAction};
+use crate::diagrams;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
     pub allow_registration: bool,
     pub server_port: u16,
+    /// Kroki server for the diagrams that are not drawn by the backend
+    /// itself. None keeps every diagram on this server.
+    pub kroki_url: Option<String>,
+    /// The code block languages drawn as diagrams, with their aliases and
+    /// formats.
+    #[serde(skip)]
+    pub diagram_languages: DiagramLanguages,
+    /// LLM requests per user and quota window, 0 for unlimited.
+    pub llm_request_quota: i64,
+    /// LLM tokens (prompt and answer) per user and quota window, 0 for
+    /// unlimited.
+    pub llm_token_quota: i64,
+    pub llm_quota_window_secs: i64,
+    /// Which API the model server speaks.
+    pub llm_provider: ProviderKind,
+    pub llm_url: String,
+    pub llm_model: String,
+    /// Bearer token for OpenAI-compatible servers that require one.
+    pub llm_api_key: Option<String>,
+    /// The model that turns documents and questions into embeddings, to
+    /// search the user's documents for the chat. None turns this off.
+    pub llm_embedding_model: Option<String>,
+    /// What can be done with selected text, from `LLM_ACTIONS_FILE` or
+    /// the defaults.
+    pub llm_actions: Vec<AiAction>,
 }
 
 impl Default for Config {
@@ -16,6 +46,21 @@ impl Default for Config {
             jwt_secret: "your-secret-key-change-this-in-production".to_string(),
             allow_registration: true,
             server_port: 3001,
+            kroki_url: None,
+            diagram_languages: diagrams::diagram_languages(
+                &diagrams::default_diagram_names(false),
+                diagrams::DEFAULT_DIAGRAM_ALIASES,
+                DiagramFormat::Svg,
+            ),
+            llm_request_quota: 100,
+            llm_token_quota: 200_000,
+            llm_quota_window_secs: 24 * 60 * 60,
+            llm_provider: ProviderKind::Ollama,
+            llm_url: "http://localhost:11434".to_string(),
+            llm_model: "llama3.2".to_string(),
+            llm_api_key: None,
+            llm_embedding_model: None,
+            llm_actions: actions::default_actions(),
         }
     }
 }
@@ -41,6 +86,64 @@ impl Config {
             config.server_port = port.parse().unwrap_or(3001);
         }
 
+        if let Ok(url) = std::env::var("KROKI_URL") {
+            config.kroki_url = Some(url).filter(|url| !url.is_empty());
+        }
+
+        // Which diagrams can be drawn depends on whether there is a Kroki
+        // server
+        let diagram_names = std::env::var("DIAGRAM_LANGUAGES")
+            .ok()
+            .filter(|names| !names.trim().is_empty())
+            .unwrap_or_else(|| diagrams::default_diagram_names(config.kroki_url.is_some()));
+        let diagram_aliases =
+            std::env::var("DIAGRAM_ALIASES").unwrap_or_else(|_| diagrams::DEFAULT_DIAGRAM_ALIASES.to_string());
+        let diagram_format = match std::env::var("DIAGRAM_FORMAT") {
+            Ok(format) if !format.is_empty() => format.parse().map_err(anyhow::Error::msg)?,
+            _ => DiagramFormat::Svg,
+        };
+        config.diagram_languages = diagrams::diagram_languages(&diagram_names, &diagram_aliases, diagram_format);
+
+        if let Ok(quota) = std::env::var("LLM_REQUEST_QUOTA") {
+            config.llm_request_quota = quota.parse().unwrap_or(config.llm_request_quota);
+        }
+
+        if let Ok(quota) = std::env::var("LLM_TOKEN_QUOTA") {
+            config.llm_token_quota = quota.parse().unwrap_or(config.llm_token_quota);
+        }
+
+        if let Ok(window) = std::env::var("LLM_QUOTA_WINDOW_SECS") {
+            config.llm_quota_window_secs = window.parse().unwrap_or(config.llm_quota_window_secs);
+        }
+
+        if let Ok(provider) = std::env::var("LLM_PROVIDER") {
+            config.llm_provider = provider.parse().map_err(anyhow::Error::msg)?;
+        }
+
+        // OLLAMA_ADDR and OLLAMA_MODEL are still understood from before other
+        // model servers were supported
+        if let Ok(url) = std::env::var("LLM_URL").or_else(|_| std::env::var("OLLAMA_ADDR")) {
+            config.llm_url = url;
+        }
+
+        if let Ok(model) = std::env::var("LLM_MODEL").or_else(|_| std::env::var("OLLAMA_MODEL")) {
+            config.llm_model = model;
+        }
+
+        if let Ok(api_key) = std::env::var("LLM_API_KEY") {
+            config.llm_api_key = Some(api_key).filter(|key| !key.is_empty());
+        }
+
+        if let Ok(model) = std::env::var("LLM_EMBEDDING_MODEL") {
+            config.llm_embedding_model = Some(model).filter(|model| !model.is_empty());
+        }
+
+        if let Ok(path) = std::env::var("LLM_ACTIONS_FILE") {
+            if !path.is_empty() {
+                config.llm_actions = actions::load_actions(&path)?;
+            }
+        }
+
         Ok(config)
     }
 }
\ No newline at end of file
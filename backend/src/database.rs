@@ -1,19 +1,30 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use dr_markdown_llm::Source;
 use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
-use crate::models::{Document, User};
+use crate::models::{
+    ChatThread, ChatThreadMessage, Document, DocumentChunk, DocumentRevision, DocumentSearchHit, DocumentTag, Folder,
+    LlmQuotaOverride, LlmUsage, PromptTemplate, User,
+};
 use crate::THEME_LIGHT;
 
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    /// Woken whenever a document is created or changed.
+    documents_changed: Arc<Notify>,
 }
 
 impl Database {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            documents_changed: Arc::new(Notify::new()),
+        }
     }
 
     /// Create a new Database, ensuring the parent directory for the SQLite file exists.
@@ -25,7 +36,7 @@ impl Database {
             }
         }
         let pool = SqlitePool::connect(database_url).await?;
-        Ok(Self { pool })
+        Ok(Self::new(pool))
     }
 
     pub async fn migrate(&self) -> Result<()> {
@@ -139,21 +150,37 @@ impl Database {
         Ok(user)
     }
 
+    /// Whether the user manages the shared AI prompts. False for users that
+    /// do not exist.
+    pub async fn is_admin(&self, user_id: Uuid) -> Result<bool> {
+        let is_admin = sqlx::query_scalar!(
+            r#"SELECT is_admin as "is_admin: bool" FROM users WHERE id = ?"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(is_admin.unwrap_or(false))
+    }
+
     // Document operations
     pub async fn create_document(
         &self,
         user_id: Uuid,
         title: &str,
         content: &str,
+        folder_id: Option<Uuid>,
     ) -> Result<Document> {
         let id = Uuid::new_v4();
         let now = Utc::now();
 
+        let mut tx = self.pool.begin().await?;
+
         let document = sqlx::query_as!(
             Document,
             r#"
-            INSERT INTO documents (id, user_id, title, content, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT INTO documents (id, user_id, title, content, created_at, updated_at, folder_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             RETURNING
                 id as "id: Uuid",
                 user_id as "user_id: Uuid",
@@ -161,18 +188,24 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             "#,
             id,
             user_id,
             title,
             content,
             now,
-            now
+            now,
+            folder_id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        Self::insert_revision(&mut tx, &document).await?;
+        tx.commit().await?;
+        self.documents_changed.notify_one();
+
         Ok(document)
     }
 
@@ -191,7 +224,8 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             FROM documents WHERE id = ? AND user_id = ?
             "#,
             document_id,
@@ -214,7 +248,8 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             FROM documents WHERE user_id = ? ORDER BY updated_at DESC
             "#,
             user_id
@@ -225,12 +260,91 @@ impl Database {
         Ok(documents)
     }
 
+    /// Ids of all documents of a user, least recently updated first.
+    pub async fn find_document_ids_by_user(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        let ids = sqlx::query_scalar!(
+            r#"SELECT id as "id: Uuid" FROM documents WHERE user_id = ? ORDER BY updated_at"#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Documents of a user that carry `tag`, most recently updated first.
+    pub async fn find_documents_by_tag(&self, user_id: Uuid, tag: &str) -> Result<Vec<Document>> {
+        let documents = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                d.id as "id: Uuid",
+                d.user_id as "user_id: Uuid",
+                d.title,
+                d.content,
+                d.created_at as "created_at: DateTime<Utc>",
+                d.updated_at as "updated_at: DateTime<Utc>",
+                d.share_token,
+                d.folder_id as "folder_id: Uuid"
+            FROM documents d
+            INNER JOIN document_tags t ON t.document_id = d.id
+            WHERE d.user_id = ? AND t.tag = ?
+            ORDER BY d.updated_at DESC
+            "#,
+            user_id,
+            tag
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(documents)
+    }
+
+    /// Full-text search over the titles and content of a user's documents,
+    /// best matches first. Every word of `query` is matched as a prefix.
+    pub async fn search_documents(
+        &self,
+        user_id: Uuid,
+        query: &str,
+    ) -> Result<Vec<DocumentSearchHit>> {
+        let Some(match_expression) = fts_match_expression(query) else {
+            return Ok(Vec::new());
+        };
+
+        // Title matches weigh more than content matches
+        let hits = sqlx::query_as!(
+            DocumentSearchHit,
+            r#"
+            SELECT
+                d.id as "id!: Uuid",
+                d.title as "title!",
+                snippet(documents_fts, 2, char(57344), char(57345), '…', 16) as "snippet!: String",
+                d.updated_at as "updated_at!: DateTime<Utc>"
+            FROM documents_fts
+            INNER JOIN documents d ON d.id = documents_fts.document_id
+            WHERE documents_fts MATCH ? AND d.user_id = ?
+            ORDER BY bm25(documents_fts, 0.0, 10.0, 1.0)
+            LIMIT 50
+            "#,
+            match_expression,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hits)
+    }
+
+    /// Updates the title and/or content. With `last_updated_at` the update
+    /// only happens if the document was not changed since; None means it was,
+    /// or that the document does not exist.
     pub async fn update_document(
         &self,
         document_id: Uuid,
         user_id: Uuid,
         title: Option<&str>,
         content: Option<&str>,
+        last_updated_at: Option<DateTime<Utc>>,
     ) -> Result<Option<Document>> {
         let now = Utc::now();
 
@@ -241,15 +355,15 @@ impl Database {
 
         let existing = existing.unwrap();
 
-        let new_title = title.unwrap_or(&existing.title);
-        let new_content = content.unwrap_or(&existing.content);
+        let mut tx = self.pool.begin().await?;
 
+        // Checking the time in the same statement leaves no gap for another save
         let document = sqlx::query_as!(
             Document,
             r#"
             UPDATE documents
-            SET title = ?, content = ?, updated_at = ?
-            WHERE id = ? AND user_id = ?
+            SET title = COALESCE(?, title), content = COALESCE(?, content), updated_at = ?
+            WHERE id = ? AND user_id = ? AND (? IS NULL OR updated_at = ?)
             RETURNING
                 id as "id: Uuid",
                 user_id as "user_id: Uuid",
@@ -257,20 +371,159 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             "#,
-            new_title,
-            new_content,
+            title,
+            content,
             now,
             document_id,
-            user_id
+            user_id,
+            last_updated_at,
+            last_updated_at
         )
-        .fetch_one(&self.pool)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(document) = document else {
+            return Ok(None);
+        };
+
+        // Only record a new revision when something actually changed
+        if document.title != existing.title || document.content != existing.content {
+            Self::insert_revision(&mut tx, &document).await?;
+        }
+        tx.commit().await?;
+        self.documents_changed.notify_one();
+
+        Ok(Some(document))
+    }
+
+    /// Saves the content of a live editing session. Sessions save every few
+    /// seconds, so a revision is only recorded when `record_revision` says
+    /// so. None means the document no longer exists.
+    pub async fn save_live_document(
+        &self,
+        document_id: Uuid,
+        content: &str,
+        record_revision: bool,
+    ) -> Result<Option<Document>> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let document = sqlx::query_as!(
+            Document,
+            r#"
+            UPDATE documents
+            SET content = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                title,
+                content,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>",
+                share_token,
+                folder_id as "folder_id: Uuid"
+            "#,
+            content,
+            now,
+            document_id
+        )
+        .fetch_optional(&mut *tx)
         .await?;
+        let Some(document) = document else {
+            return Ok(None);
+        };
+
+        if record_revision {
+            Self::insert_revision(&mut tx, &document).await?;
+        }
+        tx.commit().await?;
+        self.documents_changed.notify_one();
 
         Ok(Some(document))
     }
 
+    /// A document its owner or one of its collaborators may edit.
+    pub async fn find_editable_document(
+        &self,
+        document_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<Document>> {
+        let document = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                title,
+                content,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>",
+                share_token,
+                folder_id as "folder_id: Uuid"
+            FROM documents
+            WHERE id = ? AND (
+                user_id = ?
+                OR EXISTS (SELECT 1 FROM document_collaborators c WHERE c.document_id = documents.id AND c.user_id = ?)
+            )
+            "#,
+            document_id,
+            user_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(document)
+    }
+
+    pub async fn add_collaborator(&self, document_id: Uuid, user_id: Uuid) -> Result<()> {
+        let now = Utc::now();
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO document_collaborators (document_id, user_id, created_at) VALUES (?, ?, ?)",
+            document_id,
+            user_id,
+            now
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_collaborator(&self, document_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM document_collaborators WHERE document_id = ? AND user_id = ?",
+            document_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The usernames of a document's collaborators.
+    pub async fn find_collaborators(&self, document_id: Uuid) -> Result<Vec<String>> {
+        let usernames = sqlx::query_scalar!(
+            r#"
+            SELECT u.username
+            FROM document_collaborators c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.document_id = ?
+            ORDER BY u.username
+            "#,
+            document_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(usernames)
+    }
+
     pub async fn set_share_token(
         &self,
         document_id: Uuid,
@@ -295,7 +548,8 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             "#,
             share_token,
             document_id,
@@ -318,7 +572,8 @@ impl Database {
                 content,
                 created_at as "created_at: DateTime<Utc>",
                 updated_at as "updated_at: DateTime<Utc>",
-                share_token
+                share_token,
+                folder_id as "folder_id: Uuid"
             FROM documents WHERE share_token = ?
             "#,
             share_token
@@ -329,6 +584,40 @@ impl Database {
         Ok(document)
     }
 
+    /// Moves a document into a folder, or to the top level when `folder_id` is
+    /// `None`. The folder must belong to the same user.
+    pub async fn move_document(
+        &self,
+        document_id: Uuid,
+        user_id: Uuid,
+        folder_id: Option<Uuid>,
+    ) -> Result<Option<Document>> {
+        let document = sqlx::query_as!(
+            Document,
+            r#"
+            UPDATE documents
+            SET folder_id = ?
+            WHERE id = ? AND user_id = ?
+            RETURNING
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                title,
+                content,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>",
+                share_token,
+                folder_id as "folder_id: Uuid"
+            "#,
+            folder_id,
+            document_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(document)
+    }
+
     pub async fn delete_document(&self, document_id: Uuid, user_id: Uuid) -> Result<bool> {
         let result = sqlx::query!(
             "DELETE FROM documents WHERE id = ? AND user_id = ?",
@@ -340,4 +629,986 @@ impl Database {
 
         Ok(result.rows_affected() > 0)
     }
-}
\ No newline at end of file
+
+    // Folder operations
+    pub async fn create_folder(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        parent_id: Option<Uuid>,
+    ) -> Result<Folder> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let folder = sqlx::query_as!(
+            Folder,
+            r#"
+            INSERT INTO folders (id, user_id, parent_id, name, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                parent_id as "parent_id: Uuid",
+                name,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            "#,
+            id,
+            user_id,
+            parent_id,
+            name,
+            now,
+            now
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(folder)
+    }
+
+    pub async fn find_folder_by_id(&self, folder_id: Uuid, user_id: Uuid) -> Result<Option<Folder>> {
+        let folder = sqlx::query_as!(
+            Folder,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                parent_id as "parent_id: Uuid",
+                name,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM folders WHERE id = ? AND user_id = ?
+            "#,
+            folder_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(folder)
+    }
+
+    pub async fn find_folders_by_user(&self, user_id: Uuid) -> Result<Vec<Folder>> {
+        let folders = sqlx::query_as!(
+            Folder,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                parent_id as "parent_id: Uuid",
+                name,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM folders WHERE user_id = ? ORDER BY name COLLATE NOCASE
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(folders)
+    }
+
+    pub async fn update_folder(
+        &self,
+        folder_id: Uuid,
+        user_id: Uuid,
+        name: Option<&str>,
+        parent_id: Option<Option<Uuid>>,
+    ) -> Result<Option<Folder>> {
+        let now = Utc::now();
+
+        let existing = self.find_folder_by_id(folder_id, user_id).await?;
+        if existing.is_none() {
+            return Ok(None);
+        }
+
+        let existing = existing.unwrap();
+
+        let new_name = name.unwrap_or(&existing.name);
+        let new_parent_id = parent_id.unwrap_or(existing.parent_id);
+
+        let folder = sqlx::query_as!(
+            Folder,
+            r#"
+            UPDATE folders
+            SET name = ?, parent_id = ?, updated_at = ?
+            WHERE id = ? AND user_id = ?
+            RETURNING
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                parent_id as "parent_id: Uuid",
+                name,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            "#,
+            new_name,
+            new_parent_id,
+            now,
+            folder_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Some(folder))
+    }
+
+    /// Deletes a folder. Its documents and subfolders move up into the parent
+    /// folder instead of being deleted with it.
+    pub async fn delete_folder(&self, folder_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let existing = self.find_folder_by_id(folder_id, user_id).await?;
+        let Some(existing) = existing else {
+            return Ok(false);
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            "UPDATE documents SET folder_id = ? WHERE folder_id = ? AND user_id = ?",
+            existing.parent_id,
+            folder_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE folders SET parent_id = ? WHERE parent_id = ? AND user_id = ?",
+            existing.parent_id,
+            folder_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM folders WHERE id = ? AND user_id = ?",
+            folder_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Tag operations
+    pub async fn add_tag(&self, document_id: Uuid, tag: &str) -> Result<()> {
+        let now = Utc::now();
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO document_tags (document_id, tag, created_at) VALUES (?, ?, ?)",
+            document_id,
+            tag,
+            now
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_tag(&self, document_id: Uuid, tag: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM document_tags WHERE document_id = ? AND tag = ?",
+            document_id,
+            tag
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_tags_by_document(&self, document_id: Uuid) -> Result<Vec<String>> {
+        let tags = sqlx::query_scalar!(
+            "SELECT tag FROM document_tags WHERE document_id = ? ORDER BY tag",
+            document_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    /// All tags on all documents of a user.
+    pub async fn find_tags_by_user(&self, user_id: Uuid) -> Result<Vec<DocumentTag>> {
+        let tags = sqlx::query_as!(
+            DocumentTag,
+            r#"
+            SELECT
+                t.document_id as "document_id: Uuid",
+                t.tag
+            FROM document_tags t
+            INNER JOIN documents d ON d.id = t.document_id
+            WHERE d.user_id = ?
+            ORDER BY t.tag
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tags)
+    }
+
+    // Revision operations
+    async fn insert_revision(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        document: &Document,
+    ) -> Result<()> {
+        let id = Uuid::new_v4();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO document_revisions (id, document_id, revision, title, content, created_at)
+            VALUES (
+                ?,
+                ?,
+                (SELECT COALESCE(MAX(revision), 0) + 1 FROM document_revisions WHERE document_id = ?),
+                ?,
+                ?,
+                ?
+            )
+            "#,
+            id,
+            document.id,
+            document.id,
+            document.title,
+            document.content,
+            document.updated_at
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_revisions_by_document(
+        &self,
+        document_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<DocumentRevision>> {
+        let revisions = sqlx::query_as!(
+            DocumentRevision,
+            r#"
+            SELECT
+                r.id as "id: Uuid",
+                r.document_id as "document_id: Uuid",
+                r.revision,
+                r.title,
+                r.content,
+                r.created_at as "created_at: DateTime<Utc>"
+            FROM document_revisions r
+            INNER JOIN documents d ON d.id = r.document_id
+            WHERE r.document_id = ? AND d.user_id = ?
+            ORDER BY r.revision DESC
+            "#,
+            document_id,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(revisions)
+    }
+
+    pub async fn find_revision(
+        &self,
+        document_id: Uuid,
+        user_id: Uuid,
+        revision: i64,
+    ) -> Result<Option<DocumentRevision>> {
+        let revision = sqlx::query_as!(
+            DocumentRevision,
+            r#"
+            SELECT
+                r.id as "id: Uuid",
+                r.document_id as "document_id: Uuid",
+                r.revision,
+                r.title,
+                r.content,
+                r.created_at as "created_at: DateTime<Utc>"
+            FROM document_revisions r
+            INNER JOIN documents d ON d.id = r.document_id
+            WHERE r.document_id = ? AND d.user_id = ? AND r.revision = ?
+            "#,
+            document_id,
+            user_id,
+            revision
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(revision)
+    }
+
+    // Chat operations
+    pub async fn create_chat_thread(&self, document_id: Uuid, user_id: Uuid, title: &str) -> Result<ChatThread> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+
+        sqlx::query!(
+            "INSERT INTO chat_threads (id, document_id, user_id, title, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
+            id,
+            document_id,
+            user_id,
+            title,
+            now,
+            now
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(ChatThread {
+            id,
+            document_id,
+            user_id,
+            title: title.to_string(),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    /// The threads about a document, most recently continued first.
+    pub async fn find_chat_threads_by_document(&self, document_id: Uuid, user_id: Uuid) -> Result<Vec<ChatThread>> {
+        let threads = sqlx::query_as!(
+            ChatThread,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                document_id as "document_id: Uuid",
+                user_id as "user_id: Uuid",
+                title,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM chat_threads
+            WHERE document_id = ? AND user_id = ?
+            ORDER BY updated_at DESC
+            "#,
+            document_id,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(threads)
+    }
+
+    pub async fn find_chat_thread(
+        &self,
+        thread_id: Uuid,
+        document_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<ChatThread>> {
+        let thread = sqlx::query_as!(
+            ChatThread,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                document_id as "document_id: Uuid",
+                user_id as "user_id: Uuid",
+                title,
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM chat_threads
+            WHERE id = ? AND document_id = ? AND user_id = ?
+            "#,
+            thread_id,
+            document_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(thread)
+    }
+
+    pub async fn rename_chat_thread(&self, thread_id: Uuid, title: &str) -> Result<()> {
+        sqlx::query!("UPDATE chat_threads SET title = ? WHERE id = ?", title, thread_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_chat_thread(&self, thread_id: Uuid, document_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM chat_threads WHERE id = ? AND document_id = ? AND user_id = ?",
+            thread_id,
+            document_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The messages of a thread, oldest first.
+    pub async fn find_chat_messages(&self, thread_id: Uuid) -> Result<Vec<ChatThreadMessage>> {
+        let messages = sqlx::query_as!(
+            ChatThreadMessage,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                thread_id as "thread_id: Uuid",
+                role,
+                content,
+                sources,
+                created_at as "created_at: DateTime<Utc>"
+            FROM chat_messages
+            WHERE thread_id = ?
+            ORDER BY created_at
+            "#,
+            thread_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(messages)
+    }
+
+    /// Adds a message to a thread, which moves the thread to the top of the
+    /// document's threads.
+    pub async fn add_chat_message(
+        &self,
+        thread_id: Uuid,
+        role: &str,
+        content: &str,
+        sources: &[Source],
+    ) -> Result<ChatThreadMessage> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        let sources = (!sources.is_empty()).then(|| serde_json::to_string(sources)).transpose()?;
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO chat_messages (id, thread_id, role, content, sources, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            id,
+            thread_id,
+            role,
+            content,
+            sources,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("UPDATE chat_threads SET updated_at = ? WHERE id = ?", now, thread_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(ChatThreadMessage {
+            id,
+            thread_id,
+            role: role.to_string(),
+            content: content.to_string(),
+            sources,
+            created_at: now,
+        })
+    }
+
+    // Prompt template operations
+    /// The shared prompts followed by the user's own, each by name.
+    pub async fn find_prompt_templates(&self, user_id: Uuid) -> Result<Vec<PromptTemplate>> {
+        let prompts = sqlx::query_as!(
+            PromptTemplate,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                name,
+                template,
+                is_default as "is_default: bool",
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM prompt_templates
+            WHERE user_id IS NULL OR user_id = ?
+            ORDER BY user_id IS NOT NULL, name COLLATE NOCASE
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(prompts)
+    }
+
+    /// A prompt the user can use: a shared one or one of their own.
+    pub async fn find_prompt_template(&self, prompt_id: Uuid, user_id: Uuid) -> Result<Option<PromptTemplate>> {
+        let prompt = sqlx::query_as!(
+            PromptTemplate,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                name,
+                template,
+                is_default as "is_default: bool",
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM prompt_templates
+            WHERE id = ? AND (user_id IS NULL OR user_id = ?)
+            "#,
+            prompt_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(prompt)
+    }
+
+    pub async fn find_default_prompt_template(&self) -> Result<Option<PromptTemplate>> {
+        let prompt = sqlx::query_as!(
+            PromptTemplate,
+            r#"
+            SELECT
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                name,
+                template,
+                is_default as "is_default: bool",
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            FROM prompt_templates
+            WHERE user_id IS NULL AND is_default
+            "#
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(prompt)
+    }
+
+    /// Adds a prompt, shared with everyone if there is no user. A new
+    /// default prompt replaces the previous one.
+    pub async fn create_prompt_template(
+        &self,
+        user_id: Option<Uuid>,
+        name: &str,
+        template: &str,
+        is_default: bool,
+    ) -> Result<PromptTemplate> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+
+        if is_default {
+            sqlx::query!("UPDATE prompt_templates SET is_default = FALSE WHERE is_default")
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query!(
+            "INSERT INTO prompt_templates (id, user_id, name, template, is_default, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            id,
+            user_id,
+            name,
+            template,
+            is_default,
+            now,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(PromptTemplate {
+            id,
+            user_id,
+            name: name.to_string(),
+            template: template.to_string(),
+            is_default,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub async fn update_prompt_template(
+        &self,
+        prompt: &PromptTemplate,
+        name: Option<&str>,
+        template: Option<&str>,
+        is_default: Option<bool>,
+    ) -> Result<PromptTemplate> {
+        let now = Utc::now();
+        let new_name = name.unwrap_or(&prompt.name);
+        let new_template = template.unwrap_or(&prompt.template);
+        let new_is_default = is_default.unwrap_or(prompt.is_default);
+
+        let mut tx = self.pool.begin().await?;
+
+        if new_is_default {
+            sqlx::query!("UPDATE prompt_templates SET is_default = FALSE WHERE is_default AND id != ?", prompt.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let updated = sqlx::query_as!(
+            PromptTemplate,
+            r#"
+            UPDATE prompt_templates
+            SET name = ?, template = ?, is_default = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING
+                id as "id: Uuid",
+                user_id as "user_id: Uuid",
+                name,
+                template,
+                is_default as "is_default: bool",
+                created_at as "created_at: DateTime<Utc>",
+                updated_at as "updated_at: DateTime<Utc>"
+            "#,
+            new_name,
+            new_template,
+            new_is_default,
+            now,
+            prompt.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    pub async fn delete_prompt_template(&self, prompt_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM prompt_templates WHERE id = ?", prompt_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Document index operations
+    /// Waits until a document is created or changed. Changes while nobody
+    /// waits are not lost, the next call returns right away.
+    pub async fn documents_changed(&self) {
+        self.documents_changed.notified().await;
+    }
+
+    /// Documents that are not in the index with their current version and
+    /// the given embedding model, except those whose current version already
+    /// failed to index.
+    pub async fn find_documents_to_index(&self, model: &str) -> Result<Vec<Document>> {
+        let documents = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                d.id as "id: Uuid",
+                d.user_id as "user_id: Uuid",
+                d.title,
+                d.content,
+                d.created_at as "created_at: DateTime<Utc>",
+                d.updated_at as "updated_at: DateTime<Utc>",
+                d.share_token,
+                d.folder_id as "folder_id: Uuid"
+            FROM documents d
+            LEFT JOIN document_index i ON i.document_id = d.id
+            LEFT JOIN document_index_failures f
+                ON f.document_id = d.id AND f.model = ? AND f.updated_at = d.updated_at
+            WHERE (i.document_id IS NULL OR i.model != ? OR i.indexed_at != d.updated_at)
+                AND f.document_id IS NULL
+            ORDER BY d.updated_at DESC
+            "#,
+            model,
+            model
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(documents)
+    }
+
+    /// Replaces the chunks of a document, recording which version of the
+    /// document they were made from.
+    pub async fn replace_document_chunks(
+        &self,
+        document: &Document,
+        model: &str,
+        chunks: &[(String, Vec<u8>)],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM document_chunks WHERE document_id = ?", document.id)
+            .execute(&mut *tx)
+            .await?;
+
+        for (position, (content, embedding)) in chunks.iter().enumerate() {
+            let id = Uuid::new_v4();
+            let position = position as i64;
+            sqlx::query!(
+                "INSERT INTO document_chunks (id, document_id, user_id, position, content, embedding) VALUES (?, ?, ?, ?, ?, ?)",
+                id,
+                document.id,
+                document.user_id,
+                position,
+                content,
+                embedding
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO document_index (document_id, model, indexed_at) VALUES (?, ?, ?)
+            ON CONFLICT (document_id) DO UPDATE SET model = excluded.model, indexed_at = excluded.indexed_at
+            "#,
+            document.id,
+            model,
+            document.updated_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM document_index_failures WHERE document_id = ?", document.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Records that the current version of a document could not be indexed
+    /// with the given model, so that it is left alone until it changes.
+    pub async fn record_index_failure(&self, document: &Document, model: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_index_failures (document_id, model, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT (document_id) DO UPDATE SET
+                model = excluded.model,
+                updated_at = excluded.updated_at
+            "#,
+            document.id,
+            model,
+            document.updated_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets all indexing failures, so that every document is tried again.
+    pub async fn clear_index_failures(&self) -> Result<()> {
+        sqlx::query!("DELETE FROM document_index_failures")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// All indexed chunks of a user's documents made with the given model.
+    pub async fn find_document_chunks(&self, user_id: Uuid, model: &str) -> Result<Vec<DocumentChunk>> {
+        let chunks = sqlx::query_as!(
+            DocumentChunk,
+            r#"
+            SELECT
+                c.document_id as "document_id: Uuid",
+                d.title,
+                c.content,
+                c.embedding
+            FROM document_chunks c
+            JOIN documents d ON d.id = c.document_id
+            JOIN document_index i ON i.document_id = c.document_id
+            WHERE c.user_id = ? AND i.model = ?
+            ORDER BY c.document_id, c.position
+            "#,
+            user_id,
+            model
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(chunks)
+    }
+
+    // LLM quota operations
+    /// Records an LLM request if `allow` accepts the requests the user made
+    /// since `since`, and forgets about older ones. The write lock is taken
+    /// up front, so that no other request is counted in between. Returns
+    /// `None` if the request was not allowed.
+    pub async fn reserve_llm_usage(
+        &self,
+        user_id: Uuid,
+        tokens: i64,
+        since: DateTime<Utc>,
+        allow: impl FnOnce(&[LlmUsage]) -> bool,
+    ) -> Result<Option<Uuid>> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+
+        sqlx::query!(
+            "DELETE FROM llm_usage WHERE user_id = ? AND created_at < ?",
+            user_id,
+            since
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let usage = sqlx::query_as!(
+            LlmUsage,
+            r#"
+            SELECT
+                tokens,
+                created_at as "created_at: DateTime<Utc>"
+            FROM llm_usage
+            WHERE user_id = ?
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if !allow(&usage) {
+            return Ok(None);
+        }
+
+        sqlx::query!(
+            "INSERT INTO llm_usage (id, user_id, tokens, created_at) VALUES (?, ?, ?, ?)",
+            id,
+            user_id,
+            tokens,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(id))
+    }
+
+    /// Forgets a request that never reached the model.
+    pub async fn delete_llm_usage(&self, usage_id: Uuid) -> Result<()> {
+        sqlx::query!("DELETE FROM llm_usage WHERE id = ?", usage_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_llm_usage_tokens(&self, usage_id: Uuid, tokens: i64) -> Result<()> {
+        sqlx::query!("UPDATE llm_usage SET tokens = ? WHERE id = ?", tokens, usage_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The LLM requests of a user since `since`, oldest first.
+    pub async fn find_llm_usage_since(&self, user_id: Uuid, since: DateTime<Utc>) -> Result<Vec<LlmUsage>> {
+        let usage = sqlx::query_as!(
+            LlmUsage,
+            r#"
+            SELECT
+                tokens,
+                created_at as "created_at: DateTime<Utc>"
+            FROM llm_usage
+            WHERE user_id = ? AND created_at >= ?
+            ORDER BY created_at
+            "#,
+            user_id,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    pub async fn find_llm_quota_override(&self, user_id: Uuid) -> Result<Option<LlmQuotaOverride>> {
+        let quota = sqlx::query_as!(
+            LlmQuotaOverride,
+            "SELECT request_limit, token_limit FROM llm_quotas WHERE user_id = ?",
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(quota)
+    }
+
+    // Diagram cache operations
+    pub async fn find_cached_diagram(&self, hash: &str, format: &str) -> Result<Option<Vec<u8>>> {
+        let image = sqlx::query_scalar!(
+            "SELECT image FROM diagram_cache WHERE hash = ? AND format = ?",
+            hash,
+            format
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(image)
+    }
+
+    /// Caches a diagram and drops the oldest ones once all images together
+    /// are larger than `max_size` bytes.
+    pub async fn cache_diagram(
+        &self,
+        hash: &str,
+        format: &str,
+        diagram_type: &str,
+        image: &[u8],
+        max_size: i64,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO diagram_cache (hash, format, diagram_type, image, created_at) VALUES (?, ?, ?, ?, ?)",
+            hash,
+            format,
+            diagram_type,
+            image,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM diagram_cache WHERE rowid IN (
+                SELECT rowid FROM (
+                    SELECT rowid, SUM(length(image)) OVER (ORDER BY created_at DESC, rowid DESC) AS total
+                    FROM diagram_cache
+                )
+                WHERE total > ?
+            )
+            "#,
+            max_size
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Turns user input into an FTS5 query: every word is quoted (so operators and
+/// special characters are taken literally) and matched as a prefix.
+fn fts_match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
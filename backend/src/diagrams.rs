@@ -0,0 +1,367 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use dr_markdown_render::{
+    escape_html, find_diagrams, render_graphviz, Diagram, DiagramFormat, DiagramLanguage, DiagramLanguages,
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    auth::AuthUser,
+    handlers::AppError,
+    models::{DiagramRequest, DiagramSourceQuery},
+    AppState,
+};
+
+/// Everything Kroki can draw, the diagram languages when a Kroki server is
+/// configured and no others are picked.
+const KROKI_DIAGRAMS: &[&str] = &[
+    "actdiag", "blockdiag", "bpmn", "bytefield", "c4plantuml", "d2", "dbml", "ditaa", "erd", "excalidraw",
+    "graphviz", "mermaid", "nomnoml", "nwdiag", "packetdiag", "pikchr", "plantuml", "rackdiag", "seqdiag",
+    "structurizr", "svgbob", "symbolator", "tikz", "umlet", "vega", "vegalite", "wavedrom", "wireviz",
+];
+
+/// The language drawn by the backend itself.
+const GRAPHVIZ: &str = "graphviz";
+
+/// Other names of diagram languages, as `alias=language`.
+pub const DEFAULT_DIAGRAM_ALIASES: &str = "dot=graphviz,puml=plantuml";
+
+/// How long to wait for the Kroki server.
+const KROKI_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a Graphviz layout may take, and how long a request waits for
+/// its turn.
+const GRAPHVIZ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Graphviz layouts that run at the same time. A layout cannot be stopped,
+/// so one that runs past the timeout keeps its place until it ends, and slow
+/// graphs can never occupy more blocking threads than this.
+const MAX_GRAPHVIZ_LAYOUTS: usize = 2;
+static GRAPHVIZ_LAYOUTS: Semaphore = Semaphore::const_new(MAX_GRAPHVIZ_LAYOUTS);
+
+/// Longest diagram source that is rendered, in bytes.
+const MAX_SOURCE_LENGTH: usize = 8 * 1024;
+
+// Diagrams are only ever shown as images, but they could be opened directly,
+// so nothing in them may run
+const SVG_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'unsafe-inline'";
+
+/// Size of all cached images, beyond which the oldest ones are dropped.
+const MAX_CACHE_SIZE: i64 = 256 * 1024 * 1024;
+
+// What is behind a hash never changes. Images asked for with a login are
+// only kept by the browser, not by shared caches on the way.
+const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+const CACHE_FOREVER_PRIVATE: &str = "private, max-age=31536000, immutable";
+
+// How much of an error message fits into an error image
+const ERROR_LINES: usize = 20;
+const ERROR_LINE_LENGTH: usize = 84;
+
+/// The languages that can be drawn without a Kroki server, or with one.
+pub fn default_diagram_names(kroki: bool) -> String {
+    if kroki {
+        KROKI_DIAGRAMS.join(",")
+    } else {
+        GRAPHVIZ.to_string()
+    }
+}
+
+/// The diagram languages from comma separated lists of `names` and of
+/// `aliases` as `alias=language`. Graphviz is drawn here and always SVG, the
+/// others come in `format`.
+pub fn diagram_languages(names: &str, aliases: &str, format: DiagramFormat) -> DiagramLanguages {
+    let mut languages: Vec<DiagramLanguage> = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| DiagramLanguage {
+            format: if name == GRAPHVIZ { DiagramFormat::Svg } else { format },
+            ..DiagramLanguage::new(name)
+        })
+        .collect();
+
+    for alias in aliases.split(',').map(str::trim).filter(|alias| !alias.is_empty()) {
+        let Some((alias, name)) = alias.split_once('=') else {
+            println!("Ignoring diagram alias '{}', expected alias=language", alias);
+            continue;
+        };
+        // Aliases of languages that are not drawn are left out
+        if let Some(language) = languages.iter_mut().find(|language| language.name == name.trim()) {
+            language.aliases.push(alias.trim().to_string());
+        }
+    }
+
+    DiagramLanguages::new(languages)
+}
+
+/// Renders a diagram in its format. Graphviz diagrams are drawn here, all
+/// others are sent to the Kroki server if one is configured. Results are
+/// cached, so each diagram is only rendered once.
+pub async fn render_image(state: &AppState, diagram: &Diagram) -> Result<Vec<u8>, AppError> {
+    let unsupported = || AppError::UnsupportedDiagram(diagram.kind.clone());
+    let language = state.config.diagram_languages.find(&diagram.kind).ok_or_else(unsupported)?;
+    if language.name != diagram.kind {
+        return Err(unsupported());
+    }
+    let built_in = language.name == GRAPHVIZ;
+    if built_in && diagram.format != DiagramFormat::Svg {
+        return Err(AppError::UnsupportedDiagram(format!("{} {}", diagram.kind, diagram.format.as_str())));
+    }
+    if diagram.source.len() > MAX_SOURCE_LENGTH {
+        return Err(AppError::InvalidDiagram("Diagram is too long".to_string()));
+    }
+
+    let hash = diagram.hash();
+    let format = diagram.format.as_str();
+    if let Some(image) = state.db.find_cached_diagram(&hash, format).await? {
+        return Ok(image);
+    }
+
+    let image = if built_in {
+        let permit = tokio::time::timeout(GRAPHVIZ_TIMEOUT, GRAPHVIZ_LAYOUTS.acquire())
+            .await
+            .map_err(|_| AppError::DiagramBusy)?
+            .map_err(|_| AppError::DiagramBusy)?;
+        let source = diagram.source.clone();
+        let layout = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            render_graphviz(&source)
+        });
+        // The layout cannot be interrupted, but the request does not wait for it
+        tokio::time::timeout(GRAPHVIZ_TIMEOUT, layout)
+            .await
+            .map_err(|_| AppError::InvalidDiagram("The diagram took too long to lay out".to_string()))?
+            .map_err(|e| AppError::InvalidDiagram(e.to_string()))?
+            .map_err(|e| AppError::InvalidDiagram(e.to_string()))?
+            .into_bytes()
+    } else {
+        let kroki_url = state.config.kroki_url.as_deref().ok_or_else(unsupported)?;
+        fetch_from_kroki(kroki_url, diagram).await?
+    };
+
+    state.db.cache_diagram(&hash, format, &diagram.kind, &image, MAX_CACHE_SIZE).await?;
+    Ok(image)
+}
+
+async fn fetch_from_kroki(kroki_url: &str, diagram: &Diagram) -> Result<Vec<u8>, AppError> {
+    let client = reqwest::Client::builder()
+        .timeout(KROKI_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+
+    let response = client
+        .post(format!(
+            "{}/{}/{}",
+            kroki_url.trim_end_matches('/'),
+            diagram.kind,
+            diagram.format.as_str()
+        ))
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(diagram.source.clone())
+        .send()
+        .await
+        .map_err(|e| AppError::DiagramServer(e.to_string()))?;
+
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| AppError::DiagramServer(e.to_string()))?;
+
+    // Kroki answers 400 when it cannot parse the diagram
+    match status {
+        StatusCode::OK => Ok(body.to_vec()),
+        StatusCode::BAD_REQUEST => Err(AppError::InvalidDiagram(format!(
+            "Invalid diagram: {}",
+            String::from_utf8_lossy(&body)
+                .trim()
+                .chars()
+                .take(ERROR_LINES * ERROR_LINE_LENGTH)
+                .collect::<String>()
+        ))),
+        status => Err(AppError::DiagramServer(status.to_string())),
+    }
+}
+
+fn image_response(format: DiagramFormat, image: Vec<u8>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, format.mime_type()),
+            (header::CONTENT_SECURITY_POLICY, SVG_CONTENT_SECURITY_POLICY),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        image,
+    )
+}
+
+/// Renders a diagram given by its language, or one of its aliases, and its
+/// source. The format is the language's unless `output_format` is given.
+/// Errors are images, like those of `get_diagram`.
+pub async fn render_diagram(
+    _auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(request): Json<DiagramRequest>,
+) -> Response {
+    let Some(language) = state.config.diagram_languages.find(&request.diagram_type) else {
+        return error_response(AppError::UnsupportedDiagram(request.diagram_type));
+    };
+    let format = match request.output_format.as_deref().map(str::parse) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => return error_response(AppError::InvalidDiagram(e)),
+        None => language.format,
+    };
+
+    let diagram = Diagram {
+        kind: language.name.clone(),
+        source: request.diagram_source,
+        format,
+    };
+    match render_image(&state, &diagram).await {
+        Ok(image) => image_response(format, image).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Serves a diagram by the hash of its content, from the cache or else
+/// rendered from the `source`. Rendering needs a login in the Authorization
+/// header, or the share token of a document with the diagram as `token`.
+/// Errors are images as well, with the message of the diagram compiler, so
+/// that they show up in place of the diagram.
+pub async fn get_diagram(
+    State(state): State<AppState>,
+    Path((diagram_type, file_name)): Path<(String, String)>,
+    Query(query): Query<DiagramSourceQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let file = file_name
+        .split_once('.')
+        .filter(|(hash, _)| is_hash(hash))
+        .and_then(|(hash, extension)| Some((hash, extension.parse::<DiagramFormat>().ok()?)));
+    let Some((hash, format)) = file else {
+        return error_response(AppError::DiagramNotFound);
+    };
+
+    let etag = format!("\"{}.{}\"", hash, format.as_str());
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains(&etag));
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let cache_control = if headers.contains_key(header::AUTHORIZATION) {
+        CACHE_FOREVER_PRIVATE
+    } else {
+        CACHE_FOREVER
+    };
+    match cached_diagram(&state, &diagram_type, hash, format, &query, &headers).await {
+        Ok(image) => (
+            [(header::CACHE_CONTROL, cache_control.to_string()), (header::ETAG, etag)],
+            image_response(format, image),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn cached_diagram(
+    state: &AppState,
+    diagram_type: &str,
+    hash: &str,
+    format: DiagramFormat,
+    query: &DiagramSourceQuery,
+    headers: &HeaderMap,
+) -> Result<Vec<u8>, AppError> {
+    if let Some(image) = state.db.find_cached_diagram(hash, format.as_str()).await? {
+        return Ok(image);
+    }
+
+    let encoded_source = query.source.as_deref().ok_or(AppError::DiagramNotFound)?;
+    let diagram = Diagram::from_encoded(diagram_type, encoded_source, MAX_SOURCE_LENGTH)
+        .ok_or_else(|| AppError::InvalidDiagram("The diagram is not encoded correctly or too long".to_string()))?;
+    if diagram.hash() != hash {
+        return Err(AppError::InvalidDiagram("The diagram does not match its hash".to_string()));
+    }
+    if !may_render(state, headers, query.token.as_deref(), hash).await? {
+        return Err(AppError::DiagramNotAllowed);
+    }
+
+    render_image(state, &Diagram { format, ..diagram }).await
+}
+
+/// Whether the request comes with a login, or `share_token` is the share
+/// token of a document with the diagram. Logins are only taken from the
+/// Authorization header, never from the URL, where they would end up in logs
+/// and caches.
+async fn may_render(state: &AppState, headers: &HeaderMap, share_token: Option<&str>, hash: &str) -> Result<bool, AppError> {
+    if AuthUser::from_headers(headers, state).await.is_ok() {
+        return Ok(true);
+    }
+    let Some(share_token) = share_token else {
+        return Ok(false);
+    };
+    match state.db.find_document_by_share_token(share_token).await? {
+        Some(document) => Ok(find_diagrams(&document.content, &state.config.diagram_languages)
+            .iter()
+            .any(|diagram| diagram.hash() == hash)),
+        None => Ok(false),
+    }
+}
+
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn error_response(error: AppError) -> Response {
+    (
+        error.status(),
+        [(header::CACHE_CONTROL, "no-store")],
+        image_response(DiagramFormat::Svg, error_svg(&error.to_string()).into_bytes()),
+    )
+        .into_response()
+}
+
+/// An image with the message of a diagram that could not be rendered.
+fn error_svg(message: &str) -> String {
+    let lines: Vec<String> = message
+        .lines()
+        .flat_map(|line| {
+            // Control characters are not allowed in XML
+            let chars: Vec<char> = line.chars().filter(|c| !c.is_control()).collect();
+            if chars.is_empty() {
+                vec![String::new()]
+            } else {
+                chars
+                    .chunks(ERROR_LINE_LENGTH)
+                    .map(|chunk| chunk.iter().collect())
+                    .collect()
+            }
+        })
+        .take(ERROR_LINES)
+        .collect();
+
+    let height = 44 + 16 * lines.len();
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"640\" height=\"{height}\" viewBox=\"0 0 640 {height}\">\
+         <rect x=\"0.5\" y=\"0.5\" width=\"639\" height=\"{}\" rx=\"4\" fill=\"#fef2f2\" stroke=\"#fca5a5\"/>\
+         <text x=\"12\" y=\"24\" font-family=\"sans-serif\" font-size=\"13\" font-weight=\"bold\" fill=\"#991b1b\">Diagram error</text>",
+        height - 1
+    );
+    for (number, line) in lines.iter().enumerate() {
+        svg.push_str(&format!(
+            "<text x=\"12\" y=\"{}\" font-family=\"monospace\" font-size=\"12\" fill=\"#7f1d1d\" xml:space=\"preserve\">{}</text>",
+            46 + 16 * number,
+            escape_html(line)
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,89 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use dr_markdown_llm::ChatMessage;
+use validator::Validate;
+
+use crate::{
+    auth::AuthUser,
+    handlers::AppError,
+    llm,
+    models::{EditSuggestionRequest, EditSuggestionResponse, TextRange},
+    AppState,
+};
+
+const EDIT_INSTRUCTIONS: &str = "You are an editor of Markdown documents. Rewrite the text the user gives you as they instruct. Reply with the rewritten text only: no explanations, no introduction, and do not wrap it in a code block.";
+
+/// Asks the model to rewrite the document, or the selected part of it, and
+/// returns the rewrite next to the original for the user to review.
+pub async fn suggest_edit(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(request): Json<EditSuggestionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let original = match request.selection {
+        Some(range) => select(&request.content, range)?,
+        None => request.content.clone(),
+    };
+
+    let system_prompt = match request.selection {
+        // The rest of the document helps to keep the rewrite consistent
+        Some(_) => format!(
+            "{}\n\nThe text is part of this document:\n\n---\n{}\n---",
+            EDIT_INSTRUCTIONS, request.content
+        ),
+        None => EDIT_INSTRUCTIONS.to_string(),
+    };
+    let messages = vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(format!("Instruction: {}\n\nText:\n{}", request.instruction, original)),
+    ];
+
+    let (answer, headers) = llm::complete_answer(&state, auth_user.user_id, messages).await?;
+
+    let response = EditSuggestionResponse {
+        replacement: clean_replacement(&original, &answer),
+        original,
+        selection: request.selection,
+    };
+    Ok((headers, Json(response)))
+}
+
+/// The selected part of the content, checking that something inside of it
+/// is selected.
+pub fn select(content: &str, range: TextRange) -> Result<String, AppError> {
+    let length = content.chars().count();
+    if range.start >= range.end || range.end > length {
+        return Err(AppError::InvalidSelection);
+    }
+
+    Ok(content.chars().skip(range.start).take(range.end - range.start).collect())
+}
+
+/// Models like to put their answer into a code block and get the line
+/// breaks at the end wrong despite being told, which would show up as
+/// changes.
+fn clean_replacement(original: &str, answer: &str) -> String {
+    let mut replacement = answer.trim_matches('\n').to_string();
+
+    if !original.trim_start().starts_with("```") {
+        if let Some(inner) = unwrap_code_block(&replacement) {
+            replacement = inner;
+        }
+    }
+
+    let leading = original.len() - original.trim_start_matches('\n').len();
+    let trailing = original.len() - original.trim_end_matches('\n').len();
+    format!("{}{}{}", "\n".repeat(leading), replacement, "\n".repeat(trailing))
+}
+
+fn unwrap_code_block(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    let (first_line, rest) = trimmed.split_once('\n')?;
+    if !first_line.starts_with("```") || !rest.trim_end().ends_with("```") {
+        return None;
+    }
+
+    let inner = rest.trim_end().strip_suffix("```")?;
+    Some(inner.trim_matches('\n').to_string())
+}
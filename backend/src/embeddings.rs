@@ -0,0 +1,228 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use dr_markdown_llm::{ChatMessage, LlmProvider, Source};
+use uuid::Uuid;
+
+use crate::{database::Database, handlers::AppError, models::Document, AppState};
+
+/// Chunks are closed at the next paragraph once they are this many
+/// characters long, longer paragraphs are cut.
+const CHUNK_LENGTH: usize = 1200;
+/// Texts sent to the embedding model in one request.
+const EMBED_BATCH: usize = 32;
+/// Documents are saved every few seconds while someone types, so changes
+/// are collected for a while before they are indexed.
+const INDEX_DELAY: Duration = Duration::from_secs(10);
+/// Chunks given to the model for a question.
+const SOURCE_CHUNKS: usize = 5;
+/// Chunks less similar to the question are left out, even if fewer than
+/// `SOURCE_CHUNKS` remain.
+const MIN_SIMILARITY: f32 = 0.3;
+
+/// Keeps the chunks and embeddings of all documents up to date in the
+/// background: documents not yet indexed with `model` at startup, and
+/// every document that is created or changed afterwards.
+pub fn spawn_indexer(db: Database, llm: Arc<dyn LlmProvider>, model: String) {
+    tokio::spawn(async move {
+        // Documents that failed before the restart get another chance, e.g.
+        // after the model server was fixed
+        if let Err(e) = db.clear_index_failures().await {
+            println!("Failed to clear index failures: {}", e);
+        }
+        loop {
+            if let Err(e) = index_documents(&db, llm.as_ref(), &model).await {
+                println!("Failed to index documents: {}", e);
+            }
+            db.documents_changed().await;
+            tokio::time::sleep(INDEX_DELAY).await;
+        }
+    });
+}
+
+/// Indexes every outdated document. A document that fails is put aside
+/// until it changes, so that a document the model cannot handle is not
+/// sent to it again on every round.
+async fn index_documents(db: &Database, llm: &dyn LlmProvider, model: &str) -> anyhow::Result<()> {
+    for document in db.find_documents_to_index(model).await? {
+        if let Err(e) = index_document(db, llm, model, &document).await {
+            db.record_index_failure(&document, model).await?;
+            println!("Failed to index document {}: {}", document.id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn index_document(db: &Database, llm: &dyn LlmProvider, model: &str, document: &Document) -> anyhow::Result<()> {
+    let chunks = chunk_document(&document.title, &document.content);
+
+    let mut embeddings = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(EMBED_BATCH) {
+        embeddings.extend(llm.embed(model, batch).await?);
+    }
+
+    let rows: Vec<(String, Vec<u8>)> = chunks
+        .into_iter()
+        .zip(embeddings)
+        .map(|(chunk, embedding)| (chunk, encode(&normalize(embedding))))
+        .collect();
+    db.replace_document_chunks(document, model, &rows).await
+}
+
+/// Splits a document into pieces of a few paragraphs. Every piece starts
+/// with the document title and the heading it is under, which helps to
+/// match it with questions.
+pub fn chunk_document(title: &str, content: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut heading = String::new();
+    let mut current = String::new();
+
+    let mut close = |heading: &str, current: &mut String| {
+        if current.is_empty() {
+            return;
+        }
+        let label = if heading.is_empty() {
+            title.to_string()
+        } else {
+            format!("{} › {}", title, heading)
+        };
+        chunks.push(format!("{}\n\n{}", label, current));
+        current.clear();
+    };
+
+    for paragraph in content.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        // A new section starts a new chunk
+        if paragraph.starts_with('#') {
+            close(&heading, &mut current);
+            let first_line = paragraph.lines().next().unwrap_or_default();
+            heading = first_line.trim_start_matches('#').trim().to_string();
+        }
+
+        for piece in cut(paragraph) {
+            if current.chars().count() + piece.chars().count() > CHUNK_LENGTH {
+                close(&heading, &mut current);
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(piece);
+        }
+    }
+    close(&heading, &mut current);
+
+    chunks
+}
+
+/// Cuts a text into pieces of at most `CHUNK_LENGTH` characters, at
+/// whitespace where possible.
+fn cut(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.chars().count() > CHUNK_LENGTH {
+        let limit = rest.char_indices().nth(CHUNK_LENGTH).map(|(index, _)| index).unwrap_or(rest.len());
+        let end = rest[..limit].rfind(char::is_whitespace).filter(|end| *end > 0).unwrap_or(limit);
+        pieces.push(rest[..end].trim());
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Scaled to length 1, so that the dot product of two embeddings is their
+/// cosine similarity.
+fn normalize(mut embedding: Vec<f32>) -> Vec<f32> {
+    let length = embedding.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if length > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= length);
+    }
+    embedding
+}
+
+fn encode(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .collect()
+}
+
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Finds the parts of the user's other documents that are closest to the
+/// question. Returns the documents they come from, numbered for citing, and
+/// a message that gives them to the model, or nothing if none fit.
+///
+/// This loads and scores every chunk embedding of the user for each
+/// question, which is fine for personal notes but grows with the number of
+/// documents.
+pub async fn find_sources(
+    state: &AppState,
+    user_id: Uuid,
+    question: &str,
+    open_document: Uuid,
+) -> Result<Option<(Vec<Source>, ChatMessage)>, AppError> {
+    let model = state
+        .config
+        .llm_embedding_model
+        .as_deref()
+        .ok_or(AppError::DocumentSearchDisabled)?;
+
+    let question = state
+        .llm
+        .embed(model, &[question.to_string()])
+        .await
+        .map_err(|e| AppError::Llm(e.to_string()))?
+        .pop()
+        .map(normalize)
+        .unwrap_or_default();
+
+    // The open document is part of the prompt already
+    let mut scored: Vec<_> = state
+        .db
+        .find_document_chunks(user_id, model)
+        .await?
+        .into_iter()
+        .filter(|chunk| chunk.document_id != open_document)
+        .map(|chunk| (similarity(&question, &decode(&chunk.embedding)), chunk))
+        .filter(|(score, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored.truncate(SOURCE_CHUNKS);
+
+    if scored.is_empty() {
+        return Ok(None);
+    }
+
+    // One number per document, the best match first
+    let mut sources: Vec<Source> = Vec::new();
+    let mut excerpts: Vec<Vec<String>> = Vec::new();
+    for (_, chunk) in scored {
+        match sources.iter().position(|source| source.document_id == chunk.document_id) {
+            Some(index) => excerpts[index].push(chunk.content),
+            None => {
+                sources.push(Source {
+                    number: sources.len() + 1,
+                    document_id: chunk.document_id,
+                    title: chunk.title,
+                });
+                excerpts.push(vec![chunk.content]);
+            }
+        }
+    }
+
+    let mut prompt = String::from(
+        "These parts of the user's other documents may help with the question. Where you use one, cite it with its number in square brackets, e.g. [1].\n",
+    );
+    for (source, excerpts) in sources.iter().zip(excerpts) {
+        prompt.push_str(&format!("\n[{}] {}\n---\n{}\n---\n", source.number, source.title, excerpts.join("\n\n")));
+    }
+
+    Ok(Some((sources, ChatMessage::system(prompt))))
+}
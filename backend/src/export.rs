@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+
+use async_zip::{base::write::ZipFileWriter, Compression, ZipDateTime, ZipDateTimeBuilder, ZipEntryBuilder};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use dr_markdown_render::{escape_html, find_diagrams, Diagram, Renderer, SanitizePolicy};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::io::AsyncWrite;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    database::Database,
+    diagrams,
+    handlers::AppError,
+    models::{Document, DocumentExportQuery, ExportFormat, Folder},
+    AppState, HTML_ALLOW_ATTRIBUTES, HTML_ALLOW_CLASSES, HTML_ALLOW_TAGS, HTML_ALLOW_URL_SCHEMES,
+    HTML_DENY_TAGS,
+};
+
+// Describes the archive so that other tools do not have to parse the
+// front matter of every file
+#[derive(Serialize)]
+struct Manifest {
+    version: u32,
+    exported_at: DateTime<Utc>,
+    documents: Vec<ManifestDocument>,
+}
+
+#[derive(Serialize)]
+struct ManifestDocument {
+    id: Uuid,
+    title: String,
+    path: String,
+    folder_id: Option<Uuid>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+pub async fn export_documents(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let document_ids = state.db.find_document_ids_by_user(auth_user.user_id).await?;
+    let folders = state.db.find_folders_by_user(auth_user.user_id).await?;
+
+    let mut tags: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for document_tag in state.db.find_tags_by_user(auth_user.user_id).await? {
+        tags.entry(document_tag.document_id).or_default().push(document_tag.tag);
+    }
+
+    // The archive is written into a pipe while the browser downloads it, and
+    // documents are loaded one at a time as they are compressed, so only the
+    // current one is held in memory
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let archive = tokio::spawn(write_archive(
+        writer,
+        state.db.clone(),
+        auth_user.user_id,
+        document_ids,
+        folders,
+        tags,
+    ));
+
+    // A failure ends the body with an error, which aborts the download
+    // instead of completing it with a broken archive
+    let failure = futures::stream::once(archive).filter_map(|result| async move {
+        let error = match result {
+            Ok(Ok(())) => return None,
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        println!("Failed to export documents: {}", error);
+        Some(Err(std::io::Error::other(error)))
+    });
+    let body = Body::from_stream(ReaderStream::new(reader).chain(failure));
+
+    let file_name = format!("dr-markdown-export-{}.zip", Utc::now().format("%Y-%m-%d"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition(&file_name)),
+        ],
+        body,
+    ))
+}
+
+pub async fn export_document(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Query(query): Query<DocumentExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let document = state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let file_name = safe_name(&document.title, "Untitled");
+    let (content_type, extension, body) = match query.format {
+        ExportFormat::Html => {
+            let diagrams = embed_diagrams(&state, &document.content).await;
+            let policy = sanitize_policy();
+            let body = Renderer::new()
+                .diagram_languages(&state.config.diagram_languages)
+                .diagrams(|diagram| diagrams.get(diagram).cloned().unwrap_or_else(|| diagram_fallback(diagram)))
+                .highlight_code(true)
+                .mathml(true)
+                .sanitizer(|html| policy.clean(&html))
+                .render(&document.content);
+            let html = dr_markdown_render::standalone_html(&document.title, &body);
+            ("text/html; charset=utf-8", "html", html.into_bytes())
+        }
+        ExportFormat::Pdf => {
+            let config = state.config.clone();
+            let pdf = tokio::task::spawn_blocking(move || {
+                dr_markdown_render::render_pdf(&document.title, &document.content, &config.diagram_languages)
+            })
+            .await
+            .map_err(|e| AppError::Export(e.to_string()))?
+            .map_err(|e| AppError::Export(e.to_string()))?;
+            ("application/pdf", "pdf", pdf)
+        }
+    };
+
+    let file_name = format!("{}.{}", file_name, extension);
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition(&file_name)),
+        ],
+        body,
+    ))
+}
+
+/// Renders every diagram of a document and turns it into an `<img>` with
+/// the SVG inlined as a data URL. Diagrams that cannot be rendered are left
+/// out of the map.
+async fn embed_diagrams(state: &AppState, markdown: &str) -> HashMap<Diagram, String> {
+    let mut embedded = HashMap::new();
+
+    for diagram in find_diagrams(markdown, &state.config.diagram_languages) {
+        if embedded.contains_key(&diagram) {
+            continue;
+        }
+        match diagrams::render_image(state, &diagram).await {
+            Ok(image) => {
+                let data_url = format!(
+                    "data:{};base64,{}",
+                    diagram.format.mime_type(),
+                    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &image)
+                );
+                let image = dr_markdown_render::diagram_image(&data_url, &diagram);
+                embedded.insert(diagram, image);
+            }
+            Err(e) => println!("Failed to render {} diagram for export: {}", diagram.kind, e),
+        }
+    }
+
+    embedded
+}
+
+/// The frontend's sanitizer policy, plus the data URLs of embedded diagrams.
+fn sanitize_policy() -> SanitizePolicy {
+    SanitizePolicy::new()
+        .allow_tags(HTML_ALLOW_TAGS.unwrap_or_default())
+        .deny_tags(HTML_DENY_TAGS.unwrap_or_default())
+        .allow_attributes(HTML_ALLOW_ATTRIBUTES.unwrap_or_default())
+        .allow_url_schemes(HTML_ALLOW_URL_SCHEMES.unwrap_or_default())
+        .allow_class_prefixes(HTML_ALLOW_CLASSES.unwrap_or_default())
+        .allow_data_images(true)
+}
+
+// Shown instead of a diagram that could not be rendered
+fn diagram_fallback(diagram: &Diagram) -> String {
+    format!(
+        "<pre class=\"diagram-error\"><code>{}</code></pre>",
+        escape_html(&diagram.source)
+    )
+}
+
+/// `Content-Disposition` for a download. Titles may contain any character,
+/// so the name is also given in the RFC 5987 form.
+fn content_disposition(file_name: &str) -> String {
+    let ascii_name: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii() && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii_name, encoded)
+}
+
+async fn write_archive(
+    writer: impl AsyncWrite + Unpin,
+    db: Database,
+    user_id: Uuid,
+    document_ids: Vec<Uuid>,
+    folders: Vec<Folder>,
+    mut tags: HashMap<Uuid, Vec<String>>,
+) -> anyhow::Result<()> {
+    let folder_paths = folder_paths(&folders);
+
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut used_paths = HashSet::new();
+    let mut manifest = Manifest {
+        version: 1,
+        exported_at: Utc::now(),
+        documents: Vec::with_capacity(document_ids.len()),
+    };
+
+    // Oldest first, so that the numbering of documents with the same title
+    // stays the same between exports
+    for document_id in document_ids {
+        // Documents deleted since the export started are left out
+        let Some(document) = db.find_document_by_id(document_id, user_id).await? else {
+            continue;
+        };
+        let directory = document
+            .folder_id
+            .and_then(|id| folder_paths.get(&id))
+            .map(|path| format!("{}/", path))
+            .unwrap_or_default();
+        let path = unique_path(&mut used_paths, &directory, &file_name(&document.title));
+        let tags = tags.remove(&document.id).unwrap_or_default();
+
+        let entry = ZipEntryBuilder::new(path.clone().into(), Compression::Deflate)
+            .last_modification_date(zip_time(document.updated_at));
+        let data = format!("{}{}", front_matter(&document, &tags), document.content);
+        zip.write_entry_whole(entry, data.as_bytes()).await?;
+
+        manifest.documents.push(ManifestDocument {
+            id: document.id,
+            title: document.title,
+            path,
+            folder_id: document.folder_id,
+            created_at: document.created_at,
+            updated_at: document.updated_at,
+            tags,
+        });
+    }
+
+    let entry = ZipEntryBuilder::new("manifest.json".into(), Compression::Deflate)
+        .last_modification_date(zip_time(manifest.exported_at));
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    zip.write_entry_whole(entry, &manifest).await?;
+
+    let mut writer = zip.close().await?.into_inner();
+    tokio::io::AsyncWriteExt::shutdown(&mut writer).await?;
+    Ok(())
+}
+
+/// YAML front matter with the metadata that is not part of the content.
+/// Strings are written as JSON, which YAML reads as double-quoted scalars.
+fn front_matter(document: &Document, tags: &[String]) -> String {
+    let quote = |value: &str| serde_json::to_string(value).unwrap_or_default();
+    format!(
+        "---\nid: {}\ntitle: {}\ncreated_at: {}\nupdated_at: {}\ntags: [{}]\n---\n\n",
+        document.id,
+        quote(&document.title),
+        document.created_at.to_rfc3339(),
+        document.updated_at.to_rfc3339(),
+        tags.iter().map(|tag| quote(tag)).collect::<Vec<_>>().join(", "),
+    )
+}
+
+fn zip_time(time: DateTime<Utc>) -> ZipDateTime {
+    ZipDateTimeBuilder::new()
+        .year(time.year().clamp(1980, 2107))
+        .month(time.month())
+        .day(time.day())
+        .hour(time.hour())
+        .minute(time.minute())
+        .second(time.second())
+        .build()
+}
+
+/// The directory of every folder inside the archive, e.g. `Work/Meetings`.
+fn folder_paths(folders: &[Folder]) -> HashMap<Uuid, String> {
+    let by_id: HashMap<Uuid, &Folder> = folders.iter().map(|f| (f.id, f)).collect();
+
+    folders
+        .iter()
+        .map(|folder| {
+            let mut names = vec![safe_name(&folder.name, "Folder")];
+            let mut parent = folder.parent_id;
+            // The depth limit only guards against a corrupted tree
+            while let Some(parent_folder) = parent.and_then(|id| by_id.get(&id)) {
+                if names.len() > folders.len() {
+                    break;
+                }
+                names.push(safe_name(&parent_folder.name, "Folder"));
+                parent = parent_folder.parent_id;
+            }
+            names.reverse();
+            (folder.id, names.join("/"))
+        })
+        .collect()
+}
+
+fn file_name(title: &str) -> String {
+    format!("{}.md", safe_name(title, "Untitled"))
+}
+
+/// Replaces characters that are not allowed in file names on common
+/// platforms.
+fn safe_name(name: &str, fallback: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.').trim();
+
+    if name.is_empty() {
+        fallback.to_string()
+    } else {
+        name.chars().take(200).collect()
+    }
+}
+
+/// Appends ` (2)`, ` (3)`, ... to the file name until the path is not taken
+/// yet. Paths are compared case-insensitively so that the archive can be
+/// extracted on case-insensitive file systems.
+fn unique_path(used: &mut HashSet<String>, directory: &str, file_name: &str) -> String {
+    let stem = file_name.strip_suffix(".md").unwrap_or(file_name);
+    let mut path = format!("{}{}", directory, file_name);
+    let mut counter = 2;
+    while !used.insert(path.to_lowercase()) {
+        path = format!("{}{} ({}).md", directory, stem, counter);
+        counter += 1;
+    }
+    path
+}
@@ -1,9 +1,10 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use std::collections::HashMap;
 use uuid::Uuid;
 use validator::Validate;
 
@@ -12,7 +13,10 @@ use crate::{
     models::{
         AuthResponse, CreateDocumentRequest, CreateUserRequest, DocumentResponse,
         DocumentSummary, LoginRequest, UpdateDocumentRequest, UserResponse, SettingsRequest,
-        SharedDocumentResponse,
+        SharedDocumentResponse, RevisionResponse, RevisionSummary, SearchQuery,
+        SearchResultResponse, MoveDocumentRequest, CreateFolderRequest, UpdateFolderRequest,
+        FolderResponse, DocumentListQuery, AddTagRequest, LlmQuotaResponse, ClientConfigResponse,
+        AddCollaboratorRequest, normalize_tag,
     },
     AppState,
 };
@@ -29,7 +33,7 @@ pub async fn register(
     request.validate()?;
 
     // Check if user already exists
-    if let Some(_) = state.db.find_user_by_username(&request.username).await? {
+    if state.db.find_user_by_username(&request.username).await?.is_some() {
         return Err(AppError::UserAlreadyExists);
     }
 
@@ -121,9 +125,17 @@ pub async fn create_document(
 
     let content = request.content.unwrap_or_default();
 
+    if let Some(folder_id) = request.folder_id {
+        state
+            .db
+            .find_folder_by_id(folder_id, auth_user.user_id)
+            .await?
+            .ok_or(AppError::FolderNotFound)?;
+    }
+
     let document = state
         .db
-        .create_document(auth_user.user_id, &request.title, &content)
+        .create_document(auth_user.user_id, &request.title, &content, request.folder_id)
         .await?;
 
     let response: DocumentResponse = document.into();
@@ -133,13 +145,41 @@ pub async fn create_document(
 pub async fn get_documents(
     auth_user: AuthUser,
     State(state): State<AppState>,
+    Query(query): Query<DocumentListQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let documents = state.db.find_documents_by_user(auth_user.user_id).await?;
+    let documents = match query.tag.as_deref().map(normalize_tag) {
+        Some(Some(tag)) => state.db.find_documents_by_tag(auth_user.user_id, &tag).await?,
+        Some(None) => return Err(AppError::InvalidTag),
+        None => state.db.find_documents_by_user(auth_user.user_id).await?,
+    };
 
-    let summaries: Vec<DocumentSummary> = documents.into_iter().map(Into::into).collect();
+    let mut tags: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for document_tag in state.db.find_tags_by_user(auth_user.user_id).await? {
+        tags.entry(document_tag.document_id).or_default().push(document_tag.tag);
+    }
+
+    let summaries: Vec<DocumentSummary> = documents
+        .into_iter()
+        .map(|document| {
+            let mut summary = DocumentSummary::from(document);
+            summary.tags = tags.remove(&summary.id).unwrap_or_default();
+            summary
+        })
+        .collect();
     Ok(Json(summaries))
 }
 
+pub async fn search_documents(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let hits = state.db.search_documents(auth_user.user_id, &query.q).await?;
+
+    let results: Vec<SearchResultResponse> = hits.into_iter().map(Into::into).collect();
+    Ok(Json(results))
+}
+
 pub async fn get_document(
     auth_user: AuthUser,
     State(state): State<AppState>,
@@ -147,7 +187,7 @@ pub async fn get_document(
 ) -> Result<impl IntoResponse, AppError> {
     let document = state
         .db
-        .find_document_by_id(document_id, auth_user.user_id)
+        .find_editable_document(document_id, auth_user.user_id)
         .await?
         .ok_or(AppError::DocumentNotFound)?;
 
@@ -163,16 +203,33 @@ pub async fn update_document(
 ) -> Result<impl IntoResponse, AppError> {
     request.validate()?;
 
+    // Collaborators save into the owner's document
+    let owner_id = state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?
+        .user_id;
+
     let document = state
         .db
         .update_document(
             document_id,
-            auth_user.user_id,
+            owner_id,
             request.title.as_deref(),
             request.content.as_deref(),
+            request.last_updated_at,
         )
-        .await?
-        .ok_or(AppError::DocumentNotFound)?;
+        .await?;
+    let Some(document) = document else {
+        let current = state
+            .db
+            .find_document_by_id(document_id, owner_id)
+            .await?
+            .ok_or(AppError::DocumentNotFound)?;
+        // Someone else saved in the meantime; hand back the server copy
+        return Err(AppError::Conflict(Box::new(current.into())));
+    };
 
     let response: DocumentResponse = document.into();
     Ok(Json(response))
@@ -195,6 +252,234 @@ pub async fn delete_document(
     Ok(StatusCode::NO_CONTENT)
 }
 
+pub async fn move_document(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Json(request): Json<MoveDocumentRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(folder_id) = request.folder_id {
+        state
+            .db
+            .find_folder_by_id(folder_id, auth_user.user_id)
+            .await?
+            .ok_or(AppError::FolderNotFound)?;
+    }
+
+    let document = state
+        .db
+        .move_document(document_id, auth_user.user_id, request.folder_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let response: DocumentResponse = document.into();
+    Ok(Json(response))
+}
+
+pub async fn get_folders(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let folders = state.db.find_folders_by_user(auth_user.user_id).await?;
+
+    let response: Vec<FolderResponse> = folders.into_iter().map(Into::into).collect();
+    Ok(Json(response))
+}
+
+pub async fn create_folder(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(request): Json<CreateFolderRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    if let Some(parent_id) = request.parent_id {
+        state
+            .db
+            .find_folder_by_id(parent_id, auth_user.user_id)
+            .await?
+            .ok_or(AppError::FolderNotFound)?;
+    }
+
+    let folder = state
+        .db
+        .create_folder(auth_user.user_id, &request.name, request.parent_id)
+        .await?;
+
+    let response: FolderResponse = folder.into();
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn update_folder(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(folder_id): Path<Uuid>,
+    Json(request): Json<UpdateFolderRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    // Walk up from the new parent to make sure the folder does not end up
+    // inside its own subtree
+    if let Some(Some(parent_id)) = request.parent_id {
+        let mut ancestor = Some(parent_id);
+        while let Some(id) = ancestor {
+            if id == folder_id {
+                return Err(AppError::FolderCycle);
+            }
+            ancestor = state
+                .db
+                .find_folder_by_id(id, auth_user.user_id)
+                .await?
+                .ok_or(AppError::FolderNotFound)?
+                .parent_id;
+        }
+    }
+
+    let folder = state
+        .db
+        .update_folder(folder_id, auth_user.user_id, request.name.as_deref(), request.parent_id)
+        .await?
+        .ok_or(AppError::FolderNotFound)?;
+
+    let response: FolderResponse = folder.into();
+    Ok(Json(response))
+}
+
+pub async fn delete_folder(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(folder_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let deleted = state
+        .db
+        .delete_folder(folder_id, auth_user.user_id)
+        .await?;
+
+    if !deleted {
+        return Err(AppError::FolderNotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_tags(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    // Collaborators see the tags, only the owner changes them
+    state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let tags = state.db.find_tags_by_document(document_id).await?;
+    Ok(Json(tags))
+}
+
+pub async fn add_tag(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Json(request): Json<AddTagRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let tag = normalize_tag(&request.tag).ok_or(AppError::InvalidTag)?;
+
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    state.db.add_tag(document_id, &tag).await?;
+
+    let tags = state.db.find_tags_by_document(document_id).await?;
+    Ok(Json(tags))
+}
+
+pub async fn remove_tag(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, tag)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let tag = normalize_tag(&tag).ok_or(AppError::InvalidTag)?;
+
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    state.db.remove_tag(document_id, &tag).await?;
+
+    let tags = state.db.find_tags_by_document(document_id).await?;
+    Ok(Json(tags))
+}
+
+pub async fn get_collaborators(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    // Collaborators see who else edits, only the owner changes that
+    state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let collaborators = state.db.find_collaborators(document_id).await?;
+    Ok(Json(collaborators))
+}
+
+/// Lets another user edit the document, live together with the owner.
+pub async fn add_collaborator(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Json(request): Json<AddCollaboratorRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let user = state
+        .db
+        .find_user_by_username(request.username.trim())
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    // The owner can edit anyway
+    if user.id != auth_user.user_id {
+        state.db.add_collaborator(document_id, user.id).await?;
+    }
+
+    let collaborators = state.db.find_collaborators(document_id).await?;
+    Ok(Json(collaborators))
+}
+
+pub async fn remove_collaborator(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, username)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .db
+        .find_document_by_id(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    if let Some(user) = state.db.find_user_by_username(&username).await? {
+        state.db.remove_collaborator(document_id, user.id).await?;
+    }
+
+    let collaborators = state.db.find_collaborators(document_id).await?;
+    Ok(Json(collaborators))
+}
+
 pub async fn create_share_link(
     auth_user: AuthUser,
     State(state): State<AppState>,
@@ -244,6 +529,90 @@ pub async fn get_shared_document(
     Ok(Json(response))
 }
 
+pub async fn get_revisions(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    // The owner and the collaborators share the history
+    let document = state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let revisions = state
+        .db
+        .find_revisions_by_document(document_id, document.user_id)
+        .await?;
+
+    let summaries: Vec<RevisionSummary> = revisions.into_iter().map(Into::into).collect();
+    Ok(Json(summaries))
+}
+
+pub async fn get_revision(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, revision)): Path<(Uuid, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let document = state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::RevisionNotFound)?;
+
+    let revision = state
+        .db
+        .find_revision(document_id, document.user_id, revision)
+        .await?
+        .ok_or(AppError::RevisionNotFound)?;
+
+    let response: RevisionResponse = revision.into();
+    Ok(Json(response))
+}
+
+pub async fn restore_revision(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path((document_id, revision)): Path<(Uuid, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    // Collaborators may restore, like any other edit
+    let document = state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await?
+        .ok_or(AppError::RevisionNotFound)?;
+
+    let revision = state
+        .db
+        .find_revision(document_id, document.user_id, revision)
+        .await?
+        .ok_or(AppError::RevisionNotFound)?;
+
+    // Restoring writes the old state as a new revision, so history is never lost
+    let document = state
+        .db
+        .update_document(
+            document_id,
+            document.user_id,
+            Some(&revision.title),
+            Some(&revision.content),
+            None,
+        )
+        .await?
+        .ok_or(AppError::DocumentNotFound)?;
+
+    let response: DocumentResponse = document.into();
+    Ok(Json(response))
+}
+
+/// Server settings the frontend reads when it starts, before anyone logs in.
+pub async fn get_client_config(State(state): State<AppState>) -> impl IntoResponse {
+    Json(ClientConfigResponse {
+        diagrams: state.config.diagram_languages.iter().map(Into::into).collect(),
+    })
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
     #[error("Database error: {0}")]
@@ -260,15 +629,62 @@ pub enum AppError {
     UserNotFound,
     #[error("{0}")]
     Llm(String) ,
+    #[error("The selection is empty or outside of the document")]
+    InvalidSelection,
     #[error("Document not found")]
     DocumentNotFound,
+    #[error("Revision not found")]
+    RevisionNotFound,
+    #[error("Folder not found")]
+    FolderNotFound,
+    #[error("Chat not found")]
+    ChatThreadNotFound,
+    #[error("Searching documents is not enabled")]
+    DocumentSearchDisabled,
+    #[error("AI action not found")]
+    AiActionNotFound,
+    #[error("Prompt not found")]
+    PromptNotFound,
+    #[error("Only admins can change shared prompts")]
+    AdminOnly,
+    #[error("{0} is missing")]
+    MissingActionArgument(String),
+    #[error("Tags must be between 1 and 50 characters long")]
+    InvalidTag,
+    #[error("A folder cannot be moved into itself or one of its subfolders")]
+    FolderCycle,
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+    #[error("The import has {0}")]
+    ImportTooLarge(String),
+    #[error("Export failed: {0}")]
+    Export(String),
+    #[error("Diagram not found")]
+    DiagramNotFound,
+    #[error("{0} diagrams cannot be rendered")]
+    UnsupportedDiagram(String),
+    #[error("{0}")]
+    InvalidDiagram(String),
+    #[error("Diagram server error: {0}")]
+    DiagramServer(String),
+    #[error("Log in to render new diagrams")]
+    DiagramNotAllowed,
+    #[error("Too many diagrams are being drawn, try again shortly")]
+    DiagramBusy,
     #[error("Registration is disabled")]
     RegistrationDisabled,
+    #[error("Document was modified by someone else")]
+    Conflict(Box<DocumentResponse>),
+    #[error("AI quota exceeded")]
+    QuotaExceeded {
+        retry_after: u64,
+        quota: Box<LlmQuotaResponse>,
+    },
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let status = match self {
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
             AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Validation(_) => StatusCode::BAD_REQUEST,
             AppError::Auth(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -276,14 +692,54 @@ impl IntoResponse for AppError {
             AppError::InvalidCredentials => StatusCode::UNAUTHORIZED,
             AppError::UserNotFound => StatusCode::NOT_FOUND,
             AppError::DocumentNotFound => StatusCode::NOT_FOUND,
+            AppError::RevisionNotFound => StatusCode::NOT_FOUND,
+            AppError::FolderNotFound => StatusCode::NOT_FOUND,
+            AppError::ChatThreadNotFound => StatusCode::NOT_FOUND,
+            AppError::DocumentSearchDisabled => StatusCode::BAD_REQUEST,
+            AppError::AiActionNotFound => StatusCode::NOT_FOUND,
+            AppError::MissingActionArgument(_) => StatusCode::BAD_REQUEST,
+            AppError::PromptNotFound => StatusCode::NOT_FOUND,
+            AppError::AdminOnly => StatusCode::FORBIDDEN,
+            AppError::FolderCycle => StatusCode::BAD_REQUEST,
+            AppError::InvalidTag => StatusCode::BAD_REQUEST,
+            AppError::InvalidUpload(_) => StatusCode::BAD_REQUEST,
+            AppError::ImportTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Export(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::DiagramNotFound => StatusCode::NOT_FOUND,
+            AppError::UnsupportedDiagram(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidDiagram(_) => StatusCode::BAD_REQUEST,
+            AppError::DiagramServer(_) => StatusCode::BAD_GATEWAY,
+            AppError::DiagramNotAllowed => StatusCode::UNAUTHORIZED,
+            AppError::DiagramBusy => StatusCode::SERVICE_UNAVAILABLE,
             AppError::Llm(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::InvalidSelection => StatusCode::BAD_REQUEST,
             AppError::RegistrationDisabled => StatusCode::FORBIDDEN,
-        };
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
 
-        let body = Json(serde_json::json!({
+        let mut body = serde_json::json!({
             "error": self.to_string()
-        }));
+        });
+
+        // Include the current server copy so the client can resolve the conflict
+        if let AppError::Conflict(document) = &self {
+            body["document"] = serde_json::json!(document);
+        }
+
+        // Tell the client when it may try again, in seconds
+        if let AppError::QuotaExceeded { retry_after, quota } = &self {
+            body["retry_after"] = serde_json::json!(retry_after);
+            body["quota"] = serde_json::json!(quota);
+            return (status, [(header::RETRY_AFTER, retry_after.to_string())], Json(body)).into_response();
+        }
 
-        (status, body).into_response()
+        (status, Json(body)).into_response()
     }
 }
\ No newline at end of file
@@ -0,0 +1,592 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use axum::{
+    body::Bytes,
+    extract::{Multipart, State},
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use crate::{
+    auth::AuthUser,
+    handlers::AppError,
+    models::{FolderResponse, ImportFileResult, ImportResponse, ImportStatus},
+    AppState,
+};
+
+/// Largest request body accepted by the import endpoint.
+pub const MAX_UPLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+/// Largest single document that is imported (after decompression).
+const MAX_DOCUMENT_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Most data a single import may decompress, across all uploaded files.
+const MAX_IMPORT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Most files a single import may contain, counting every entry of an
+/// archive and every uploaded file.
+const MAX_IMPORT_ENTRIES: usize = 1000;
+
+/// Most folders a single import may create.
+const MAX_IMPORT_FOLDERS: usize = 200;
+
+/// Longest folder name, matching the limit on folders created directly.
+const MAX_FOLDER_NAME_LENGTH: usize = 255;
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd", "txt"];
+
+/// A Markdown file taken from an upload, with the directories it was in.
+struct MarkdownFile {
+    path: String,
+    directories: Vec<String>,
+    file_stem: String,
+    content: String,
+}
+
+/// What is left of the limits of one import.
+struct Budget {
+    bytes: u64,
+    entries: usize,
+}
+
+impl Budget {
+    fn new() -> Self {
+        Self {
+            bytes: MAX_IMPORT_SIZE,
+            entries: MAX_IMPORT_ENTRIES,
+        }
+    }
+}
+
+/// What a single uploaded file turned into.
+enum Extracted {
+    File(MarkdownFile),
+    Skipped { path: String, reason: String },
+    Failed { path: String, reason: String },
+}
+
+pub async fn import_documents(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let mut uploads = Vec::new();
+    let mut target_folder = None;
+    let mut create_folders = false;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidUpload(e.body_text()))?
+    {
+        match field.name().unwrap_or_default() {
+            "folder_id" => {
+                let text = field.text().await.map_err(|e| AppError::InvalidUpload(e.body_text()))?;
+                if !text.is_empty() {
+                    let folder_id = Uuid::parse_str(&text)
+                        .map_err(|_| AppError::InvalidUpload("Invalid folder id".to_string()))?;
+                    target_folder = Some(folder_id);
+                }
+            }
+            "create_folders" => {
+                let text = field.text().await.map_err(|e| AppError::InvalidUpload(e.body_text()))?;
+                create_folders = text == "true";
+            }
+            "files" => {
+                let file_name = field.file_name().unwrap_or("untitled.md").to_string();
+                let data = field.bytes().await.map_err(|e| AppError::InvalidUpload(e.body_text()))?;
+                uploads.push((file_name, data));
+            }
+            _ => {}
+        }
+    }
+
+    if uploads.is_empty() {
+        return Err(AppError::InvalidUpload("No files were uploaded".to_string()));
+    }
+
+    if let Some(folder_id) = target_folder {
+        state
+            .db
+            .find_folder_by_id(folder_id, auth_user.user_id)
+            .await?
+            .ok_or(AppError::FolderNotFound)?;
+    }
+
+    // Directories map onto existing folders of the same name where possible,
+    // so importing the same archive twice does not duplicate the tree
+    let mut known_folders: HashMap<(Option<Uuid>, String), Uuid> = state
+        .db
+        .find_folders_by_user(auth_user.user_id)
+        .await?
+        .into_iter()
+        .map(|folder| ((folder.parent_id, folder.name), folder.id))
+        .collect();
+
+    // Decompressing is CPU-bound, so it is kept off the async workers
+    let extracted = tokio::task::spawn_blocking(move || extract_uploads(&uploads, create_folders))
+        .await
+        .map_err(|e| AppError::InvalidUpload(e.to_string()))??;
+
+    let mut results = Vec::new();
+    let mut created_folders = Vec::new();
+
+    for extracted in extracted {
+        let file = match extracted {
+            Extracted::File(file) => file,
+            Extracted::Skipped { path, reason } => {
+                results.push(ImportFileResult {
+                    path,
+                    status: ImportStatus::Skipped,
+                    document: None,
+                    message: Some(reason),
+                });
+                continue;
+            }
+            Extracted::Failed { path, reason } => {
+                results.push(ImportFileResult {
+                    path,
+                    status: ImportStatus::Failed,
+                    document: None,
+                    message: Some(reason),
+                });
+                continue;
+            }
+        };
+
+        let mut folder_id = target_folder;
+        if create_folders {
+            for name in &file.directories {
+                let key = (folder_id, name.clone());
+                folder_id = Some(match known_folders.get(&key) {
+                    Some(id) => *id,
+                    None => {
+                        let folder = state
+                            .db
+                            .create_folder(auth_user.user_id, name, folder_id)
+                            .await?;
+                        known_folders.insert(key, folder.id);
+                        let id = folder.id;
+                        created_folders.push(FolderResponse::from(folder));
+                        id
+                    }
+                });
+            }
+        }
+
+        let title = document_title(&file.content, &file.file_stem);
+        let result = match state
+            .db
+            .create_document(auth_user.user_id, &title, &file.content, folder_id)
+            .await
+        {
+            Ok(document) => ImportFileResult {
+                path: file.path,
+                status: ImportStatus::Imported,
+                document: Some(document.into()),
+                message: None,
+            },
+            Err(e) => {
+                println!("Failed to import {}: {}", file.path, e);
+                ImportFileResult {
+                    path: file.path,
+                    status: ImportStatus::Failed,
+                    document: None,
+                    message: Some("Could not save the document".to_string()),
+                }
+            }
+        };
+        results.push(result);
+    }
+
+    Ok(Json(ImportResponse {
+        results,
+        folders: created_folders,
+    }))
+}
+
+/// Extracts all uploads. Imports with more files or folders than allowed
+/// are rejected as a whole, before anything is saved.
+fn extract_uploads(
+    uploads: &[(String, Bytes)],
+    create_folders: bool,
+) -> Result<Vec<Extracted>, AppError> {
+    let mut budget = Budget::new();
+    let mut extracted = Vec::new();
+    for (file_name, data) in uploads {
+        extracted.extend(extract_markdown_files(file_name, data, &mut budget)?);
+    }
+
+    if create_folders && folder_count(&extracted) > MAX_IMPORT_FOLDERS {
+        return Err(AppError::ImportTooLarge(format!(
+            "more than {} folders",
+            MAX_IMPORT_FOLDERS
+        )));
+    }
+
+    Ok(extracted)
+}
+
+/// Expands one uploaded file into the Markdown files it contains: the file
+/// itself, or every Markdown file inside a ZIP archive. Every byte read is
+/// taken from `budget`, and files stop being read once it runs out.
+fn extract_markdown_files(
+    file_name: &str,
+    data: &[u8],
+    budget: &mut Budget,
+) -> Result<Vec<Extracted>, AppError> {
+    let file_name = base_name(file_name);
+
+    if has_extension(file_name, &["zip"]) {
+        return extract_zip(file_name, data, budget);
+    }
+
+    take_entries(budget, 1)?;
+
+    if !has_extension(file_name, MARKDOWN_EXTENSIONS) {
+        return Ok(vec![Extracted::Skipped {
+            path: file_name.to_string(),
+            reason: "Not a Markdown file".to_string(),
+        }]);
+    }
+
+    let size = data.len() as u64;
+    if size > MAX_DOCUMENT_SIZE {
+        return Ok(vec![too_large(file_name)]);
+    }
+    if size > budget.bytes {
+        return Ok(vec![over_budget(file_name)]);
+    }
+    budget.bytes -= size;
+
+    Ok(vec![markdown_file(file_name, Vec::new(), data.to_vec())])
+}
+
+fn extract_zip(
+    archive_name: &str,
+    data: &[u8],
+    budget: &mut Budget,
+) -> Result<Vec<Extracted>, AppError> {
+    let mut archive = match ZipArchive::new(Cursor::new(data)) {
+        Ok(archive) => archive,
+        Err(e) => {
+            return Ok(vec![Extracted::Failed {
+                path: archive_name.to_string(),
+                reason: format!("Not a valid ZIP archive: {}", e),
+            }])
+        }
+    };
+
+    take_entries(budget, archive.len())?;
+
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                files.push(Extracted::Failed {
+                    path: format!("{} (entry {})", archive_name, index + 1),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        // Entries that would escape the archive root are ignored entirely
+        let Some(entry_path) = entry.enclosed_name() else { continue };
+        let components: Vec<String> = entry_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let path = components.join("/");
+
+        // Metadata that archivers add next to the actual files
+        if components.iter().any(|c| c.starts_with('.') || c == "__MACOSX") {
+            continue;
+        }
+
+        let Some((file_name, directories)) = components.split_last() else { continue };
+
+        if !has_extension(file_name, MARKDOWN_EXTENSIONS) {
+            files.push(Extracted::Skipped {
+                path,
+                reason: "Not a Markdown file".to_string(),
+            });
+            continue;
+        }
+
+        if let Some(name) = directories.iter().find(|name| name.chars().count() > MAX_FOLDER_NAME_LENGTH) {
+            files.push(Extracted::Failed {
+                path,
+                reason: format!(
+                    "Folder name is longer than {} characters: {}…",
+                    MAX_FOLDER_NAME_LENGTH,
+                    name.chars().take(40).collect::<String>()
+                ),
+            });
+            continue;
+        }
+
+        if entry.size() > MAX_DOCUMENT_SIZE {
+            files.push(too_large(&path));
+            continue;
+        }
+        if entry.size() > budget.bytes {
+            files.push(over_budget(&path));
+            continue;
+        }
+
+        // The size in the header cannot be trusted, so the read is capped too.
+        // One byte past the limit is read to tell a file that is exactly at the
+        // limit from one that goes over it.
+        let limit = MAX_DOCUMENT_SIZE.min(budget.bytes);
+        let mut content = Vec::new();
+        let read = (&mut entry).take(limit + 1).read_to_end(&mut content);
+        budget.bytes = budget.bytes.saturating_sub(content.len() as u64);
+        if let Err(e) = read {
+            files.push(Extracted::Failed {
+                path,
+                reason: format!("Could not extract file: {}", e),
+            });
+            continue;
+        }
+        if content.len() as u64 > limit {
+            files.push(if limit < MAX_DOCUMENT_SIZE {
+                over_budget(&path)
+            } else {
+                too_large(&path)
+            });
+            continue;
+        }
+
+        files.push(markdown_file(&path, directories.to_vec(), content));
+    }
+
+    Ok(files)
+}
+
+fn take_entries(budget: &mut Budget, count: usize) -> Result<(), AppError> {
+    budget.entries = budget.entries.checked_sub(count).ok_or_else(|| {
+        AppError::ImportTooLarge(format!("more than {} files", MAX_IMPORT_ENTRIES))
+    })?;
+    Ok(())
+}
+
+/// Number of distinct folders the extracted files would be put into.
+fn folder_count(extracted: &[Extracted]) -> usize {
+    let mut folders = HashSet::new();
+    for extracted in extracted {
+        if let Extracted::File(file) = extracted {
+            for depth in 1..=file.directories.len() {
+                folders.insert(&file.directories[..depth]);
+            }
+        }
+    }
+    folders.len()
+}
+
+fn too_large(path: &str) -> Extracted {
+    Extracted::Failed {
+        path: path.to_string(),
+        reason: format!("File is larger than {} MB", MAX_DOCUMENT_SIZE / 1024 / 1024),
+    }
+}
+
+fn over_budget(path: &str) -> Extracted {
+    Extracted::Failed {
+        path: path.to_string(),
+        reason: format!(
+            "The import is larger than {} MB in total",
+            MAX_IMPORT_SIZE / 1024 / 1024
+        ),
+    }
+}
+
+fn markdown_file(path: &str, directories: Vec<String>, data: Vec<u8>) -> Extracted {
+    let content = match String::from_utf8(data) {
+        Ok(content) => content,
+        Err(_) => {
+            return Extracted::Failed {
+                path: path.to_string(),
+                reason: "File is not valid UTF-8 text".to_string(),
+            }
+        }
+    };
+
+    let file_stem = Path::new(base_name(path))
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Extracted::File(MarkdownFile {
+        path: path.to_string(),
+        directories,
+        file_stem,
+        // Editors on Windows like to start files with a byte order mark
+        content: content.trim_start_matches('\u{feff}').to_string(),
+    })
+}
+
+/// The text of the first level-one heading, or the file name without its
+/// extension when the document has none.
+fn document_title(content: &str, file_stem: &str) -> String {
+    let mut in_code_block = false;
+    let mut heading = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        if let Some(text) = trimmed.strip_prefix("# ") {
+            heading = Some(text.trim().trim_end_matches('#').trim());
+            break;
+        }
+    }
+
+    let title = match heading {
+        Some(text) if !text.is_empty() => text,
+        _ if !file_stem.trim().is_empty() => file_stem.trim(),
+        _ => "Untitled",
+    };
+
+    title.chars().take(255).collect()
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn has_extension(file_name: &str, extensions: &[&str]) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn outcome(extracted: &Extracted) -> (&str, Option<&str>) {
+        match extracted {
+            Extracted::File(file) => (file.path.as_str(), None),
+            Extracted::Skipped { path, reason } | Extracted::Failed { path, reason } => {
+                (path.as_str(), Some(reason.as_str()))
+            }
+        }
+    }
+
+    #[test]
+    fn entries_up_to_the_limit_are_imported() {
+        let content = vec![b'a'; MAX_DOCUMENT_SIZE as usize];
+        let data = archive(&[("notes/big.md", &content)]);
+
+        let files = extract_markdown_files("notes.zip", &data, &mut Budget::new()).unwrap();
+        assert_eq!(files.len(), 1);
+        match &files[0] {
+            Extracted::File(file) => {
+                assert_eq!(file.content.len(), MAX_DOCUMENT_SIZE as usize);
+                assert_eq!(file.directories, ["notes"]);
+            }
+            other => panic!("unexpected result: {:?}", outcome(other)),
+        }
+    }
+
+    #[test]
+    fn oversized_entries_are_reported() {
+        let content = vec![b'a'; MAX_DOCUMENT_SIZE as usize + 1];
+        let data = archive(&[("big.md", &content), ("small.md", b"# Small")]);
+
+        let files = extract_markdown_files("notes.zip", &data, &mut Budget::new()).unwrap();
+        assert!(matches!(&files[0], Extracted::Failed { reason, .. } if reason.contains("larger than")));
+        assert!(matches!(&files[1], Extracted::File(file) if file.content == "# Small"));
+    }
+
+    #[test]
+    fn oversized_uploads_are_reported() {
+        let content = vec![b'a'; MAX_DOCUMENT_SIZE as usize + 1];
+
+        let files = extract_markdown_files("big.md", &content, &mut Budget::new()).unwrap();
+        assert!(matches!(&files[0], Extracted::Failed { reason, .. } if reason.contains("larger than")));
+    }
+
+    #[test]
+    fn total_size_is_limited() {
+        let data = archive(&[("one.md", &[b'a'; 600]), ("two.md", &[b'b'; 600])]);
+        let mut budget = Budget {
+            bytes: 1000,
+            ..Budget::new()
+        };
+
+        let files = extract_markdown_files("notes.zip", &data, &mut budget).unwrap();
+        assert!(matches!(&files[0], Extracted::File(_)));
+        assert!(matches!(&files[1], Extracted::Failed { reason, .. } if reason.contains("in total")));
+
+        let files = extract_markdown_files("three.md", &[b'c'; 600], &mut budget).unwrap();
+        assert!(matches!(&files[0], Extracted::Failed { reason, .. } if reason.contains("in total")));
+    }
+
+    #[test]
+    fn long_folder_names_are_rejected() {
+        let path = format!("{}/notes.md", "f".repeat(MAX_FOLDER_NAME_LENGTH + 1));
+        let fine = format!("{}/notes.md", "f".repeat(MAX_FOLDER_NAME_LENGTH));
+        let data = archive(&[(&path, b"# Notes"), (&fine, b"# Notes")]);
+
+        let files = extract_markdown_files("notes.zip", &data, &mut Budget::new()).unwrap();
+        assert!(matches!(&files[0], Extracted::Failed { reason, .. } if reason.contains("Folder name")));
+        assert!(matches!(&files[1], Extracted::File(_)));
+    }
+
+    #[test]
+    fn entry_count_is_limited() {
+        let names: Vec<String> = (0..=MAX_IMPORT_ENTRIES).map(|i| format!("{}.md", i)).collect();
+        let entries: Vec<(&str, &[u8])> =
+            names.iter().map(|name| (name.as_str(), &b"# Note"[..])).collect();
+        let data = archive(&entries);
+
+        let result = extract_markdown_files("notes.zip", &data, &mut Budget::new());
+        assert!(matches!(result, Err(AppError::ImportTooLarge(_))));
+
+        let data = archive(&entries[1..]);
+        let mut budget = Budget::new();
+        assert!(extract_markdown_files("notes.zip", &data, &mut budget).is_ok());
+        let result = extract_markdown_files("more.md", b"# More", &mut budget);
+        assert!(matches!(result, Err(AppError::ImportTooLarge(_))));
+    }
+
+    #[test]
+    fn folder_count_is_limited() {
+        let path = format!("{}note.md", "f/".repeat(MAX_IMPORT_FOLDERS + 1));
+        let uploads = [("notes.zip".to_string(), Bytes::from(archive(&[(&path, b"# Note")])))];
+
+        assert!(matches!(extract_uploads(&uploads, true), Err(AppError::ImportTooLarge(_))));
+        assert!(extract_uploads(&uploads, false).is_ok());
+    }
+}
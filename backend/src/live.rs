@@ -0,0 +1,329 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    response::{IntoResponse, Response},
+};
+use dr_markdown_collab::{ClientMessage, Participant, ServerMessage, TextOperation};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, database::Database, handlers::AppError, models::Document, AppState};
+
+/// How often pending live edits are written back to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How often a live session records a revision of the document, besides
+/// the one when the session ends.
+const REVISION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How many past operations a session keeps for transforming late edits.
+/// Clients that are further behind start over from the current content.
+const MAX_HISTORY: usize = 1000;
+
+// What the frontend passes when opening the socket (browsers cannot set headers)
+#[derive(Deserialize)]
+pub struct LiveQuery {
+    pub token: String,
+}
+
+/// Keeps track of the documents currently being edited live.
+#[derive(Clone, Default)]
+pub struct LiveHub {
+    sessions: Arc<Mutex<HashMap<Uuid, Arc<LiveSession>>>>,
+}
+
+struct LiveSession {
+    document_id: Uuid,
+    state: Mutex<SessionState>,
+    events: broadcast::Sender<ServerMessage>,
+}
+
+struct SessionState {
+    content: String,
+    // The latest operations, the last of which made the current revision
+    history: VecDeque<TextOperation>,
+    revision: usize,
+    participants: Vec<Participant>,
+    /// Edits that are not saved yet.
+    dirty: bool,
+    /// Saved edits that are not in a revision yet.
+    unrecorded: bool,
+    last_revision_at: Instant,
+}
+
+impl LiveHub {
+    /// Joins (or starts) the live session of a document. Returns the session,
+    /// the initial state for the new participant and a receiver for all
+    /// events that happen after it.
+    async fn join(
+        &self,
+        db: &Database,
+        document: &Document,
+        participant: Participant,
+    ) -> (Arc<LiveSession>, ServerMessage, broadcast::Receiver<ServerMessage>) {
+        let session = self
+            .sessions
+            .lock()
+            .await
+            .entry(document.id)
+            .or_insert_with(|| {
+                let (events, _) = broadcast::channel(256);
+                let session = Arc::new(LiveSession {
+                    document_id: document.id,
+                    state: Mutex::new(SessionState {
+                        content: document.content.clone(),
+                        history: VecDeque::new(),
+                        revision: 0,
+                        participants: Vec::new(),
+                        dirty: false,
+                        unrecorded: false,
+                        last_revision_at: Instant::now(),
+                    }),
+                    events,
+                });
+                tokio::spawn(flush_periodically(Arc::downgrade(&session), db.clone()));
+                session
+            })
+            .clone();
+
+        // Subscribe while holding the state lock so no edit falls between the
+        // snapshot and the event stream. A session whose last participant is
+        // still saving it is simply joined again.
+        let mut state = session.state.lock().await;
+        state.participants.push(participant.clone());
+        let receiver = session.events.subscribe();
+        let init = ServerMessage::Init {
+            client_id: participant.client_id,
+            revision: state.revision as u64,
+            content: state.content.clone(),
+            participants: state.participants.clone(),
+        };
+        let _ = session.events.send(ServerMessage::Presence {
+            participants: state.participants.clone(),
+        });
+        drop(state);
+
+        (session, init, receiver)
+    }
+
+    /// Removes a participant. The last one to leave writes any outstanding
+    /// edits and closes the session.
+    async fn leave(&self, db: &Database, session: &Arc<LiveSession>, client_id: Uuid) {
+        {
+            let mut state = session.state.lock().await;
+            state.participants.retain(|p| p.client_id != client_id);
+            if !state.participants.is_empty() {
+                let _ = session.events.send(ServerMessage::Presence {
+                    participants: state.participants.clone(),
+                });
+                return;
+            }
+        }
+
+        // Flush before removing the session, otherwise a new one could load
+        // stale content from the database. Only this session waits for the
+        // database, others can still be joined and left meanwhile.
+        session.flush(db, true).await;
+
+        let mut sessions = self.sessions.lock().await;
+        let state = session.state.lock().await;
+        // Someone may have joined while the edits were written
+        let current = sessions.get(&session.document_id).is_some_and(|other| Arc::ptr_eq(other, session));
+        if current && state.participants.is_empty() {
+            sessions.remove(&session.document_id);
+        }
+    }
+}
+
+impl LiveSession {
+    /// Transforms a client edit against everything it has not seen yet,
+    /// applies it and broadcasts the result.
+    async fn apply_edit(
+        &self,
+        client_id: Uuid,
+        revision: u64,
+        operation: TextOperation,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().await;
+
+        let revision = revision as usize;
+        if revision > state.revision {
+            return Err("Edit is based on an unknown revision".to_string());
+        }
+        let oldest = state.revision - state.history.len();
+        if revision < oldest {
+            return Err("Edit is based on a revision that is too old".to_string());
+        }
+
+        let mut operation = operation;
+        for concurrent in state.history.range(revision - oldest..) {
+            operation = TextOperation::transform(&operation, concurrent)
+                .map_err(|e| e.to_string())?
+                .0;
+        }
+
+        state.content = operation.apply(&state.content).map_err(|e| e.to_string())?;
+        state.history.push_back(operation.clone());
+        if state.history.len() > MAX_HISTORY {
+            state.history.pop_front();
+        }
+        state.revision += 1;
+        state.dirty = true;
+
+        let _ = self.events.send(ServerMessage::Edit {
+            revision: state.revision as u64,
+            client_id,
+            operation,
+        });
+
+        Ok(())
+    }
+
+    /// Saves outstanding edits. A revision is recorded when the session
+    /// `closing`, or at most every `REVISION_INTERVAL`.
+    async fn flush(&self, db: &Database, closing: bool) {
+        let mut state = self.state.lock().await;
+        let record_revision = (state.dirty || state.unrecorded)
+            && (closing || state.last_revision_at.elapsed() >= REVISION_INTERVAL);
+        if !state.dirty && !record_revision {
+            return;
+        }
+
+        match db
+            .save_live_document(self.document_id, &state.content, record_revision)
+            .await
+        {
+            Ok(Some(document)) => {
+                state.dirty = false;
+                if record_revision {
+                    state.unrecorded = false;
+                    state.last_revision_at = Instant::now();
+                } else {
+                    state.unrecorded = true;
+                }
+                let _ = self.events.send(ServerMessage::Saved {
+                    updated_at: document.updated_at,
+                });
+            }
+            Ok(None) => println!("Live document {} no longer exists", self.document_id),
+            Err(e) => println!("Failed to save live document {}: {}", self.document_id, e),
+        }
+    }
+}
+
+/// Saves the session every `FLUSH_INTERVAL` until it is closed and dropped.
+async fn flush_periodically(session: Weak<LiveSession>, db: Database) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(session) = session.upgrade() else { break };
+        session.flush(&db, false).await;
+    }
+}
+
+pub async fn live_document_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Path(document_id): Path<Uuid>,
+    Query(query): Query<LiveQuery>,
+) -> Result<Response, Response> {
+    let auth_user = AuthUser::from_token(&query.token, &state)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // The owner and the collaborators edit together
+    let document = state
+        .db
+        .find_editable_document(document_id, auth_user.user_id)
+        .await
+        .map_err(|e| AppError::from(e).into_response())?
+        .ok_or_else(|| AppError::DocumentNotFound.into_response())?;
+
+    let user = state
+        .db
+        .find_user_by_id(auth_user.user_id)
+        .await
+        .map_err(|e| AppError::from(e).into_response())?
+        .ok_or_else(|| AppError::UserNotFound.into_response())?;
+
+    Ok(ws.on_upgrade(move |socket| run_connection(socket, state, document, user.username)))
+}
+
+async fn run_connection(socket: WebSocket, state: AppState, document: Document, username: String) {
+    let client_id = Uuid::new_v4();
+    let participant = Participant { client_id, username };
+    let (session, init, mut events) = state.live.join(&state.db, &document, participant).await;
+
+    let (mut sender, mut receiver) = socket.split();
+
+    // Messages meant for this client only
+    let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<ServerMessage>();
+
+    let mut forward = tokio::spawn(async move {
+        // The snapshot has to arrive before any event that builds on it
+        if let Ok(text) = serde_json::to_string(&init) {
+            if sender.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let message = tokio::select! {
+                Some(message) = direct_rx.recv() => message,
+                event = events.recv() => match event {
+                    // The client's own edit comes back as an acknowledgement
+                    Ok(ServerMessage::Edit { revision, client_id: author, .. }) if author == client_id => {
+                        ServerMessage::Ack { revision }
+                    }
+                    Ok(message) => message,
+                    // A client that fell behind cannot catch up; it reconnects
+                    Err(_) => break,
+                },
+                else => break,
+            };
+
+            let Ok(text) = serde_json::to_string(&message) else { continue };
+            if sender.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let message = tokio::select! {
+            message = receiver.next() => message,
+            _ = &mut forward => break,
+        };
+
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            Some(Ok(_)) => continue,
+        };
+
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Edit { revision, operation }) => {
+                if let Err(message) = session.apply_edit(client_id, revision, operation).await {
+                    let _ = direct_tx.send(ServerMessage::Error { message });
+                }
+            }
+            Err(e) => {
+                let _ = direct_tx.send(ServerMessage::Error {
+                    message: format!("Invalid message: {}", e),
+                });
+            }
+        }
+    }
+
+    forward.abort();
+    state.live.leave(&state.db, &session, client_id).await;
+}
@@ -1,16 +1,22 @@
-
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use axum::{
     extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue},
     response::IntoResponse,
-    Json, body::Body
+    Json, body::{Body, Bytes}
 };
+use dr_markdown_llm::{ChatEvent, ChatMessage, ChatStream, Source};
+use futures::StreamExt;
+use uuid::Uuid;
 
 use crate::{
     AppState,
+    auth::AuthUser,
+    database::Database,
     handlers::AppError,
-    OLLAMA_ADDR,
-    OLLAMA_MODEL,
+    models::LlmQuotaResponse,
+    prompts::{self, PromptVariables},
+    quota::LlmQuota,
 };
 
 // What the frontend will send to our backend
@@ -20,55 +26,194 @@ pub struct ChatApiRequest {
     pub message: String,
 }
 
-// What we will send to Ollama's /api/generate endpoint
-#[derive(Serialize)]
-struct OllamaRequest {
-    model: String,
-    prompt: String,
-    stream: bool,
-}
-
-pub async fn ollama_chat_handler(
-    State(_state): State<AppState>, // Assuming you have AppState
+/// Answers a single question about the document, without a conversation
+/// that is kept.
+pub async fn chat_handler(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
     Json(payload): Json<ChatApiRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-
-    // Construct a detailed prompt for the LLM
-    let prompt = format!(
-        "You are a helpful writing assistant. The user is currently editing a document with the following content:\n\n---\n{}\n---\n\nNow, please answer the user's question, and if you do propose new content for the document please use markdown syntax: {}",
-        payload.context,
-        payload.message
-    );
-
-    let ollama_request = OllamaRequest {
-        model: OLLAMA_MODEL.to_string(),
-        prompt,
-        stream: true,
+    let variables = PromptVariables {
+        content: &payload.context,
+        question: &payload.message,
+        ..Default::default()
     };
-    println!("Ollama Request Prompt: {}", ollama_request.prompt);
+    let system = prompts::system_prompt(&state, auth_user.user_id, None, &variables).await?;
+    let messages = vec![system, ChatMessage::user(payload.message)];
+    stream_answer(&state, auth_user.user_id, messages, Vec::new(), |_| {}).await
+}
+
+/// Checks the quota and starts the answer. The request is counted before
+/// the model server is asked, with a rough estimate of the prompt size until
+/// the model server reports the actual number of tokens at the end of the
+/// answer.
+async fn start_answer(
+    state: &AppState,
+    user_id: Uuid,
+    messages: &[ChatMessage],
+) -> Result<(ChatStream, Uuid, LlmQuotaResponse), AppError> {
+    let (usage_id, quota) = LlmQuota::reserve(state, user_id, estimate_tokens(messages)).await?;
+
+    // The messages are the user's documents and questions, which do not
+    // belong in the log
+    println!("LLM request to {} with {} messages", state.llm.model(), messages.len());
+
+    match state.llm.stream_chat(messages).await {
+        Ok(answer) => Ok((answer, usage_id, quota)),
+        Err(e) => {
+            // Nothing was generated, so the request does not count
+            state.db.delete_llm_usage(usage_id).await?;
+            Err(AppError::Llm(e.to_string()))
+        }
+    }
+}
+
+/// Roughly four characters per token, until the model server reports the
+/// actual number.
+fn estimate_tokens(messages: &[ChatMessage]) -> i64 {
+    let length: usize = messages.iter().map(|message| message.content.len()).sum();
+    (length / 4) as i64
+}
+
+/// Follows an answer while it is streamed to the browser. When the browser
+/// stops reading, because the user stopped the answer or went away, axum
+/// drops the body and with it the request to the model server, which then
+/// stops generating. What was written up to there is still counted and kept.
+struct AnswerRecorder<F: FnOnce(String)> {
+    db: Database,
+    usage_id: Uuid,
+    prompt_tokens: i64,
+    text: String,
+    /// Taken once the answer is complete or failed.
+    on_answer: Option<F>,
+}
+
+impl<F: FnOnce(String)> AnswerRecorder<F> {
+    fn record(&mut self, event: &ChatEvent) {
+        match event {
+            ChatEvent::Delta { text } => self.text.push_str(text),
+            ChatEvent::Done { usage } => {
+                if let Some(usage) = usage {
+                    record_tokens(self.db.clone(), self.usage_id, usage.total() as i64);
+                }
+                if let Some(on_answer) = self.on_answer.take() {
+                    on_answer(std::mem::take(&mut self.text));
+                }
+            }
+            ChatEvent::Error { message } => {
+                println!("LLM answer failed: {}", message);
+                self.on_answer = None;
+            }
+            ChatEvent::Sources { .. } => {}
+        }
+    }
+}
+
+impl<F: FnOnce(String)> Drop for AnswerRecorder<F> {
+    fn drop(&mut self) {
+        let Some(on_answer) = self.on_answer.take() else {
+            return;
+        };
+        println!("LLM answer cancelled by the client");
 
-    let client = reqwest::Client::new();
-    let res = client.post(format!("{}/api/generate", OLLAMA_ADDR))
-        .header("Content-Type", "application/json")
-        .json(&ollama_request)
-        .send()
-        .await
-        .map_err(|_e| AppError::Llm("Failed to send request to LLM".into()))?;
+        // The model server never got to report the tokens
+        let answer_tokens = (self.text.len() / 4) as i64;
+        record_tokens(self.db.clone(), self.usage_id, self.prompt_tokens + answer_tokens);
+        if !self.text.is_empty() {
+            on_answer(std::mem::take(&mut self.text));
+        }
+    }
+}
 
+/// Sends the conversation to the configured model server and streams the
+/// answer to the browser as one `ChatEvent` per line, after the `sources`
+/// if there are any. `on_answer` gets the complete answer once the model is
+/// done, or the part written until the browser stopped reading.
+pub async fn stream_answer(
+    state: &AppState,
+    user_id: Uuid,
+    messages: Vec<ChatMessage>,
+    sources: Vec<Source>,
+    on_answer: impl FnOnce(String) + Send + 'static,
+) -> Result<impl IntoResponse, AppError> {
+    let (answer, usage_id, quota) = start_answer(state, user_id, &messages).await?;
+    let answer: ChatStream = if sources.is_empty() {
+        answer
+    } else {
+        Box::pin(futures::stream::iter([ChatEvent::Sources { sources }]).chain(answer))
+    };
+
+    let mut recorder = AnswerRecorder {
+        db: state.db.clone(),
+        usage_id,
+        prompt_tokens: estimate_tokens(&messages),
+        text: String::new(),
+        on_answer: Some(on_answer),
+    };
+    let stream = answer.map(move |event| {
+        recorder.record(&event);
 
-    // Get the response body as a stream of bytes
-    let stream = res.bytes_stream();
+        let mut line = serde_json::to_vec(&event).unwrap_or_default();
+        line.push(b'\n');
+        Ok::<_, std::convert::Infallible>(Bytes::from(line))
+    });
 
     // Create a streaming body for the Axum response
     let body = Body::from_stream(stream);
 
     // Set the content type for a streaming response
-    let headers = [
-        (axum::http::header::CONTENT_TYPE, "text/event-stream"),
-        (axum::http::header::CACHE_CONTROL, "no-cache"),
-        (axum::http::header::HeaderName::from_static("x-accel-buffering"), "no"),
-    ];
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert(HeaderName::from_static("x-accel-buffering"), HeaderValue::from_static("no"));
+    headers.extend(quota_headers(&quota));
 
     Ok((headers, body))
+}
+
+/// Like [`stream_answer`], but waits for the complete answer.
+pub async fn complete_answer(
+    state: &AppState,
+    user_id: Uuid,
+    messages: Vec<ChatMessage>,
+) -> Result<(String, HeaderMap), AppError> {
+    let (mut answer, usage_id, quota) = start_answer(state, user_id, &messages).await?;
+
+    let mut text = String::new();
+    while let Some(event) = answer.next().await {
+        match event {
+            ChatEvent::Delta { text: delta } => text.push_str(&delta),
+            ChatEvent::Done { usage } => {
+                if let Some(usage) = usage {
+                    state.db.update_llm_usage_tokens(usage_id, usage.total() as i64).await?;
+                }
+                break;
+            }
+            ChatEvent::Error { message } => return Err(AppError::Llm(message)),
+            ChatEvent::Sources { .. } => {}
+        }
+    }
+
+    Ok((text, quota_headers(&quota)))
+}
+
+fn quota_headers(quota: &LlmQuotaResponse) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(remaining) = quota.requests_remaining {
+        headers.insert(HeaderName::from_static("x-quota-requests-remaining"), remaining.into());
+    }
+    if let Some(remaining) = quota.tokens_remaining {
+        headers.insert(HeaderName::from_static("x-quota-tokens-remaining"), remaining.into());
+    }
+    headers
+}
 
+/// Replaces the estimate stored for the request with the tokens the model
+/// server counted.
+fn record_tokens(db: Database, usage_id: Uuid, tokens: i64) {
+    tokio::spawn(async move {
+        if let Err(e) = db.update_llm_usage_tokens(usage_id, tokens).await {
+            println!("Failed to record LLM token usage: {}", e);
+        }
+    });
 }
@@ -1,13 +1,24 @@
+mod actions;
 mod auth;
+mod chat;
 mod config;
 mod database;
+mod diagrams;
+mod edit;
+mod embeddings;
+mod export;
 mod handlers;
+mod import;
+mod live;
 mod models;
+mod prompts;
+mod quota;
 mod routes;
 mod llm;
 
 use anyhow::Result;
 use axum::Router;
+use dr_markdown_llm::{LlmProvider, OllamaProvider, OpenAiProvider, ProviderKind};
 use sqlx::sqlite::SqlitePool;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
@@ -15,32 +26,27 @@ use tower_http::services::{ServeDir, ServeFile};
 
 use crate::config::Config;
 use crate::database::Database;
+use crate::live::LiveHub;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
     pub config: Arc<Config>,
+    pub live: LiveHub,
+    pub llm: Arc<dyn LlmProvider>,
 }
 
-const APP_BASE: &str = match option_env!("LEPTOS_APP_BASE_PATH") {
-    Some(path) => path,
-    None => "",
-};
-
 const SERVER_ADDR: &str = match option_env!("SERVER_ADDR") {
     Some(addr) => addr,
     None => "127.0.0.1",
 };
 
-const OLLAMA_ADDR: &str = match option_env!("OLLAMA_ADDR") {
-    Some(addr) => addr,
-    None => "http://localhost:11434",
-};
-
-const OLLAMA_MODEL: &str = match option_env!("OLLAMA_MODEL") {
-    Some(model) => model,
-    None => "llama3.2",
-};
+// Changes to the HTML sanitizer's allow-list, the same as for the frontend
+const HTML_ALLOW_TAGS: Option<&str> = option_env!("HTML_ALLOW_TAGS");
+const HTML_DENY_TAGS: Option<&str> = option_env!("HTML_DENY_TAGS");
+const HTML_ALLOW_ATTRIBUTES: Option<&str> = option_env!("HTML_ALLOW_ATTRIBUTES");
+const HTML_ALLOW_URL_SCHEMES: Option<&str> = option_env!("HTML_ALLOW_URL_SCHEMES");
+const HTML_ALLOW_CLASSES: Option<&str> = option_env!("HTML_ALLOW_CLASSES");
 
 
 fn get_server_port() -> u16 {
@@ -50,6 +56,19 @@ fn get_server_port() -> u16 {
     }
 }
 
+fn create_llm_provider(config: &Config) -> Arc<dyn LlmProvider> {
+    match config.llm_provider {
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(&config.llm_url, &config.llm_model)),
+        ProviderKind::OpenAi => {
+            let provider = OpenAiProvider::new(&config.llm_url, &config.llm_model);
+            match &config.llm_api_key {
+                Some(api_key) => Arc::new(provider.api_key(api_key)),
+                None => Arc::new(provider),
+            }
+        }
+    }
+}
+
 pub const THEME_LIGHT: &str = "light";
 pub const THEME_DARK: &str = "dark";
 
@@ -66,7 +85,20 @@ async fn main() -> Result<()> {
     let db = Database::new(pool);
     db.migrate().await?;
 
-    let state = AppState { db, config };
+    let llm = create_llm_provider(&config);
+    println!("Answering with {} model {} at {}", config.llm_provider, llm.model(), config.llm_url);
+
+    if let Some(model) = &config.llm_embedding_model {
+        println!("Indexing documents with embedding model {}", model);
+        embeddings::spawn_indexer(db.clone(), llm.clone(), model.clone());
+    }
+
+    let state = AppState {
+        db,
+        config,
+        live: LiveHub::default(),
+        llm,
+    };
     
     // Resolve the frontend dist directory.
     // Check relative to the executable first, then relative to CWD.
@@ -1,8 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use dr_markdown_llm::Source;
+use dr_markdown_render::DiagramLanguage;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::actions::{AiAction, Placement};
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -22,8 +26,95 @@ pub struct Document {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub share_token: Option<String>,
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Folder {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DocumentTag {
+    pub document_id: Uuid,
+    pub tag: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DocumentRevision {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub revision: i64,
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ChatThread {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ChatThreadMessage {
+    pub id: Uuid,
+    pub thread_id: Uuid,
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: String,
+    /// The documents an answer drew on, as JSON list of [`Source`]s.
+    pub sources: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A system prompt for the AI chat. Prompts without a user are shared with
+/// everyone.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PromptTemplate {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub name: String,
+    pub template: String,
+    /// Takes the place of the built-in prompt when the user does not pick one.
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A piece of a document with its embedding, as stored for searching by
+/// meaning.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DocumentChunk {
+    pub document_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub embedding: Vec<u8>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DocumentSearchHit {
+    pub id: Uuid,
+    pub title: String,
+    /// Excerpt around the match, with matched terms wrapped in
+    /// [`SNIPPET_MATCH_START`] and [`SNIPPET_MATCH_END`].
+    pub snippet: String,
+    pub updated_at: DateTime<Utc>,
 }
 
+// Private use characters the search query puts around matched terms
+pub const SNIPPET_MATCH_START: char = '\u{E000}';
+pub const SNIPPET_MATCH_END: char = '\u{E001}';
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateUserRequest {
     #[validate(length(min = 3, max = 50))]
@@ -73,6 +164,7 @@ pub struct CreateDocumentRequest {
     #[validate(length(min = 1, max = 255))]
     pub title: String,
     pub content: Option<String>,
+    pub folder_id: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -80,9 +172,12 @@ pub struct UpdateDocumentRequest {
     #[validate(length(min = 1, max = 255))]
     pub title: Option<String>,
     pub content: Option<String>,
+    /// The `updated_at` of the copy the client edited. When present, the update is
+    /// rejected with a conflict if the document has changed since.
+    pub last_updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DocumentResponse {
     pub id: Uuid,
     pub title: String,
@@ -90,6 +185,7 @@ pub struct DocumentResponse {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub share_token: Option<String>,
+    pub folder_id: Option<Uuid>,
 }
 
 impl From<Document> for DocumentResponse {
@@ -101,6 +197,98 @@ impl From<Document> for DocumentResponse {
             created_at: doc.created_at,
             updated_at: doc.updated_at,
             share_token: doc.share_token,
+            folder_id: doc.folder_id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveDocumentRequest {
+    /// Target folder, or `null` for the top level.
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateFolderRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateFolderRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+    /// Absent leaves the folder where it is, `null` moves it to the top level.
+    #[serde(default, deserialize_with = "double_option")]
+    pub parent_id: Option<Option<Uuid>>,
+}
+
+// Distinguishes a field set to `null` from a missing one
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderResponse {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Folder> for FolderResponse {
+    fn from(folder: Folder) -> Self {
+        Self {
+            id: folder.id,
+            parent_id: folder.parent_id,
+            name: folder.name,
+            created_at: folder.created_at,
+            updated_at: folder.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResultResponse {
+    pub id: Uuid,
+    pub title: String,
+    /// HTML-escaped excerpt with the matched terms wrapped in `<mark>` tags.
+    pub snippet: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<DocumentSearchHit> for SearchResultResponse {
+    fn from(hit: DocumentSearchHit) -> Self {
+        let mut snippet = String::with_capacity(hit.snippet.len());
+        for c in hit.snippet.chars() {
+            match c {
+                SNIPPET_MATCH_START => snippet.push_str("<mark>"),
+                SNIPPET_MATCH_END => snippet.push_str("</mark>"),
+                '&' => snippet.push_str("&amp;"),
+                '<' => snippet.push_str("&lt;"),
+                '>' => snippet.push_str("&gt;"),
+                '"' => snippet.push_str("&quot;"),
+                '\'' => snippet.push_str("&#39;"),
+                c => snippet.push(c),
+            }
+        }
+
+        Self {
+            id: hit.id,
+            title: hit.title,
+            snippet,
+            updated_at: hit.updated_at,
         }
     }
 }
@@ -117,6 +305,8 @@ pub struct DocumentSummary {
     pub title: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub folder_id: Option<Uuid>,
+    pub tags: Vec<String>,
 }
 
 impl From<Document> for DocumentSummary {
@@ -126,6 +316,375 @@ impl From<Document> for DocumentSummary {
             title: doc.title,
             created_at: doc.created_at,
             updated_at: doc.updated_at,
+            folder_id: doc.folder_id,
+            tags: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentListQuery {
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddTagRequest {
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddCollaboratorRequest {
+    pub username: String,
+}
+
+/// Tags are compared case-insensitively, so they are stored trimmed and in
+/// lowercase. Returns `None` for tags that are empty or too long.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.chars().count() > 50 {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Html,
+    Pdf,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+/// The outcome of importing a single file, either uploaded directly or
+/// found inside a ZIP archive.
+#[derive(Debug, Serialize)]
+pub struct ImportFileResult {
+    pub path: String,
+    pub status: ImportStatus,
+    pub document: Option<DocumentSummary>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub results: Vec<ImportFileResult>,
+    /// Folders that were created for the directories of imported files
+    pub folders: Vec<FolderResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionSummary {
+    pub revision: i64,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<DocumentRevision> for RevisionSummary {
+    fn from(revision: DocumentRevision) -> Self {
+        Self {
+            revision: revision.revision,
+            title: revision.title,
+            created_at: revision.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    pub revision: i64,
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<DocumentRevision> for RevisionResponse {
+    fn from(revision: DocumentRevision) -> Self {
+        Self {
+            revision: revision.revision,
+            title: revision.title,
+            content: revision.content,
+            created_at: revision.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LlmUsage {
+    pub tokens: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Limits of a single user that differ from the server defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LlmQuotaOverride {
+    pub request_limit: Option<i64>,
+    pub token_limit: Option<i64>,
+}
+
+/// How much of the LLM quota a user has used. Limits and remaining amounts
+/// are `None` when unlimited.
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmQuotaResponse {
+    pub request_limit: Option<i64>,
+    pub requests_used: i64,
+    pub requests_remaining: Option<i64>,
+    pub token_limit: Option<i64>,
+    pub tokens_used: i64,
+    pub tokens_remaining: Option<i64>,
+    pub window_seconds: i64,
+    /// When the oldest request still counted drops out of the window.
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateChatThreadRequest {
+    #[validate(length(max = 255))]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChatMessageRequest {
+    #[validate(length(min = 1))]
+    pub message: String,
+    /// The document as the user sees it, which may have unsaved changes.
+    /// The saved document is used if left out.
+    pub context: Option<String>,
+    /// Look for parts of the user's other documents that help answering.
+    #[serde(default)]
+    pub search_documents: bool,
+    /// The prompt template to answer with, the default one if left out.
+    pub prompt_id: Option<Uuid>,
+    /// The text selected in the editor, for templates that use it.
+    pub selection: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatThreadSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<ChatThread> for ChatThreadSummary {
+    fn from(thread: ChatThread) -> Self {
+        Self {
+            id: thread.id,
+            title: thread.title,
+            created_at: thread.created_at,
+            updated_at: thread.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatMessageResponse {
+    pub id: Uuid,
+    pub role: String,
+    pub content: String,
+    pub sources: Vec<Source>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ChatThreadMessage> for ChatMessageResponse {
+    fn from(message: ChatThreadMessage) -> Self {
+        let sources = message
+            .sources
+            .and_then(|sources| serde_json::from_str(&sources).ok())
+            .unwrap_or_default();
+
+        Self {
+            id: message.id,
+            role: message.role,
+            content: message.content,
+            sources,
+            created_at: message.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatThreadResponse {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ChatMessageResponse>,
+}
+
+impl ChatThreadResponse {
+    pub fn new(thread: ChatThread, messages: Vec<ChatThreadMessage>) -> Self {
+        Self {
+            id: thread.id,
+            title: thread.title,
+            created_at: thread.created_at,
+            updated_at: thread.updated_at,
+            messages: messages.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A range of a text in characters (not bytes), end exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EditSuggestionRequest {
+    /// The whole document as the user sees it.
+    pub content: String,
+    #[validate(length(min = 1))]
+    pub instruction: String,
+    /// The part of the document to rewrite, all of it if left out.
+    pub selection: Option<TextRange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EditSuggestionResponse {
+    /// The text that was rewritten, the selection or the whole document.
+    pub original: String,
+    pub replacement: String,
+    pub selection: Option<TextRange>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AiActionRequest {
+    /// The whole document as the user sees it.
+    pub content: String,
+    pub selection: TextRange,
+    /// The value the action asked the user for, if any.
+    pub argument: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiActionResponse {
+    pub id: String,
+    pub label: String,
+    pub argument: Option<String>,
+    pub placement: Placement,
+}
+
+impl From<&AiAction> for AiActionResponse {
+    fn from(action: &AiAction) -> Self {
+        Self {
+            id: action.id.clone(),
+            label: action.label.clone(),
+            argument: action.argument.clone(),
+            placement: action.placement,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePromptTemplateRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1, max = 20000))]
+    pub template: String,
+    /// Offer the prompt to everyone, for admins only.
+    #[serde(default)]
+    pub shared: bool,
+    /// Use the prompt when none is picked, for shared prompts only.
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePromptTemplateRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    #[validate(length(min = 1, max = 20000))]
+    pub template: Option<String>,
+    pub is_default: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptTemplateResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub template: String,
+    pub shared: bool,
+    pub is_default: bool,
+    /// Whether the user may change or delete the prompt.
+    pub editable: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PromptTemplateResponse {
+    pub fn new(prompt: PromptTemplate, is_admin: bool) -> Self {
+        Self {
+            id: prompt.id,
+            shared: prompt.user_id.is_none(),
+            editable: prompt.user_id.is_some() || is_admin,
+            name: prompt.name,
+            template: prompt.template,
+            is_default: prompt.is_default,
+            updated_at: prompt.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptTemplateListResponse {
+    pub prompts: Vec<PromptTemplateResponse>,
+    /// Whether the user may add and change shared prompts.
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiagramSourceQuery {
+    /// The diagram source, compressed and encoded like in Kroki URLs.
+    pub source: Option<String>,
+    /// The share token of a document with the diagram, which lets visitors
+    /// of shared documents render diagrams that are not cached.
+    pub token: Option<String>,
+}
+
+/// A diagram to render, named like in Kroki's JSON requests.
+#[derive(Debug, Deserialize)]
+pub struct DiagramRequest {
+    pub diagram_type: String,
+    pub diagram_source: String,
+    /// `svg` or `png`, the language's format if missing.
+    pub output_format: Option<String>,
+}
+
+/// What the frontend needs to know about the server before it starts.
+#[derive(Debug, Serialize)]
+pub struct ClientConfigResponse {
+    pub diagrams: Vec<DiagramLanguageResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiagramLanguageResponse {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub format: String,
+}
+
+impl From<&DiagramLanguage> for DiagramLanguageResponse {
+    fn from(language: &DiagramLanguage) -> Self {
+        Self {
+            name: language.name.clone(),
+            aliases: language.aliases.clone(),
+            format: language.format.as_str().to_string(),
+        }
+    }
+}
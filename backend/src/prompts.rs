@@ -0,0 +1,198 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use dr_markdown_llm::ChatMessage;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::AuthUser,
+    handlers::AppError,
+    models::{
+        CreatePromptTemplateRequest, PromptTemplate, PromptTemplateListResponse, PromptTemplateResponse,
+        UpdatePromptTemplateRequest,
+    },
+    AppState,
+};
+
+/// The system prompt when the user picks none and no admin has made a
+/// shared prompt the default.
+const BUILT_IN_PROMPT: &str = "You are a helpful writing assistant. The user is currently editing a document with the following content:\n\n---\n{content}\n---\n\nAnswer the user's questions, and if you do propose new content for the document please use markdown syntax.";
+
+/// What a template can refer to as `{title}`, `{content}`, `{selection}`
+/// and `{question}`.
+#[derive(Debug, Default)]
+pub struct PromptVariables<'a> {
+    pub title: &'a str,
+    pub content: &'a str,
+    pub selection: &'a str,
+    pub question: &'a str,
+}
+
+impl PromptVariables<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "title" => Some(self.title),
+            "content" => Some(self.content),
+            "selection" => Some(self.selection),
+            "question" => Some(self.question),
+            _ => None,
+        }
+    }
+}
+
+/// Fills in the variables in one pass, so that braces in the document or the
+/// question are left alone. Unknown names in braces are kept as they are.
+pub fn render(template: &str, variables: &PromptVariables) -> String {
+    fill_placeholders(template, |name| variables.get(name))
+}
+
+/// Replaces every `{name}` for which `value` returns something. Inserted
+/// values are not searched for placeholders again.
+pub fn fill_placeholders<'a>(template: &str, value: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut prompt = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        prompt.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let variable = rest[1..]
+            .find('}')
+            .and_then(|end| value(&rest[1..=end]).map(|value| (value, end + 2)));
+        match variable {
+            Some((value, length)) => {
+                prompt.push_str(value);
+                rest = &rest[length..];
+            }
+            None => {
+                prompt.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+/// The system message for a chat: the prompt the user picked, or else the
+/// default one.
+pub async fn system_prompt(
+    state: &AppState,
+    user_id: Uuid,
+    prompt_id: Option<Uuid>,
+    variables: &PromptVariables<'_>,
+) -> Result<ChatMessage, AppError> {
+    let template = match prompt_id {
+        Some(prompt_id) => Some(
+            state
+                .db
+                .find_prompt_template(prompt_id, user_id)
+                .await?
+                .ok_or(AppError::PromptNotFound)?,
+        ),
+        None => state.db.find_default_prompt_template().await?,
+    };
+
+    let template = template.map(|prompt| prompt.template);
+    Ok(ChatMessage::system(render(
+        template.as_deref().unwrap_or(BUILT_IN_PROMPT),
+        variables,
+    )))
+}
+
+async fn is_admin(state: &AppState, user_id: Uuid) -> Result<bool, AppError> {
+    Ok(state.db.is_admin(user_id).await?)
+}
+
+/// Finds a prompt the user may change: one of their own, or a shared one if
+/// they are an admin.
+async fn find_editable_prompt(state: &AppState, prompt_id: Uuid, user_id: Uuid) -> Result<PromptTemplate, AppError> {
+    let prompt = state
+        .db
+        .find_prompt_template(prompt_id, user_id)
+        .await?
+        .ok_or(AppError::PromptNotFound)?;
+
+    if prompt.user_id.is_none() && !is_admin(state, user_id).await? {
+        return Err(AppError::AdminOnly);
+    }
+    Ok(prompt)
+}
+
+pub async fn get_prompts(auth_user: AuthUser, State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let is_admin = is_admin(&state, auth_user.user_id).await?;
+    let prompts = state.db.find_prompt_templates(auth_user.user_id).await?;
+
+    Ok(Json(PromptTemplateListResponse {
+        prompts: prompts
+            .into_iter()
+            .map(|prompt| PromptTemplateResponse::new(prompt, is_admin))
+            .collect(),
+        is_admin,
+    }))
+}
+
+pub async fn create_prompt(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(request): Json<CreatePromptTemplateRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    // Only shared prompts can be the default for everyone
+    let shared = request.shared || request.is_default;
+    let is_admin = is_admin(&state, auth_user.user_id).await?;
+    if shared && !is_admin {
+        return Err(AppError::AdminOnly);
+    }
+
+    let prompt = state
+        .db
+        .create_prompt_template(
+            (!shared).then_some(auth_user.user_id),
+            request.name.trim(),
+            &request.template,
+            request.is_default,
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(PromptTemplateResponse::new(prompt, is_admin))))
+}
+
+pub async fn update_prompt(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(prompt_id): Path<Uuid>,
+    Json(request): Json<UpdatePromptTemplateRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    request.validate()?;
+
+    let prompt = find_editable_prompt(&state, prompt_id, auth_user.user_id).await?;
+    let is_default = request.is_default.filter(|_| prompt.user_id.is_none());
+    let prompt = state
+        .db
+        .update_prompt_template(
+            &prompt,
+            request.name.as_deref().map(str::trim),
+            request.template.as_deref(),
+            is_default,
+        )
+        .await?;
+
+    // Shared prompts only get this far for admins
+    Ok(Json(PromptTemplateResponse::new(prompt, true)))
+}
+
+pub async fn delete_prompt(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(prompt_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let prompt = find_editable_prompt(&state, prompt_id, auth_user.user_id).await?;
+    state.db.delete_prompt_template(prompt.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,227 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    handlers::AppError,
+    models::{LlmQuotaResponse, LlmUsage},
+    AppState,
+};
+
+/// The LLM quota of a user: the limits that apply and the requests made
+/// within the quota window, which ends now.
+pub struct LlmQuota {
+    request_limit: Option<i64>,
+    token_limit: Option<i64>,
+    window: Duration,
+    usage: Vec<LlmUsage>,
+    now: DateTime<Utc>,
+}
+
+impl LlmQuota {
+    pub async fn load(state: &AppState, user_id: Uuid) -> Result<Self, AppError> {
+        let limits = Limits::load(state, user_id).await?;
+        let now = Utc::now();
+        let usage = state.db.find_llm_usage_since(user_id, now - limits.window).await?;
+        Ok(Self::new(&limits, usage, now))
+    }
+
+    /// Counts a request of about `tokens` tokens against the quota, unless
+    /// the quota is used up. Checking and counting happen in one
+    /// transaction, so that requests made at the same time cannot all slip
+    /// through. Returns the id of the usage record, to settle the tokens
+    /// once the answer is complete, and the quota with the request counted.
+    pub async fn reserve(state: &AppState, user_id: Uuid, tokens: i64) -> Result<(Uuid, LlmQuotaResponse), AppError> {
+        let limits = Limits::load(state, user_id).await?;
+        let now = Utc::now();
+
+        let mut exceeded = None;
+        let usage_id = state
+            .db
+            .reserve_llm_usage(user_id, tokens, now - limits.window, |usage| {
+                let quota = Self::new(&limits, usage.to_vec(), now);
+                exceeded = quota.retry_after().map(|retry_after| (retry_after, quota.response()));
+                exceeded.is_none()
+            })
+            .await?;
+
+        match (usage_id, exceeded) {
+            (Some(usage_id), _) => Ok((usage_id, Self::load(state, user_id).await?.response())),
+            (None, Some((retry_after, quota))) => Err(AppError::QuotaExceeded {
+                retry_after: retry_after.num_seconds().max(1) as u64,
+                quota: Box::new(quota),
+            }),
+            (None, None) => unreachable!("usage is only refused when the quota is exceeded"),
+        }
+    }
+
+    fn new(limits: &Limits, usage: Vec<LlmUsage>, now: DateTime<Utc>) -> Self {
+        Self {
+            request_limit: limits.request_limit,
+            token_limit: limits.token_limit,
+            window: limits.window,
+            usage,
+            now,
+        }
+    }
+
+    fn requests_used(&self) -> i64 {
+        self.usage.len() as i64
+    }
+
+    fn tokens_used(&self) -> i64 {
+        self.usage.iter().map(|usage| usage.tokens).sum()
+    }
+
+    /// How long the user has to wait until the next request is allowed, or
+    /// `None` if it is allowed now.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let mut allowed_at = None;
+
+        if let Some(limit) = self.request_limit {
+            let excess = self.requests_used() - limit;
+            if excess >= 0 {
+                // Enough of the oldest requests have to leave the window
+                allowed_at = self.usage.get(excess as usize).map(|usage| usage.created_at);
+            }
+        }
+
+        if let Some(limit) = self.token_limit {
+            let mut tokens = self.tokens_used();
+            for usage in &self.usage {
+                if tokens < limit {
+                    break;
+                }
+                tokens -= usage.tokens;
+                allowed_at = allowed_at.max(Some(usage.created_at));
+            }
+        }
+
+        allowed_at.map(|at| (at + self.window - self.now).max(Duration::seconds(1)))
+    }
+
+    pub fn response(&self) -> LlmQuotaResponse {
+        let requests_used = self.requests_used();
+        let tokens_used = self.tokens_used();
+
+        LlmQuotaResponse {
+            request_limit: self.request_limit,
+            requests_used,
+            requests_remaining: self.request_limit.map(|limit| (limit - requests_used).max(0)),
+            token_limit: self.token_limit,
+            tokens_used,
+            tokens_remaining: self.token_limit.map(|limit| (limit - tokens_used).max(0)),
+            window_seconds: self.window.num_seconds(),
+            resets_at: self.usage.first().map(|usage| usage.created_at + self.window),
+        }
+    }
+}
+
+/// The limits that apply to a user and the window they apply to.
+struct Limits {
+    request_limit: Option<i64>,
+    token_limit: Option<i64>,
+    window: Duration,
+}
+
+impl Limits {
+    async fn load(state: &AppState, user_id: Uuid) -> Result<Self, AppError> {
+        let config = &state.config;
+        let limits = state.db.find_llm_quota_override(user_id).await?.unwrap_or_default();
+
+        // Zero stands for no limit
+        let limit = |limit: Option<i64>, default: i64| Some(limit.unwrap_or(default)).filter(|limit| *limit > 0);
+
+        Ok(Self {
+            request_limit: limit(limits.request_limit, config.llm_request_quota),
+            token_limit: limit(limits.token_limit, config.llm_token_quota),
+            window: Duration::seconds(config.llm_quota_window_secs.max(1)),
+        })
+    }
+}
+
+pub async fn get_llm_quota(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let quota = LlmQuota::load(&state, auth_user.user_id).await?;
+    Ok(Json(quota.response()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(request_limit: Option<i64>, token_limit: Option<i64>, usage: &[(i64, i64)]) -> LlmQuota {
+        let now = DateTime::parse_from_rfc3339("2026-01-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let limits = Limits {
+            request_limit,
+            token_limit,
+            window: Duration::seconds(3600),
+        };
+        // Usage as (seconds ago, tokens), oldest first
+        let usage = usage
+            .iter()
+            .map(|(ago, tokens)| LlmUsage {
+                tokens: *tokens,
+                created_at: now - Duration::seconds(*ago),
+            })
+            .collect();
+        LlmQuota::new(&limits, usage, now)
+    }
+
+    #[test]
+    fn allowed_below_the_limits() {
+        assert_eq!(quota(None, None, &[(10, 1000)]).retry_after(), None);
+        assert_eq!(quota(Some(2), Some(1000), &[(10, 999)]).retry_after(), None);
+    }
+
+    #[test]
+    fn request_limit_waits_for_the_oldest_request() {
+        // The request made 3000 seconds ago leaves the window in 600 seconds
+        let quota = quota(Some(2), None, &[(3000, 1), (2000, 1)]);
+        assert_eq!(quota.retry_after(), Some(Duration::seconds(600)));
+    }
+
+    #[test]
+    fn request_limit_waits_for_enough_requests() {
+        // Two requests have to leave the window before a third is allowed
+        let quota = quota(Some(2), None, &[(3000, 1), (2000, 1), (1000, 1)]);
+        assert_eq!(quota.retry_after(), Some(Duration::seconds(1600)));
+    }
+
+    #[test]
+    fn token_limit_waits_until_enough_tokens_leave() {
+        // Once the oldest request leaves, 800 tokens are used
+        let quota = quota(None, Some(1000), &[(3500, 400), (3000, 400), (100, 400)]);
+        assert_eq!(quota.retry_after(), Some(Duration::seconds(100)));
+    }
+
+    #[test]
+    fn the_later_limit_applies() {
+        // A request is allowed again in 100 seconds, tokens only in 600
+        let quota = quota(Some(3), Some(500), &[(3500, 100), (3000, 100), (2000, 400)]);
+        assert_eq!(quota.retry_after(), Some(Duration::seconds(600)));
+    }
+
+    #[test]
+    fn retry_after_is_at_least_a_second() {
+        let quota = quota(Some(1), None, &[(3600, 1)]);
+        assert_eq!(quota.retry_after(), Some(Duration::seconds(1)));
+    }
+
+    #[test]
+    fn response_counts_the_window() {
+        let response = quota(Some(5), Some(1000), &[(3000, 300), (100, 900)]).response();
+        assert_eq!(response.requests_used, 2);
+        assert_eq!(response.requests_remaining, Some(3));
+        assert_eq!(response.tokens_used, 1200);
+        assert_eq!(response.tokens_remaining, Some(0));
+        assert_eq!(response.window_seconds, 3600);
+        assert_eq!(
+            response.resets_at,
+            Some(DateTime::parse_from_rfc3339("2026-01-01T12:10:00Z").unwrap().with_timezone(&Utc))
+        );
+    }
+}
@@ -1,23 +1,62 @@
 use axum::{
+    extract::DefaultBodyLimit,
     routing::{delete, get, post, put},
     Router,
 };
 
-use crate::{handlers, AppState, llm};
+use crate::{actions, chat, diagrams, edit, export, handlers, import, live, prompts, quota, AppState, llm};
 
 pub fn create_routes() -> Router<AppState> {
     Router::new()
+        .route("/config", get(handlers::get_client_config))
         .route("/auth/register", post(handlers::register))
         .route("/auth/login", post(handlers::login))
         .route("/auth/profile", get(handlers::get_profile))
         .route("/auth/profile", put(handlers::update_user_settings))
         .route("/documents", post(handlers::create_document))
         .route("/documents", get(handlers::get_documents))
+        .route("/documents/search", get(handlers::search_documents))
+        .route("/documents/export", get(export::export_documents))
+        .route(
+            "/documents/import",
+            post(import::import_documents).layer(DefaultBodyLimit::max(import::MAX_UPLOAD_SIZE)),
+        )
         .route("/documents/{id}", get(handlers::get_document))
         .route("/documents/{id}", put(handlers::update_document))
         .route("/documents/{id}", delete(handlers::delete_document))
+        .route("/documents/{id}/folder", put(handlers::move_document))
+        .route("/documents/{id}/export", get(export::export_document))
+        .route("/documents/{id}/tags", get(handlers::get_tags))
+        .route("/documents/{id}/tags", post(handlers::add_tag))
+        .route("/documents/{id}/tags/{tag}", delete(handlers::remove_tag))
+        .route("/documents/{id}/collaborators", get(handlers::get_collaborators))
+        .route("/documents/{id}/collaborators", post(handlers::add_collaborator))
+        .route("/documents/{id}/collaborators/{username}", delete(handlers::remove_collaborator))
         .route("/documents/{id}/share", post(handlers::create_share_link))
         .route("/documents/{id}/share", delete(handlers::remove_share_link))
+        .route("/documents/{id}/live", get(live::live_document_handler))
+        .route("/documents/{id}/revisions", get(handlers::get_revisions))
+        .route("/documents/{id}/revisions/{rev}", get(handlers::get_revision))
+        .route("/documents/{id}/revisions/{rev}/restore", post(handlers::restore_revision))
+        .route("/documents/{id}/chats", get(chat::get_chat_threads))
+        .route("/documents/{id}/chats", post(chat::create_chat_thread))
+        .route("/documents/{id}/chats/{thread}", get(chat::get_chat_thread))
+        .route("/documents/{id}/chats/{thread}", delete(chat::delete_chat_thread))
+        .route("/documents/{id}/chats/{thread}/messages", post(chat::post_chat_message))
+        .route("/folders", get(handlers::get_folders))
+        .route("/folders", post(handlers::create_folder))
+        .route("/folders/{id}", put(handlers::update_folder))
+        .route("/folders/{id}", delete(handlers::delete_folder))
         .route("/shared/{token}", get(handlers::get_shared_document))
-        .route("/llm", post(llm::ollama_chat_handler))
+        .route("/render/diagram", post(diagrams::render_diagram))
+        .route("/diagrams/{type}/{file}", get(diagrams::get_diagram))
+        .route("/llm", post(llm::chat_handler))
+        .route("/llm/edit", post(edit::suggest_edit))
+        .route("/llm/actions", get(actions::get_ai_actions))
+        .route("/llm/actions/{action}", post(actions::run_ai_action))
+        .route("/llm/quota", get(quota::get_llm_quota))
+        .route("/prompts", get(prompts::get_prompts))
+        .route("/prompts", post(prompts::create_prompt))
+        .route("/prompts/{id}", put(prompts::update_prompt))
+        .route("/prompts/{id}", delete(prompts::delete_prompt))
 }
\ No newline at end of file
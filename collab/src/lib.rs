@@ -0,0 +1,9 @@
+//! Shared building blocks for real-time collaborative editing: an operational
+//! transformation type for plain text and the WebSocket messages exchanged
+//! between the editor and the backend.
+
+mod operation;
+mod protocol;
+
+pub use operation::{Op, OtError, TextOperation};
+pub use protocol::{ClientMessage, Participant, ServerMessage};
@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+
+/// A single component of a [`TextOperation`]. Lengths are counted in Unicode
+/// scalar values (`char`s), never in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum OtError {
+    #[error("Operation expects a document of length {expected}, got {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error("Operations cannot be combined because their lengths do not match")]
+    IncompatibleOperations,
+}
+
+/// An edit of a whole text, expressed as a sequence of retain/insert/delete
+/// steps that together cover the entire document.
+///
+/// Operations can be composed and transformed against each other, which is
+/// what lets concurrent edits from several clients converge on the same text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Op>", into = "Vec<Op>")]
+pub struct TextOperation {
+    ops: Vec<Op>,
+    base_len: usize,
+    target_len: usize,
+}
+
+impl From<Vec<Op>> for TextOperation {
+    fn from(ops: Vec<Op>) -> Self {
+        let mut operation = TextOperation::new();
+        for op in ops {
+            operation.push(op);
+        }
+        operation
+    }
+}
+
+impl From<TextOperation> for Vec<Op> {
+    fn from(operation: TextOperation) -> Self {
+        operation.ops
+    }
+}
+
+impl TextOperation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Length of the text this operation can be applied to.
+    pub fn base_len(&self) -> usize {
+        self.base_len
+    }
+
+    /// Length of the text after applying this operation.
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    /// True if applying the operation leaves the text unchanged.
+    pub fn is_noop(&self) -> bool {
+        self.ops.iter().all(|op| matches!(op, Op::Retain(_)))
+    }
+
+    pub fn retain(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.base_len += n;
+        self.target_len += n;
+        if let Some(Op::Retain(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(Op::Retain(n));
+        }
+        self
+    }
+
+    pub fn insert(&mut self, text: &str) -> &mut Self {
+        if text.is_empty() {
+            return self;
+        }
+        self.target_len += text.chars().count();
+
+        // Keep inserts in front of deletes at the same position so equal edits
+        // always have the same representation
+        match self.ops.as_mut_slice() {
+            [.., Op::Insert(last)] => last.push_str(text),
+            [.., Op::Insert(prev), Op::Delete(_)] => prev.push_str(text),
+            [.., Op::Delete(_)] => {
+                let delete = self.ops.pop().expect("last op exists");
+                self.ops.push(Op::Insert(text.to_string()));
+                self.ops.push(delete);
+            }
+            _ => self.ops.push(Op::Insert(text.to_string())),
+        }
+        self
+    }
+
+    pub fn delete(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.base_len += n;
+        if let Some(Op::Delete(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(Op::Delete(n));
+        }
+        self
+    }
+
+    fn push(&mut self, op: Op) {
+        match op {
+            Op::Retain(n) => self.retain(n),
+            Op::Insert(text) => self.insert(&text),
+            Op::Delete(n) => self.delete(n),
+        };
+    }
+
+    /// Builds the operation turning `old` into `new` by trimming their common
+    /// prefix and suffix and replacing whatever is left in between.
+    pub fn from_diff(old: &str, new: &str) -> Self {
+        let old_chars: Vec<char> = old.chars().collect();
+        let new_chars: Vec<char> = new.chars().collect();
+
+        let prefix = old_chars
+            .iter()
+            .zip(&new_chars)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = old_chars[prefix..]
+            .iter()
+            .rev()
+            .zip(new_chars[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let inserted: String = new_chars[prefix..new_chars.len() - suffix].iter().collect();
+
+        let mut operation = TextOperation::new();
+        operation
+            .retain(prefix)
+            .insert(&inserted)
+            .delete(old_chars.len() - suffix - prefix)
+            .retain(suffix);
+        operation
+    }
+
+    /// Applies the operation to `text`.
+    pub fn apply(&self, text: &str) -> Result<String, OtError> {
+        let actual = text.chars().count();
+        if actual != self.base_len {
+            return Err(OtError::LengthMismatch { expected: self.base_len, actual });
+        }
+
+        let mut chars = text.chars();
+        let mut result = String::with_capacity(text.len());
+        for op in &self.ops {
+            match op {
+                Op::Retain(n) => result.extend(chars.by_ref().take(*n)),
+                Op::Insert(inserted) => result.push_str(inserted),
+                Op::Delete(n) => {
+                    chars.by_ref().take(*n).for_each(drop);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Combines `self` followed by `other` into a single operation with the
+    /// same effect.
+    pub fn compose(&self, other: &TextOperation) -> Result<TextOperation, OtError> {
+        if self.target_len != other.base_len {
+            return Err(OtError::IncompatibleOperations);
+        }
+
+        let mut result = TextOperation::new();
+        let mut ops1 = self.ops.iter().cloned();
+        let mut ops2 = other.ops.iter().cloned();
+        let mut op1 = ops1.next();
+        let mut op2 = ops2.next();
+
+        loop {
+            match (op1.take(), op2.take()) {
+                (None, None) => break,
+                (Some(Op::Delete(n)), rest) => {
+                    result.delete(n);
+                    op1 = ops1.next();
+                    op2 = rest;
+                }
+                (rest, Some(Op::Insert(text))) => {
+                    result.insert(&text);
+                    op1 = rest;
+                    op2 = ops2.next();
+                }
+                (None, _) | (_, None) => return Err(OtError::IncompatibleOperations),
+                (Some(Op::Retain(n1)), Some(Op::Retain(n2))) => {
+                    let n = n1.min(n2);
+                    result.retain(n);
+                    op1 = remainder(Op::Retain(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Retain(n2 - n), &mut ops2);
+                }
+                (Some(Op::Insert(text)), Some(Op::Delete(n2))) => {
+                    let len = text.chars().count();
+                    let n = len.min(n2);
+                    op1 = remainder(Op::Insert(text.chars().skip(n).collect()), &mut ops1);
+                    op2 = remainder(Op::Delete(n2 - n), &mut ops2);
+                }
+                (Some(Op::Insert(text)), Some(Op::Retain(n2))) => {
+                    let len = text.chars().count();
+                    let n = len.min(n2);
+                    result.insert(&text.chars().take(n).collect::<String>());
+                    op1 = remainder(Op::Insert(text.chars().skip(n).collect()), &mut ops1);
+                    op2 = remainder(Op::Retain(n2 - n), &mut ops2);
+                }
+                (Some(Op::Retain(n1)), Some(Op::Delete(n2))) => {
+                    let n = n1.min(n2);
+                    result.delete(n);
+                    op1 = remainder(Op::Retain(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Delete(n2 - n), &mut ops2);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Transforms two concurrent operations `a` and `b` (both based on the same
+    /// text) into `(a', b')` such that applying `a` then `b'` gives the same
+    /// result as applying `b` then `a'`. When both insert at the same position,
+    /// the text inserted by `a` ends up first.
+    pub fn transform(
+        a: &TextOperation,
+        b: &TextOperation,
+    ) -> Result<(TextOperation, TextOperation), OtError> {
+        if a.base_len != b.base_len {
+            return Err(OtError::IncompatibleOperations);
+        }
+
+        let mut a_prime = TextOperation::new();
+        let mut b_prime = TextOperation::new();
+        let mut ops1 = a.ops.iter().cloned();
+        let mut ops2 = b.ops.iter().cloned();
+        let mut op1 = ops1.next();
+        let mut op2 = ops2.next();
+
+        loop {
+            match (op1.take(), op2.take()) {
+                (None, None) => break,
+                (Some(Op::Insert(text)), rest) => {
+                    b_prime.retain(text.chars().count());
+                    a_prime.insert(&text);
+                    op1 = ops1.next();
+                    op2 = rest;
+                }
+                (rest, Some(Op::Insert(text))) => {
+                    a_prime.retain(text.chars().count());
+                    b_prime.insert(&text);
+                    op1 = rest;
+                    op2 = ops2.next();
+                }
+                (None, _) | (_, None) => return Err(OtError::IncompatibleOperations),
+                (Some(Op::Retain(n1)), Some(Op::Retain(n2))) => {
+                    let n = n1.min(n2);
+                    a_prime.retain(n);
+                    b_prime.retain(n);
+                    op1 = remainder(Op::Retain(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Retain(n2 - n), &mut ops2);
+                }
+                (Some(Op::Delete(n1)), Some(Op::Delete(n2))) => {
+                    // Both sides deleted the same text; nothing left to do
+                    let n = n1.min(n2);
+                    op1 = remainder(Op::Delete(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Delete(n2 - n), &mut ops2);
+                }
+                (Some(Op::Delete(n1)), Some(Op::Retain(n2))) => {
+                    let n = n1.min(n2);
+                    a_prime.delete(n);
+                    op1 = remainder(Op::Delete(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Retain(n2 - n), &mut ops2);
+                }
+                (Some(Op::Retain(n1)), Some(Op::Delete(n2))) => {
+                    let n = n1.min(n2);
+                    b_prime.delete(n);
+                    op1 = remainder(Op::Retain(n1 - n), &mut ops1);
+                    op2 = remainder(Op::Delete(n2 - n), &mut ops2);
+                }
+            }
+        }
+
+        Ok((a_prime, b_prime))
+    }
+
+    /// Maps a cursor position in the original text to the matching position
+    /// after the operation has been applied.
+    pub fn transform_index(&self, index: usize) -> usize {
+        let mut old_pos = 0;
+        let mut new_index = index;
+        for op in &self.ops {
+            if old_pos > index {
+                break;
+            }
+            match op {
+                Op::Retain(n) => old_pos += n,
+                Op::Insert(text) => {
+                    if old_pos < index {
+                        new_index += text.chars().count();
+                    }
+                }
+                Op::Delete(n) => {
+                    new_index -= (*n).min(index - old_pos);
+                    old_pos += n;
+                }
+            }
+        }
+        new_index
+    }
+}
+
+/// Returns the leftover part of a partially consumed op, or the next op once
+/// it has been used up entirely.
+fn remainder(op: Op, ops: &mut impl Iterator<Item = Op>) -> Option<Op> {
+    let exhausted = match &op {
+        Op::Retain(n) | Op::Delete(n) => *n == 0,
+        Op::Insert(text) => text.is_empty(),
+    };
+    if exhausted {
+        ops.next()
+    } else {
+        Some(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::TextOperation;
+
+    /// Steps of a random operation: 0 retains, 1 deletes and 2 inserts.
+    type Steps = Vec<(u8, usize, String)>;
+
+    fn steps() -> impl Strategy<Value = Steps> {
+        prop::collection::vec((0u8..3, 1usize..5, "[xyé😀]{1,3}"), 0..10)
+    }
+
+    /// Builds an operation for a text of `len` chars out of random steps,
+    /// retaining whatever they leave over.
+    fn build(len: usize, steps: &Steps) -> TextOperation {
+        let mut operation = TextOperation::new();
+        let mut remaining = len;
+        for (kind, n, text) in steps {
+            match kind {
+                0 => {
+                    operation.retain((*n).min(remaining));
+                    remaining -= (*n).min(remaining);
+                }
+                1 => {
+                    operation.delete((*n).min(remaining));
+                    remaining -= (*n).min(remaining);
+                }
+                _ => {
+                    operation.insert(text);
+                }
+            }
+        }
+        operation.retain(remaining);
+        operation
+    }
+
+    proptest! {
+        #[test]
+        fn transformed_operations_converge(text in "[abc😀]{0,16}", a in steps(), b in steps()) {
+            let a = build(text.chars().count(), &a);
+            let b = build(text.chars().count(), &b);
+            let (a_prime, b_prime) = TextOperation::transform(&a, &b).unwrap();
+
+            let a_then_b = b_prime.apply(&a.apply(&text).unwrap()).unwrap();
+            let b_then_a = a_prime.apply(&b.apply(&text).unwrap()).unwrap();
+            prop_assert_eq!(a_then_b, b_then_a);
+        }
+
+        #[test]
+        fn composing_equals_applying_in_turn(text in "[abc😀]{0,16}", a in steps(), b in steps()) {
+            let a = build(text.chars().count(), &a);
+            let after_a = a.apply(&text).unwrap();
+            let b = build(after_a.chars().count(), &b);
+
+            let composed = a.compose(&b).unwrap();
+            prop_assert_eq!(composed.apply(&text).unwrap(), b.apply(&after_a).unwrap());
+        }
+
+        #[test]
+        fn diffs_turn_one_text_into_the_other(old in "[abc😀]{0,16}", new in "[abc😀]{0,16}") {
+            prop_assert_eq!(TextOperation::from_diff(&old, &new).apply(&old).unwrap(), new);
+        }
+    }
+}
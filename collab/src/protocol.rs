@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::TextOperation;
+
+/// Someone currently connected to a live document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    pub client_id: Uuid,
+    pub username: String,
+}
+
+/// Messages sent by the editor to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// A local edit, based on the last server revision the client has seen.
+    Edit {
+        revision: u64,
+        operation: TextOperation,
+    },
+}
+
+/// Messages sent by the server to connected editors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// First message after connecting: the current state of the document.
+    Init {
+        client_id: Uuid,
+        revision: u64,
+        content: String,
+        participants: Vec<Participant>,
+    },
+    /// The client's own edit was applied as `revision`.
+    Ack { revision: u64 },
+    /// Another client's edit, already transformed to apply on top of the
+    /// previous revision.
+    Edit {
+        revision: u64,
+        client_id: Uuid,
+        operation: TextOperation,
+    },
+    /// The set of connected participants changed.
+    Presence { participants: Vec<Participant> },
+    /// The merged content was written to the database.
+    Saved { updated_at: DateTime<Utc> },
+    Error { message: String },
+}
@@ -1,7 +1,11 @@
+use chrono::{DateTime, Utc};
+use dr_markdown_llm::ChatEvent;
+use futures::stream::TryStreamExt;
 use gloo_net::http::{RequestBuilder, Response};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
 use uuid::Uuid;
+use wasm_streams::ReadableStream;
+use web_sys::AbortSignal;
 
 use crate::models::*;
 
@@ -27,10 +31,48 @@ pub struct ApiClient {
     token: Option<String>,
 }
 
-#[derive(Serialize)]
-pub struct ChatApiRequest {
-    pub context: String,
-    pub message: String,
+/// Reads a binary response, naming the file after its `Content-Disposition`.
+async fn handle_download(response: Response, fallback_name: &str) -> Result<DownloadedFile, ApiError> {
+    if !response.ok() {
+        return Err(error_from_response(response).await);
+    }
+
+    let file_name = response
+        .headers()
+        .get("Content-Disposition")
+        .and_then(|value| attachment_file_name(&value))
+        .unwrap_or_else(|| fallback_name.to_string());
+    let content_type = response
+        .headers()
+        .get("Content-Type")
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let data = response.binary().await.map_err(|e| ApiError {
+        error: format!("Download error: {}", e),
+        status: 0,
+        document: None,
+    })?;
+
+    Ok(DownloadedFile {
+        file_name,
+        content_type,
+        data,
+    })
+}
+
+/// The file name from a `Content-Disposition` header, preferring the UTF-8
+/// `filename*` form over the plain one.
+fn attachment_file_name(header: &str) -> Option<String> {
+    let parts = || header.split(';').map(str::trim);
+    parts()
+        .find_map(|part| part.strip_prefix("filename*=UTF-8''"))
+        .and_then(|encoded| js_sys::decode_uri_component(encoded).ok())
+        .map(String::from)
+        .or_else(|| {
+            parts()
+                .find_map(|part| part.strip_prefix("filename="))
+                .map(|name| name.trim_matches('"').to_string())
+        })
 }
 
 async fn handle_response<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
@@ -38,16 +80,50 @@ async fn handle_response<T: DeserializeOwned>(response: Response) -> Result<T, A
         response.json().await.map_err(|e| ApiError {
             error: format!("JSON parse error: {}", e),
             status: 0,
+            document: None,
         })
     } else {
-        let status = response.status();
-        let mut error: ApiError = response.json().await.unwrap_or(ApiError {
-            error: format!("API error: {}", status),
-            status: 0,
-        });
-        error.status = status;
-        Err(error)
+        Err(error_from_response(response).await)
+    }
+}
+
+async fn error_from_response(response: Response) -> ApiError {
+    let status = response.status();
+    let mut error: ApiError = response.json().await.unwrap_or(ApiError {
+        error: format!("API error: {}", status),
+        status: 0,
+        document: None,
+    });
+    error.status = status;
+    error
+}
+
+/// Reads a streamed AI answer, calling `on_event` for every event until the
+/// answer is done. Lines and characters can be split across chunks, so bytes
+/// are collected until a line is complete.
+pub async fn read_chat_events(response: Response, mut on_event: impl FnMut(ChatEvent)) -> Result<(), String> {
+    let Some(body) = response.body() else {
+        return Err("The answer is empty".to_string());
+    };
+    let mut stream = ReadableStream::from_raw(body).into_stream();
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = stream.try_next().await.map_err(|e| format!("{:?}", e))? {
+        buffer.extend_from_slice(&js_sys::Uint8Array::from(chunk).to_vec());
+
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let Ok(event) = serde_json::from_slice::<ChatEvent>(&line) else {
+                continue;
+            };
+            let finished = event.is_final();
+            on_event(event);
+            if finished {
+                return Ok(());
+            }
+        }
     }
+    Ok(())
 }
 
 impl ApiClient {
@@ -79,6 +155,7 @@ impl ApiClient {
         let response = req.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
         handle_response(response).await
     }
@@ -93,11 +170,13 @@ impl ApiClient {
             .map_err(|e| ApiError {
                 error: format!("Serialization error: {}", e),
                 status: 0,
+                document: None,
             })?;
 
         let response = request.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
 
         handle_response(response).await
@@ -113,11 +192,13 @@ impl ApiClient {
             .map_err(|e| ApiError {
                 error: format!("Serialization error: {}", e),
                 status: 0,
+                document: None,
             })?;
         
         let response = request.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
 
         handle_response(response).await
@@ -133,11 +214,13 @@ impl ApiClient {
             .map_err(|e| ApiError {
                 error: format!("Serialization error: {}", e),
                 status: 0,
+                document: None,
             })?;
 
         let response = request.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
 
         handle_response(response).await
@@ -154,6 +237,12 @@ impl ApiClient {
         self.send_request_builder(req_builder).await
     }
 
+    pub async fn search_documents(&self, query: &str) -> Result<Vec<SearchResult>, ApiError> {
+        let query = String::from(js_sys::encode_uri_component(query));
+        let req_builder = self.build_request("GET", &format!("/documents/search?q={}", query));
+        self.send_request_builder(req_builder).await
+    }
+
     pub async fn get_document(&self, id: Uuid) -> Result<Document, ApiError> {
         let req_builder = self.build_request("GET", &format!("/documents/{}", id));
         self.send_request_builder(req_builder).await
@@ -169,11 +258,13 @@ impl ApiClient {
             .map_err(|e| ApiError {
                 error: format!("Serialization error: {}", e),
                 status: 0,
+                document: None,
             })?;
 
         let response = request.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
         
         handle_response(response).await
@@ -184,21 +275,25 @@ impl ApiClient {
         id: Uuid,
         title: Option<&str>,
         content: Option<&str>,
+        last_updated_at: Option<DateTime<Utc>>,
     ) -> Result<Document, ApiError> {
         let request = self
             .build_request("PUT", &format!("/documents/{}", id))
             .json(&UpdateDocumentRequest {
                 title: title.map(|s| s.to_string()),
                 content: content.map(|s| s.to_string()),
+                last_updated_at,
             })
             .map_err(|e| ApiError {
                 error: format!("Serialization error: {}", e),
                 status: 0,
+                document: None,
             })?;
         
         let response = request.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
 
         handle_response(response).await
@@ -209,6 +304,236 @@ impl ApiClient {
         let response = req_builder.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
+        })?;
+
+        if response.ok() {
+            Ok(())
+        } else {
+            let status = response.status();
+            let mut error: ApiError = response.json().await.unwrap_or(ApiError {
+                error: format!("API error: {}", status),
+                status: 0,
+                document: None,
+            });
+            error.status = status;
+            Err(error)
+        }
+    }
+
+    pub async fn move_document(&self, id: Uuid, folder_id: Option<Uuid>) -> Result<Document, ApiError> {
+        let request = self
+            .build_request("PUT", &format!("/documents/{}/folder", id))
+            .json(&MoveDocumentRequest { folder_id })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn get_tags(&self, id: Uuid) -> Result<Vec<String>, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/tags", id));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn add_tag(&self, id: Uuid, tag: &str) -> Result<Vec<String>, ApiError> {
+        let request = self
+            .build_request("POST", &format!("/documents/{}/tags", id))
+            .json(&AddTagRequest { tag: tag.to_string() })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn remove_tag(&self, id: Uuid, tag: &str) -> Result<Vec<String>, ApiError> {
+        let tag = String::from(js_sys::encode_uri_component(tag));
+        let req_builder = self.build_request("DELETE", &format!("/documents/{}/tags/{}", id, tag));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn get_collaborators(&self, id: Uuid) -> Result<Vec<String>, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/collaborators", id));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn add_collaborator(&self, id: Uuid, username: &str) -> Result<Vec<String>, ApiError> {
+        let request = self
+            .build_request("POST", &format!("/documents/{}/collaborators", id))
+            .json(&AddCollaboratorRequest { username: username.to_string() })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn remove_collaborator(&self, id: Uuid, username: &str) -> Result<Vec<String>, ApiError> {
+        let username = String::from(js_sys::encode_uri_component(username));
+        let req_builder = self.build_request("DELETE", &format!("/documents/{}/collaborators/{}", id, username));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn export_documents(&self) -> Result<DownloadedFile, ApiError> {
+        let response = self
+            .build_request("GET", "/documents/export")
+            .send()
+            .await
+            .map_err(|e| ApiError {
+                error: format!("Network error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        handle_download(response, "dr-markdown-export.zip").await
+    }
+
+    /// Downloads a single document rendered by the server, `format` is
+    /// either `html` or `pdf`.
+    pub async fn export_document(&self, id: Uuid, format: &str) -> Result<DownloadedFile, ApiError> {
+        let response = self
+            .build_request("GET", &format!("/documents/{}/export?format={}", id, format))
+            .send()
+            .await
+            .map_err(|e| ApiError {
+                error: format!("Network error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        handle_download(response, &format!("document.{}", format)).await
+    }
+
+    pub async fn import_documents(
+        &self,
+        files: &[ImportFile],
+        create_folders: bool,
+    ) -> Result<ImportResponse, ApiError> {
+        let form_error = |e: wasm_bindgen::JsValue| ApiError {
+            error: format!("Upload error: {:?}", e),
+            status: 0,
+            document: None,
+        };
+
+        let form = web_sys::FormData::new().map_err(form_error)?;
+        form.append_with_str("create_folders", if create_folders { "true" } else { "false" })
+            .map_err(form_error)?;
+        for file in files {
+            let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(file.data.as_slice()));
+            let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(form_error)?;
+            form.append_with_blob_and_filename("files", &blob, &file.name)
+                .map_err(form_error)?;
+        }
+
+        // Not built with build_request: the browser has to set the multipart
+        // content type (including the boundary) itself
+        let url = format!("{}/documents/import", api_url());
+        let mut builder = RequestBuilder::new(&url).method(gloo_net::http::Method::POST);
+        if let Some(token) = &self.token {
+            builder = builder.header("Authorization", &format!("Bearer {}", token));
+        }
+
+        let request = builder.body(form).map_err(|e| ApiError {
+            error: format!("Serialization error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn get_folders(&self) -> Result<Vec<Folder>, ApiError> {
+        let req_builder = self.build_request("GET", "/folders");
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn create_folder(&self, name: &str, parent_id: Option<Uuid>) -> Result<Folder, ApiError> {
+        let request = self
+            .build_request("POST", "/folders")
+            .json(&CreateFolderRequest {
+                name: name.to_string(),
+                parent_id,
+            })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn update_folder(
+        &self,
+        id: Uuid,
+        name: Option<&str>,
+        parent_id: Option<Option<Uuid>>,
+    ) -> Result<Folder, ApiError> {
+        let request = self
+            .build_request("PUT", &format!("/folders/{}", id))
+            .json(&UpdateFolderRequest {
+                name: name.map(|s| s.to_string()),
+                parent_id,
+            })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn delete_folder(&self, id: Uuid) -> Result<(), ApiError> {
+        let req_builder = self.build_request("DELETE", &format!("/folders/{}", id));
+        let response = req_builder.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
         })?;
 
         if response.ok() {
@@ -218,6 +543,7 @@ impl ApiClient {
             let mut error: ApiError = response.json().await.unwrap_or(ApiError {
                 error: format!("API error: {}", status),
                 status: 0,
+                document: None,
             });
             error.status = status;
             Err(error)
@@ -229,6 +555,7 @@ impl ApiClient {
         let response = req_builder.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
         handle_response(response).await
     }
@@ -238,19 +565,250 @@ impl ApiClient {
         let response = req_builder.send().await.map_err(|e| ApiError {
             error: format!("Network error: {}", e),
             status: 0,
+            document: None,
         })?;
         handle_response(response).await
     }
 
+    pub async fn get_revisions(&self, id: Uuid) -> Result<Vec<RevisionSummary>, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/revisions", id));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn get_revision(&self, id: Uuid, revision: i64) -> Result<Revision, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/revisions/{}", id, revision));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn restore_revision(&self, id: Uuid, revision: i64) -> Result<Document, ApiError> {
+        let req_builder = self.build_request("POST", &format!("/documents/{}/revisions/{}/restore", id, revision));
+        self.send_request_builder(req_builder).await
+    }
+
     pub async fn get_shared_document(&self, token: &str) -> Result<SharedDocument, ApiError> {
         let req_builder = self.build_request("GET", &format!("/shared/{}", token));
         self.send_request_builder(req_builder).await
     }
 
-    pub async fn ollama_chat_streaming(&self, body: &impl serde::Serialize) -> Result<gloo_net::http::Response, gloo_net::Error> {
-        self.build_request("POST", "/llm")
-            .json(body)?
+    /// WebSocket URL of a document's live editing session. Browsers cannot set
+    /// headers on WebSockets, so the token goes into the query string.
+    pub fn live_document_url(&self, id: Uuid) -> String {
+        let mut base = api_url();
+        if base.starts_with('/') {
+            let origin = web_sys::window()
+                .and_then(|window| window.location().origin().ok())
+                .unwrap_or_default();
+            base = format!("{}{}", origin, base);
+        }
+        let base = match base.strip_prefix("http") {
+            Some(rest) => format!("ws{}", rest),
+            None => base,
+        };
+        format!(
+            "{}/documents/{}/live?token={}",
+            base,
+            id,
+            self.token.as_deref().unwrap_or_default()
+        )
+    }
+
+    pub async fn llm_quota(&self) -> Result<LlmQuota, ApiError> {
+        let req_builder = self.build_request("GET", "/llm/quota");
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn suggest_edit(
+        &self,
+        content: &str,
+        instruction: &str,
+        selection: Option<TextRange>,
+    ) -> Result<EditSuggestion, ApiError> {
+        let request = self
+            .build_request("POST", "/llm/edit")
+            .json(&EditSuggestionRequest {
+                content: content.to_string(),
+                instruction: instruction.to_string(),
+                selection,
+            })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn get_config(&self) -> Result<ClientConfig, ApiError> {
+        let req_builder = self.build_request("GET", "/config");
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn get_ai_actions(&self) -> Result<Vec<AiAction>, ApiError> {
+        let req_builder = self.build_request("GET", "/llm/actions");
+        self.send_request_builder(req_builder).await
+    }
+
+    /// Starts an AI action on the selection, the answer is streamed like a
+    /// chat answer.
+    pub async fn run_ai_action(
+        &self,
+        action_id: &str,
+        content: &str,
+        selection: TextRange,
+        argument: Option<String>,
+    ) -> Result<Response, ApiError> {
+        let request = self
+            .build_request("POST", &format!("/llm/actions/{}", action_id))
+            .json(&AiActionRequest {
+                content: content.to_string(),
+                selection,
+                argument,
+            })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        if response.ok() {
+            Ok(response)
+        } else {
+            Err(error_from_response(response).await)
+        }
+    }
+
+    pub async fn get_chat_threads(&self, document_id: Uuid) -> Result<Vec<ChatThreadSummary>, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/chats", document_id));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn get_chat_thread(&self, document_id: Uuid, thread_id: Uuid) -> Result<ChatThread, ApiError> {
+        let req_builder = self.build_request("GET", &format!("/documents/{}/chats/{}", document_id, thread_id));
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn create_chat_thread(&self, document_id: Uuid) -> Result<ChatThread, ApiError> {
+        let request = self
+            .build_request("POST", &format!("/documents/{}/chats", document_id))
+            .json(&CreateChatThreadRequest { title: None })
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn delete_chat_thread(&self, document_id: Uuid, thread_id: Uuid) -> Result<(), ApiError> {
+        let req_builder = self.build_request("DELETE", &format!("/documents/{}/chats/{}", document_id, thread_id));
+        let response = req_builder.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        if response.ok() {
+            Ok(())
+        } else {
+            Err(error_from_response(response).await)
+        }
+    }
+
+    /// Asks the next question of a thread. The answer is streamed, one
+    /// `ChatEvent` per line, until it is done or `abort` is triggered.
+    pub async fn send_chat_message(
+        &self,
+        document_id: Uuid,
+        thread_id: Uuid,
+        request: &ChatMessageRequest,
+        abort: &AbortSignal,
+    ) -> Result<gloo_net::http::Response, gloo_net::Error> {
+        self.build_request("POST", &format!("/documents/{}/chats/{}/messages", document_id, thread_id))
+            .abort_signal(Some(abort))
+            .json(request)?
             .send()
             .await
     }
+
+    pub async fn get_prompts(&self) -> Result<PromptTemplateList, ApiError> {
+        let req_builder = self.build_request("GET", "/prompts");
+        self.send_request_builder(req_builder).await
+    }
+
+    pub async fn create_prompt(&self, request: &CreatePromptTemplateRequest) -> Result<PromptTemplate, ApiError> {
+        let request = self
+            .build_request("POST", "/prompts")
+            .json(request)
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn update_prompt(
+        &self,
+        id: Uuid,
+        request: &UpdatePromptTemplateRequest,
+    ) -> Result<PromptTemplate, ApiError> {
+        let request = self
+            .build_request("PUT", &format!("/prompts/{}", id))
+            .json(request)
+            .map_err(|e| ApiError {
+                error: format!("Serialization error: {}", e),
+                status: 0,
+                document: None,
+            })?;
+
+        let response = request.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        handle_response(response).await
+    }
+
+    pub async fn delete_prompt(&self, id: Uuid) -> Result<(), ApiError> {
+        let req_builder = self.build_request("DELETE", &format!("/prompts/{}", id));
+        let response = req_builder.send().await.map_err(|e| ApiError {
+            error: format!("Network error: {}", e),
+            status: 0,
+            document: None,
+        })?;
+
+        if response.ok() {
+            Ok(())
+        } else {
+            Err(error_from_response(response).await)
+        }
+    }
 }
\ No newline at end of file
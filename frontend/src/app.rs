@@ -2,6 +2,7 @@ use leptos::prelude::*;
 use leptos_router::components::{Router, Routes, Route, A};
 use leptos_router::path;
 use leptos_meta::*;
+use uuid::Uuid;
 use web_sys::window;
 
 use crate::{
@@ -15,11 +16,6 @@ pub const APP_BASE: &str = match option_env!("LEPTOS_APP_BASE_PATH") {
     None => "",
 };
 
-pub const KROKI_URL: &str = match option_env!("KROKI_URL") {
-    Some(url) => url,
-    None => "https://kroki.io",
-};
-
 pub const THEME_LIGHT: &str = "light";
 pub const THEME_DARK: &str = "dark";
 
@@ -35,12 +31,34 @@ pub fn use_editor() -> EditorContext {
     use_context::<EditorContext>().expect("EditorContext not found")
 }
 
+/// The text selected in the editor, which the AI assistant's prompts can
+/// refer to.
+#[derive(Clone, Copy)]
+pub struct EditorSelectionContext(pub RwSignal<String>);
+pub fn use_editor_selection() -> EditorSelectionContext {
+    use_context::<EditorSelectionContext>().expect("EditorSelectionContext not found")
+}
+
+/// Text the AI assistant asks to insert at the cursor of the editor.
+#[derive(Clone, Copy)]
+pub struct EditorInsertContext(pub RwSignal<Option<String>>);
+pub fn use_editor_insert() -> EditorInsertContext {
+    use_context::<EditorInsertContext>().expect("EditorInsertContext not found")
+}
+
 #[derive(Clone, Copy)]
 pub struct ChatSidebarContext(pub RwSignal<bool>);
 pub fn use_chat_sidebar() -> ChatSidebarContext {
     use_context::<ChatSidebarContext>().expect("ChatSidebarContext not found")
 }
 
+/// The document open in the editor, which the AI assistant talks about.
+#[derive(Clone, Copy)]
+pub struct CurrentDocumentContext(pub RwSignal<Option<Uuid>>);
+pub fn use_current_document() -> CurrentDocumentContext {
+    use_context::<CurrentDocumentContext>().expect("CurrentDocumentContext not found")
+}
+
 /// Tracks whether the current document has unsaved edits.
 #[derive(Clone, Copy)]
 pub struct DirtyContext(pub RwSignal<bool>);
@@ -52,6 +70,8 @@ pub fn use_dirty() -> DirtyContext {
 pub fn App() -> impl IntoView {
     provide_meta_context();
     view! {
+        // Colors of highlighted code, for the light and the dark theme
+        <Style>{dr_markdown_render::highlight_stylesheet()}</Style>
         <AuthProvider>
             <AppLayout/>
         </AuthProvider>
@@ -71,6 +91,15 @@ fn AppLayout() -> impl IntoView {
     let editor_open = RwSignal::new(String::new());
     provide_context(EditorContext(editor_open));
 
+    let editor_selection = RwSignal::new(String::new());
+    provide_context(EditorSelectionContext(editor_selection));
+
+    let editor_insert = RwSignal::new(None::<String>);
+    provide_context(EditorInsertContext(editor_insert));
+
+    let current_document = RwSignal::new(None::<Uuid>);
+    provide_context(CurrentDocumentContext(current_document));
+
     let dirty = RwSignal::new(false);
     provide_context(DirtyContext(dirty));
 
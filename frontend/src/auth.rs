@@ -136,8 +136,8 @@ pub fn AuthProvider(children: ChildrenFn) -> impl IntoView {
 
     let logout = Action::new_local(move |_: &()| {
         async move {
-            let _ = LocalStorage::delete(TOKEN_KEY);
-            let _ = LocalStorage::delete(USER_KEY);
+            LocalStorage::delete(TOKEN_KEY);
+            LocalStorage::delete(USER_KEY);
 
             set_state.update(|s| {
                 s.token = None;
@@ -147,7 +147,7 @@ pub fn AuthProvider(children: ChildrenFn) -> impl IntoView {
     });
 
     let auth_context = AuthContext {
-        state: state,
+        state,
         login,
         register,
         logout,
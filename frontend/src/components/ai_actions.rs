@@ -0,0 +1,115 @@
+use leptos::prelude::*;
+
+use crate::models::AiAction;
+
+/// Toolbar menu of the AI actions for the selected text. Actions that need
+/// an argument, like the language to translate to, ask for it in the menu.
+#[component]
+pub fn AiActionMenu(
+    actions: Signal<Vec<AiAction>>,
+    running: Signal<bool>,
+    on_run: impl Fn(AiAction, Option<String>) + 'static + Clone + Send + Sync,
+) -> impl IntoView {
+    let (show_menu, set_show_menu) = signal(false);
+    // The action waiting for its argument
+    let (asking, set_asking) = signal(None::<AiAction>);
+    let argument = RwSignal::new(String::new());
+
+    let close = move || {
+        set_show_menu.set(false);
+        set_asking.set(None);
+        argument.set(String::new());
+    };
+
+    let run_asked = {
+        let on_run = on_run.clone();
+        move || {
+            let value = argument.get_untracked().trim().to_string();
+            if let Some(action) = asking.get_untracked().filter(|_| !value.is_empty()) {
+                close();
+                on_run(action, Some(value));
+            }
+        }
+    };
+    let run_asked_key = run_asked.clone();
+
+    view! {
+        <div class="relative">
+            <button
+                class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 disabled:opacity-50 transition-colors"
+                on:click=move |_| if show_menu.get_untracked() { close() } else { set_show_menu.set(true) }
+                disabled=move || running.get() || actions.get().is_empty()
+                title="Let the AI work on the selected text"
+            >
+                <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M13 10V3L4 14h7v7l9-11h-7z"></path>
+                </svg>
+                <span class="hidden sm:inline">
+                    {move || if running.get() { "Writing..." } else { "AI Actions" }}
+                </span>
+            </button>
+            <Show when=move || show_menu.get()>
+                <div class="absolute left-0 mt-1 w-52 py-1 bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 rounded-lg shadow-lg z-20">
+                    {
+                        let run_asked = run_asked.clone();
+                        let run_asked_key = run_asked_key.clone();
+                        let on_run = on_run.clone();
+                        move || match asking.get() {
+                            Some(action) => {
+                                let run_asked = run_asked.clone();
+                                let run_asked_key = run_asked_key.clone();
+                                view! {
+                                    <div class="px-3 py-2">
+                                        <label class="block mb-1 text-xs font-medium text-gray-700 dark:text-gray-300">
+                                            {action.argument.clone().unwrap_or_default()}
+                                        </label>
+                                        <input
+                                            type="text"
+                                            class="w-full px-2 py-1 text-xs border border-gray-200 dark:border-gray-600 rounded bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                                            prop:value=argument
+                                            on:input=move |ev| argument.set(event_target_value(&ev))
+                                            on:keydown=move |ev| {
+                                                if ev.key() == "Enter" {
+                                                    ev.prevent_default();
+                                                    run_asked_key();
+                                                } else if ev.key() == "Escape" {
+                                                    close();
+                                                }
+                                            }
+                                        />
+                                        <button
+                                            class="mt-2 w-full px-2 py-1 text-xs font-medium text-white bg-blue-600 rounded hover:bg-blue-700 disabled:opacity-50"
+                                            disabled=move || argument.get().trim().is_empty()
+                                            on:click=move |_| run_asked()
+                                        >
+                                            {action.label.clone()}
+                                        </button>
+                                    </div>
+                                }.into_any()
+                            }
+                            None => actions.get().into_iter().map(|action| {
+                                let on_run = on_run.clone();
+                                let label = action.label.clone();
+                                view! {
+                                    <button
+                                        class="block w-full px-3 py-1.5 text-left text-xs text-gray-700 dark:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700"
+                                        on:click=move |_| {
+                                            if action.argument.is_some() {
+                                                set_asking.set(Some(action.clone()));
+                                            } else {
+                                                close();
+                                                on_run(action.clone(), None);
+                                            }
+                                        }
+                                    >
+                                        {label}
+                                    </button>
+                                }
+                            }).collect_view().into_any(),
+                        }
+                    }
+                </div>
+            </Show>
+        </div>
+    }
+}
@@ -0,0 +1,215 @@
+use leptos::prelude::*;
+
+use crate::components::{apply_hunks, diff_hunks, Hunk};
+use crate::models::EditSuggestion;
+
+/// Unchanged runs longer than this are shortened to their first and last
+/// lines.
+const CONTEXT_LINES: usize = 3;
+
+/// Asks what the AI should change about the document or the selection.
+#[component]
+pub fn EditPrompt(
+    /// What will be rewritten, e.g. "The whole document".
+    scope: String,
+    pending: Signal<bool>,
+    error: Signal<Option<String>>,
+    on_submit: impl Fn(String) + 'static + Clone + Send + Sync,
+    on_cancel: impl Fn() + 'static + Clone + Send + Sync,
+) -> impl IntoView {
+    let instruction = RwSignal::new(String::new());
+    let submit = move || {
+        let text = instruction.get_untracked();
+        if !text.trim().is_empty() && !pending.get_untracked() {
+            on_submit(text);
+        }
+    };
+    let submit_key = submit.clone();
+
+    view! {
+        <div class="fixed inset-0 bg-black/50 backdrop-blur-sm z-50 flex items-center justify-center p-4">
+            <div class="bg-white dark:bg-gray-800 rounded-xl shadow-xl max-w-lg w-full p-6">
+                <h3 class="text-lg font-semibold text-gray-900 dark:text-gray-50 mb-1">"Edit with AI"</h3>
+                <p class="text-xs text-gray-500 dark:text-gray-400 mb-3">
+                    {scope}" will be rewritten. You can review every change before it is kept."
+                </p>
+                <textarea
+                    class="w-full p-2.5 text-sm border border-gray-200 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 resize-none focus:outline-none focus:ring-2 focus:ring-blue-500"
+                    rows="3"
+                    placeholder="e.g. Fix spelling and grammar, make it shorter, add a summary..."
+                    prop:value=instruction
+                    on:input=move |ev| instruction.set(event_target_value(&ev))
+                    on:keypress=move |ev| {
+                        if ev.key() == "Enter" && !ev.shift_key() {
+                            ev.prevent_default();
+                            submit_key();
+                        }
+                    }
+                ></textarea>
+                {move || error.get().map(|err| view! {
+                    <p class="mt-2 text-xs text-red-600 dark:text-red-400">{err}</p>
+                })}
+                <div class="flex gap-2 mt-4">
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                        on:click=move |_| on_cancel()
+                    >
+                        "Cancel"
+                    </button>
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 disabled:opacity-50 transition-colors"
+                        on:click=move |_| submit()
+                        disabled=move || pending.get() || instruction.get().trim().is_empty()
+                    >
+                        {move || if pending.get() { "Thinking..." } else { "Suggest Changes" }}
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
+
+/// Shows an AI rewrite as a diff against the original, where every changed
+/// hunk can be accepted or rejected. `on_change` gets the rewritten text
+/// with the accepted hunks after every decision.
+#[component]
+pub fn SuggestionReview(
+    suggestion: EditSuggestion,
+    on_change: impl Fn(String) + 'static + Clone + Send + Sync,
+    on_close: impl Fn() + 'static + Clone + Send + Sync,
+) -> impl IntoView {
+    let hunks = StoredValue::new(diff_hunks(&suggestion.original, &suggestion.replacement));
+    let count = hunks.with_value(|hunks| hunks.len());
+    let changes = hunks.with_value(|hunks| hunks.iter().filter(|hunk| matches!(hunk, Hunk::Changed { .. })).count());
+
+    // None while undecided, which keeps the original
+    let decisions = RwSignal::new(vec![None::<bool>; count]);
+    let decide = move |index: Option<usize>, accept: bool| {
+        decisions.update(|decisions| match index {
+            Some(index) => decisions[index] = Some(accept),
+            None => decisions.iter_mut().for_each(|decision| *decision = Some(accept)),
+        });
+        let accepted = decisions.get_untracked();
+        on_change(hunks.with_value(|hunks| apply_hunks(hunks, |index| accepted[index] == Some(true))));
+    };
+    let pending = move || {
+        let decisions = decisions.get();
+        hunks.with_value(|hunks| {
+            hunks
+                .iter()
+                .zip(&decisions)
+                .filter(|(hunk, decision)| matches!(hunk, Hunk::Changed { .. }) && decision.is_none())
+                .count()
+        })
+    };
+
+    let decide_all = decide.clone();
+    let decide_none = decide.clone();
+
+    view! {
+        <div class="h-full flex flex-col">
+            <div class="px-4 py-2 flex items-center gap-2 flex-wrap border-b border-gray-200 dark:border-gray-700 bg-blue-50 dark:bg-blue-900/20">
+                <span class="text-sm font-medium text-blue-800 dark:text-blue-200">"AI suggestion"</span>
+                <span class="text-xs text-blue-700/80 dark:text-blue-300/80">
+                    {move || match (changes, pending()) {
+                        (0, _) => "No changes".to_string(),
+                        (_, 0) => "All changes reviewed".to_string(),
+                        (_, pending) => format!("{} of {} changes to review", pending, changes),
+                    }}
+                </span>
+                <div class="flex-1"></div>
+                <button
+                    class="px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-white dark:bg-gray-700 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-600 transition-colors"
+                    on:click=move |_| decide_none(None, false)
+                >
+                    "Reject All"
+                </button>
+                <button
+                    class="px-3 py-1.5 text-xs font-medium text-green-700 dark:text-green-300 bg-green-100 dark:bg-green-900/30 rounded-lg hover:bg-green-200 dark:hover:bg-green-900/50 transition-colors"
+                    on:click=move |_| decide_all(None, true)
+                >
+                    "Accept All"
+                </button>
+                <button
+                    class="px-3 py-1.5 text-xs font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 transition-colors"
+                    on:click=move |_| on_close()
+                    title="Finish the review, undecided changes are left out"
+                >
+                    "Done"
+                </button>
+            </div>
+            <div class="flex-1 overflow-auto p-4 font-mono text-xs">
+                {hunks.with_value(|hunks| hunks.iter().cloned().enumerate().map(|(index, hunk)| match hunk {
+                    Hunk::Same(text) => view! {
+                        <div class="text-gray-500 dark:text-gray-400 whitespace-pre-wrap break-all">{shorten(&text)}</div>
+                    }.into_any(),
+                    Hunk::Changed { old, new } => {
+                        let decide_accept = decide.clone();
+                        let decide_reject = decide.clone();
+                        let decision = move || decisions.get()[index];
+                        view! {
+                            <div class="my-2 border border-gray-200 dark:border-gray-700 rounded-lg overflow-hidden"
+                                class=("opacity-60", move || decision().is_some())
+                            >
+                                <Show when=move || decision() != Some(true)>
+                                    {lines(&old, "-", "bg-red-50 dark:bg-red-900/20 text-red-800 dark:text-red-300")}
+                                </Show>
+                                <Show when=move || decision() != Some(false)>
+                                    {lines(&new, "+", "bg-green-50 dark:bg-green-900/20 text-green-800 dark:text-green-300")}
+                                </Show>
+                                <div class="px-2 py-1.5 flex items-center gap-2 font-sans bg-gray-50 dark:bg-gray-800">
+                                    <span class="flex-1 text-[11px] text-gray-500 dark:text-gray-400">
+                                        {move || match decision() {
+                                            Some(true) => "Accepted",
+                                            Some(false) => "Rejected",
+                                            None => "",
+                                        }}
+                                    </span>
+                                    <button
+                                        class="px-2 py-1 text-[11px] font-medium text-gray-700 dark:text-gray-300 rounded hover:bg-gray-200 dark:hover:bg-gray-700 disabled:opacity-40"
+                                        disabled=move || decision() == Some(false)
+                                        on:click=move |_| decide_reject(Some(index), false)
+                                    >
+                                        "Reject"
+                                    </button>
+                                    <button
+                                        class="px-2 py-1 text-[11px] font-medium text-green-700 dark:text-green-300 rounded hover:bg-green-100 dark:hover:bg-green-900/40 disabled:opacity-40"
+                                        disabled=move || decision() == Some(true)
+                                        on:click=move |_| decide_accept(Some(index), true)
+                                    >
+                                        "Accept"
+                                    </button>
+                                </div>
+                            </div>
+                        }.into_any()
+                    }
+                }).collect_view())}
+            </div>
+        </div>
+    }
+}
+
+fn lines(text: &str, marker: &'static str, class: &'static str) -> impl IntoView {
+    text.lines()
+        .map(|line| view! {
+            <div class=format!("flex {}", class)>
+                <span class="w-4 shrink-0 text-center select-none">{marker}</span>
+                <span class="whitespace-pre-wrap break-all pr-2">{line.to_string()}</span>
+            </div>
+        })
+        .collect_view()
+}
+
+fn shorten(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= CONTEXT_LINES * 2 + 1 {
+        return lines.join("\n");
+    }
+
+    format!(
+        "{}\n⋯ {} unchanged lines\n{}",
+        lines[..CONTEXT_LINES].join("\n"),
+        lines.len() - CONTEXT_LINES * 2,
+        lines[lines.len() - CONTEXT_LINES..].join("\n")
+    )
+}
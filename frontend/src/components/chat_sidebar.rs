@@ -1,98 +1,227 @@
 use leptos::*;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
-use crate::app::{use_chat_sidebar, use_editor};
-use crate::api::{ApiClient, ChatApiRequest};
+use gloo_storage::{LocalStorage, Storage};
+use crate::app::{use_chat_sidebar, use_current_document, use_editor, use_editor_insert, use_editor_selection, APP_BASE};
+use crate::api::{read_chat_events, ApiClient};
 use crate::auth::use_auth;
-use futures::stream::TryStreamExt;
-use js_sys;
-use wasm_streams::ReadableStream;
+use crate::components::PromptManager;
+use crate::markdown::render_answer;
+use crate::models::{ChatMessageRequest, ChatThreadMessage, ChatThreadSummary, LlmQuota, LlmQuotaExceeded, PromptTemplate};
+use dr_markdown_llm::{ChatEvent, Source};
+use uuid::Uuid;
+use web_sys::{AbortController, AbortSignal};
 
-
-#[derive(serde::Deserialize, Clone)]
-pub struct OllamaStreamResponse {
-    pub response: String,
-}
+/// Remembers the prompt picked in the assistant across visits.
+const PROMPT_KEY: &str = "chat_prompt";
 
 #[derive(Clone, Debug, PartialEq)]
 struct ChatMessage {
     sender: String,
+    /// Markdown for answers, plain text for questions.
     text: String,
+    /// The other documents the answer cites by number.
+    sources: Vec<Source>,
+}
+
+impl From<ChatThreadMessage> for ChatMessage {
+    fn from(message: ChatThreadMessage) -> Self {
+        if message.role == "assistant" {
+            ChatMessage { sender: "AI".to_string(), text: message.content, sources: message.sources }
+        } else {
+            ChatMessage { sender: "User".to_string(), text: message.content, sources: Vec::new() }
+        }
+    }
 }
 
 #[component]
 pub fn ChatSidebar() -> impl IntoView {
     let chat_sidebar = use_chat_sidebar();
     let editor = use_editor();
+    let editor_selection = use_editor_selection();
+    let editor_insert = use_editor_insert();
+    let current_document = use_current_document();
     let auth = use_auth();
 
     let messages = RwSignal::new(Vec::<ChatMessage>::new());
     let user_input = RwSignal::new(String::new());
     let is_thinking = RwSignal::new(false);
+    let search_documents = RwSignal::new(false);
+    let quota = RwSignal::new(None::<LlmQuota>);
+    let threads = RwSignal::new(Vec::<ChatThreadSummary>::new());
+    // None until the first question of a new conversation is asked
+    let active_thread = RwSignal::new(None::<Uuid>);
+    let prompts = RwSignal::new(Vec::<PromptTemplate>::new());
+    let is_admin = RwSignal::new(false);
+    // None answers with the default prompt
+    let selected_prompt = RwSignal::new(LocalStorage::get::<Uuid>(PROMPT_KEY).ok());
+    let show_prompt_manager = RwSignal::new(false);
+    // Stops the answer that is being written
+    let abort = StoredValue::new_local(None::<AbortController>);
+    let stop_answer = move || {
+        if let Some(controller) = abort.try_update_value(Option::take).flatten() {
+            controller.abort();
+        }
+    };
+
+    let refresh_prompts = move || {
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if let Ok(list) = ApiClient::with_token(token).get_prompts().await {
+                    // The picked prompt may have been deleted
+                    if let Some(id) = selected_prompt.get_untracked() {
+                        if !list.prompts.iter().any(|prompt| prompt.id == id) {
+                            selected_prompt.set(None);
+                            LocalStorage::delete(PROMPT_KEY);
+                        }
+                    }
+                    prompts.set(list.prompts);
+                    is_admin.set(list.is_admin);
+                }
+            }
+        });
+    };
+
+    let refresh_quota = move || {
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if let Ok(current) = ApiClient::with_token(token).llm_quota().await {
+                    quota.set(Some(current));
+                }
+            }
+        });
+    };
+
+    Effect::new(move |_| {
+        if chat_sidebar.0.get() {
+            refresh_quota();
+            refresh_prompts();
+        }
+    });
+
+    let refresh_threads = move |document_id: Uuid| {
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if let Ok(list) = ApiClient::with_token(token).get_chat_threads(document_id).await {
+                    if current_document.0.get_untracked() == Some(document_id) {
+                        threads.set(list);
+                    }
+                }
+            }
+        });
+    };
+
+    let open_thread = move |document_id: Uuid, thread_id: Option<Uuid>| {
+        active_thread.set(thread_id);
+        messages.set(Vec::new());
+        let Some(thread_id) = thread_id else { return };
+
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if let Ok(thread) = ApiClient::with_token(token).get_chat_thread(document_id, thread_id).await {
+                    // Another thread may have been picked in the meantime
+                    if active_thread.get_untracked() == Some(thread.id) {
+                        messages.set(thread.messages.into_iter().map(Into::into).collect());
+                    }
+                }
+            }
+        });
+    };
+
+    // Continue the latest conversation about the document that is opened
+    Effect::new(move |_| {
+        let document_id = current_document.0.get();
+        stop_answer();
+        threads.set(Vec::new());
+        active_thread.set(None);
+        messages.set(Vec::new());
+        let Some(document_id) = document_id else { return };
+
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if let Ok(list) = ApiClient::with_token(token).get_chat_threads(document_id).await {
+                    if current_document.0.get_untracked() == Some(document_id) {
+                        let latest = list.first().map(|thread| thread.id);
+                        threads.set(list);
+                        open_thread(document_id, latest);
+                    }
+                }
+            }
+        });
+    });
+
+    let delete_thread = move || {
+        let (Some(document_id), Some(thread_id)) = (current_document.0.get_untracked(), active_thread.get_untracked()) else {
+            return;
+        };
+        spawn_local(async move {
+            if let Some(token) = auth.state.get_untracked().token {
+                if ApiClient::with_token(token).delete_chat_thread(document_id, thread_id).await.is_ok() {
+                    threads.update(|list| list.retain(|thread| thread.id != thread_id));
+                    let latest = threads.with_untracked(|list| list.first().map(|thread| thread.id));
+                    open_thread(document_id, latest);
+                }
+            }
+        });
+    };
+
+    let set_last_message = move |text: String| {
+        messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                last_msg.text = text;
+            }
+        });
+    };
 
     let do_submit = move || {
         let message = user_input.get_untracked();
-        if message.is_empty() { return; }
+        if message.is_empty() || is_thinking.get_untracked() { return; }
+        let Some(document_id) = current_document.0.get_untracked() else { return; };
 
         let context = editor.0.get_untracked();
-        messages.update(|msgs| msgs.push(ChatMessage { sender: "User".to_string(), text: message.clone() }));
+        messages.update(|msgs| msgs.push(ChatMessage { sender: "User".to_string(), text: message.clone(), sources: Vec::new() }));
         user_input.set("".to_string());
 
-        messages.update(|msgs| msgs.push(ChatMessage { sender: "AI".to_string(), text: "".to_string() }));
+        messages.update(|msgs| msgs.push(ChatMessage { sender: "AI".to_string(), text: "".to_string(), sources: Vec::new() }));
+        let selection = Some(editor_selection.0.get_untracked()).filter(|selection| !selection.is_empty());
+        let request = ChatMessageRequest {
+            message: message.clone(),
+            context: Some(context),
+            search_documents: search_documents.get_untracked(),
+            prompt_id: selected_prompt.get_untracked(),
+            selection,
+        };
+        let Ok(controller) = AbortController::new() else { return; };
+        let signal = controller.signal();
+        abort.set_value(Some(controller));
         is_thinking.set(true);
 
         spawn_local(async move {
-            let body = ChatApiRequest { context, message };
             let token = auth.state.get_untracked().token;
 
             if let Some(token) = token {
                 let client = ApiClient::with_token(token);
-                let res = client.ollama_chat_streaming(&body).await;
-
-                if let Ok(res) = res {
-                    let raw_stream = res.body().unwrap();
-                    let mut stream = ReadableStream::from_raw(raw_stream).into_stream();
-
-                    loop {
-                        match stream.try_next().await {
-                            Ok(Some(chunk)) => {
-                                let decoder = web_sys::TextDecoder::new().unwrap();
-                                let uint8_array = js_sys::Uint8Array::from(chunk);
-                                let mut vec = vec![0u8; uint8_array.length() as usize];
-                                uint8_array.copy_to(&mut vec[..]);
-                                let chunk_str = decoder.decode_with_u8_array(&vec[..]).unwrap();
-
-                                for line in chunk_str.split('\n').filter(|s| !s.is_empty()) {
-                                    if let Ok(parsed) = serde_json::from_str::<OllamaStreamResponse>(line) {
-                                        messages.update(|msgs| {
-                                            if let Some(last_msg) = msgs.last_mut() {
-                                                last_msg.text.push_str(&parsed.response.replace("\n", "<br/>"));
-                                            }
-                                        });
-                                    }
-                                }
-                            }
-                            Ok(None) => {
-                                break;
-                            }
-                            Err(e) => {
-                                messages.update(|msgs| {
-                                    if let Some(last_msg) = msgs.last_mut() {
-                                        last_msg.text = format!("Error: {:?}", e);
-                                    }
-                                });
-                                break;
-                            }
-                        }
+
+                // The first question of a new conversation starts a thread
+                let thread_id = match active_thread.get_untracked() {
+                    Some(thread_id) => Ok(thread_id),
+                    None => client.create_chat_thread(document_id).await.map(|thread| {
+                        active_thread.set(Some(thread.id));
+                        thread.id
+                    }),
+                };
+
+                match thread_id {
+                    Ok(thread_id) => {
+                        let res = client.send_chat_message(document_id, thread_id, &request, &signal).await;
+                        read_answer(res, messages, quota, &signal).await;
                     }
-                } else {
-                     messages.update(|msgs| {
-                        if let Some(last_msg) = msgs.last_mut() {
-                            last_msg.text = "Error: Could not connect to the server.".to_string();
-                        }
-                    });
+                    Err(err) => set_last_message(format!("Error: {}", err.error)),
                 }
+                refresh_quota();
+                refresh_threads(document_id);
             }
+            abort.set_value(None);
             is_thinking.set(false);
         });
     };
@@ -126,34 +255,147 @@ pub fn ChatSidebar() -> impl IntoView {
                 <div class="w-8"></div>
             </header>
 
+            <Show when=move || current_document.0.get().is_some()>
+                <div class="px-3 py-2 flex items-center gap-1.5 border-b border-gray-200 dark:border-gray-700 shrink-0">
+                    <select
+                        class="flex-1 min-w-0 px-2 py-1 text-xs border border-gray-200 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-700 dark:text-gray-200 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        title="Conversations about this document"
+                        disabled=move || is_thinking.get()
+                        on:change=move |ev| {
+                            if let Some(document_id) = current_document.0.get_untracked() {
+                                open_thread(document_id, Uuid::parse_str(&event_target_value(&ev)).ok());
+                            }
+                        }
+                    >
+                        <Show when=move || active_thread.get().is_none()>
+                            <option value="" selected=true>"New conversation"</option>
+                        </Show>
+                        <For
+                            each=move || threads.get()
+                            key=|thread| (thread.id, thread.title.clone())
+                            children=move |thread| {
+                                let id = thread.id;
+                                let title = if thread.title.is_empty() { "New conversation".to_string() } else { thread.title };
+                                view! {
+                                    <option value=id.to_string() selected=move || active_thread.get() == Some(id)>{title}</option>
+                                }
+                            }
+                        />
+                    </select>
+                    <button
+                        class="p-1.5 rounded-lg text-gray-500 dark:text-gray-400 hover:bg-gray-100 dark:hover:bg-gray-700 disabled:opacity-50 transition-colors"
+                        title="New conversation"
+                        disabled=move || is_thinking.get() || active_thread.get().is_none()
+                        on:click=move |_| {
+                            if let Some(document_id) = current_document.0.get_untracked() {
+                                open_thread(document_id, None);
+                            }
+                        }
+                    >
+                        <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 4v16m8-8H4"></path>
+                        </svg>
+                    </button>
+                    <button
+                        class="p-1.5 rounded-lg text-gray-500 dark:text-gray-400 hover:bg-red-50 hover:text-red-600 dark:hover:bg-red-900/30 dark:hover:text-red-400 disabled:opacity-50 transition-colors"
+                        title="Delete conversation"
+                        disabled=move || is_thinking.get() || active_thread.get().is_none()
+                        on:click=move |_| delete_thread()
+                    >
+                        <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16"></path>
+                        </svg>
+                    </button>
+                </div>
+            </Show>
+
             <div class="flex-1 overflow-y-auto p-4 space-y-3">
                 <Show when=move || messages.get().is_empty()>
                     <div class="text-center py-8">
                         <svg class="w-10 h-10 mx-auto text-gray-300 dark:text-gray-600 mb-3" fill="none" stroke="currentColor" viewBox="0 0 24 24">
                             <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M8 10h.01M12 10h.01M16 10h.01M9 16H5a2 2 0 01-2-2V6a2 2 0 012-2h14a2 2 0 012 2v8a2 2 0 01-2 2h-5l-5 5v-5z"></path>
                         </svg>
-                        <p class="text-xs text-gray-400 dark:text-gray-500">"Ask the AI about your document"</p>
+                        <p class="text-xs text-gray-400 dark:text-gray-500">
+                            {move || if current_document.0.get().is_some() { "Ask the AI about your document" } else { "Open a document to ask the AI about it" }}
+                        </p>
                     </div>
                 </Show>
                 <For
-                    each=move || messages.get()
-                    key=|msg| format!("{}-{}", msg.sender, msg.text)
-                    children=move |msg| {
-                        let is_user = Memo::new(move |_| msg.sender == "User");
+                    each=move || messages.get().into_iter().enumerate()
+                    key=|(index, msg)| (*index, msg.sender.clone())
+                    children=move |(index, msg)| {
+                        // Only the answer that is written changes, the others are not rendered again
+                        let text = Memo::new(move |_| messages.with(|msgs| msgs.get(index).map(|msg| msg.text.clone()).unwrap_or_default()));
+                        let sources = Memo::new(move |_| messages.with(|msgs| msgs.get(index).map(|msg| msg.sources.clone()).unwrap_or_default()));
+                        let streaming = Memo::new(move |_| is_thinking.get() && messages.with(|msgs| index + 1 == msgs.len()));
+                        let copied = RwSignal::new(false);
+
+                        if msg.sender == "User" {
+                            return view! {
+                                <div class="text-right">
+                                    <div class="inline-block p-3 rounded-xl max-w-[85%] text-sm text-left whitespace-pre-wrap break-words bg-blue-600 text-white">
+                                        {move || text.get()}
+                                    </div>
+                                </div>
+                            }.into_any();
+                        }
+
+                        let copy = move |_| {
+                            let Some(window) = web_sys::window() else { return };
+                            let promise = window.navigator().clipboard().write_text(&text.get_untracked());
+                            spawn_local(async move {
+                                if wasm_bindgen_futures::JsFuture::from(promise).await.is_ok() {
+                                    copied.set(true);
+                                    set_timeout(move || copied.set(false), std::time::Duration::from_secs(2));
+                                }
+                            });
+                        };
+
                         view! {
-                            <div class=move || if is_user.get() { "text-right" } else { "text-left" }>
-                                <div class="inline-block p-3 rounded-xl max-w-[85%] text-sm"
-                                     class=("bg-blue-600", move || is_user.get())
-                                     class=("text-white", move || is_user.get())
-                                     class=("bg-gray-100", move || !is_user.get())
-                                     class=("dark:bg-gray-700", move || !is_user.get())
-                                     class=("text-gray-800", move || !is_user.get())
-                                     class=("dark:text-gray-200", move || !is_user.get())
-                                >
-                                    <div inner_html={msg.text}></div>
+                            <div class="text-left">
+                                <div class="inline-block p-3 rounded-xl max-w-[85%] text-sm bg-gray-100 dark:bg-gray-700 text-gray-800 dark:text-gray-200">
+                                    <div
+                                        class="chat-answer prose max-w-none dark:prose-invert break-words"
+                                        inner_html=move || {
+                                            let token = auth.state.get_untracked().token.unwrap_or_default();
+                                            render_answer(&text.get(), !streaming.get(), &token)
+                                        }
+                                    ></div>
+                                    {move || {
+                                        let sources = sources.get();
+                                        (!sources.is_empty()).then(|| view! {
+                                            <div class="mt-2 pt-2 border-t border-gray-200 dark:border-gray-600 text-xs space-y-0.5">
+                                                {sources.into_iter().map(|source| view! {
+                                                    <a
+                                                        class="block truncate text-blue-600 dark:text-blue-400 hover:underline"
+                                                        href=format!("{}/documents/{}", APP_BASE, source.document_id)
+                                                        target="_blank"
+                                                        title="Open the document in a new tab"
+                                                    >
+                                                        {format!("[{}] {}", source.number, source.title)}
+                                                    </a>
+                                                }).collect_view()}
+                                            </div>
+                                        })
+                                    }}
+                                    <Show when=move || !streaming.get() && !text.get().is_empty()>
+                                        <div class="mt-2 flex gap-3 text-[11px] text-gray-500 dark:text-gray-400">
+                                            <button class="hover:text-blue-600 dark:hover:text-blue-400" on:click=copy>
+                                                {move || if copied.get() { "Copied" } else { "Copy" }}
+                                            </button>
+                                            <button
+                                                class="hover:text-blue-600 dark:hover:text-blue-400 disabled:opacity-50"
+                                                title="Insert the answer at the cursor of the editor"
+                                                disabled=move || current_document.0.get().is_none()
+                                                on:click=move |_| editor_insert.0.set(Some(text.get_untracked()))
+                                            >
+                                                "Insert at cursor"
+                                            </button>
+                                        </div>
+                                    </Show>
                                 </div>
                             </div>
-                        }
+                        }.into_any()
                     }
                 />
                 <Show when=move || is_thinking.get()>
@@ -184,17 +426,213 @@ pub fn ChatSidebar() -> impl IntoView {
                             }
                         }
                     ></textarea>
+                    <Show
+                        when=move || is_thinking.get()
+                        fallback=move || view! {
+                            <button
+                                class="self-end p-2.5 text-white bg-blue-600 rounded-lg hover:bg-blue-700 disabled:opacity-50 transition-colors shrink-0"
+                                on:click=move |_| { do_submit(); }
+                                disabled=move || current_document.0.get().is_none()
+                                title="Send"
+                            >
+                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 19l9 2-9-18-9 18 9-2zm0 0v-8"></path>
+                                </svg>
+                            </button>
+                        }
+                    >
+                        <button
+                            class="self-end p-2.5 text-white bg-red-600 rounded-lg hover:bg-red-700 transition-colors shrink-0"
+                            on:click=move |_| stop_answer()
+                            title="Stop the answer"
+                        >
+                            <svg class="w-4 h-4" fill="currentColor" viewBox="0 0 24 24">
+                                <rect x="6" y="6" width="12" height="12" rx="1.5"></rect>
+                            </svg>
+                        </button>
+                    </Show>
+                </div>
+                <label class="mt-1.5 flex items-center gap-1.5 text-[11px] text-gray-500 dark:text-gray-400 cursor-pointer select-none">
+                    <input
+                        type="checkbox"
+                        class="rounded border-gray-300 dark:border-gray-600"
+                        prop:checked=search_documents
+                        on:change=move |ev| search_documents.set(event_target_checked(&ev))
+                    />
+                    "Search all my documents"
+                </label>
+                <div class="mt-1.5 flex items-center gap-1.5">
+                    <select
+                        class="flex-1 min-w-0 px-2 py-1 text-[11px] border border-gray-200 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-600 dark:text-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        title="How the assistant answers"
+                        on:change=move |ev| {
+                            let id = Uuid::parse_str(&event_target_value(&ev)).ok();
+                            match id {
+                                Some(id) => { let _ = LocalStorage::set(PROMPT_KEY, id); }
+                                None => LocalStorage::delete(PROMPT_KEY),
+                            }
+                            selected_prompt.set(id);
+                        }
+                    >
+                        <option value="" selected=move || selected_prompt.get().is_none()>"Default assistant"</option>
+                        <For
+                            each=move || prompts.get()
+                            key=|prompt| (prompt.id, prompt.name.clone())
+                            children=move |prompt| {
+                                let id = prompt.id;
+                                let label = if prompt.shared { prompt.name } else { format!("{} (mine)", prompt.name) };
+                                view! {
+                                    <option value=id.to_string() selected=move || selected_prompt.get() == Some(id)>{label}</option>
+                                }
+                            }
+                        />
+                    </select>
                     <button
-                        class="self-end p-2.5 text-white bg-blue-600 rounded-lg hover:bg-blue-700 disabled:opacity-50 transition-colors shrink-0"
-                        on:click=move |_| { do_submit(); }
-                        disabled=move || is_thinking.get()
+                        class="p-1 rounded-lg text-gray-500 dark:text-gray-400 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                        title="Manage prompts"
+                        on:click=move |_| show_prompt_manager.set(true)
                     >
-                        <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 19l9 2-9-18-9 18 9-2zm0 0v-8"></path>
+                        <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M11 5H6a2 2 0 00-2 2v11a2 2 0 002 2h11a2 2 0 002-2v-5m-1.414-9.414a2 2 0 112.828 2.828L11.828 15H9v-2.828l8.586-8.586z"></path>
                         </svg>
                     </button>
                 </div>
+                {move || quota.get().and_then(|quota| {
+                    let used_up = quota.requests_remaining == Some(0) || quota.tokens_remaining == Some(0);
+                    quota_summary(&quota).map(|summary| view! {
+                        <p
+                            class="mt-1.5 text-[11px] text-gray-400 dark:text-gray-500"
+                            class=("text-red-500", used_up)
+                            class=("dark:text-red-400", used_up)
+                        >
+                            {summary}
+                        </p>
+                    })
+                })}
             </div>
         </aside>
+
+        <Show when=move || show_prompt_manager.get()>
+            <PromptManager
+                prompts=prompts.into()
+                is_admin=is_admin.into()
+                on_change=refresh_prompts
+                on_close=move || show_prompt_manager.set(false)
+            />
+        </Show>
+    }
+}
+
+/// Shows the streamed answer in the last message as it arrives, or why
+/// there is none. A stopped answer keeps what was written until then.
+async fn read_answer(
+    res: Result<gloo_net::http::Response, gloo_net::Error>,
+    messages: RwSignal<Vec<ChatMessage>>,
+    quota: RwSignal<Option<LlmQuota>>,
+    abort: &AbortSignal,
+) {
+    let set_last_message = move |text: String| {
+        messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                last_msg.text = text;
+            }
+        });
+    };
+
+    let stopped = move || {
+        messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                if last_msg.text.is_empty() {
+                    last_msg.text = "Stopped.".to_string();
+                }
+            }
+        });
+    };
+
+    let res = match res {
+        Ok(res) => res,
+        Err(_) if abort.aborted() => {
+            stopped();
+            return;
+        }
+        Err(_) => {
+            set_last_message("Error: Could not connect to the server.".to_string());
+            return;
+        }
+    };
+
+    if !res.ok() {
+        let text = if res.status() == 429 {
+            match res.json::<LlmQuotaExceeded>().await {
+                Ok(exceeded) => {
+                    quota.set(Some(exceeded.quota));
+                    format!("{}. Try again in {}.", exceeded.error, format_wait(exceeded.retry_after))
+                }
+                Err(_) => "Error: The AI quota is used up.".to_string(),
+            }
+        } else {
+            format!("Error: The server answered with status {}.", res.status())
+        };
+        set_last_message(text);
+        return;
+    }
+
+    let result = read_chat_events(res, |event| match event {
+        ChatEvent::Sources { sources } => messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                last_msg.sources = sources;
+            }
+        }),
+        ChatEvent::Delta { text } => messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                last_msg.text.push_str(&text);
+            }
+        }),
+        ChatEvent::Done { .. } => {}
+        ChatEvent::Error { message } => messages.update(|msgs| {
+            if let Some(last_msg) = msgs.last_mut() {
+                if !last_msg.text.is_empty() {
+                    last_msg.text.push_str("\n\n");
+                }
+                last_msg.text.push_str(&format!("Error: {}", message));
+            }
+        }),
+    })
+    .await;
+
+    if let Err(e) = result {
+        if abort.aborted() {
+            stopped();
+        } else {
+            set_last_message(format!("Error: {}", e));
+        }
+    }
+}
+
+// E.g. "12 of 50 requests · 80k tokens left", or nothing when unlimited
+fn quota_summary(quota: &LlmQuota) -> Option<String> {
+    let mut parts = Vec::new();
+    if let (Some(remaining), Some(limit)) = (quota.requests_remaining, quota.request_limit) {
+        parts.push(format!("{} of {} requests", remaining, limit));
+    }
+    if let Some(remaining) = quota.tokens_remaining {
+        parts.push(format!("{} tokens", format_count(remaining)));
+    }
+    (!parts.is_empty()).then(|| format!("{} left", parts.join(" · ")))
+}
+
+fn format_count(count: i64) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{:.1}M", count as f64 / 1_000_000.0),
+    }
+}
+
+fn format_wait(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{} seconds", seconds),
+        60..3600 => format!("{} minutes", seconds.div_ceil(60)),
+        _ => format!("{} hours", seconds.div_ceil(3600)),
     }
 }
@@ -0,0 +1,77 @@
+use leptos::prelude::*;
+
+use crate::components::DiffView;
+use crate::models::Document;
+
+#[component]
+pub fn ConflictDialog(
+    server: Document,
+    mine: Signal<String>,
+    on_keep_mine: impl Fn() + 'static + Clone + Send + Sync,
+    on_take_theirs: impl Fn() + 'static + Clone + Send + Sync,
+    on_merge: impl Fn() + 'static + Clone + Send + Sync,
+    on_cancel: impl Fn() + 'static + Clone + Send + Sync,
+) -> impl IntoView {
+    let server_content = Signal::derive({
+        let content = server.content.clone();
+        move || content.clone()
+    });
+
+    view! {
+        <div class="fixed inset-0 bg-black/50 backdrop-blur-sm z-50 flex items-center justify-center p-4">
+            <div class="bg-white dark:bg-gray-800 rounded-xl shadow-xl max-w-3xl w-full max-h-[85vh] flex flex-col p-6">
+                <div class="flex items-center gap-3 mb-4">
+                    <div class="p-2 bg-amber-100 dark:bg-amber-900/30 rounded-lg">
+                        <svg class="w-5 h-5 text-amber-600 dark:text-amber-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 9v2m0 4h.01m-6.938 4h13.856c1.54 0 2.502-1.667 1.732-2.5L13.732 4c-.77-.833-1.964-.833-2.732 0L3.34 16.5c-.77.833.192 2.5 1.732 2.5z"></path>
+                        </svg>
+                    </div>
+                    <h3 class="text-lg font-semibold text-gray-900 dark:text-gray-50">"Document Changed"</h3>
+                </div>
+                <p class="text-sm text-gray-600 dark:text-gray-400 mb-1">
+                    "This document was saved from somewhere else while you were editing it."
+                </p>
+                <p class="text-xs text-gray-500 dark:text-gray-400 mb-3">
+                    {format!("Server version from {}", server.updated_at.format("%b %d, %Y %H:%M:%S"))}
+                    " — lines marked "
+                    <span class="text-red-600 dark:text-red-400 font-mono">"-"</span>
+                    " are only on the server, lines marked "
+                    <span class="text-green-600 dark:text-green-400 font-mono">"+"</span>
+                    " are only in your copy."
+                </p>
+                <div class="flex-1 min-h-0 overflow-auto mb-4">
+                    <DiffView old=server_content new=mine />
+                </div>
+                <div class="flex gap-2 flex-wrap">
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                        on:click=move |_| on_cancel()
+                    >
+                        "Cancel"
+                    </button>
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                        on:click=move |_| on_take_theirs()
+                        title="Discard your changes and load the server version"
+                    >
+                        "Use Server Version"
+                    </button>
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-blue-700 dark:text-blue-300 bg-blue-50 dark:bg-blue-900/30 rounded-lg hover:bg-blue-100 dark:hover:bg-blue-900/50 transition-colors"
+                        on:click=move |_| on_merge()
+                        title="Combine both versions with conflict markers and keep editing"
+                    >
+                        "Merge"
+                    </button>
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-white bg-amber-600 rounded-lg hover:bg-amber-700 transition-colors"
+                        on:click=move |_| on_keep_mine()
+                        title="Overwrite the server version with your copy"
+                    >
+                        "Keep Mine"
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,139 @@
+use leptos::prelude::*;
+use similar::{ChangeTag, DiffTag, TextDiff};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffLine {
+    pub tag: ChangeTag,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// Computes a line-by-line diff between two texts.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: change.tag(),
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
+
+/// Merges two versions of a text, keeping common lines once and wrapping every
+/// differing region in git-style conflict markers.
+pub fn merge_with_markers(mine: &str, theirs: &str) -> String {
+    let diff = TextDiff::from_lines(theirs, mine);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+
+    let push_lines = |out: &mut String, lines: &[&str]| {
+        for line in lines {
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    };
+
+    let mut merged = String::new();
+    for op in diff.ops() {
+        if op.tag() == DiffTag::Equal {
+            push_lines(&mut merged, &old_lines[op.old_range()]);
+        } else {
+            merged.push_str("<<<<<<< Your changes\n");
+            push_lines(&mut merged, &new_lines[op.new_range()]);
+            merged.push_str("=======\n");
+            push_lines(&mut merged, &old_lines[op.old_range()]);
+            merged.push_str(">>>>>>> Server version\n");
+        }
+    }
+    merged
+}
+
+/// A run of lines that both texts share, or lines that differ between them.
+/// Lines keep their line breaks, so joining the parts gives back the texts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hunk {
+    Same(String),
+    Changed { old: String, new: String },
+}
+
+/// Splits two texts into shared and changed lines, with neighbouring
+/// changes combined into one hunk.
+pub fn diff_hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+
+    let mut hunks = Vec::new();
+    for op in diff.ops() {
+        let old_text = old_lines[op.old_range()].concat();
+        let new_text = new_lines[op.new_range()].concat();
+        match (op.tag(), hunks.last_mut()) {
+            (DiffTag::Equal, _) => hunks.push(Hunk::Same(old_text)),
+            (_, Some(Hunk::Changed { old, new })) => {
+                old.push_str(&old_text);
+                new.push_str(&new_text);
+            }
+            _ => hunks.push(Hunk::Changed { old: old_text, new: new_text }),
+        }
+    }
+    hunks
+}
+
+/// Puts a text back together from hunks, taking the new lines of the hunks
+/// that are `accepted` (by index) and the old lines of all others.
+pub fn apply_hunks(hunks: &[Hunk], accepted: impl Fn(usize) -> bool) -> String {
+    hunks
+        .iter()
+        .enumerate()
+        .map(|(index, hunk)| match hunk {
+            Hunk::Same(text) => text.as_str(),
+            Hunk::Changed { new, .. } if accepted(index) => new.as_str(),
+            Hunk::Changed { old, .. } => old.as_str(),
+        })
+        .collect()
+}
+
+#[component]
+pub fn DiffView(
+    old: Signal<String>,
+    new: Signal<String>,
+) -> impl IntoView {
+    let lines = Memo::new(move |_| line_diff(&old.get(), &new.get()));
+    let unchanged = move || lines.get().iter().all(|line| line.tag == ChangeTag::Equal);
+
+    view! {
+        <div class="font-mono text-xs border border-gray-200 dark:border-gray-700 rounded-lg overflow-auto">
+            <Show
+                when=move || !unchanged()
+                fallback=|| view! {
+                    <p class="p-4 text-center text-gray-500 dark:text-gray-400 font-sans">"No differences"</p>
+                }
+            >
+                {move || lines.get().into_iter().map(|line| {
+                    let (row_class, marker) = match line.tag {
+                        ChangeTag::Delete => ("bg-red-50 dark:bg-red-900/20 text-red-800 dark:text-red-300", "-"),
+                        ChangeTag::Insert => ("bg-green-50 dark:bg-green-900/20 text-green-800 dark:text-green-300", "+"),
+                        ChangeTag::Equal => ("text-gray-600 dark:text-gray-400", " "),
+                    };
+                    view! {
+                        <div class=format!("flex {}", row_class)>
+                            <span class="w-10 shrink-0 px-1 text-right text-gray-400 dark:text-gray-500 select-none">
+                                {line.old_line.map(|n| n.to_string()).unwrap_or_default()}
+                            </span>
+                            <span class="w-10 shrink-0 px-1 text-right text-gray-400 dark:text-gray-500 select-none">
+                                {line.new_line.map(|n| n.to_string()).unwrap_or_default()}
+                            </span>
+                            <span class="w-4 shrink-0 text-center select-none">{marker}</span>
+                            <span class="whitespace-pre-wrap break-all pr-2">{line.text}</span>
+                        </div>
+                    }
+                }).collect_view()}
+            </Show>
+        </div>
+    }
+}
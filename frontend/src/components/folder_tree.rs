@@ -0,0 +1,527 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use leptos::prelude::*;
+use uuid::Uuid;
+
+use crate::models::{Document, DocumentSummary, Folder};
+
+const DRAG_DOCUMENT: &str = "document:";
+const DRAG_FOLDER: &str = "folder:";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DragItem {
+    Document(Uuid),
+    Folder(Uuid),
+}
+
+impl DragItem {
+    fn encode(self) -> String {
+        match self {
+            DragItem::Document(id) => format!("{}{}", DRAG_DOCUMENT, id),
+            DragItem::Folder(id) => format!("{}{}", DRAG_FOLDER, id),
+        }
+    }
+
+    fn decode(data: &str) -> Option<Self> {
+        if let Some(id) = data.strip_prefix(DRAG_DOCUMENT) {
+            Uuid::parse_str(id).ok().map(DragItem::Document)
+        } else if let Some(id) = data.strip_prefix(DRAG_FOLDER) {
+            Uuid::parse_str(id).ok().map(DragItem::Folder)
+        } else {
+            None
+        }
+    }
+}
+
+fn start_drag(ev: &web_sys::DragEvent, item: DragItem) {
+    if let Some(data) = ev.data_transfer() {
+        let _ = data.set_data("text/plain", &item.encode());
+        data.set_effect_allowed("move");
+    }
+}
+
+fn dropped_item(ev: &web_sys::DragEvent) -> Option<DragItem> {
+    ev.data_transfer()
+        .and_then(|data| data.get_data("text/plain").ok())
+        .and_then(|data| DragItem::decode(&data))
+}
+
+// Everything the recursive tree rendering needs, shared by all levels
+#[derive(Clone)]
+struct TreeContext {
+    documents: Signal<Vec<DocumentSummary>>,
+    folders: Signal<Vec<Folder>>,
+    selected_document: Signal<Option<Document>>,
+    new_folder_parent: RwSignal<Option<Option<Uuid>>>,
+    expanded: RwSignal<HashSet<Uuid>>,
+    renaming: RwSignal<Option<Uuid>>,
+    // `Some(None)` highlights the top level
+    drop_target: RwSignal<Option<Option<Uuid>>>,
+    selected_tag: RwSignal<Option<String>>,
+    on_select: Arc<dyn Fn(Uuid) + Send + Sync>,
+    on_move_document: Arc<dyn Fn(Uuid, Option<Uuid>) + Send + Sync>,
+    on_move_folder: Arc<dyn Fn(Uuid, Option<Uuid>) + Send + Sync>,
+    on_create_folder: Arc<dyn Fn(String, Option<Uuid>) + Send + Sync>,
+    on_rename_folder: Arc<dyn Fn(Uuid, String) + Send + Sync>,
+    on_delete_folder: Arc<dyn Fn(Uuid) + Send + Sync>,
+}
+
+impl TreeContext {
+    fn drop_on(&self, item: DragItem, target: Option<Uuid>) {
+        self.drop_target.set(None);
+        match item {
+            DragItem::Document(id) => {
+                let current = self.documents.with_untracked(|docs| {
+                    docs.iter().find(|doc| doc.id == id).map(|doc| doc.folder_id)
+                });
+                if current != Some(target) {
+                    (self.on_move_document)(id, target);
+                }
+            }
+            DragItem::Folder(id) => {
+                let current = self.folders.with_untracked(|folders| {
+                    folders.iter().find(|folder| folder.id == id).map(|folder| folder.parent_id)
+                });
+                if target != Some(id) && current != Some(target) {
+                    (self.on_move_folder)(id, target);
+                }
+            }
+        }
+        if let Some(folder_id) = target {
+            self.expanded.update(|expanded| {
+                expanded.insert(folder_id);
+            });
+        }
+    }
+}
+
+fn indent(depth: usize) -> String {
+    format!("padding-left: {}rem", 0.75 + depth as f32 * 1.0)
+}
+
+/// Collapsible folder tree of the user's documents. Documents and folders can
+/// be dragged onto a folder, or onto empty space to move them to the top level.
+/// While a tag is selected, the tree is replaced by a flat list of the
+/// documents carrying it.
+#[component]
+pub fn FolderTree(
+    documents: Signal<Vec<DocumentSummary>>,
+    folders: Signal<Vec<Folder>>,
+    selected_document: Signal<Option<Document>>,
+    /// Set to `Some(parent)` to show the input for a new folder under `parent`.
+    new_folder_parent: RwSignal<Option<Option<Uuid>>>,
+    selected_tag: RwSignal<Option<String>>,
+    on_select: impl Fn(Uuid) + Send + Sync + 'static,
+    on_move_document: impl Fn(Uuid, Option<Uuid>) + Send + Sync + 'static,
+    on_move_folder: impl Fn(Uuid, Option<Uuid>) + Send + Sync + 'static,
+    on_create_folder: impl Fn(String, Option<Uuid>) + Send + Sync + 'static,
+    on_rename_folder: impl Fn(Uuid, String) + Send + Sync + 'static,
+    on_delete_folder: impl Fn(Uuid) + Send + Sync + 'static,
+) -> impl IntoView {
+    let ctx = TreeContext {
+        documents,
+        folders,
+        selected_document,
+        new_folder_parent,
+        expanded: RwSignal::new(HashSet::new()),
+        renaming: RwSignal::new(None),
+        drop_target: RwSignal::new(None),
+        selected_tag,
+        on_select: Arc::new(on_select),
+        on_move_document: Arc::new(on_move_document),
+        on_move_folder: Arc::new(on_move_folder),
+        on_create_folder: Arc::new(on_create_folder),
+        on_rename_folder: Arc::new(on_rename_folder),
+        on_delete_folder: Arc::new(on_delete_folder),
+    };
+
+    // Keep the folder of the open document expanded
+    let expanded = ctx.expanded;
+    Effect::new(move |_| {
+        let Some(folder_id) = selected_document.with(|doc| doc.as_ref().and_then(|doc| doc.folder_id)) else {
+            return;
+        };
+        let ancestors = folders.with_untracked(|folders| {
+            let mut ancestors = Vec::new();
+            let mut current = Some(folder_id);
+            while let Some(id) = current {
+                if ancestors.contains(&id) {
+                    break;
+                }
+                ancestors.push(id);
+                current = folders.iter().find(|folder| folder.id == id).and_then(|folder| folder.parent_id);
+            }
+            ancestors
+        });
+        expanded.update(|expanded| expanded.extend(ancestors));
+    });
+
+    let drop_target = ctx.drop_target;
+    let ctx_drop = ctx.clone();
+    let ctx_tagged = ctx.clone();
+
+    let tagged_documents = move || {
+        let tag = selected_tag.get().unwrap_or_default();
+        documents.with(|docs| {
+            docs.iter()
+                .filter(|doc| doc.tags.contains(&tag))
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    view! {
+        <Show when=move || selected_tag.get().is_some()>
+            <div class="p-3">
+                <div class="flex items-center justify-between px-3 mb-2">
+                    <span class="text-xs font-medium text-gray-500 dark:text-gray-400">
+                        "Tagged " <span class="text-blue-600 dark:text-blue-400">"#" {move || selected_tag.get().unwrap_or_default()}</span>
+                    </span>
+                    <button
+                        class="text-xs text-gray-400 hover:text-gray-600 dark:hover:text-gray-300"
+                        on:click=move |_| selected_tag.set(None)
+                    >
+                        "Clear"
+                    </button>
+                </div>
+                <For
+                    each=tagged_documents
+                    key=|doc| (doc.id, doc.title.clone(), doc.updated_at, doc.tags.clone())
+                    children={
+                        let ctx_tagged = ctx_tagged.clone();
+                        move |doc| document_row(ctx_tagged.clone(), doc, 0)
+                    }
+                />
+            </div>
+        </Show>
+        <div
+            class="min-h-full p-3 rounded-lg transition-colors"
+            class=("hidden", move || selected_tag.get().is_some())
+            class=("bg-blue-50/50", move || drop_target.get() == Some(None))
+            class=("dark:bg-blue-900/10", move || drop_target.get() == Some(None))
+            on:dragover=move |ev: web_sys::DragEvent| {
+                ev.prevent_default();
+                drop_target.set(Some(None));
+            }
+            on:dragleave=move |_| drop_target.set(None)
+            on:drop=move |ev: web_sys::DragEvent| {
+                ev.prevent_default();
+                if let Some(item) = dropped_item(&ev) {
+                    ctx_drop.drop_on(item, None);
+                }
+            }
+        >
+            {tree_level(ctx, None, 0)}
+        </div>
+    }
+}
+
+fn tree_level(ctx: TreeContext, parent: Option<Uuid>, depth: usize) -> AnyView {
+    let folders = ctx.folders;
+    let documents = ctx.documents;
+    let new_folder_parent = ctx.new_folder_parent;
+
+    let child_folders = move || {
+        folders.with(|folders| {
+            folders
+                .iter()
+                .filter(|folder| folder.parent_id == parent)
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    // Documents in folders we do not know about are shown at the top level
+    let child_documents = move || {
+        let known: HashSet<Uuid> = folders.with(|folders| folders.iter().map(|folder| folder.id).collect());
+        documents.with(|docs| {
+            docs.iter()
+                .filter(|doc| match doc.folder_id {
+                    Some(folder_id) if known.contains(&folder_id) => parent == Some(folder_id),
+                    _ => parent.is_none(),
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let ctx_new = ctx.clone();
+    let ctx_folders = ctx.clone();
+    let ctx_documents = ctx;
+
+    view! {
+        {move || (new_folder_parent.get() == Some(parent)).then(|| new_folder_input(ctx_new.clone(), parent, depth))}
+        <For
+            each=child_folders
+            key=|folder| (folder.id, folder.name.clone())
+            children=move |folder| folder_row(ctx_folders.clone(), folder, depth)
+        />
+        <For
+            each=child_documents
+            key=|doc| (doc.id, doc.title.clone(), doc.updated_at, doc.tags.clone())
+            children=move |doc| document_row(ctx_documents.clone(), doc, depth)
+        />
+    }
+    .into_any()
+}
+
+fn new_folder_input(ctx: TreeContext, parent: Option<Uuid>, depth: usize) -> impl IntoView {
+    let (name, set_name) = signal(String::new());
+    let new_folder_parent = ctx.new_folder_parent;
+    let submit = move || {
+        // Enter and the blur that follows must not create the folder twice
+        if new_folder_parent.get_untracked() != Some(parent) {
+            return;
+        }
+        let name = name.get_untracked().trim().to_string();
+        if !name.is_empty() {
+            (ctx.on_create_folder)(name, parent);
+        }
+        new_folder_parent.set(None);
+    };
+    let submit_on_key = submit.clone();
+    let input_ref = NodeRef::<leptos::html::Input>::new();
+    Effect::new(move |_| {
+        if let Some(input) = input_ref.get() {
+            let _ = input.focus();
+        }
+    });
+
+    view! {
+        <div class="py-1 pr-1" style=indent(depth)>
+            <input
+                node_ref=input_ref
+                type="text"
+                placeholder="Folder name"
+                class="w-full px-2 py-1 text-sm border border-gray-300 dark:border-gray-600 rounded-md bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                prop:value=name
+                on:input=move |ev| set_name.set(event_target_value(&ev))
+                on:keydown=move |ev| {
+                    match ev.key().as_str() {
+                        "Enter" => submit_on_key(),
+                        "Escape" => new_folder_parent.set(None),
+                        _ => {}
+                    }
+                }
+                on:blur=move |_| submit()
+            />
+        </div>
+    }
+}
+
+fn folder_row(ctx: TreeContext, folder: Folder, depth: usize) -> impl IntoView {
+    let folder_id = folder.id;
+    let expanded = ctx.expanded;
+    let renaming = ctx.renaming;
+    let drop_target = ctx.drop_target;
+    let new_folder_parent = ctx.new_folder_parent;
+    let is_expanded = move || expanded.with(|expanded| expanded.contains(&folder_id));
+    let is_drop_target = move || drop_target.get() == Some(Some(folder_id));
+
+    let toggle = move || {
+        expanded.update(|expanded| {
+            if !expanded.remove(&folder_id) {
+                expanded.insert(folder_id);
+            }
+        })
+    };
+
+    let ctx_drop = ctx.clone();
+    let ctx_rename = ctx.clone();
+    let ctx_delete = ctx.clone();
+    let ctx_children = ctx;
+    let name = folder.name.clone();
+
+    view! {
+        <div>
+            <div
+                class="group flex items-center gap-1 pr-1 py-1.5 rounded-lg mb-0.5 cursor-pointer border transition-all"
+                class=("border-blue-300", is_drop_target)
+                class=("dark:border-blue-600", is_drop_target)
+                class=("bg-blue-50", is_drop_target)
+                class=("dark:bg-blue-900/30", is_drop_target)
+                class=("border-transparent", move || !is_drop_target())
+                class=("hover:bg-gray-50", move || !is_drop_target())
+                class=("dark:hover:bg-gray-700/50", move || !is_drop_target())
+                style=indent(depth)
+                draggable="true"
+                on:dragstart=move |ev: web_sys::DragEvent| {
+                    ev.stop_propagation();
+                    start_drag(&ev, DragItem::Folder(folder_id));
+                }
+                on:dragover=move |ev: web_sys::DragEvent| {
+                    ev.prevent_default();
+                    ev.stop_propagation();
+                    drop_target.set(Some(Some(folder_id)));
+                }
+                on:drop=move |ev: web_sys::DragEvent| {
+                    ev.prevent_default();
+                    ev.stop_propagation();
+                    if let Some(item) = dropped_item(&ev) {
+                        ctx_drop.drop_on(item, Some(folder_id));
+                    }
+                }
+                on:click=move |_| toggle()
+            >
+                <svg
+                    class="w-3.5 h-3.5 shrink-0 text-gray-400 transition-transform"
+                    class=("rotate-90", is_expanded)
+                    fill="none" stroke="currentColor" viewBox="0 0 24 24"
+                >
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 5l7 7-7 7"></path>
+                </svg>
+                <svg class="w-4 h-4 shrink-0 text-amber-500 dark:text-amber-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M3 7v10a2 2 0 002 2h14a2 2 0 002-2V9a2 2 0 00-2-2h-6l-2-2H5a2 2 0 00-2 2z"></path>
+                </svg>
+                <Show
+                    when=move || renaming.get() == Some(folder_id)
+                    fallback={
+                        let name = name.clone();
+                        move || view! {
+                            <span class="flex-1 min-w-0 truncate text-sm font-medium text-gray-800 dark:text-gray-200">{name.clone()}</span>
+                        }
+                    }
+                >
+                    {
+                        let ctx_rename = ctx_rename.clone();
+                        let original = name.clone();
+                        let submit = move |value: String| {
+                            if renaming.get_untracked() != Some(folder_id) {
+                                return;
+                            }
+                            let value = value.trim().to_string();
+                            renaming.set(None);
+                            if !value.is_empty() && value != original {
+                                (ctx_rename.on_rename_folder)(folder_id, value);
+                            }
+                        };
+                        let submit_on_key = submit.clone();
+                        let input_ref = NodeRef::<leptos::html::Input>::new();
+                        Effect::new(move |_| {
+                            if let Some(input) = input_ref.get() {
+                                let _ = input.focus();
+                                input.select();
+                            }
+                        });
+                        view! {
+                            <input
+                                node_ref=input_ref
+                                type="text"
+                                class="flex-1 min-w-0 px-1.5 py-0.5 text-sm border border-gray-300 dark:border-gray-600 rounded bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                                value=name.clone()
+                                on:click=|ev| ev.stop_propagation()
+                                on:keydown=move |ev| {
+                                    match ev.key().as_str() {
+                                        "Enter" => submit_on_key(event_target_value(&ev)),
+                                        "Escape" => renaming.set(None),
+                                        _ => {}
+                                    }
+                                }
+                                on:blur=move |ev| submit(event_target_value(&ev))
+                            />
+                        }
+                    }
+                </Show>
+                <div class="hidden group-hover:flex items-center shrink-0">
+                    <button
+                        class="p-1 rounded text-gray-400 hover:text-gray-600 dark:hover:text-gray-300"
+                        title="New subfolder"
+                        on:click=move |ev| {
+                            ev.stop_propagation();
+                            expanded.update(|expanded| {
+                                expanded.insert(folder_id);
+                            });
+                            new_folder_parent.set(Some(Some(folder_id)));
+                        }
+                    >
+                        <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 4v16m8-8H4"></path>
+                        </svg>
+                    </button>
+                    <button
+                        class="p-1 rounded text-gray-400 hover:text-gray-600 dark:hover:text-gray-300"
+                        title="Rename folder"
+                        on:click=move |ev| {
+                            ev.stop_propagation();
+                            renaming.set(Some(folder_id));
+                        }
+                    >
+                        <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M11 5H6a2 2 0 00-2 2v11a2 2 0 002 2h11a2 2 0 002-2v-5m-1.414-9.414a2 2 0 112.828 2.828L11.828 15H9v-2.828l8.586-8.586z"></path>
+                        </svg>
+                    </button>
+                    <button
+                        class="p-1 rounded text-gray-400 hover:text-red-500 dark:hover:text-red-400"
+                        title="Delete folder (its contents move up)"
+                        on:click=move |ev| {
+                            ev.stop_propagation();
+                            (ctx_delete.on_delete_folder)(folder_id);
+                        }
+                    >
+                        <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16"></path>
+                        </svg>
+                    </button>
+                </div>
+            </div>
+            {move || is_expanded().then(|| tree_level(ctx_children.clone(), Some(folder_id), depth + 1))}
+        </div>
+    }
+}
+
+fn document_row(ctx: TreeContext, doc: DocumentSummary, depth: usize) -> impl IntoView {
+    let doc_id = doc.id;
+    let selected_document = ctx.selected_document;
+    let selected_tag = ctx.selected_tag;
+    let is_selected = move || {
+        selected_document.get()
+            .map(|selected| selected.id == doc_id)
+            .unwrap_or(false)
+    };
+
+    view! {
+        <button
+            class=move || format!(
+                "w-full text-left pr-3 py-2.5 rounded-lg mb-1 transition-all {}",
+                if is_selected() {
+                    "bg-blue-50 dark:bg-blue-900/30 border border-blue-200 dark:border-blue-700"
+                } else {
+                    "hover:bg-gray-50 dark:hover:bg-gray-700/50 border border-transparent"
+                }
+            )
+            style=indent(depth)
+            draggable="true"
+            on:dragstart=move |ev: web_sys::DragEvent| start_drag(&ev, DragItem::Document(doc_id))
+            on:click=move |_| (ctx.on_select)(doc_id)
+        >
+            <div class="font-medium text-gray-900 dark:text-gray-100 text-sm truncate">
+                {doc.title.clone()}
+            </div>
+            <div class="text-xs text-gray-400 dark:text-gray-500 mt-0.5">
+                {doc.updated_at.format("%b %d, %Y").to_string()}
+            </div>
+            <Show when={
+                let has_tags = !doc.tags.is_empty();
+                move || has_tags
+            }>
+                <div class="flex flex-wrap gap-1 mt-1.5">
+                    {doc.tags.iter().cloned().map(|tag| {
+                        let tag_for_click = tag.clone();
+                        view! {
+                            <span
+                                class="px-1.5 py-0.5 text-[10px] leading-none rounded-full bg-gray-100 dark:bg-gray-700 text-gray-600 dark:text-gray-300 hover:bg-blue-100 dark:hover:bg-blue-900/40 hover:text-blue-700 dark:hover:text-blue-300"
+                                on:click=move |ev| {
+                                    ev.stop_propagation();
+                                    selected_tag.set(Some(tag_for_click.clone()));
+                                }
+                            >
+                                "#" {tag}
+                            </span>
+                        }
+                    }).collect_view()}
+                </div>
+            </Show>
+        </button>
+    }
+}
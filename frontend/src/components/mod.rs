@@ -1,5 +1,19 @@
 pub mod sidebar;
+pub mod ai_actions;
+pub mod ai_edit;
 pub mod chat_sidebar;
+pub mod conflict_dialog;
+pub mod diff_view;
+pub mod folder_tree;
+pub mod prompt_manager;
+pub mod revision_history;
 
 pub use sidebar::DocumentSidebar;
-pub use chat_sidebar::ChatSidebar;
\ No newline at end of file
+pub use chat_sidebar::ChatSidebar;
+pub use conflict_dialog::ConflictDialog;
+pub use ai_actions::AiActionMenu;
+pub use ai_edit::{EditPrompt, SuggestionReview};
+pub use diff_view::{DiffView, Hunk, apply_hunks, diff_hunks, merge_with_markers};
+pub use folder_tree::FolderTree;
+pub use prompt_manager::PromptManager;
+pub use revision_history::RevisionHistory;
\ No newline at end of file
@@ -0,0 +1,240 @@
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use uuid::Uuid;
+
+use crate::api::ApiClient;
+use crate::auth::use_auth;
+use crate::models::{CreatePromptTemplateRequest, PromptTemplate, UpdatePromptTemplateRequest};
+
+/// Lists the prompts the AI assistant can answer with and edits the user's
+/// own ones, and the shared ones for admins. `on_change` is called after
+/// every change so the list can be loaded again.
+#[component]
+pub fn PromptManager(
+    prompts: Signal<Vec<PromptTemplate>>,
+    is_admin: Signal<bool>,
+    on_change: impl Fn() + 'static + Clone + Send + Sync,
+    on_close: impl Fn() + 'static + Clone + Send + Sync,
+) -> impl IntoView {
+    let auth = use_auth();
+    let on_change = StoredValue::new(on_change);
+
+    // None while a new prompt is written
+    let editing = RwSignal::new(None::<Uuid>);
+    let name = RwSignal::new(String::new());
+    let template = RwSignal::new(String::new());
+    let shared = RwSignal::new(false);
+    let is_default = RwSignal::new(false);
+    let saving = RwSignal::new(false);
+    let error = RwSignal::new(None::<String>);
+
+    let reset = move || {
+        editing.set(None);
+        name.set(String::new());
+        template.set(String::new());
+        shared.set(false);
+        is_default.set(false);
+        error.set(None);
+    };
+
+    let edit = move |prompt: PromptTemplate| {
+        editing.set(Some(prompt.id));
+        name.set(prompt.name);
+        template.set(prompt.template);
+        shared.set(prompt.shared);
+        is_default.set(prompt.is_default);
+        error.set(None);
+    };
+
+    let save = move || {
+        let Some(token) = auth.state.get_untracked().token else { return };
+        if saving.get_untracked() {
+            return;
+        }
+        saving.set(true);
+        spawn_local(async move {
+            let client = ApiClient::with_token(token);
+            let result = match editing.get_untracked() {
+                Some(id) => {
+                    let request = UpdatePromptTemplateRequest {
+                        name: Some(name.get_untracked()),
+                        template: Some(template.get_untracked()),
+                        is_default: shared.get_untracked().then(|| is_default.get_untracked()),
+                    };
+                    client.update_prompt(id, &request).await
+                }
+                None => {
+                    let request = CreatePromptTemplateRequest {
+                        name: name.get_untracked(),
+                        template: template.get_untracked(),
+                        shared: shared.get_untracked(),
+                        is_default: is_default.get_untracked(),
+                    };
+                    client.create_prompt(&request).await
+                }
+            };
+            saving.set(false);
+            match result {
+                Ok(_) => {
+                    reset();
+                    on_change.with_value(|on_change| on_change());
+                }
+                Err(err) => error.set(Some(err.error)),
+            }
+        });
+    };
+
+    let delete = move |id: Uuid| {
+        let Some(token) = auth.state.get_untracked().token else { return };
+        spawn_local(async move {
+            match ApiClient::with_token(token).delete_prompt(id).await {
+                Ok(()) => {
+                    if editing.get_untracked() == Some(id) {
+                        reset();
+                    }
+                    on_change.with_value(|on_change| on_change());
+                }
+                Err(err) => error.set(Some(err.error)),
+            }
+        });
+    };
+
+    view! {
+        <div class="fixed inset-0 bg-black/50 backdrop-blur-sm z-50 flex items-center justify-center p-4">
+            <div class="bg-white dark:bg-gray-800 rounded-xl shadow-xl max-w-lg w-full max-h-[90vh] flex flex-col">
+                <div class="px-6 pt-6 pb-3 flex items-center justify-between">
+                    <h3 class="text-lg font-semibold text-gray-900 dark:text-gray-50">"Assistant Prompts"</h3>
+                    <button
+                        class="p-1.5 rounded-lg hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                        on:click=move |_| on_close()
+                        title="Close"
+                    >
+                        <svg class="w-5 h-5 text-gray-500 dark:text-gray-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                        </svg>
+                    </button>
+                </div>
+
+                <div class="px-6 overflow-y-auto">
+                    <Show
+                        when=move || !prompts.get().is_empty()
+                        fallback=|| view! {
+                            <p class="text-xs text-gray-400 dark:text-gray-500">"No prompts yet, the assistant uses its built-in one."</p>
+                        }
+                    >
+                        <ul class="divide-y divide-gray-100 dark:divide-gray-700 border border-gray-200 dark:border-gray-700 rounded-lg">
+                            <For
+                                each=move || prompts.get()
+                                key=|prompt| (prompt.id, prompt.updated_at, prompt.is_default)
+                                children=move |prompt| {
+                                    let id = prompt.id;
+                                    let editable = prompt.editable;
+                                    let label = prompt.name.clone();
+                                    let badge = if prompt.is_default {
+                                        Some("Default")
+                                    } else if prompt.shared {
+                                        Some("Shared")
+                                    } else {
+                                        None
+                                    };
+                                    view! {
+                                        <li class="px-3 py-2 flex items-center gap-2 text-sm">
+                                            <span class="flex-1 truncate text-gray-800 dark:text-gray-200">{label}</span>
+                                            {badge.map(|badge| view! {
+                                                <span class="px-1.5 py-0.5 text-[10px] font-medium text-blue-700 dark:text-blue-300 bg-blue-50 dark:bg-blue-900/40 rounded">{badge}</span>
+                                            })}
+                                            <Show when=move || editable>
+                                                <button
+                                                    class="text-xs text-blue-600 dark:text-blue-400 hover:underline"
+                                                    on:click={
+                                                        let prompt = prompt.clone();
+                                                        move |_| edit(prompt.clone())
+                                                    }
+                                                >
+                                                    "Edit"
+                                                </button>
+                                                <button
+                                                    class="text-xs text-red-600 dark:text-red-400 hover:underline"
+                                                    on:click=move |_| delete(id)
+                                                >
+                                                    "Delete"
+                                                </button>
+                                            </Show>
+                                        </li>
+                                    }
+                                }
+                            />
+                        </ul>
+                    </Show>
+
+                    <div class="mt-4 space-y-2">
+                        <h4 class="text-sm font-medium text-gray-800 dark:text-gray-200">
+                            {move || if editing.get().is_some() { "Edit prompt" } else { "New prompt" }}
+                        </h4>
+                        <input
+                            type="text"
+                            class="w-full px-2.5 py-1.5 text-sm border border-gray-200 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                            placeholder="Name, e.g. Proofreader"
+                            prop:value=name
+                            on:input=move |ev| name.set(event_target_value(&ev))
+                        />
+                        <textarea
+                            class="w-full p-2.5 text-sm font-mono border border-gray-200 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 resize-y focus:outline-none focus:ring-2 focus:ring-blue-500"
+                            rows="6"
+                            placeholder="You are a strict proofreader. The document is titled {title}:\n\n{content}"
+                            prop:value=template
+                            on:input=move |ev| template.set(event_target_value(&ev))
+                        ></textarea>
+                        <p class="text-[11px] text-gray-500 dark:text-gray-400">
+                            "{title}, {content}, {selection} and {question} are replaced with the document title, its text, the selected text and the question."
+                        </p>
+                        <Show when=move || is_admin.get()>
+                            <div class="flex gap-4 text-xs text-gray-600 dark:text-gray-300">
+                                <label class="flex items-center gap-1.5 cursor-pointer select-none">
+                                    <input
+                                        type="checkbox"
+                                        class="rounded border-gray-300 dark:border-gray-600"
+                                        prop:checked=shared
+                                        // A prompt cannot change owner once it exists
+                                        disabled=move || editing.get().is_some()
+                                        on:change=move |ev| shared.set(event_target_checked(&ev))
+                                    />
+                                    "Shared with everyone"
+                                </label>
+                                <label class="flex items-center gap-1.5 cursor-pointer select-none">
+                                    <input
+                                        type="checkbox"
+                                        class="rounded border-gray-300 dark:border-gray-600"
+                                        prop:checked=is_default
+                                        disabled=move || !shared.get()
+                                        on:change=move |ev| is_default.set(event_target_checked(&ev))
+                                    />
+                                    "Default for everyone"
+                                </label>
+                            </div>
+                        </Show>
+                        {move || error.get().map(|err| view! {
+                            <p class="text-xs text-red-600 dark:text-red-400">{err}</p>
+                        })}
+                    </div>
+                </div>
+
+                <div class="flex gap-2 px-6 pt-4 pb-6">
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                        on:click=move |_| reset()
+                    >
+                        {move || if editing.get().is_some() { "Cancel Edit" } else { "Clear" }}
+                    </button>
+                    <button
+                        class="flex-1 px-4 py-2.5 text-sm font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 disabled:opacity-50 transition-colors"
+                        on:click=move |_| save()
+                        disabled=move || saving.get() || name.get().trim().is_empty() || template.get().trim().is_empty()
+                    >
+                        {move || if editing.get().is_some() { "Save Prompt" } else { "Add Prompt" }}
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
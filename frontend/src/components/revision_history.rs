@@ -0,0 +1,216 @@
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::ApiClient;
+use crate::components::DiffView;
+use crate::models::{Document, RevisionSummary};
+
+#[component]
+pub fn RevisionHistory(
+    document_id: Uuid,
+    client: Arc<ApiClient>,
+    on_restore: impl Fn(Document) + 'static + Clone,
+    on_close: impl Fn() + 'static + Clone,
+) -> impl IntoView {
+    let revisions = RwSignal::new(Vec::<RevisionSummary>::new());
+    let loading = RwSignal::new(true);
+    let error = RwSignal::new(Option::<String>::None);
+
+    // The two revisions being compared (older on the left, newer on the right)
+    let base_rev = RwSignal::new(Option::<i64>::None);
+    let compare_rev = RwSignal::new(Option::<i64>::None);
+    let contents = RwSignal::new(HashMap::<i64, String>::new());
+
+    // Load the revision list once when the panel opens
+    let client_list = client.clone();
+    spawn_local(async move {
+        match client_list.get_revisions(document_id).await {
+            Ok(list) => {
+                compare_rev.set(list.first().map(|r| r.revision));
+                base_rev.set(list.get(1).or(list.first()).map(|r| r.revision));
+                revisions.set(list);
+            }
+            Err(err) => error.set(Some(err.error)),
+        }
+        loading.set(false);
+    });
+
+    // Fetch the content of any selected revision we have not seen yet
+    let client_content = client.clone();
+    Effect::new(move |_| {
+        for rev in [base_rev.get(), compare_rev.get()].into_iter().flatten() {
+            if contents.with_untracked(|c| c.contains_key(&rev)) {
+                continue;
+            }
+            let client = client_content.clone();
+            spawn_local(async move {
+                match client.get_revision(document_id, rev).await {
+                    Ok(revision) => contents.update(|c| {
+                        c.insert(revision.revision, revision.content);
+                    }),
+                    Err(err) => error.set(Some(err.error)),
+                }
+            });
+        }
+    });
+
+    let old_content = Signal::derive(move || {
+        base_rev.get()
+            .and_then(|rev| contents.with(|c| c.get(&rev).cloned()))
+            .unwrap_or_default()
+    });
+    let new_content = Signal::derive(move || {
+        compare_rev.get()
+            .and_then(|rev| contents.with(|c| c.get(&rev).cloned()))
+            .unwrap_or_default()
+    });
+
+    let client_restore = client.clone();
+    let restore = Action::new_local(move |rev: &i64| {
+        let rev = *rev;
+        let client = client_restore.clone();
+        async move { client.restore_revision(document_id, rev).await }
+    });
+
+    let on_restore_clone = on_restore.clone();
+    Effect::new(move |_| {
+        restore.value().with(|opt_result| match opt_result {
+            Some(Ok(doc)) => on_restore_clone(doc.clone()),
+            Some(Err(err)) => error.set(Some(err.error.clone())),
+            None => {}
+        });
+    });
+
+    let revision_label = |rev: &RevisionSummary| {
+        format!("#{} — {}", rev.revision, rev.created_at.format("%b %d, %Y %H:%M"))
+    };
+
+    let parse_rev = |value: String| value.parse::<i64>().ok();
+
+    view! {
+        <div class="fixed inset-0 bg-black/50 backdrop-blur-sm z-50 flex items-center justify-center p-4">
+            <div class="bg-white dark:bg-gray-800 rounded-xl shadow-xl max-w-5xl w-full h-[85vh] flex flex-col">
+                <div class="flex items-center justify-between p-4 border-b border-gray-200 dark:border-gray-700 shrink-0">
+                    <div class="flex items-center gap-3">
+                        <div class="p-2 bg-blue-100 dark:bg-blue-900/30 rounded-lg">
+                            <svg class="w-5 h-5 text-blue-600 dark:text-blue-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 8v4l3 3m6-3a9 9 0 11-18 0 9 9 0 0118 0z"></path>
+                            </svg>
+                        </div>
+                        <h3 class="text-lg font-semibold text-gray-900 dark:text-gray-50">"Revision History"</h3>
+                    </div>
+                    <button
+                        class="p-1.5 rounded-lg text-gray-400 hover:text-gray-600 dark:hover:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                        on:click=move |_| on_close()
+                    >
+                        <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                        </svg>
+                    </button>
+                </div>
+
+                {move || error.get().map(|err| view! {
+                    <div class="bg-red-50 dark:bg-red-900/20 border-l-4 border-red-400 px-4 py-2 m-4 mb-0 rounded-r-lg">
+                        <p class="text-sm text-red-700 dark:text-red-400">{err}</p>
+                    </div>
+                })}
+
+                <Show
+                    when=move || !loading.get()
+                    fallback=|| view! {
+                        <div class="flex-1 flex items-center justify-center">
+                            <div class="animate-spin rounded-full h-8 w-8 border-b-2 border-blue-600"></div>
+                        </div>
+                    }
+                >
+                    <div class="flex-1 flex flex-col md:flex-row min-h-0">
+                        // Revision list
+                        <div class="md:w-64 shrink-0 max-h-48 md:max-h-none overflow-y-auto border-b md:border-b-0 md:border-r border-gray-200 dark:border-gray-700 p-2">
+                            <For
+                                each=move || revisions.get()
+                                key=|rev| rev.revision
+                                children=move |rev| {
+                                    let number = rev.revision;
+                                    let is_latest = move || revisions.with(|r| r.first().map(|f| f.revision) == Some(number));
+                                    view! {
+                                        <div
+                                            class="px-3 py-2 rounded-lg mb-1 cursor-pointer transition-colors"
+                                            class=("bg-blue-50", move || compare_rev.get() == Some(number))
+                                            class=("dark:bg-blue-900/30", move || compare_rev.get() == Some(number))
+                                            class=("hover:bg-gray-50", move || compare_rev.get() != Some(number))
+                                            class=("dark:hover:bg-gray-700/50", move || compare_rev.get() != Some(number))
+                                            on:click=move |_| compare_rev.set(Some(number))
+                                        >
+                                            <div class="flex items-center justify-between gap-2">
+                                                <span class="text-sm font-medium text-gray-900 dark:text-gray-100">{format!("Revision {}", number)}</span>
+                                                <Show
+                                                    when=is_latest
+                                                    fallback=move || view! {
+                                                        <button
+                                                            class="px-2 py-0.5 text-xs font-medium text-blue-700 dark:text-blue-300 bg-blue-50 dark:bg-blue-900/30 rounded hover:bg-blue-100 dark:hover:bg-blue-900/50 disabled:opacity-50 transition-colors"
+                                                            disabled=move || restore.pending().get()
+                                                            on:click=move |ev| {
+                                                                ev.stop_propagation();
+                                                                restore.dispatch(number);
+                                                            }
+                                                        >
+                                                            "Restore"
+                                                        </button>
+                                                    }
+                                                >
+                                                    <span class="text-xs text-gray-400 dark:text-gray-500">"Current"</span>
+                                                </Show>
+                                            </div>
+                                            <div class="text-xs text-gray-500 dark:text-gray-400 truncate">{rev.title.clone()}</div>
+                                            <div class="text-xs text-gray-400 dark:text-gray-500">{rev.created_at.format("%b %d, %Y %H:%M").to_string()}</div>
+                                        </div>
+                                    }
+                                }
+                            />
+                        </div>
+
+                        // Diff between two revisions
+                        <div class="flex-1 flex flex-col min-h-0 p-4 gap-3">
+                            <div class="flex items-center gap-2 flex-wrap text-xs text-gray-600 dark:text-gray-400">
+                                "Compare"
+                                <select
+                                    class="px-2 py-1 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100"
+                                    on:change=move |ev| base_rev.set(parse_rev(event_target_value(&ev)))
+                                >
+                                    {move || revisions.get().iter().map(|rev| {
+                                        let number = rev.revision;
+                                        view! {
+                                            <option value=number selected=move || base_rev.get() == Some(number)>
+                                                {revision_label(rev)}
+                                            </option>
+                                        }
+                                    }).collect_view()}
+                                </select>
+                                "with"
+                                <select
+                                    class="px-2 py-1 border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100"
+                                    on:change=move |ev| compare_rev.set(parse_rev(event_target_value(&ev)))
+                                >
+                                    {move || revisions.get().iter().map(|rev| {
+                                        let number = rev.revision;
+                                        view! {
+                                            <option value=number selected=move || compare_rev.get() == Some(number)>
+                                                {revision_label(rev)}
+                                            </option>
+                                        }
+                                    }).collect_view()}
+                                </select>
+                            </div>
+                            <div class="flex-1 min-h-0 overflow-auto">
+                                <DiffView old=old_content new=new_content />
+                            </div>
+                        </div>
+                    </div>
+                </Show>
+            </div>
+        </div>
+    }
+}
@@ -1,18 +1,36 @@
+use leptos::html;
 use leptos::prelude::*;
+use leptos::task::spawn_local;
 use uuid::Uuid;
+use wasm_bindgen_futures::JsFuture;
 
-use crate::models::{Document, DocumentSummary};
+use crate::components::FolderTree;
+use crate::models::{Document, DocumentSummary, Folder, ImportFile, ImportFileResult, ImportStatus, SearchResult};
 use crate::app::{use_sidebar, use_dirty};
 
 #[component]
 pub fn DocumentSidebar(
     documents: Signal<Vec<DocumentSummary>>,
+    folders: Signal<Vec<Folder>>,
     selected_document: Signal<Option<Document>>,
     loading: Signal<bool>,
+    search_results: Signal<Option<Vec<SearchResult>>>,
     on_select: impl Fn(Uuid) + Clone + Send + Sync + 'static,
     on_create: impl Fn(String) + Clone + Send + Sync + 'static,
     on_logout: impl Fn() + Clone + Send + Sync + 'static,
     on_theme: impl Fn() + Clone + Send + Sync + 'static,
+    on_search: impl Fn(String) + Clone + Send + Sync + 'static,
+    on_move_document: impl Fn(Uuid, Option<Uuid>) + Clone + Send + Sync + 'static,
+    on_move_folder: impl Fn(Uuid, Option<Uuid>) + Clone + Send + Sync + 'static,
+    on_create_folder: impl Fn(String, Option<Uuid>) + Clone + Send + Sync + 'static,
+    on_rename_folder: impl Fn(Uuid, String) + Clone + Send + Sync + 'static,
+    on_delete_folder: impl Fn(Uuid) + Clone + Send + Sync + 'static,
+    /// Called with the picked files and whether directories inside ZIP
+    /// archives should become folders
+    on_import: impl Fn(Vec<ImportFile>, bool) + Clone + Send + Sync + 'static,
+    on_export: impl Fn() + Clone + Send + Sync + 'static,
+    importing: Signal<bool>,
+    import_results: RwSignal<Option<Vec<ImportFileResult>>>,
     user_name: String,
 ) -> impl IntoView {
     let sidebar = use_sidebar();
@@ -20,6 +38,58 @@ pub fn DocumentSidebar(
 
     let (new_doc_title, set_new_doc_title) = signal(String::new());
     let (show_create_form, set_show_create_form) = signal(false);
+    let (search_query, set_search_query) = signal(String::new());
+    let new_folder_parent = RwSignal::new(None::<Option<Uuid>>);
+    let selected_tag = RwSignal::new(None::<String>);
+
+    // Import dialog
+    let (show_import_dialog, set_show_import_dialog) = signal(false);
+    let (import_create_folders, set_import_create_folders) = signal(true);
+    let (import_file_count, set_import_file_count) = signal(0u32);
+    let import_input: NodeRef<html::Input> = NodeRef::new();
+
+    let start_import = move || {
+        let Some(files) = import_input.get().and_then(|input| input.files()) else { return };
+        let files: Vec<web_sys::File> = (0..files.length()).filter_map(|i| files.get(i)).collect();
+        if files.is_empty() {
+            return;
+        }
+        let create_folders = import_create_folders.get_untracked();
+        let on_import = on_import.clone();
+        spawn_local(async move {
+            let mut picked = Vec::new();
+            for file in files {
+                let Ok(buffer) = JsFuture::from(file.array_buffer()).await else { continue };
+                picked.push(ImportFile {
+                    name: file.name(),
+                    data: js_sys::Uint8Array::new(&buffer).to_vec(),
+                });
+            }
+            on_import(picked, create_folders);
+        });
+    };
+
+    let close_import_dialog = move || {
+        set_show_import_dialog.set(false);
+        set_import_file_count.set(0);
+        import_results.set(None);
+    };
+
+    // Tag cloud: every tag with the number of documents carrying it, most
+    // used first
+    let tag_cloud = Memo::new(move |_| {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        documents.with(|docs| {
+            for tag in docs.iter().flat_map(|doc| doc.tags.iter()) {
+                match counts.iter_mut().find(|(t, _)| t == tag) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((tag.clone(), 1)),
+                }
+            }
+        });
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    });
 
     // Unsaved changes confirmation
     let (pending_select_id, set_pending_select_id) = signal(None::<Uuid>);
@@ -38,7 +108,6 @@ pub fn DocumentSidebar(
                 set_show_create_form.set(false);
             }
         }
-        ()
     });
 
     // Select trigger — only fires after unsaved check passes
@@ -48,7 +117,6 @@ pub fn DocumentSidebar(
         if let Some(uuid) = select_trigger.get() {
             on_select_clone(uuid);
         }
-        ()
     });
 
     let close_sidebar = move || sidebar.0.set(false);
@@ -87,6 +155,18 @@ pub fn DocumentSidebar(
                         <span class="text-lg font-semibold text-gray-800 dark:text-gray-100">"Documents"</span>
                     </div>
                     <div class="flex items-center gap-1">
+                        <button
+                            class="p-2 rounded-lg text-gray-400 hover:text-gray-600 dark:hover:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
+                            on:click=move |_| {
+                                let on_export = on_export.clone();
+                                on_export();
+                            }
+                            title="Export all documents"
+                        >
+                            <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-4l-4 4m0 0l-4-4m4 4V4"></path>
+                            </svg>
+                        </button>
                         <button
                             class="p-2 rounded-lg text-gray-400 hover:text-gray-600 dark:hover:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700 transition-colors"
                             on:click=move |_| {
@@ -133,15 +213,35 @@ pub fn DocumentSidebar(
                 <Show
                     when=move || show_create_form.get()
                     fallback=move || view! {
-                        <button
-                            class="w-full flex items-center justify-center gap-2 px-4 py-2.5 text-sm font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 dark:focus:ring-offset-gray-800 transition-colors"
-                            on:click=move |_| set_show_create_form.set(true)
-                        >
-                            <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 4v16m8-8H4"></path>
-                            </svg>
-                            "New Document"
-                        </button>
+                        <div class="flex gap-2">
+                            <button
+                                class="flex-1 flex items-center justify-center gap-2 px-4 py-2.5 text-sm font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 dark:focus:ring-offset-gray-800 transition-colors"
+                                on:click=move |_| set_show_create_form.set(true)
+                            >
+                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 4v16m8-8H4"></path>
+                                </svg>
+                                "New Document"
+                            </button>
+                            <button
+                                class="px-3 py-2.5 text-gray-600 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                                on:click=move |_| new_folder_parent.set(Some(None))
+                                title="New Folder"
+                            >
+                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 13h6m-3-3v6m-9 1V7a2 2 0 012-2h6l2 2h6a2 2 0 012 2v8a2 2 0 01-2 2H5a2 2 0 01-2-2z"></path>
+                                </svg>
+                            </button>
+                            <button
+                                class="px-3 py-2.5 text-gray-600 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                                on:click=move |_| set_show_import_dialog.set(true)
+                                title="Import"
+                            >
+                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-8l-4-4m0 0L8 8m4-4v12"></path>
+                                </svg>
+                            </button>
+                        </div>
                     }
                 >
                     <div class="space-y-2">
@@ -176,74 +276,252 @@ pub fn DocumentSidebar(
                         </div>
                     </div>
                 </Show>
+
+                // Search box
+                <div class="relative mt-3">
+                    <svg class="w-4 h-4 absolute left-3 top-1/2 -translate-y-1/2 text-gray-400 pointer-events-none" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M21 21l-6-6m2-5a7 7 0 11-14 0 7 7 0 0114 0z"></path>
+                    </svg>
+                    <input
+                        type="search"
+                        placeholder="Search documents"
+                        class="w-full pl-9 pr-3 py-2 text-sm border border-gray-300 dark:border-gray-600 rounded-lg bg-white dark:bg-gray-700 text-gray-900 dark:text-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        prop:value=search_query
+                        on:input={
+                            let on_search = on_search.clone();
+                            move |ev| {
+                                let query = event_target_value(&ev);
+                                set_search_query.set(query.clone());
+                                on_search(query);
+                            }
+                        }
+                        on:keydown=move |ev| {
+                            if ev.key() == "Escape" {
+                                set_search_query.set(String::new());
+                                on_search(String::new());
+                            }
+                        }
+                    />
+                </div>
+
+                // Tag cloud
+                <Show when=move || !tag_cloud.get().is_empty()>
+                    <div class="flex flex-wrap gap-1.5 mt-3 max-h-20 overflow-y-auto">
+                        {move || {
+                            let max = tag_cloud.with(|tags| tags.first().map(|(_, count)| *count).unwrap_or(1));
+                            tag_cloud.get().into_iter().map(|(tag, count)| {
+                                let tag_for_click = tag.clone();
+                                let tag_for_class = tag.clone();
+                                let is_selected = move || selected_tag.get().as_deref() == Some(tag_for_class.as_str());
+                                let size = if count * 3 > max * 2 { "text-sm" } else { "text-xs" };
+                                view! {
+                                    <button
+                                        class=format!("px-2 py-0.5 rounded-full transition-colors {}", size)
+                                        class=("bg-blue-600", is_selected.clone())
+                                        class=("text-white", is_selected.clone())
+                                        class=("bg-gray-100", {
+                                            let is_selected = is_selected.clone();
+                                            move || !is_selected()
+                                        })
+                                        class=("dark:bg-gray-700", {
+                                            let is_selected = is_selected.clone();
+                                            move || !is_selected()
+                                        })
+                                        class=("text-gray-600", {
+                                            let is_selected = is_selected.clone();
+                                            move || !is_selected()
+                                        })
+                                        class=("dark:text-gray-300", move || !is_selected())
+                                        title=format!("{} document(s)", count)
+                                        on:click=move |_| {
+                                            selected_tag.update(|selected| {
+                                                *selected = if selected.as_deref() == Some(tag_for_click.as_str()) {
+                                                    None
+                                                } else {
+                                                    Some(tag_for_click.clone())
+                                                };
+                                            });
+                                        }
+                                    >
+                                        "#" {tag}
+                                    </button>
+                                }
+                            }).collect_view()
+                        }}
+                    </div>
+                </Show>
             </header>
 
             // Document list
             <div class="flex-1 overflow-y-auto">
-                <Show
-                    when=move || loading.get()
-                    fallback=move || view! {
-                        <div class="p-3">
-                            <For
-                                each=move || documents.get()
-                                key=|doc| (doc.id, doc.title.clone())
-                                children=move |doc| {
-                                    let try_select = try_select.clone();
-                                    let is_selected = move || {
-                                        selected_document.get()
-                                            .map(|selected| selected.id == doc.id)
-                                            .unwrap_or(false)
-                                    };
+                // Search results
+                <Show when=move || search_results.get().is_some()>
+                    <div class="p-3">
+                        <For
+                            each=move || search_results.get().unwrap_or_default()
+                            key=|result| result.id
+                            children=move |result| {
+                                view! {
+                                    <button
+                                        class="w-full text-left px-3 py-2.5 rounded-lg mb-1 transition-all hover:bg-gray-50 dark:hover:bg-gray-700/50 border border-transparent"
+                                        on:click=move |_| try_select(result.id)
+                                    >
+                                        <div class="font-medium text-gray-900 dark:text-gray-100 text-sm truncate">
+                                            {result.title.clone()}
+                                        </div>
+                                        <div
+                                            class="text-xs text-gray-500 dark:text-gray-400 mt-0.5 line-clamp-2 [&_mark]:bg-yellow-200 dark:[&_mark]:bg-yellow-700/60 [&_mark]:text-inherit [&_mark]:rounded-sm"
+                                            inner_html=result.snippet.clone()
+                                        ></div>
+                                    </button>
+                                }
+                            }
+                        />
+
+                        <Show when=move || search_results.get().is_some_and(|results| results.is_empty())>
+                            <p class="text-center py-12 px-4 text-sm text-gray-500 dark:text-gray-400">"No matching documents"</p>
+                        </Show>
+                    </div>
+                </Show>
+
+                <div class="h-full" class=("hidden", move || search_results.get().is_some())>
+                    <Show
+                        when=move || loading.get()
+                        fallback=move || view! {
+                            <div class="h-full">
+                                <FolderTree
+                                    documents=documents
+                                    folders=folders
+                                    selected_document=selected_document
+                                    new_folder_parent=new_folder_parent
+                                    selected_tag=selected_tag
+                                    on_select=try_select
+                                    on_move_document=on_move_document.clone()
+                                    on_move_folder=on_move_folder.clone()
+                                    on_create_folder=on_create_folder.clone()
+                                    on_rename_folder=on_rename_folder.clone()
+                                    on_delete_folder=on_delete_folder.clone()
+                                />
 
-                                    view! {
+                                <Show when=move || documents.get().is_empty() && folders.get().is_empty()>
+                                    <div class="text-center py-12 px-4">
+                                        <svg class="w-12 h-12 mx-auto text-gray-300 dark:text-gray-600 mb-3" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z"></path>
+                                        </svg>
+                                        <p class="text-sm text-gray-500 dark:text-gray-400 mb-3">"No documents yet"</p>
                                         <button
-                                            class=move || format!(
-                                                "w-full text-left px-3 py-2.5 rounded-lg mb-1 transition-all {}",
-                                                if is_selected() {
-                                                    "bg-blue-50 dark:bg-blue-900/30 border border-blue-200 dark:border-blue-700"
-                                                } else {
-                                                    "hover:bg-gray-50 dark:hover:bg-gray-700/50 border border-transparent"
-                                                }
-                                            )
-                                            on:click=move |_| {
-                                                try_select(doc.id);
-                                            }
+                                            class="text-blue-600 dark:text-blue-400 hover:text-blue-700 dark:hover:text-blue-300 text-sm font-medium"
+                                            on:click=move |_| set_show_create_form.set(true)
                                         >
-                                            <div class="font-medium text-gray-900 dark:text-gray-100 text-sm truncate">
-                                                {doc.title.clone()}
-                                            </div>
-                                            <div class="text-xs text-gray-400 dark:text-gray-500 mt-0.5">
-                                                {doc.updated_at.format("%b %d, %Y").to_string()}
-                                            </div>
+                                            "Create your first document"
                                         </button>
-                                    }
+                                    </div>
+                                </Show>
+                            </div>
+                        }
+                    >
+                        <div class="p-4 text-center">
+                            <div class="animate-spin rounded-full h-8 w-8 border-b-2 border-blue-600 mx-auto"></div>
+                            <p class="text-sm text-gray-500 dark:text-gray-400 mt-2">"Loading documents..."</p>
+                        </div>
+                    </Show>
+                </div>
+            </div>
+        </aside>
+
+        // Import dialog
+        <Show when=move || show_import_dialog.get()>
+            <div class="fixed inset-0 bg-black/50 backdrop-blur-sm z-50 flex items-center justify-center p-4">
+                <div class="bg-white dark:bg-gray-800 rounded-xl shadow-xl max-w-md w-full p-6 flex flex-col max-h-[85vh]">
+                    <div class="flex items-center gap-3 mb-4">
+                        <div class="p-2 bg-blue-100 dark:bg-blue-900/30 rounded-lg">
+                            <svg class="w-5 h-5 text-blue-600 dark:text-blue-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-8l-4-4m0 0L8 8m4-4v12"></path>
+                            </svg>
+                        </div>
+                        <h3 class="text-lg font-semibold text-gray-900 dark:text-gray-50">"Import Documents"</h3>
+                    </div>
+
+                    <Show
+                        when=move || import_results.get().is_some()
+                        fallback=move || view! {
+                            <p class="text-sm text-gray-600 dark:text-gray-400 mb-4">
+                                "Pick Markdown files or ZIP archives of a directory tree. Each Markdown file becomes a document titled after its first heading or its file name."
+                            </p>
+                            <input
+                                type="file"
+                                multiple=true
+                                accept=".md,.markdown,.mdown,.mkd,.txt,.zip"
+                                node_ref=import_input
+                                class="block w-full text-sm text-gray-600 dark:text-gray-300 mb-3 file:mr-3 file:px-3 file:py-1.5 file:rounded-lg file:border-0 file:text-sm file:font-medium file:bg-gray-100 dark:file:bg-gray-700 file:text-gray-700 dark:file:text-gray-200 hover:file:bg-gray-200 dark:hover:file:bg-gray-600"
+                                on:change=move |_| {
+                                    let count = import_input.get().and_then(|input| input.files()).map(|files| files.length()).unwrap_or(0);
+                                    set_import_file_count.set(count);
                                 }
                             />
-
-                            <Show when=move || documents.get().is_empty()>
-                                <div class="text-center py-12 px-4">
-                                    <svg class="w-12 h-12 mx-auto text-gray-300 dark:text-gray-600 mb-3" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z"></path>
-                                    </svg>
-                                    <p class="text-sm text-gray-500 dark:text-gray-400 mb-3">"No documents yet"</p>
-                                    <button
-                                        class="text-blue-600 dark:text-blue-400 hover:text-blue-700 dark:hover:text-blue-300 text-sm font-medium"
-                                        on:click=move |_| set_show_create_form.set(true)
-                                    >
-                                        "Create your first document"
-                                    </button>
-                                </div>
-                            </Show>
+                            <label class="flex items-center gap-2 text-sm text-gray-700 dark:text-gray-300 mb-6">
+                                <input
+                                    type="checkbox"
+                                    class="rounded"
+                                    prop:checked=import_create_folders
+                                    on:change=move |ev| set_import_create_folders.set(event_target_checked(&ev))
+                                />
+                                "Create folders from the directories in ZIP archives"
+                            </label>
+                        }
+                    >
+                        <div class="overflow-y-auto mb-6 -mx-2">
+                            <p class="px-2 mb-2 text-sm text-gray-600 dark:text-gray-400">
+                                {move || import_results.with(|results| {
+                                    let results = results.as_deref().unwrap_or_default();
+                                    let imported = results.iter().filter(|r| r.status == ImportStatus::Imported).count();
+                                    format!("Imported {} of {} file(s)", imported, results.len())
+                                })}
+                            </p>
+                            {move || import_results.get().unwrap_or_default().into_iter().map(|result| {
+                                let (label, badge) = match result.status {
+                                    ImportStatus::Imported => ("Imported", "bg-green-100 dark:bg-green-900/30 text-green-700 dark:text-green-400"),
+                                    ImportStatus::Skipped => ("Skipped", "bg-gray-100 dark:bg-gray-700 text-gray-600 dark:text-gray-400"),
+                                    ImportStatus::Failed => ("Failed", "bg-red-100 dark:bg-red-900/30 text-red-700 dark:text-red-400"),
+                                };
+                                let detail = result.document.map(|doc| doc.title).or(result.message);
+                                view! {
+                                    <div class="flex items-start gap-2 px-2 py-1.5 text-sm">
+                                        <span class=format!("shrink-0 px-1.5 py-0.5 text-xs font-medium rounded {}", badge)>{label}</span>
+                                        <div class="min-w-0">
+                                            <div class="truncate text-gray-800 dark:text-gray-200" title=result.path.clone()>{result.path.clone()}</div>
+                                            {detail.map(|detail| view! {
+                                                <div class="truncate text-xs text-gray-500 dark:text-gray-400">{detail}</div>
+                                            })}
+                                        </div>
+                                    </div>
+                                }
+                            }).collect_view()}
                         </div>
-                    }
-                >
-                    <div class="p-4 text-center">
-                        <div class="animate-spin rounded-full h-8 w-8 border-b-2 border-blue-600 mx-auto"></div>
-                        <p class="text-sm text-gray-500 dark:text-gray-400 mt-2">"Loading documents..."</p>
+                    </Show>
+
+                    <div class="flex gap-3">
+                        <button
+                            class="flex-1 px-4 py-2.5 text-sm font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                            on:click=move |_| close_import_dialog()
+                        >
+                            {move || if import_results.get().is_some() { "Close" } else { "Cancel" }}
+                        </button>
+                        <button
+                            class="flex-1 px-4 py-2.5 text-sm font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+                            class=("hidden", move || import_results.get().is_some())
+                            disabled=move || importing.get() || import_file_count.get() == 0
+                            on:click={
+                                let start_import = start_import.clone();
+                                move |_| start_import()
+                            }
+                        >
+                            {move || if importing.get() { "Importing..." } else { "Import" }}
+                        </button>
                     </div>
-                </Show>
+                </div>
             </div>
-        </aside>
+        </Show>
 
         // Unsaved changes dialog
         <Show when=move || show_unsaved_dialog.get()>
@@ -0,0 +1,28 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+use crate::models::DownloadedFile;
+
+/// Hands a file to the browser as if it had been downloaded from a link.
+pub fn save_file(file: &DownloadedFile) -> Result<(), String> {
+    let window = web_sys::window().ok_or("No window")?;
+    let document = window.document().ok_or("No document")?;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(file.data.as_slice()));
+    let options = BlobPropertyBag::new();
+    options.set_type(&file.content_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|e| format!("{:?}", e))?;
+    let url = Url::create_object_url_with_blob(&blob).map_err(|e| format!("{:?}", e))?;
+
+    let anchor: HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(|e| format!("{:?}", e))?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(&file.file_name);
+    anchor.click();
+
+    let _ = Url::revoke_object_url(&url);
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chrono::{DateTime, Utc};
+use dr_markdown_collab::{ClientMessage, Participant, ServerMessage, TextOperation};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LiveStatus {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+/// Callbacks through which a [`LiveConnection`] reports what happens on the
+/// server side.
+pub struct LiveHandlers {
+    /// The server copy of the document after (re)connecting.
+    pub on_init: Box<dyn Fn(String)>,
+    /// A remote edit that has to be applied to the local text.
+    pub on_remote_edit: Box<dyn Fn(TextOperation)>,
+    pub on_presence: Box<dyn Fn(Vec<Participant>)>,
+    /// The server wrote the document to the database.
+    pub on_saved: Box<dyn Fn(DateTime<Utc>, bool)>,
+    pub on_status: Box<dyn Fn(LiveStatus)>,
+}
+
+// Client side of the OT protocol: at most one edit is in flight at a time and
+// everything typed meanwhile is buffered into a single operation
+enum SyncState {
+    Synchronized,
+    AwaitingConfirm(TextOperation),
+    AwaitingWithBuffer(TextOperation, TextOperation),
+}
+
+struct LiveState {
+    revision: u64,
+    sync: SyncState,
+    // The server's text at `revision`, once it is known
+    server_content: Option<String>,
+}
+
+/// A WebSocket connection to the live editing session of one document.
+/// The socket is closed when the connection is dropped.
+pub struct LiveConnection {
+    socket: WebSocket,
+    state: Rc<RefCell<LiveState>>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_open: Closure<dyn FnMut()>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+impl LiveConnection {
+    pub fn connect(url: &str, handlers: LiveHandlers) -> Result<Self, String> {
+        let socket = WebSocket::new(url).map_err(|e| format!("{:?}", e))?;
+        let state = Rc::new(RefCell::new(LiveState {
+            revision: 0,
+            sync: SyncState::Synchronized,
+            server_content: None,
+        }));
+        let handlers = Rc::new(handlers);
+
+        (handlers.on_status)(LiveStatus::Connecting);
+
+        let on_open = {
+            let handlers = handlers.clone();
+            Closure::<dyn FnMut()>::new(move || (handlers.on_status)(LiveStatus::Connected))
+        };
+
+        let on_close = {
+            let handlers = handlers.clone();
+            Closure::<dyn FnMut(CloseEvent)>::new(move |_: CloseEvent| {
+                (handlers.on_status)(LiveStatus::Disconnected)
+            })
+        };
+
+        let on_message = {
+            let socket = socket.clone();
+            let state = state.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let Some(text) = event.data().as_string() else { return };
+                let Ok(message) = serde_json::from_str::<ServerMessage>(&text) else { return };
+                handle_message(&socket, &state, &handlers, message);
+            })
+        };
+
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            socket,
+            state,
+            _on_message: on_message,
+            _on_open: on_open,
+            _on_close: on_close,
+        })
+    }
+
+    /// Reports an edit made locally (already applied to the local text).
+    /// Edits made while the socket is not open are picked up when the
+    /// connection is (re)established.
+    pub fn local_edit(&self, operation: TextOperation) {
+        if operation.is_noop() || self.socket.ready_state() != WebSocket::OPEN {
+            return;
+        }
+        let mut state = self.state.borrow_mut();
+        state.sync = match std::mem::replace(&mut state.sync, SyncState::Synchronized) {
+            SyncState::Synchronized => {
+                send_edit(&self.socket, state.revision, &operation);
+                SyncState::AwaitingConfirm(operation)
+            }
+            SyncState::AwaitingConfirm(outstanding) => {
+                SyncState::AwaitingWithBuffer(outstanding, operation)
+            }
+            SyncState::AwaitingWithBuffer(outstanding, buffer) => match buffer.compose(&operation) {
+                Ok(buffer) => SyncState::AwaitingWithBuffer(outstanding, buffer),
+                // Our own bookkeeping is off; start over from the server copy
+                Err(_) => {
+                    let _ = self.socket.close();
+                    SyncState::Synchronized
+                }
+            },
+        };
+    }
+
+    /// The last text the server is known to have, which local edits made
+    /// after losing the connection are based on.
+    pub fn server_content(&self) -> Option<String> {
+        self.state.borrow().server_content.clone()
+    }
+
+    /// Drops the connection so that it gets re-established from the server
+    /// copy of the document.
+    pub fn resync(&self) {
+        let _ = self.socket.close();
+    }
+}
+
+impl Drop for LiveConnection {
+    fn drop(&mut self) {
+        self.socket.set_onclose(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onopen(None);
+        let _ = self.socket.close();
+    }
+}
+
+fn send_edit(socket: &WebSocket, revision: u64, operation: &TextOperation) {
+    let message = ClientMessage::Edit {
+        revision,
+        operation: operation.clone(),
+    };
+    if let Ok(text) = serde_json::to_string(&message) {
+        let _ = socket.send_with_str(&text);
+    }
+}
+
+fn handle_message(
+    socket: &WebSocket,
+    state: &RefCell<LiveState>,
+    handlers: &LiveHandlers,
+    message: ServerMessage,
+) {
+    match message {
+        ServerMessage::Init { revision, content, participants, .. } => {
+            {
+                let mut state = state.borrow_mut();
+                state.revision = revision;
+                state.sync = SyncState::Synchronized;
+                state.server_content = Some(content.clone());
+            }
+            (handlers.on_init)(content);
+            (handlers.on_presence)(participants);
+        }
+        ServerMessage::Ack { revision } => {
+            let mut state = state.borrow_mut();
+            state.revision = revision;
+            // The acknowledged edit is now part of the server copy
+            let LiveState { sync, server_content, .. } = &mut *state;
+            if let SyncState::AwaitingConfirm(outstanding) | SyncState::AwaitingWithBuffer(outstanding, _) = sync {
+                *server_content = apply_to(server_content.take(), outstanding);
+            }
+            state.sync = match std::mem::replace(&mut state.sync, SyncState::Synchronized) {
+                SyncState::AwaitingWithBuffer(_, buffer) => {
+                    send_edit(socket, revision, &buffer);
+                    SyncState::AwaitingConfirm(buffer)
+                }
+                _ => SyncState::Synchronized,
+            };
+        }
+        ServerMessage::Edit { revision, operation, .. } => {
+            let transformed = {
+                let mut state = state.borrow_mut();
+                state.revision = revision;
+                state.server_content = apply_to(state.server_content.take(), &operation);
+                let (sync, transformed) = transform_remote(
+                    std::mem::replace(&mut state.sync, SyncState::Synchronized),
+                    operation,
+                );
+                state.sync = sync;
+                transformed
+            };
+            match transformed {
+                Some(operation) => (handlers.on_remote_edit)(operation),
+                None => {
+                    let _ = socket.close();
+                }
+            }
+        }
+        ServerMessage::Presence { participants } => (handlers.on_presence)(participants),
+        ServerMessage::Saved { updated_at } => {
+            let synchronized = matches!(state.borrow().sync, SyncState::Synchronized);
+            (handlers.on_saved)(updated_at, synchronized);
+        }
+        ServerMessage::Error { message } => {
+            leptos::logging::log!("Live editing error: {}", message);
+            // Whatever we sent was rejected, so our state no longer matches
+            // the server; reconnecting resynchronizes it
+            let _ = socket.close();
+        }
+    }
+}
+
+fn apply_to(content: Option<String>, operation: &TextOperation) -> Option<String> {
+    content.and_then(|content| operation.apply(&content).ok())
+}
+
+/// Transforms a remote operation against the local edits the server has not
+/// seen yet. Returns the new sync state and the operation to apply locally.
+fn transform_remote(sync: SyncState, operation: TextOperation) -> (SyncState, Option<TextOperation>) {
+    match sync {
+        SyncState::Synchronized => (SyncState::Synchronized, Some(operation)),
+        SyncState::AwaitingConfirm(outstanding) => {
+            match TextOperation::transform(&outstanding, &operation) {
+                Ok((outstanding, operation)) => (SyncState::AwaitingConfirm(outstanding), Some(operation)),
+                Err(_) => (SyncState::Synchronized, None),
+            }
+        }
+        SyncState::AwaitingWithBuffer(outstanding, buffer) => {
+            let transformed = TextOperation::transform(&outstanding, &operation).and_then(|(outstanding, operation)| {
+                TextOperation::transform(&buffer, &operation)
+                    .map(|(buffer, operation)| (outstanding, buffer, operation))
+            });
+            match transformed {
+                Ok((outstanding, buffer, operation)) => {
+                    (SyncState::AwaitingWithBuffer(outstanding, buffer), Some(operation))
+                }
+                Err(_) => (SyncState::Synchronized, None),
+            }
+        }
+    }
+}
+
+/// Merges the local edits made since `base` into the `server` copy, which
+/// may have changed as well. If the server already has exactly the local
+/// text's changes, e.g. because the last edit arrived before the connection
+/// broke, it is kept as it is.
+pub fn rebase(base: &str, local: &str, server: &str) -> String {
+    let mine = TextOperation::from_diff(base, local);
+    let theirs = TextOperation::from_diff(base, server);
+    if mine == theirs {
+        return server.to_string();
+    }
+    TextOperation::transform(&mine, &theirs)
+        .and_then(|(mine, _)| mine.apply(server))
+        // The base does not match; the local text wins as it did before
+        .unwrap_or_else(|_| local.to_string())
+}
+
+/// Converts a UTF-16 offset (as used by the DOM) into a `char` index.
+pub fn utf16_to_char_index(text: &str, utf16_index: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units >= utf16_index as usize {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+/// Converts a `char` index into a UTF-16 offset (as used by the DOM).
+pub fn char_to_utf16_index(text: &str, char_index: usize) -> u32 {
+    text.chars().take(char_index).map(char::len_utf16).sum::<usize>() as u32
+}
@@ -2,12 +2,25 @@ mod api;
 mod app;
 mod auth;
 mod components;
+mod download;
+mod live;
+mod markdown;
 mod models;
 mod pages;
 
 use leptos::prelude::*;
+use leptos::task::spawn_local;
 
 fn main() {
     console_error_panic_hook::set_once();
-    mount_to_body(app::App);
+
+    // The preview has to know the diagram languages before it shows the
+    // first document
+    spawn_local(async {
+        match api::ApiClient::new().get_config().await {
+            Ok(config) => markdown::set_diagram_languages(&config.diagrams),
+            Err(err) => leptos::logging::log!("Could not load the server config: {}", err.error),
+        }
+        mount_to_body(app::App);
+    });
 }
\ No newline at end of file
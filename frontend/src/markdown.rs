@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, OnceLock};
+
+use dr_markdown_render::{
+    diagram_image, escape_html, Diagram, DiagramLanguage, DiagramLanguages, Renderer, SanitizePolicy,
+};
+use leptos::prelude::{ArcTrigger, Notify, Track};
+use leptos::task::spawn_local;
+use web_sys::{Blob, BlobPropertyBag, Url};
+
+use crate::api::api_url;
+use crate::models::DiagramLanguageConfig;
+
+// HTML allowed on top of the built-in allow-list, or removed from it, as
+// comma separated lists, e.g. HTML_ALLOW_TAGS="video,audio"
+const HTML_ALLOW_TAGS: Option<&str> = option_env!("HTML_ALLOW_TAGS");
+const HTML_DENY_TAGS: Option<&str> = option_env!("HTML_DENY_TAGS");
+const HTML_ALLOW_ATTRIBUTES: Option<&str> = option_env!("HTML_ALLOW_ATTRIBUTES");
+const HTML_ALLOW_URL_SCHEMES: Option<&str> = option_env!("HTML_ALLOW_URL_SCHEMES");
+const HTML_ALLOW_CLASSES: Option<&str> = option_env!("HTML_ALLOW_CLASSES");
+
+/// Where the backend serves diagrams from its cache.
+static DIAGRAM_URL: LazyLock<String> = LazyLock::new(|| format!("{}/diagrams", api_url()));
+
+/// The diagram languages of the server, the renderer's defaults until they
+/// are known.
+static DIAGRAM_LANGUAGES: OnceLock<DiagramLanguages> = OnceLock::new();
+
+/// Draws code blocks in the server's diagram languages as diagrams, from
+/// then on. Only the first call counts.
+pub fn set_diagram_languages(languages: &[DiagramLanguageConfig]) {
+    let languages = languages
+        .iter()
+        .map(|language| DiagramLanguage {
+            name: language.name.clone(),
+            aliases: language.aliases.clone(),
+            format: language.format.parse().unwrap_or_default(),
+        })
+        .collect();
+    let _ = DIAGRAM_LANGUAGES.set(DiagramLanguages::new(languages));
+}
+
+fn diagram_languages() -> &'static DiagramLanguages {
+    DIAGRAM_LANGUAGES.get_or_init(DiagramLanguages::default)
+}
+
+/// The HTML documents and AI answers may contain once they are shown.
+pub fn sanitize_policy() -> SanitizePolicy {
+    SanitizePolicy::new()
+        .allow_tags(HTML_ALLOW_TAGS.unwrap_or_default())
+        .deny_tags(HTML_DENY_TAGS.unwrap_or_default())
+        .allow_attributes(HTML_ALLOW_ATTRIBUTES.unwrap_or_default())
+        .allow_url_schemes(HTML_ALLOW_URL_SCHEMES.unwrap_or_default())
+        .allow_class_prefixes(HTML_ALLOW_CLASSES.unwrap_or_default())
+}
+
+/// How the backend is allowed to render diagrams it has not seen before.
+#[derive(Clone, Copy)]
+pub enum DiagramAccess<'a> {
+    /// The logged in user. The token is only ever sent in a header, so the
+    /// images are fetched by the page and shown from `blob:` URLs.
+    Login(&'a str),
+    /// A visitor of a shared document, with the document's share token.
+    Share(&'a str),
+}
+
+/// Diagram images fetched with the login, at most this many.
+const MAX_DIAGRAM_IMAGES: usize = 200;
+
+/// The `blob:` URLs of diagram images fetched with the login, by the URL
+/// they came from. `None` while the image is on its way.
+#[derive(Default)]
+struct DiagramImages {
+    urls: HashMap<String, Option<String>>,
+    order: VecDeque<String>,
+}
+
+thread_local! {
+    static DIAGRAM_IMAGES: RefCell<DiagramImages> = RefCell::default();
+    /// Notified whenever an image arrives, so that what shows it is rendered
+    /// again.
+    static DIAGRAM_IMAGES_LOADED: ArcTrigger = ArcTrigger::new();
+}
+
+/// An image of the diagram from the backend's cache.
+fn server_diagram(diagram: &Diagram, access: DiagramAccess) -> String {
+    let url = diagram.server_url(&DIAGRAM_URL);
+    match access {
+        DiagramAccess::Share(token) => diagram_image(&format!("{}&token={}", url, token), diagram),
+        DiagramAccess::Login(token) => match fetched_diagram(url, token) {
+            Some(blob_url) => diagram_image(&blob_url, diagram),
+            // The source stands in for the diagram until it is there
+            None => format!("<pre><code>{}</code></pre>", escape_html(&diagram.source)),
+        },
+    }
+}
+
+/// The `blob:` URL of a diagram image, or `None` if it is still being
+/// fetched, which this starts if needed.
+fn fetched_diagram(url: String, token: &str) -> Option<String> {
+    let known = DIAGRAM_IMAGES.with_borrow(|images| images.urls.get(&url).cloned());
+    if let Some(blob_url) = known {
+        return blob_url;
+    }
+
+    DIAGRAM_IMAGES.with_borrow_mut(|images| images.urls.insert(url.clone(), None));
+    let token = token.to_string();
+    spawn_local(async move {
+        match fetch_diagram(&url, &token).await {
+            Ok(blob_url) => {
+                DIAGRAM_IMAGES.with_borrow_mut(|images| {
+                    images.urls.insert(url.clone(), Some(blob_url));
+                    images.order.push_back(url);
+                    // The oldest images are the least likely to be shown
+                    while images.order.len() > MAX_DIAGRAM_IMAGES {
+                        let Some(old) = images.order.pop_front() else { break };
+                        if let Some(Some(old_blob_url)) = images.urls.remove(&old) {
+                            let _ = Url::revoke_object_url(&old_blob_url);
+                        }
+                    }
+                });
+                DIAGRAM_IMAGES_LOADED.with(|loaded| loaded.notify());
+            }
+            Err(e) => {
+                leptos::logging::log!("Failed to load diagram: {}", e);
+                // Tried again the next time the diagram is rendered
+                DIAGRAM_IMAGES.with_borrow_mut(|images| images.urls.remove(&url));
+            }
+        }
+    });
+    None
+}
+
+/// Fetches a diagram image with the login in the Authorization header.
+/// Errors come as images too, which are shown like the diagram.
+async fn fetch_diagram(url: &str, token: &str) -> Result<String, String> {
+    let response = gloo_net::http::Request::get(url)
+        .header("Authorization", &format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let content_type = response.headers().get("content-type").unwrap_or_default();
+    let data = response.binary().await.map_err(|e| e.to_string())?;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data.as_slice()));
+    let options = BlobPropertyBag::new();
+    options.set_type(&content_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(|e| format!("{:?}", e))?;
+    Url::create_object_url_with_blob(&blob).map_err(|e| format!("{:?}", e))
+}
+
+fn diagram_policy(access: DiagramAccess) -> SanitizePolicy {
+    sanitize_policy().allow_blob_images(matches!(access, DiagramAccess::Login(_)))
+}
+
+/// Renders a document for the preview and shared pages. The result is
+/// sanitized, so it can be set as `inner_html`. With a login it changes as
+/// diagram images arrive, which reactive callers are notified of.
+pub fn render_markdown(markdown_content: &str, access: DiagramAccess) -> String {
+    if let DiagramAccess::Login(_) = access {
+        DIAGRAM_IMAGES_LOADED.with(|loaded| loaded.track());
+    }
+    let policy = diagram_policy(access);
+    Renderer::new()
+        .diagram_languages(diagram_languages())
+        .diagrams(|diagram| server_diagram(diagram, access))
+        .highlight_code(true)
+        .mathml(true)
+        .sanitizer(move |html| policy.clean(&html))
+        .render(markdown_content)
+}
+
+/// Renders an AI answer in the chat like a document. While the answer is
+/// still written it is rendered again for every piece that arrives, so
+/// diagrams are only shown once it is `complete`.
+pub fn render_answer(markdown_content: &str, complete: bool, token: &str) -> String {
+    let access = DiagramAccess::Login(token);
+    let policy = diagram_policy(access);
+    let renderer = Renderer::new()
+        .diagram_languages(diagram_languages())
+        .highlight_code(true)
+        .mathml(true)
+        .sanitizer(move |html| policy.clean(&html));
+    if complete {
+        DIAGRAM_IMAGES_LOADED.with(|loaded| loaded.track());
+        renderer.diagrams(|diagram| server_diagram(diagram, access)).render(markdown_content)
+    } else {
+        renderer.render(markdown_content)
+    }
+}
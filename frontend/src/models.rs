@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use dr_markdown_llm::Source;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -41,6 +42,8 @@ pub struct Document {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub share_token: Option<String>,
+    #[serde(default)]
+    pub folder_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +58,133 @@ pub struct DocumentSummary {
     pub title: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub folder_id: Option<Uuid>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Folder {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchResult {
+    pub id: Uuid,
+    pub title: String,
+    /// HTML-escaped excerpt with the matched terms wrapped in `<mark>` tags.
+    pub snippet: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A file received from the server, e.g. an export.
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A file picked for import, read into memory.
+#[derive(Debug, Clone)]
+pub struct ImportFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFileResult {
+    pub path: String,
+    pub status: ImportStatus,
+    pub document: Option<DocumentSummary>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportResponse {
+    pub results: Vec<ImportFileResult>,
+    pub folders: Vec<Folder>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RevisionSummary {
+    pub revision: i64,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Revision {
+    pub revision: i64,
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatThreadSummary {
+    pub id: Uuid,
+    /// Empty until the first question is asked.
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatThreadMessage {
+    pub id: Uuid,
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: String,
+    /// The other documents an answer drew on.
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatThread {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ChatThreadMessage>,
+}
+
+/// How much of the AI quota is used. Limits and remaining amounts are
+/// `None` when unlimited.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LlmQuota {
+    pub request_limit: Option<i64>,
+    pub requests_used: i64,
+    pub requests_remaining: Option<i64>,
+    pub token_limit: Option<i64>,
+    pub tokens_used: i64,
+    pub tokens_remaining: Option<i64>,
+    pub window_seconds: i64,
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+/// Body of the `429 Too Many Requests` answer of the AI endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LlmQuotaExceeded {
+    pub error: String,
+    /// Seconds until the next request is allowed.
+    pub retry_after: u64,
+    pub quota: LlmQuota,
 }
 
 #[derive(Debug, Serialize)]
@@ -67,6 +197,148 @@ pub struct CreateDocumentRequest {
 pub struct UpdateDocumentRequest {
     pub title: Option<String>,
     pub content: Option<String>,
+    pub last_updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateChatThreadRequest {
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatMessageRequest {
+    pub message: String,
+    pub context: Option<String>,
+    pub search_documents: bool,
+    pub prompt_id: Option<Uuid>,
+    pub selection: Option<String>,
+}
+
+/// A system prompt the AI assistant can answer with. Shared prompts are
+/// offered to everyone and managed by admins.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromptTemplate {
+    pub id: Uuid,
+    pub name: String,
+    pub template: String,
+    pub shared: bool,
+    pub is_default: bool,
+    pub editable: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptTemplateList {
+    pub prompts: Vec<PromptTemplate>,
+    pub is_admin: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatePromptTemplateRequest {
+    pub name: String,
+    pub template: String,
+    pub shared: bool,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdatePromptTemplateRequest {
+    pub name: Option<String>,
+    pub template: Option<String>,
+    pub is_default: Option<bool>,
+}
+
+/// A range of a text in characters (not UTF-16 units), end exclusive.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct TextRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EditSuggestionRequest {
+    pub content: String,
+    pub instruction: String,
+    pub selection: Option<TextRange>,
+}
+
+/// A rewrite by the AI, to be reviewed before it goes into the document.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct EditSuggestion {
+    pub original: String,
+    pub replacement: String,
+    pub selection: Option<TextRange>,
+}
+
+/// Where the answer of an AI action goes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    Replace,
+    After,
+}
+
+/// Server settings the app reads when it starts.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ClientConfig {
+    pub diagrams: Vec<DiagramLanguageConfig>,
+}
+
+/// A code block language drawn as a diagram, e.g. `graphviz` with the
+/// alias `dot`.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct DiagramLanguageConfig {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// `svg` or `png`.
+    pub format: String,
+}
+
+/// Something the AI can do with selected text, as configured on the server.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct AiAction {
+    pub id: String,
+    pub label: String,
+    /// What to ask the user for before running the action, e.g. "Language".
+    pub argument: Option<String>,
+    pub placement: Placement,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiActionRequest {
+    pub content: String,
+    pub selection: TextRange,
+    pub argument: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddTagRequest {
+    pub tag: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddCollaboratorRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveDocumentRequest {
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateFolderRequest {
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdateFolderRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `Some(None)` moves the folder to the top level.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Option<Uuid>>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -74,10 +346,17 @@ pub struct ApiError {
     pub error: String,
     #[serde(skip, default)]
     pub status: u16,
+    /// Current server copy, sent along with a 409 Conflict on document updates.
+    #[serde(default)]
+    pub document: Option<Document>,
 }
 
 impl ApiError {
     pub fn is_unauthorized(&self) -> bool {
         self.status == 401
     }
+
+    pub fn is_conflict(&self) -> bool {
+        self.status == 409
+    }
 }
\ No newline at end of file
@@ -24,7 +24,6 @@ pub fn DocumentPage() -> impl IntoView {
                 let _ = window.location().set_href(&target);
             }
         }
-        ()
     });
 
     Effect::new(move |_| {
@@ -39,6 +38,7 @@ pub fn DocumentPage() -> impl IntoView {
     });
 
     let (document_and_client, set_document_and_client) = signal(None::<(Arc<ApiClient>, Document)>);
+    let (tags, set_tags) = signal(Vec::<String>::new());
     let (loading, set_loading) = signal(true);
     let (error, set_error) = signal(None::<String>);
 
@@ -57,6 +57,7 @@ pub fn DocumentPage() -> impl IntoView {
                     let client_arc = Arc::new(client);
                     match client_arc.get_document(doc_id).await {
                         Ok(doc) => {
+                            set_tags.set(client_arc.get_tags(doc_id).await.unwrap_or_default());
                             set_document_and_client.set(Some((client_arc, doc)));
                             set_loading.set(false);
                         }
@@ -118,6 +119,8 @@ pub fn DocumentPage() -> impl IntoView {
                                 on_delete=move |_| {
                                     set_delete_trigger.set(());
                                 }
+                                tags=tags.into()
+                                on_tags_change=move |_, new_tags| set_tags.set(new_tags)
                                 client={client.clone()}
                             />
                         }
@@ -1,23 +1,22 @@
 use leptos::*;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
-use leptos_router::hooks::use_navigate;
-use leptos_router::NavigateOptions;
 use uuid::Uuid;
 use std::sync::Arc;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use std::io::Write;
-use pulldown_cmark::{Parser, Options, Event, Tag, CodeBlockKind, TagEnd};
 use web_sys;
 use js_sys;
+use dr_markdown_collab::{Participant, TextOperation};
+use dr_markdown_llm::ChatEvent;
 
 use crate::{
-    api::ApiClient,
+    api::{read_chat_events, ApiClient},
     auth::use_auth,
-    components::DocumentSidebar,
-    models::{Document, DocumentSummary},
-    app::{THEME_LIGHT, THEME_DARK, KROKI_URL, APP_BASE, use_chat_sidebar, use_sidebar, use_editor, use_dirty},
+    download::save_file,
+    markdown::{render_markdown, DiagramAccess},
+    components::{AiActionMenu, ConflictDialog, DocumentSidebar, EditPrompt, RevisionHistory, SuggestionReview, merge_with_markers},
+    live::{char_to_utf16_index, rebase, utf16_to_char_index, LiveConnection, LiveHandlers, LiveStatus},
+    models::{AiAction, Document, DocumentSummary, EditSuggestion, Folder, ImportFileResult, Placement, SearchResult, TextRange},
+    app::{THEME_LIGHT, THEME_DARK, APP_BASE, use_chat_sidebar, use_sidebar, use_editor, use_editor_insert, use_editor_selection, use_dirty, use_current_document},
 };
 
 #[component]
@@ -53,9 +52,14 @@ pub fn HomePage() -> impl IntoView {
     let token = initial_state.token.unwrap_or_default();
 
     let documents = RwSignal::new(Vec::<DocumentSummary>::new());
+    let folders = RwSignal::new(Vec::<Folder>::new());
     let selected_document = RwSignal::new(Option::<Document>::None);
     let loading_documents = RwSignal::new(true);
     let error_message = RwSignal::new(Option::<String>::None);
+    let search_query = RwSignal::new(String::new());
+    let search_results = RwSignal::new(Option::<Vec<SearchResult>>::None);
+    let importing = RwSignal::new(false);
+    let import_results = RwSignal::new(Option::<Vec<ImportFileResult>>::None);
 
     let client = Arc::new(ApiClient::with_token(token));
     let editor_context = use_editor();
@@ -66,11 +70,14 @@ pub fn HomePage() -> impl IntoView {
     spawn_local(async move {
         match client_effect.get_documents().await {
             Ok(docs) => {
+                if let Ok(list) = client_effect.get_folders().await {
+                    folders.set(list);
+                }
                 documents.set(docs);
                 loading_documents.set(false);
             },
             Err(err) => {
-                if err.status == 401 {
+                if err.is_unauthorized() {
                     // Token expired — clear auth and redirect
                     auth.logout.dispatch(());
                 } else {
@@ -94,6 +101,8 @@ pub fn HomePage() -> impl IntoView {
                             title: doc.title.clone(),
                             created_at: doc.created_at,
                             updated_at: doc.updated_at,
+                            folder_id: doc.folder_id,
+                            tags: Vec::new(),
                         });
                     });
                     selected_document.set(Some(doc));
@@ -107,14 +116,24 @@ pub fn HomePage() -> impl IntoView {
 
     let client_for_editor = client.clone();
     let client_for_on_select = client.clone();
+    let client_for_search = client.clone();
+    let client_for_move_document = client.clone();
+    let client_for_move_folder = client.clone();
+    let client_for_create_folder = client.clone();
+    let client_for_rename_folder = client.clone();
+    let client_for_delete_folder = client.clone();
+    let client_for_import = client.clone();
+    let client_for_export = client.clone();
     let user_name = user.username.clone();
 
     view! {
         <div class="relative flex h-full bg-gray-50 dark:bg-gray-900">
             <DocumentSidebar
                 documents=documents.read_only().into()
+                folders=folders.read_only().into()
                 selected_document=selected_document.read_only().into()
                 loading=loading_documents.read_only().into()
+                search_results=search_results.read_only().into()
                 on_select=move |doc_id| {
                     let client = client_for_on_select.clone();
                     spawn_local(async move {
@@ -142,6 +161,157 @@ pub fn HomePage() -> impl IntoView {
                         auth.update_settings.dispatch(current_user);
                     }
                 }
+                on_search=move |query: String| {
+                    search_query.set(query.clone());
+                    if query.trim().is_empty() {
+                        search_results.set(None);
+                        return;
+                    }
+                    // Wait until the user stops typing for a moment, rather
+                    // than searching for every keystroke
+                    let client = client_for_search.clone();
+                    set_timeout(
+                        move || {
+                            if search_query.try_get_untracked().as_ref() != Some(&query) {
+                                return;
+                            }
+                            spawn_local(async move {
+                                let result = client.search_documents(&query).await;
+                                // Responses may arrive out of order; only show the latest
+                                if search_query.get_untracked() != query {
+                                    return;
+                                }
+                                match result {
+                                    Ok(results) => search_results.set(Some(results)),
+                                    Err(err) => error_message.set(Some(err.error)),
+                                }
+                            });
+                        },
+                        std::time::Duration::from_millis(250),
+                    );
+                }
+                on_move_document=move |doc_id, folder_id| {
+                    let client = client_for_move_document.clone();
+                    spawn_local(async move {
+                        match client.move_document(doc_id, folder_id).await {
+                            Ok(doc) => {
+                                documents.update(|docs| {
+                                    if let Some(summary) = docs.iter_mut().find(|d| d.id == doc.id) {
+                                        summary.folder_id = doc.folder_id;
+                                    }
+                                });
+                                // Don't rebuild the open editor just for its location
+                                selected_document.update_untracked(|selected| {
+                                    if let Some(selected) = selected.as_mut().filter(|s| s.id == doc.id) {
+                                        selected.folder_id = doc.folder_id;
+                                    }
+                                });
+                            }
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                on_move_folder=move |folder_id, parent_id| {
+                    let client = client_for_move_folder.clone();
+                    spawn_local(async move {
+                        match client.update_folder(folder_id, None, Some(parent_id)).await {
+                            Ok(folder) => folders.update(|list| {
+                                if let Some(existing) = list.iter_mut().find(|f| f.id == folder.id) {
+                                    *existing = folder;
+                                }
+                            }),
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                on_create_folder=move |name, parent_id| {
+                    let client = client_for_create_folder.clone();
+                    spawn_local(async move {
+                        match client.create_folder(&name, parent_id).await {
+                            Ok(folder) => folders.update(|list| {
+                                list.push(folder);
+                                list.sort_by_key(|f| f.name.to_lowercase());
+                            }),
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                on_rename_folder=move |folder_id, name| {
+                    let client = client_for_rename_folder.clone();
+                    spawn_local(async move {
+                        match client.update_folder(folder_id, Some(&name), None).await {
+                            Ok(folder) => folders.update(|list| {
+                                if let Some(existing) = list.iter_mut().find(|f| f.id == folder.id) {
+                                    *existing = folder;
+                                }
+                                list.sort_by_key(|f| f.name.to_lowercase());
+                            }),
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                on_delete_folder=move |folder_id| {
+                    let client = client_for_delete_folder.clone();
+                    spawn_local(async move {
+                        match client.delete_folder(folder_id).await {
+                            Ok(()) => {
+                                // The server moves the folder's contents up one level
+                                let parent_id = folders.with_untracked(|list| {
+                                    list.iter().find(|f| f.id == folder_id).and_then(|f| f.parent_id)
+                                });
+                                folders.update(|list| {
+                                    list.retain(|f| f.id != folder_id);
+                                    for folder in list.iter_mut().filter(|f| f.parent_id == Some(folder_id)) {
+                                        folder.parent_id = parent_id;
+                                    }
+                                });
+                                documents.update(|docs| {
+                                    for doc in docs.iter_mut().filter(|d| d.folder_id == Some(folder_id)) {
+                                        doc.folder_id = parent_id;
+                                    }
+                                });
+                            }
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                on_import=move |files, create_folders| {
+                    let client = client_for_import.clone();
+                    importing.set(true);
+                    spawn_local(async move {
+                        match client.import_documents(&files, create_folders).await {
+                            Ok(response) => {
+                                folders.update(|list| {
+                                    list.extend(response.folders);
+                                    list.sort_by_key(|f| f.name.to_lowercase());
+                                });
+                                documents.update(|docs| {
+                                    for doc in response.results.iter().filter_map(|r| r.document.clone()) {
+                                        docs.insert(0, doc);
+                                    }
+                                });
+                                import_results.set(Some(response.results));
+                            }
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                        importing.set(false);
+                    });
+                }
+                on_export=move || {
+                    let client = client_for_export.clone();
+                    spawn_local(async move {
+                        match client.export_documents().await {
+                            Ok(file) => {
+                                if let Err(err) = save_file(&file) {
+                                    error_message.set(Some(err));
+                                }
+                            }
+                            Err(err) => error_message.set(Some(err.error)),
+                        }
+                    });
+                }
+                importing=importing.read_only().into()
+                import_results=import_results
                 user_name=user_name
             />
 
@@ -187,6 +357,22 @@ pub fn HomePage() -> impl IntoView {
                             editor_context.0.set(String::new());
                             dirty.0.set(false);
                         }
+                        tags=Signal::derive(move || {
+                            let id = selected_document.with(|doc| doc.as_ref().map(|doc| doc.id));
+                            documents.with(|docs| {
+                                docs.iter()
+                                    .find(|doc| Some(doc.id) == id)
+                                    .map(|doc| doc.tags.clone())
+                                    .unwrap_or_default()
+                            })
+                        })
+                        on_tags_change=move |doc_id, tags| {
+                            documents.update(|docs| {
+                                if let Some(doc_summary) = docs.iter_mut().find(|d| d.id == doc_id) {
+                                    doc_summary.tags = tags;
+                                }
+                            });
+                        }
                         client=client_for_editor.clone()
                     />
                 </Show>
@@ -200,65 +386,13 @@ pub fn HomePage() -> impl IntoView {
     }.into_any()
 }
 
-fn generate_kroki_url(diagram_type: &str, code: &str) -> String {
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(code.as_bytes()).unwrap();
-    let compressed_bytes = encoder.finish().unwrap();
-    let encoded_data = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, compressed_bytes);
-    format!("{}/{}/svg/{}", KROKI_URL, diagram_type, encoded_data)
-}
-
-pub fn render_markdown(markdown_content: &str) -> String {
-    let parser = Parser::new_ext(markdown_content, Options::all());
-
-    let supported_diagrams = ["mermaid", "plantuml", "graphviz", "ditaa", "blockdiag", "structurizr", "seqdiag"];
-    let mut in_diagram_block = false;
-    let mut diagram_lang = String::new();
-
-    let transformed_events: Vec<Event> = parser.filter_map(|event| {
-        match event {
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
-                let lang_str = lang.into_string();
-                if supported_diagrams.contains(&lang_str.as_str()) {
-                    in_diagram_block = true;
-                    diagram_lang = lang_str;
-                    None
-                } else {
-                    Some(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang_str.into()))))
-                }
-            }
-            Event::Text(text) => {
-                if in_diagram_block {
-                    let kroki_url = generate_kroki_url(&diagram_lang, &text);
-                    let html = format!("<img class=\"kroki-diagram\" src=\"{}\" alt=\"Diagram: {}\">", kroki_url, diagram_lang);
-                    Some(Event::Html(html.into()))
-                } else {
-                    Some(Event::Text(text))
-                }
-            }
-            Event::End(TagEnd::CodeBlock) => {
-                if in_diagram_block {
-                    in_diagram_block = false;
-                    diagram_lang.clear();
-                    None
-                } else {
-                    Some(Event::End(TagEnd::CodeBlock))
-                }
-            }
-            _ => Some(event),
-        }
-    }).collect();
-
-    let mut html_output = String::new();
-    pulldown_cmark::html::push_html(&mut html_output, transformed_events.into_iter());
-    html_output
-}
-
 #[component]
 pub fn DocumentEditor(
     document: Document,
     on_save: impl Fn(Document) + 'static + Clone,
     on_delete: impl Fn(Uuid) + 'static + Clone,
+    tags: Signal<Vec<String>>,
+    on_tags_change: impl Fn(Uuid, Vec<String>) + 'static + Clone,
     client: Arc<ApiClient>,
 ) -> impl IntoView {
     let (content, set_content) = signal(document.content.clone());
@@ -273,6 +407,24 @@ pub fn DocumentEditor(
     let (show_share_dialog, set_show_share_dialog) = signal(false);
     let (share_copied, set_share_copied) = signal(false);
 
+    // Revision history state
+    let (show_history, set_show_history) = signal(false);
+
+    // Export state
+    let (show_export_menu, set_show_export_menu) = signal(false);
+    let (export_error, set_export_error) = signal(None::<String>);
+
+    // Concurrency state: the server timestamp our copy is based on, and the
+    // newer server copy when a save was rejected
+    let (last_updated_at, set_last_updated_at) = signal(document.updated_at);
+    let (conflict, set_conflict) = signal(None::<Document>);
+    let (saved_title, set_saved_title) = signal(document.title.clone());
+
+    // Live editing state
+    let live = StoredValue::new_local(None::<LiveConnection>);
+    let (live_status, set_live_status) = signal(LiveStatus::Connecting);
+    let (participants, set_participants) = signal(Vec::<Participant>::new());
+
     let chat_sidebar = use_chat_sidebar();
     let mobile_sidebar = use_sidebar();
     let dirty = use_dirty();
@@ -281,19 +433,48 @@ pub fn DocumentEditor(
     editor_context.0.set(document.content.clone());
     dirty.0.set(false);
 
+    // Another document may already be open by the time this one is cleaned up
+    let current_document = use_current_document();
+    current_document.0.set(Some(document.id));
+    let opened_id = document.id;
+    on_cleanup(move || {
+        current_document.0.try_update(|current| {
+            if *current == Some(opened_id) {
+                *current = None;
+            }
+        });
+    });
+
     let editor_ref = NodeRef::new();
     let preview_ref = NodeRef::new();
 
+    let editor_selection = use_editor_selection();
+    editor_selection.0.set(String::new());
+    let track_selection = move || {
+        let selected = editor_ref
+            .get_untracked()
+            .and_then(|editor| {
+                let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+                let current = content.get_untracked();
+                let start = utf16_to_char_index(&current, editor_el.selection_start().ok().flatten()?);
+                let end = utf16_to_char_index(&current, editor_el.selection_end().ok().flatten()?);
+                Some(current.chars().skip(start).take(end.saturating_sub(start)).collect::<String>())
+            })
+            .unwrap_or_default();
+        editor_selection.0.set(selected);
+    };
+
     let client_save = client.clone();
     let doc_id = document.id;
     let save_document = Action::new_local(move |_: &()| {
         let new_title = title.get_untracked();
         let new_content = content.get_untracked();
+        let based_on = last_updated_at.get_untracked();
         let client_save_clone = client_save.clone();
 
         async move {
             set_saving.set(true);
-            let result = client_save_clone.update_document(doc_id, Some(&new_title), Some(&new_content)).await;
+            let result = client_save_clone.update_document(doc_id, Some(&new_title), Some(&new_content), Some(based_on)).await;
             set_saving.set(false);
             result
         }
@@ -301,10 +482,16 @@ pub fn DocumentEditor(
 
     let on_save_clone = on_save.clone();
     Effect::new(move |_| {
-        save_document.value().with(|opt_result| {
-            if let Some(Ok(updated_doc)) = opt_result {
+        save_document.value().with(|opt_result| match opt_result {
+            Some(Ok(updated_doc)) => {
+                set_last_updated_at.set(updated_doc.updated_at);
+                set_saved_title.set(updated_doc.title.clone());
                 on_save_clone(updated_doc.clone());
             }
+            Some(Err(err)) if err.is_conflict() => {
+                set_conflict.set(err.document.clone());
+            }
+            _ => {}
         });
     });
 
@@ -325,6 +512,23 @@ pub fn DocumentEditor(
         });
     });
 
+    let client_export = client.clone();
+    let export_document = Action::new_local(move |format: &&'static str| {
+        let client_clone = client_export.clone();
+        let format = *format;
+        async move {
+            set_export_error.set(None);
+            match client_clone.export_document(doc_id, format).await {
+                Ok(file) => {
+                    if let Err(err) = save_file(&file) {
+                        set_export_error.set(Some(err));
+                    }
+                }
+                Err(err) => set_export_error.set(Some(err.error)),
+            }
+        }
+    });
+
     // Share actions
     let client_share = client.clone();
     let create_share = Action::new_local(move |_: &()| {
@@ -358,8 +562,372 @@ pub fn DocumentEditor(
         });
     });
 
+    // Collaborators, who edit the document live together with its owner
+    let (collaborators, set_collaborators) = signal(Vec::<String>::new());
+    let (new_collaborator, set_new_collaborator) = signal(String::new());
+    let (collaborator_error, set_collaborator_error) = signal(None::<String>);
+    let client_collaborators = client.clone();
+    let load_collaborators = Action::new_local(move |_: &()| {
+        let client_clone = client_collaborators.clone();
+        async move { client_clone.get_collaborators(doc_id).await }
+    });
+    let client_add_collaborator = client.clone();
+    let add_collaborator = Action::new_local(move |username: &String| {
+        let username = username.clone();
+        let client_clone = client_add_collaborator.clone();
+        async move { client_clone.add_collaborator(doc_id, &username).await }
+    });
+    let client_remove_collaborator = client.clone();
+    let remove_collaborator = Action::new_local(move |username: &String| {
+        let username = username.clone();
+        let client_clone = client_remove_collaborator.clone();
+        async move { client_clone.remove_collaborator(doc_id, &username).await }
+    });
+    for result in [load_collaborators.value(), add_collaborator.value(), remove_collaborator.value()] {
+        Effect::new(move |_| {
+            result.with(|opt_result| match opt_result {
+                Some(Ok(list)) => {
+                    set_collaborators.set(list.clone());
+                    set_collaborator_error.set(None);
+                }
+                Some(Err(err)) => set_collaborator_error.set(Some(err.error.clone())),
+                None => {}
+            });
+        });
+    }
+
+    let editor_url = move || {
+        let window = web_sys::window().expect("no window");
+        let origin = window.location().origin().unwrap_or_default();
+        format!("{}{}/documents/{}", origin, APP_BASE, doc_id)
+    };
+
+    // Replaces the whole text and hands the change to the live session
+    let replace_content = move |new_content: String| {
+        let operation = TextOperation::from_diff(&content.get_untracked(), &new_content);
+        live.with_value(|connection| {
+            if let Some(connection) = connection {
+                connection.local_edit(operation);
+            }
+        });
+        set_content.set(new_content.clone());
+        editor_context.0.set(new_content);
+    };
+
+    // Applies a remote edit while keeping the local selection in place
+    let apply_remote_edit = move |operation: TextOperation| {
+        let current = content.get_untracked();
+        let new_content = match operation.apply(&current) {
+            Ok(new_content) => new_content,
+            Err(_) => {
+                live.with_value(|connection| {
+                    if let Some(connection) = connection {
+                        connection.resync();
+                    }
+                });
+                return;
+            }
+        };
+
+        if let Some(editor) = editor_ref.get_untracked() {
+            let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+            let start = editor_el.selection_start().ok().flatten().unwrap_or(0);
+            let end = editor_el.selection_end().ok().flatten().unwrap_or(start);
+            let start = operation.transform_index(utf16_to_char_index(&current, start));
+            let end = operation.transform_index(utf16_to_char_index(&current, end));
+            editor_el.set_value(&new_content);
+            let _ = editor_el.set_selection_range(
+                char_to_utf16_index(&new_content, start),
+                char_to_utf16_index(&new_content, end),
+            );
+        }
+
+        set_content.set(new_content.clone());
+        editor_context.0.set(new_content);
+    };
+
+    // The server copy the local text is based on, to rebase edits made
+    // while offline on top of whatever others did meanwhile
+    let live_base = StoredValue::new(document.content.clone());
+    let live_url = client.live_document_url(doc_id);
+    let connect_live = move || {
+        if let Some(base) = live.with_value(|connection| connection.as_ref().and_then(LiveConnection::server_content)) {
+            live_base.set_value(base);
+        }
+        let handlers = LiveHandlers {
+            on_init: Box::new(move |server_content: String| {
+                if dirty.0.get_untracked() {
+                    let local = content.get_untracked();
+                    let merged = rebase(&live_base.get_value(), &local, &server_content);
+                    set_content.set(server_content);
+                    replace_content(merged);
+                } else {
+                    set_content.set(server_content.clone());
+                    editor_context.0.set(server_content);
+                }
+            }),
+            on_remote_edit: Box::new(apply_remote_edit),
+            on_presence: Box::new(move |list| set_participants.set(list)),
+            on_saved: Box::new(move |updated_at, synchronized| {
+                set_last_updated_at.set(updated_at);
+                if synchronized && title.get_untracked() == saved_title.get_untracked() {
+                    dirty.0.set(false);
+                }
+            }),
+            on_status: Box::new(move |status| set_live_status.set(status)),
+        };
+        match LiveConnection::connect(&live_url, handlers) {
+            Ok(connection) => {
+                live.try_set_value(Some(connection));
+            }
+            Err(e) => {
+                leptos::logging::log!("Failed to open live connection: {}", e);
+                set_live_status.set(LiveStatus::Disconnected);
+            }
+        }
+    };
+    connect_live();
+
+    // Keep trying to get back into the live session after losing it
+    Effect::new(move |_| {
+        if live_status.get() == LiveStatus::Disconnected {
+            let connect_live = connect_live.clone();
+            set_timeout(
+                move || {
+                    if live.try_with_value(|_| ()).is_some() {
+                        connect_live();
+                    }
+                },
+                std::time::Duration::from_secs(3),
+            );
+        }
+    });
+
+    // Tag actions
+    let (new_tag, set_new_tag) = signal(String::new());
+    let client_tag_add = client.clone();
+    let add_tag = Action::new_local(move |tag: &String| {
+        let tag = tag.clone();
+        let client_clone = client_tag_add.clone();
+        async move {
+            client_clone.add_tag(doc_id, &tag).await
+        }
+    });
+
+    let client_tag_remove = client.clone();
+    let remove_tag = Action::new_local(move |tag: &String| {
+        let tag = tag.clone();
+        let client_clone = client_tag_remove.clone();
+        async move {
+            client_clone.remove_tag(doc_id, &tag).await
+        }
+    });
+
+    for action in [add_tag, remove_tag] {
+        let on_tags_change = on_tags_change.clone();
+        Effect::new(move |_| {
+            action.value().with(|opt_result| {
+                if let Some(Ok(tags)) = opt_result {
+                    on_tags_change(doc_id, tags.clone());
+                }
+            });
+        });
+    }
+
+    // Restored revisions and server copies picked in the conflict dialog replace
+    // the local state, so propagate them like a save
+    let client_history = client.clone();
+    let (server_document, set_server_document) = signal(None::<Document>);
+    let on_save_server = on_save.clone();
+    Effect::new(move |_| {
+        if let Some(doc) = server_document.get() {
+            set_title.set(doc.title.clone());
+            replace_content(doc.content.clone());
+            set_last_updated_at.set(doc.updated_at);
+            set_saved_title.set(doc.title.clone());
+            dirty.0.set(false);
+            on_save_server(doc);
+        }
+    });
+
+    // AI edit state: the part of the text that is rewritten, as characters
+    // around it that stay, and the suggestion under review
+    let (show_edit_prompt, set_show_edit_prompt) = signal(false);
+    let (edit_selection, set_edit_selection) = signal(None::<TextRange>);
+    let (edit_error, set_edit_error) = signal(None::<String>);
+    let (suggestion, set_suggestion) = signal(None::<EditSuggestion>);
+    let suggestion_surroundings = StoredValue::new((String::new(), String::new()));
+
+    let open_edit_prompt = move || {
+        // Without a selection the whole document is rewritten
+        let selection = editor_ref.get_untracked().and_then(|editor| {
+            let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+            let current = content.get_untracked();
+            let start = utf16_to_char_index(&current, editor_el.selection_start().ok().flatten()?);
+            let end = utf16_to_char_index(&current, editor_el.selection_end().ok().flatten()?);
+            (start < end).then_some(TextRange { start, end })
+        });
+        set_edit_selection.set(selection);
+        set_edit_error.set(None);
+        set_show_edit_prompt.set(true);
+    };
+
+    let client_edit = client.clone();
+    let suggest_edit = Action::new_local(move |instruction: &String| {
+        let instruction = instruction.clone();
+        let current = content.get_untracked();
+        let selection = edit_selection.get_untracked();
+        let client_edit = client_edit.clone();
+        async move {
+            let result = client_edit.suggest_edit(&current, &instruction, selection).await;
+            (current, result)
+        }
+    });
+
+    Effect::new(move |_| {
+        suggest_edit.value().with(|opt_result| match opt_result {
+            Some((current, Ok(suggested))) => {
+                let (start, end) = suggested
+                    .selection
+                    .map(|range| (range.start, range.end))
+                    .unwrap_or((0, current.chars().count()));
+                suggestion_surroundings.set_value((
+                    current.chars().take(start).collect(),
+                    current.chars().skip(end).collect(),
+                ));
+                set_show_edit_prompt.set(false);
+                set_suggestion.set(Some(suggested.clone()));
+            }
+            Some((_, Err(err))) => set_edit_error.set(Some(err.error.clone())),
+            None => {}
+        });
+    });
+
+    // AI actions on the selected text, streamed into the document in place
+    let (ai_actions, set_ai_actions) = signal(Vec::<AiAction>::new());
+    let (action_running, set_action_running) = signal(false);
+    let (action_error, set_action_error) = signal(None::<String>);
+
+    let client_actions = client.clone();
+    spawn_local(async move {
+        if let Ok(actions) = client_actions.get_ai_actions().await {
+            set_ai_actions.set(actions);
+        }
+    });
+
+    let client_run = client.clone();
+    let run_ai_action = move |action: AiAction, argument: Option<String>| {
+        let Some(editor) = editor_ref.get_untracked() else {
+            return;
+        };
+        let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+        let current = content.get_untracked();
+        let start = utf16_to_char_index(&current, editor_el.selection_start().ok().flatten().unwrap_or(0));
+        let end = utf16_to_char_index(&current, editor_el.selection_end().ok().flatten().unwrap_or(0));
+        if start >= end {
+            set_action_error.set(Some("Select some text first".to_string()));
+            return;
+        }
+
+        // The answer goes between these, taking the place of the selection or
+        // following it
+        let insert_at = if action.placement == Placement::After { end } else { start };
+        let before: String = current.chars().take(insert_at).collect();
+        let after: String = current.chars().skip(end).collect();
+
+        set_action_error.set(None);
+        set_action_running.set(true);
+        let client_run = client_run.clone();
+        spawn_local(async move {
+            let response = match client_run.run_ai_action(&action.id, &current, TextRange { start, end }, argument).await {
+                Ok(response) => response,
+                Err(err) => {
+                    set_action_error.set(Some(err.error));
+                    set_action_running.set(false);
+                    return;
+                }
+            };
+
+            let mut answer = String::new();
+            let result = read_chat_events(response, |event| match event {
+                ChatEvent::Delta { text } => {
+                    answer.push_str(&text);
+                    replace_content(format!("{}{}{}", before, answer, after));
+                    dirty.0.set(true);
+                }
+                ChatEvent::Sources { .. } | ChatEvent::Done { .. } => {}
+                ChatEvent::Error { message } => set_action_error.set(Some(message)),
+            })
+            .await;
+            if let Err(err) = result {
+                set_action_error.set(Some(err));
+            }
+            set_action_running.set(false);
+
+            // Select what the AI wrote, so it is easy to spot or undo
+            if let Some(editor) = editor_ref.get_untracked() {
+                let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+                let text = content.get_untracked();
+                let inserted = before.chars().count();
+                let _ = editor_el.focus();
+                let _ = editor_el.set_selection_range(
+                    char_to_utf16_index(&text, inserted),
+                    char_to_utf16_index(&text, inserted + answer.chars().count()),
+                );
+            }
+        });
+    };
+
+    // Text from the AI assistant takes the place of the selection, or goes to
+    // the end of the document if the editor is not open
+    let editor_insert = use_editor_insert();
+    Effect::new(move |_| {
+        let Some(text) = editor_insert.0.get() else {
+            return;
+        };
+        editor_insert.0.set(None);
+        if action_running.get_untracked() || suggestion.with_untracked(Option::is_some) {
+            return;
+        }
+
+        let current = content.get_untracked();
+        let editor = editor_ref.get_untracked().filter(|_| is_editing.get_untracked());
+        let (start, end) = match &editor {
+            Some(editor) => {
+                let editor_el: &web_sys::HtmlTextAreaElement = editor;
+                (
+                    utf16_to_char_index(&current, editor_el.selection_start().ok().flatten().unwrap_or(0)),
+                    utf16_to_char_index(&current, editor_el.selection_end().ok().flatten().unwrap_or(0)),
+                )
+            }
+            None => {
+                let end = current.chars().count();
+                (end, end)
+            }
+        };
+        let before: String = current.chars().take(start).collect();
+        let after: String = current.chars().skip(end).collect();
+        let cursor = start + text.chars().count();
+
+        replace_content(format!("{}{}{}", before, text, after));
+        dirty.0.set(true);
+        set_is_editing.set(true);
+
+        // The editor may only be shown on the next frame
+        request_animation_frame(move || {
+            if let Some(editor) = editor_ref.get_untracked() {
+                let editor_el: &web_sys::HtmlTextAreaElement = &editor;
+                let position = char_to_utf16_index(&content.get_untracked(), cursor);
+                let _ = editor_el.focus();
+                let _ = editor_el.set_selection_range(position, position);
+            }
+        });
+    });
+
+    let auth = use_auth();
     let rendered_html = move || {
-        render_markdown(&content.get())
+        let token = auth.state.get_untracked().token.unwrap_or_default();
+        render_markdown(&content.get(), DiagramAccess::Login(&token))
     };
 
     let share_url = move || {
@@ -398,6 +966,69 @@ pub fn DocumentEditor(
                     <Show when=move || dirty.0.get()>
                         <span class="text-xs text-amber-500 dark:text-amber-400 shrink-0 font-medium">"Unsaved"</span>
                     </Show>
+                    // Live editing presence
+                    <div class="flex items-center gap-1.5 shrink-0">
+                        <div class="flex -space-x-1.5">
+                            {move || participants.get().into_iter().map(|participant| {
+                                let initial = participant.username.chars().next().unwrap_or('?').to_uppercase().to_string();
+                                view! {
+                                    <span
+                                        class="w-6 h-6 rounded-full bg-blue-600 text-white text-xs font-medium flex items-center justify-center ring-2 ring-white dark:ring-gray-800"
+                                        title=participant.username
+                                    >
+                                        {initial}
+                                    </span>
+                                }
+                            }).collect_view()}
+                        </div>
+                        <span
+                            class="w-2 h-2 rounded-full"
+                            class=("bg-green-500", move || live_status.get() == LiveStatus::Connected)
+                            class=("bg-amber-400", move || live_status.get() == LiveStatus::Connecting)
+                            class=("bg-gray-400", move || live_status.get() == LiveStatus::Disconnected)
+                            title=move || match live_status.get() {
+                                LiveStatus::Connected => "Live editing",
+                                LiveStatus::Connecting => "Connecting...",
+                                LiveStatus::Disconnected => "Offline, reconnecting",
+                            }
+                        ></span>
+                    </div>
+                </div>
+                // Tags row
+                <div class="flex items-center gap-1.5 flex-wrap mb-2">
+                    {move || tags.get().into_iter().map(|tag| {
+                        let tag_for_remove = tag.clone();
+                        view! {
+                            <span class="inline-flex items-center gap-1 pl-2 pr-1 py-0.5 text-xs rounded-full bg-blue-50 dark:bg-blue-900/30 text-blue-700 dark:text-blue-300">
+                                "#" {tag}
+                                <button
+                                    class="rounded-full p-0.5 hover:bg-blue-100 dark:hover:bg-blue-800/50"
+                                    title="Remove tag"
+                                    on:click=move |_| { remove_tag.dispatch(tag_for_remove.clone()); }
+                                >
+                                    <svg class="w-3 h-3" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                                    </svg>
+                                </button>
+                            </span>
+                        }
+                    }).collect_view()}
+                    <input
+                        type="text"
+                        placeholder="Add tag"
+                        class="w-24 px-2 py-0.5 text-xs border border-transparent rounded-full bg-transparent text-gray-700 dark:text-gray-300 hover:border-gray-200 dark:hover:border-gray-600 focus:border-gray-300 dark:focus:border-gray-600 focus:outline-none"
+                        prop:value=new_tag
+                        on:input=move |ev| set_new_tag.set(event_target_value(&ev))
+                        on:keydown=move |ev| {
+                            if ev.key() == "Enter" {
+                                let tag = new_tag.get_untracked().trim().to_string();
+                                if !tag.is_empty() {
+                                    add_tag.dispatch(tag);
+                                }
+                                set_new_tag.set(String::new());
+                            }
+                        }
+                    />
                 </div>
                 // Action buttons row — wraps on mobile
                 <div class="flex items-center gap-1.5 flex-wrap">
@@ -448,6 +1079,31 @@ pub fn DocumentEditor(
                         </button>
                     </Show>
 
+                    <Show when=move || is_editing.get()>
+                        <AiActionMenu
+                            actions=ai_actions.into()
+                            running=action_running.into()
+                            on_run=run_ai_action.clone()
+                        />
+                        {move || action_error.get().map(|err| view! {
+                            <span class="text-xs text-red-600 dark:text-red-400">{err}</span>
+                        })}
+                    </Show>
+
+                    <Show when=move || is_editing.get()>
+                        <button
+                            class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 disabled:opacity-50 transition-colors"
+                            on:click=move |_| open_edit_prompt()
+                            disabled=move || suggestion.get().is_some()
+                            title="Let the AI rewrite the selection, or the whole document"
+                        >
+                            <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M5 3v4M3 5h4M6 17v4m-2-2h4m5-16l2.286 6.857L21 12l-5.714 2.143L13 21l-2.286-6.857L5 12l5.714-2.143L13 3z"></path>
+                            </svg>
+                            <span class="hidden sm:inline">"AI Edit"</span>
+                        </button>
+                    </Show>
+
                     <button
                         class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
                         on:click=move |_| chat_sidebar.0.update(|open| *open = !*open)
@@ -461,7 +1117,10 @@ pub fn DocumentEditor(
                     // Share button
                     <button
                         class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
-                        on:click=move |_| set_show_share_dialog.set(true)
+                        on:click=move |_| {
+                            set_show_share_dialog.set(true);
+                            load_collaborators.dispatch(());
+                        }
                     >
                         <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
                             <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M8.684 13.342C8.886 12.938 9 12.482 9 12c0-.482-.114-.938-.316-1.342m0 2.684a3 3 0 110-2.684m0 2.684l6.632 3.316m-6.632-6l6.632-3.316m0 0a3 3 0 105.367-2.684 3 3 0 00-5.367 2.684zm0 9.316a3 3 0 105.368 2.684 3 3 0 00-5.368-2.684z"></path>
@@ -469,6 +1128,59 @@ pub fn DocumentEditor(
                         <span class="hidden sm:inline">"Share"</span>
                     </button>
 
+                    // History button
+                    <button
+                        class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 transition-colors"
+                        on:click=move |_| set_show_history.set(true)
+                    >
+                        <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 8v4l3 3m6-3a9 9 0 11-18 0 9 9 0 0118 0z"></path>
+                        </svg>
+                        <span class="hidden sm:inline">"History"</span>
+                    </button>
+
+                    // Export menu
+                    <div class="relative">
+                        <button
+                            class="inline-flex items-center gap-1.5 px-3 py-1.5 text-xs font-medium text-gray-700 dark:text-gray-300 bg-gray-100 dark:bg-gray-700 rounded-lg hover:bg-gray-200 dark:hover:bg-gray-600 disabled:opacity-50 transition-colors"
+                            on:click=move |_| set_show_export_menu.update(|show| *show = !*show)
+                            disabled=move || export_document.pending().get()
+                        >
+                            <svg class="w-3.5 h-3.5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-4l-4 4m0 0l-4-4m4 4V4"></path>
+                            </svg>
+                            <span class="hidden sm:inline">
+                                {move || if export_document.pending().get() { "Exporting..." } else { "Export" }}
+                            </span>
+                        </button>
+                        <Show when=move || show_export_menu.get()>
+                            <div class="absolute left-0 mt-1 w-36 py-1 bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 rounded-lg shadow-lg z-20">
+                                <button
+                                    class="block w-full px-3 py-1.5 text-left text-xs text-gray-700 dark:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700"
+                                    on:click=move |_| {
+                                        set_show_export_menu.set(false);
+                                        export_document.dispatch("html");
+                                    }
+                                >
+                                    "HTML page"
+                                </button>
+                                <button
+                                    class="block w-full px-3 py-1.5 text-left text-xs text-gray-700 dark:text-gray-300 hover:bg-gray-100 dark:hover:bg-gray-700"
+                                    on:click=move |_| {
+                                        set_show_export_menu.set(false);
+                                        export_document.dispatch("pdf");
+                                    }
+                                >
+                                    "PDF document"
+                                </button>
+                            </div>
+                        </Show>
+                    </div>
+
+                    {move || export_error.get().map(|err| view! {
+                        <span class="text-xs text-red-600 dark:text-red-400">{err}</span>
+                    })}
+
                     <div class="flex-1"></div>
 
                     <Show when=move || is_editing.get()>
@@ -596,10 +1308,111 @@ pub fn DocumentEditor(
                                 "Remove Share Link"
                             </button>
                         </Show>
+
+                        <div class="mt-5 pt-4 border-t border-gray-200 dark:border-gray-700">
+                            <h4 class="text-sm font-semibold text-gray-900 dark:text-gray-50 mb-1">"Edit together"</h4>
+                            <p class="text-sm text-gray-600 dark:text-gray-400 mb-3">
+                                "These users can edit the document live with you at "
+                                <span class="font-mono text-xs break-all">{editor_url}</span>
+                            </p>
+                            <div class="flex flex-wrap gap-1.5 mb-3">
+                                {move || collaborators.get().into_iter().map(|username| {
+                                    let username_for_remove = username.clone();
+                                    view! {
+                                        <span class="inline-flex items-center gap-1 pl-2 pr-1 py-0.5 text-xs rounded-full bg-gray-100 dark:bg-gray-700 text-gray-700 dark:text-gray-300">
+                                            {username}
+                                            <button
+                                                class="rounded-full p-0.5 hover:bg-gray-200 dark:hover:bg-gray-600"
+                                                title="Remove"
+                                                on:click=move |_| { remove_collaborator.dispatch(username_for_remove.clone()); }
+                                            >
+                                                <svg class="w-3 h-3" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M6 18L18 6M6 6l12 12"></path>
+                                                </svg>
+                                            </button>
+                                        </span>
+                                    }
+                                }).collect_view()}
+                            </div>
+                            <input
+                                type="text"
+                                placeholder="Add a username"
+                                class="w-full px-3 py-2 text-sm bg-gray-50 dark:bg-gray-700 border border-gray-200 dark:border-gray-600 rounded-lg text-gray-700 dark:text-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-500"
+                                prop:value=new_collaborator
+                                on:input=move |ev| set_new_collaborator.set(event_target_value(&ev))
+                                on:keydown=move |ev| {
+                                    if ev.key() == "Enter" {
+                                        let username = new_collaborator.get_untracked().trim().to_string();
+                                        if !username.is_empty() {
+                                            add_collaborator.dispatch(username);
+                                        }
+                                        set_new_collaborator.set(String::new());
+                                    }
+                                }
+                            />
+                            {move || collaborator_error.get().map(|err| view! {
+                                <p class="text-red-500 mt-2 text-xs">{err}</p>
+                            })}
+                        </div>
                     </div>
                 </div>
             </Show>
 
+            // AI edit prompt
+            <Show when=move || show_edit_prompt.get()>
+                <EditPrompt
+                    scope=if edit_selection.get_untracked().is_some() { "The selected text".to_string() } else { "The whole document".to_string() }
+                    pending=suggest_edit.pending().into()
+                    error=edit_error.into()
+                    on_submit=move |instruction| { suggest_edit.dispatch(instruction); }
+                    on_cancel=move || set_show_edit_prompt.set(false)
+                />
+            </Show>
+
+            // Revision history panel
+            <Show when=move || show_history.get()>
+                <RevisionHistory
+                    document_id=doc_id
+                    client=client_history.clone()
+                    on_restore=move |restored: Document| {
+                        set_show_history.set(false);
+                        set_server_document.set(Some(restored));
+                    }
+                    on_close=move || set_show_history.set(false)
+                />
+            </Show>
+
+            // Save conflict dialog
+            {move || conflict.get().map(|server| {
+                let server_updated_at = server.updated_at;
+                let server_for_merge = server.clone();
+                let server_for_theirs = server.clone();
+                view! {
+                    <ConflictDialog
+                        server=server
+                        mine=content.into()
+                        on_keep_mine=move || {
+                            set_conflict.set(None);
+                            set_last_updated_at.set(server_updated_at);
+                            save_document.dispatch(());
+                        }
+                        on_take_theirs=move || {
+                            set_conflict.set(None);
+                            set_server_document.set(Some(server_for_theirs.clone()));
+                        }
+                        on_merge=move || {
+                            let merged = merge_with_markers(&content.get_untracked(), &server_for_merge.content);
+                            set_conflict.set(None);
+                            replace_content(merged);
+                            set_last_updated_at.set(server_updated_at);
+                            dirty.0.set(true);
+                            set_is_editing.set(true);
+                        }
+                        on_cancel=move || set_conflict.set(None)
+                    />
+                }
+            })}
+
             // Content area
             <div class="flex-1 overflow-hidden">
                 <Show
@@ -622,14 +1435,29 @@ pub fn DocumentEditor(
                             </div>
                         </Show>
                         <div class="flex-1 min-h-0">
+                            {move || suggestion.get().map(|suggested| view! {
+                                <SuggestionReview
+                                    suggestion=suggested
+                                    on_change=move |rewritten: String| {
+                                        let (before, after) = suggestion_surroundings.get_value();
+                                        replace_content(format!("{}{}{}", before, rewritten, after));
+                                        dirty.0.set(true);
+                                    }
+                                    on_close=move || set_suggestion.set(None)
+                                />
+                            })}
                             <textarea
+                                class:hidden=move || suggestion.get().is_some()
                                 node_ref=editor_ref
                                 class="w-full h-full p-4 sm:p-6 border-none outline-none resize-none font-mono text-sm bg-white dark:bg-gray-900 dark:text-gray-50"
                                 prop:value=content
+                                prop:readOnly=action_running
+                                on:select=move |_| track_selection()
+                                on:keyup=move |_| track_selection()
+                                on:mouseup=move |_| track_selection()
                                 on:input=move |ev| {
                                     let new_value = event_target_value(&ev);
-                                    set_content.set(new_value.clone());
-                                    editor_context.0.set(new_value.clone());
+                                    replace_content(new_value);
                                     dirty.0.set(true);
 
                                     request_animation_frame(move || {
@@ -5,7 +5,7 @@ use leptos_router::hooks::use_params_map;
 
 use crate::api::ApiClient;
 use crate::models::SharedDocument;
-use crate::pages::home::render_markdown;
+use crate::markdown::{render_markdown, DiagramAccess};
 
 #[component]
 pub fn SharedDocumentPage() -> impl IntoView {
@@ -64,7 +64,8 @@ pub fn SharedDocumentPage() -> impl IntoView {
                         }
                     >
                         {move || document.get().map(|doc| {
-                            let rendered = render_markdown(&doc.content);
+                            let share_token = params.with_untracked(|p| p.get("token").unwrap_or_default());
+                            let rendered = render_markdown(&doc.content, DiagramAccess::Share(&share_token));
                             view! {
                                 <div class="max-w-4xl mx-auto">
                                     <header class="sticky top-0 z-10 bg-white/80 dark:bg-gray-800/80 backdrop-blur-sm border-b border-gray-200 dark:border-gray-700 px-4 sm:px-6 lg:px-8 py-4">
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Who wrote a message of a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// Tokens used for one answer, as counted by the model server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// A document an answer draws on, cited as `[number]` in the answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub number: usize,
+    pub document_id: Uuid,
+    pub title: String,
+}
+
+/// A streamed answer, the same for every kind of model server. The backend
+/// sends one event as JSON per line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// The documents the answer draws on, before the answer itself.
+    Sources { sources: Vec<Source> },
+    /// The next piece of the answer.
+    Delta { text: String },
+    /// The answer is complete. Not every server reports the tokens used.
+    Done { usage: Option<Usage> },
+    /// The answer broke off, nothing follows.
+    Error { message: String },
+}
+
+impl ChatEvent {
+    /// Whether this is the last event of an answer.
+    pub fn is_final(&self) -> bool {
+        matches!(self, ChatEvent::Done { .. } | ChatEvent::Error { .. })
+    }
+}
+
+/// The kinds of model servers that can be talked to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// Ollama's native API.
+    #[default]
+    Ollama,
+    /// The `/v1/chat/completions` API of OpenAI, which llama.cpp, vLLM,
+    /// LM Studio and others offer as well.
+    OpenAi,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ollama" => Ok(ProviderKind::Ollama),
+            "openai" => Ok(ProviderKind::OpenAi),
+            other => Err(format!("Unknown LLM provider '{}', expected 'ollama' or 'openai'", other)),
+        }
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderKind::Ollama => write!(f, "ollama"),
+            ProviderKind::OpenAi => write!(f, "openai"),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Talking to language models: the chat messages and streamed answer events
+//! shared by the web frontend and the backend and, with the `providers`
+//! feature, clients for Ollama and OpenAI-compatible model servers that
+//! answer conversations and turn texts into embeddings.
+
+mod event;
+#[cfg(feature = "providers")]
+mod ollama;
+#[cfg(feature = "providers")]
+mod openai;
+#[cfg(feature = "providers")]
+mod provider;
+
+pub use event::{ChatEvent, ChatMessage, ProviderKind, Role, Source, Usage};
+#[cfg(feature = "providers")]
+pub use ollama::OllamaProvider;
+#[cfg(feature = "providers")]
+pub use openai::OpenAiProvider;
+#[cfg(feature = "providers")]
+pub use provider::{ChatStream, LlmError, LlmProvider};
@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{ChatEvent, ChatMessage, Usage};
+use crate::provider::{check_embeddings, event_stream, send, ChatStream, LlmError, LlmProvider};
+
+/// Talks to Ollama's `/api/chat`, which streams one JSON object per line,
+/// and `/api/embed`.
+pub struct OllamaProvider {
+    base_url: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl OllamaProvider {
+    /// `base_url` is where Ollama listens, e.g. `http://localhost:11434`.
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            model: model.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct OllamaLine {
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+fn parse_line(line: &str) -> Vec<ChatEvent> {
+    let line: OllamaLine = match serde_json::from_str(line) {
+        Ok(line) => line,
+        Err(e) => {
+            return vec![ChatEvent::Error {
+                message: format!("Unexpected answer from Ollama: {}", e),
+            }]
+        }
+    };
+
+    if let Some(message) = line.error {
+        return vec![ChatEvent::Error { message }];
+    }
+
+    let mut events = Vec::new();
+    if let Some(message) = line.message.filter(|message| !message.content.is_empty()) {
+        events.push(ChatEvent::Delta { text: message.content });
+    }
+    if line.done {
+        // Ollama leaves out the prompt count when the prompt was cached
+        let usage = (line.prompt_eval_count.is_some() || line.eval_count.is_some()).then(|| Usage {
+            prompt_tokens: line.prompt_eval_count.unwrap_or(0),
+            completion_tokens: line.eval_count.unwrap_or(0),
+        });
+        events.push(ChatEvent::Done { usage });
+    }
+    events
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn stream_chat(&self, messages: &[ChatMessage]) -> Result<ChatStream, LlmError> {
+        let request = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&OllamaRequest {
+                model: &self.model,
+                messages,
+                stream: true,
+            });
+
+        let response = send(request).await?;
+        Ok(event_stream(response, parse_line))
+    }
+
+    async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        let request = self
+            .client
+            .post(format!("{}/api/embed", self.base_url))
+            .json(&EmbedRequest { model, input: texts });
+
+        let response: EmbedResponse = send(request).await?.json().await?;
+        check_embeddings(response.embeddings, texts)
+    }
+}
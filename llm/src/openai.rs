@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{ChatEvent, ChatMessage, Usage};
+use crate::provider::{check_embeddings, event_stream, send, ChatStream, LlmError, LlmProvider};
+
+/// Talks to an OpenAI-compatible `/v1/chat/completions`, which streams
+/// server-sent events, and `/v1/embeddings`. Besides OpenAI itself this is offered by llama.cpp's
+/// server, vLLM, LM Studio and most other model servers.
+pub struct OpenAiProvider {
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    /// `base_url` is the server with or without the trailing `/v1`, e.g.
+    /// `http://localhost:8080` or `https://api.openai.com/v1`.
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let base_url = base_url.strip_suffix("/v1").unwrap_or(base_url);
+
+        Self {
+            base_url: base_url.to_string(),
+            model: model.into(),
+            api_key: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sent as bearer token, local servers usually do not need one.
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.post(format!("{}/v1/{}", self.base_url, path));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    stream_options: StreamOptions,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+    error: Option<CompletionError>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    delta: Option<Delta>,
+}
+
+#[derive(Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct CompletionError {
+    message: String,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+/// Returns a parser for the event stream. The usage arrives in a chunk of
+/// its own before `[DONE]`, so it is kept until then.
+fn line_parser() -> impl FnMut(&str) -> Vec<ChatEvent> + Send {
+    let mut usage = None;
+
+    move |line| {
+        // Comments and other fields than `data` carry nothing of interest
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Vec::new();
+        };
+        if data == "[DONE]" {
+            return vec![ChatEvent::Done { usage }];
+        }
+
+        let chunk: CompletionChunk = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return vec![ChatEvent::Error {
+                    message: format!("Unexpected answer from the model server: {}", e),
+                }]
+            }
+        };
+
+        if let Some(error) = chunk.error {
+            return vec![ChatEvent::Error { message: error.message }];
+        }
+        if let Some(chunk_usage) = chunk.usage {
+            usage = Some(Usage {
+                prompt_tokens: chunk_usage.prompt_tokens,
+                completion_tokens: chunk_usage.completion_tokens,
+            });
+        }
+
+        chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta?.content)
+            .filter(|text| !text.is_empty())
+            .map(|text| ChatEvent::Delta { text })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn stream_chat(&self, messages: &[ChatMessage]) -> Result<ChatStream, LlmError> {
+        let request = self.post("chat/completions").json(&CompletionRequest {
+            model: &self.model,
+            messages,
+            stream: true,
+            stream_options: StreamOptions { include_usage: true },
+        });
+
+        let response = send(request).await?;
+        Ok(event_stream(response, line_parser()))
+    }
+
+    async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        let request = self.post("embeddings").json(&EmbeddingRequest { model, input: texts });
+
+        // The order of the data is not guaranteed, the index is
+        let mut response: EmbeddingResponse = send(request).await?.json().await?;
+        response.data.sort_by_key(|embedding| embedding.index);
+        check_embeddings(response.data.into_iter().map(|embedding| embedding.embedding).collect(), texts)
+    }
+}
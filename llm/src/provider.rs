@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use reqwest::{RequestBuilder, Response};
+use thiserror::Error;
+
+use crate::event::{ChatEvent, ChatMessage};
+
+/// The events of one answer. It always ends with [`ChatEvent::Done`] or
+/// [`ChatEvent::Error`].
+pub type ChatStream = Pin<Box<dyn Stream<Item = ChatEvent> + Send>>;
+
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("Could not reach the model server: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("The model server answered with status {status}: {message}")]
+    Status { status: u16, message: String },
+    #[error("Unexpected answer from the model server: {0}")]
+    InvalidResponse(String),
+}
+
+/// A model server that answers conversations.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// The model that answers.
+    fn model(&self) -> &str;
+
+    /// Starts answering the conversation. Fails if the server cannot be
+    /// reached or rejects the request, problems after that end the stream
+    /// with [`ChatEvent::Error`].
+    async fn stream_chat(&self, messages: &[ChatMessage]) -> Result<ChatStream, LlmError>;
+
+    /// Turns every text into an embedding vector with the embedding `model`,
+    /// in the same order as the texts.
+    async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError>;
+}
+
+/// Checks that the server returned one embedding for every text.
+pub(crate) fn check_embeddings(embeddings: Vec<Vec<f32>>, texts: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+    if embeddings.len() != texts.len() {
+        return Err(LlmError::InvalidResponse(format!(
+            "{} embeddings for {} texts",
+            embeddings.len(),
+            texts.len()
+        )));
+    }
+    Ok(embeddings)
+}
+
+/// Sends the request and turns an unsuccessful status into an error that
+/// carries what the server said about it.
+pub(crate) async fn send(request: RequestBuilder) -> Result<Response, LlmError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = response.text().await.unwrap_or_default();
+    Err(LlmError::Status {
+        status: status.as_u16(),
+        message: message.trim().to_string(),
+    })
+}
+
+struct LineStream<P> {
+    body: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    pending: VecDeque<ChatEvent>,
+    parse: P,
+    ended: bool,
+    finished: bool,
+}
+
+impl<P: FnMut(&str) -> Vec<ChatEvent>> LineStream<P> {
+    fn parse_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if !line.is_empty() {
+            self.pending.extend((self.parse)(line));
+        }
+    }
+}
+
+/// Reads the response body line by line, lines may be split across chunks,
+/// and turns every line into events with `parse`. Nothing is read after the
+/// final event, and a final event is added if the server just stops.
+pub(crate) fn event_stream<P>(response: Response, parse: P) -> ChatStream
+where
+    P: FnMut(&str) -> Vec<ChatEvent> + Send + 'static,
+{
+    let state = LineStream {
+        body: response.bytes_stream().map(|chunk| chunk.map(|bytes| bytes.to_vec())).boxed(),
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        parse,
+        ended: false,
+        finished: false,
+    };
+
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                if event.is_final() {
+                    state.finished = true;
+                    state.pending.clear();
+                }
+                return Some((event, state));
+            }
+            if state.finished {
+                return None;
+            }
+            if state.ended {
+                state.pending.push_back(ChatEvent::Done { usage: None });
+                continue;
+            }
+
+            match state.body.next().await {
+                Some(Ok(bytes)) => {
+                    state.buffer.extend_from_slice(&bytes);
+                    while let Some(end) = state.buffer.iter().position(|byte| *byte == b'\n') {
+                        let line: Vec<u8> = state.buffer.drain(..=end).collect();
+                        state.parse_line(&line);
+                    }
+                }
+                Some(Err(e)) => state.pending.push_back(ChatEvent::Error {
+                    message: format!("The answer broke off: {}", e),
+                }),
+                None => {
+                    let rest = std::mem::take(&mut state.buffer);
+                    state.parse_line(&rest);
+                    state.ended = true;
+                }
+            }
+        }
+    }))
+}
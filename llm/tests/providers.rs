@@ -0,0 +1,311 @@
+use dr_markdown_llm::{ChatEvent, ChatMessage, LlmError, LlmProvider, OllamaProvider, OpenAiProvider, Usage};
+use futures::StreamExt;
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn conversation() -> Vec<ChatMessage> {
+    vec![ChatMessage::system("You help writing."), ChatMessage::user("Hello?")]
+}
+
+async fn answer(provider: &dyn LlmProvider) -> Vec<ChatEvent> {
+    provider.stream_chat(&conversation()).await.unwrap().collect().await
+}
+
+fn delta(text: &str) -> ChatEvent {
+    ChatEvent::Delta { text: text.to_string() }
+}
+
+#[tokio::test]
+async fn ollama_streams_answer_and_usage() {
+    let server = MockServer::start().await;
+    let body = [
+        r#"{"model":"llama3.2","message":{"role":"assistant","content":"Hel"},"done":false}"#,
+        r#"{"model":"llama3.2","message":{"role":"assistant","content":"lo!"},"done":false}"#,
+        r#"{"model":"llama3.2","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":12,"eval_count":3}"#,
+    ]
+    .join("\n");
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(json!({
+            "model": "llama3.2",
+            "stream": true,
+            "messages": [
+                { "role": "system", "content": "You help writing." },
+                { "role": "user", "content": "Hello?" },
+            ],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(format!("{}/", server.uri()), "llama3.2");
+    assert_eq!(
+        answer(&provider).await,
+        vec![
+            delta("Hel"),
+            delta("lo!"),
+            ChatEvent::Done {
+                usage: Some(Usage { prompt_tokens: 12, completion_tokens: 3 })
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ollama_reports_errors_in_the_stream() {
+    let server = MockServer::start().await;
+    let body = "{\"message\":{\"content\":\"Hel\"},\"done\":false}\n{\"error\":\"model crashed\"}\n{\"message\":{\"content\":\"lo\"},\"done\":false}\n";
+    Mock::given(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(server.uri(), "llama3.2");
+    assert_eq!(
+        answer(&provider).await,
+        vec![delta("Hel"), ChatEvent::Error { message: "model crashed".to_string() }]
+    );
+}
+
+#[tokio::test]
+async fn unsuccessful_status_is_an_error() {
+    let server = MockServer::start().await;
+    Mock::given(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("{\"error\":\"model 'x' not found\"}\n"))
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(server.uri(), "x");
+    match provider.stream_chat(&conversation()).await {
+        Err(LlmError::Status { status, message }) => {
+            assert_eq!(status, 404);
+            assert_eq!(message, "{\"error\":\"model 'x' not found\"}");
+        }
+        other => panic!("expected a status error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn unreachable_server_is_an_error() {
+    let provider = OpenAiProvider::new("http://127.0.0.1:1", "model");
+    assert!(matches!(
+        provider.stream_chat(&conversation()).await,
+        Err(LlmError::Request(_))
+    ));
+}
+
+#[tokio::test]
+async fn openai_streams_answer_and_usage() {
+    let server = MockServer::start().await;
+    let body = concat!(
+        ": keep-alive\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+        "data:{\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo!\"},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":20,\"completion_tokens\":2,\"total_tokens\":22}}\n\n",
+        "data: [DONE]\n\n",
+    );
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("authorization", "Bearer secret"))
+        .and(body_partial_json(json!({
+            "model": "qwen",
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "messages": [
+                { "role": "system", "content": "You help writing." },
+                { "role": "user", "content": "Hello?" },
+            ],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let provider = OpenAiProvider::new(format!("{}/v1/", server.uri()), "qwen").api_key("secret");
+    assert_eq!(
+        answer(&provider).await,
+        vec![
+            delta("Hel"),
+            delta("lo!"),
+            ChatEvent::Done {
+                usage: Some(Usage { prompt_tokens: 20, completion_tokens: 2 })
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn openai_without_usage_or_api_key() {
+    let server = MockServer::start().await;
+    let body = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: [DONE]\n\n";
+    Mock::given(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let provider = OpenAiProvider::new(server.uri(), "local");
+    assert_eq!(answer(&provider).await, vec![delta("Hi"), ChatEvent::Done { usage: None }]);
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests[0].headers.get("authorization").is_none());
+}
+
+#[tokio::test]
+async fn openai_reports_errors_in_the_stream() {
+    let server = MockServer::start().await;
+    let body = "data: {\"error\":{\"message\":\"context too long\",\"type\":\"invalid_request_error\"}}\n\n";
+    Mock::given(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&server)
+        .await;
+
+    let provider = OpenAiProvider::new(server.uri(), "local");
+    assert_eq!(
+        answer(&provider).await,
+        vec![ChatEvent::Error { message: "context too long".to_string() }]
+    );
+}
+
+#[tokio::test]
+async fn answer_ends_when_server_stops_early() {
+    let server = MockServer::start().await;
+    Mock::given(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("{\"message\":{\"content\":\"Hel\"},\"done\":false}"))
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(server.uri(), "llama3.2");
+    assert_eq!(answer(&provider).await, vec![delta("Hel"), ChatEvent::Done { usage: None }]);
+}
+
+#[test]
+fn events_are_tagged_json() {
+    let event = ChatEvent::Done {
+        usage: Some(Usage { prompt_tokens: 1, completion_tokens: 2 }),
+    };
+    let json = serde_json::to_string(&event).unwrap();
+    assert_eq!(json, r#"{"type":"done","usage":{"prompt_tokens":1,"completion_tokens":2}}"#);
+    assert_eq!(serde_json::from_str::<ChatEvent>(&json).unwrap(), event);
+    assert_eq!(
+        serde_json::to_string(&delta("a")).unwrap(),
+        r#"{"type":"delta","text":"a"}"#
+    );
+}
+
+fn texts() -> Vec<String> {
+    vec!["First paragraph".to_string(), "Second paragraph".to_string()]
+}
+
+#[tokio::test]
+async fn ollama_embeds_texts() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/embed"))
+        .and(body_partial_json(json!({
+            "model": "nomic-embed-text",
+            "input": ["First paragraph", "Second paragraph"],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "model": "nomic-embed-text",
+            "embeddings": [[0.1, 0.2], [0.3, 0.4]],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(server.uri(), "llama3.2");
+    let embeddings = provider.embed("nomic-embed-text", &texts()).await.unwrap();
+    assert_eq!(embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+}
+
+#[tokio::test]
+async fn openai_embeds_texts_in_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/embeddings"))
+        .and(header("authorization", "Bearer secret"))
+        .and(body_partial_json(json!({
+            "model": "text-embedding-3-small",
+            "input": ["First paragraph", "Second paragraph"],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "list",
+            "data": [
+                { "object": "embedding", "index": 1, "embedding": [0.3, 0.4] },
+                { "object": "embedding", "index": 0, "embedding": [0.1, 0.2] },
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let provider = OpenAiProvider::new(format!("{}/v1", server.uri()), "qwen").api_key("secret");
+    let embeddings = provider.embed("text-embedding-3-small", &texts()).await.unwrap();
+    assert_eq!(embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+}
+
+#[tokio::test]
+async fn missing_embeddings_are_an_error() {
+    let server = MockServer::start().await;
+    Mock::given(path("/api/embed"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embeddings": [[0.1, 0.2]] })))
+        .mount(&server)
+        .await;
+
+    let provider = OllamaProvider::new(server.uri(), "llama3.2");
+    match provider.embed("nomic-embed-text", &texts()).await {
+        Err(LlmError::InvalidResponse(message)) => assert_eq!(message, "1 embeddings for 2 texts"),
+        other => panic!("expected an invalid response, got {:?}", other),
+    }
+}
+
+/// Serves `chunks` as a chunked response, flushing each one separately so
+/// that lines arrive split up.
+async fn chunked_server(chunks: &'static [&'static str]) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 16 * 1024];
+        let _ = socket.read(&mut request).await.unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        for chunk in chunks {
+            let frame = format!("{:x}\r\n{}\r\n", chunk.len(), chunk);
+            socket.write_all(frame.as_bytes()).await.unwrap();
+            socket.flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        socket.write_all(b"0\r\n\r\n").await.unwrap();
+    });
+    format!("http://{}", address)
+}
+
+#[tokio::test]
+async fn lines_split_across_chunks() {
+    let url = chunked_server(&[
+        "{\"message\":{\"content\":\"Hel\"},\"do",
+        "ne\":false}\n{\"message\":{\"content\":\"lo\"},\"done\":false}\n{\"done\":true,",
+        "\"eval_count\":2}\n",
+    ])
+    .await;
+
+    let provider = OllamaProvider::new(url, "llama3.2");
+    assert_eq!(
+        answer(&provider).await,
+        vec![
+            delta("Hel"),
+            delta("lo"),
+            ChatEvent::Done {
+                usage: Some(Usage { prompt_tokens: 0, completion_tokens: 2 })
+            },
+        ]
+    );
+}
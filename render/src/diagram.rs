@@ -0,0 +1,230 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+use sha2::{Digest, Sha256};
+
+use crate::html::Extensions;
+
+/// The diagram languages known to the renderer unless it is told otherwise.
+pub const DEFAULT_DIAGRAMS: &[&str] = &[
+    "mermaid",
+    "plantuml",
+    "graphviz",
+    "ditaa",
+    "blockdiag",
+    "structurizr",
+    "seqdiag",
+];
+
+/// How a diagram is drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DiagramFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl DiagramFormat {
+    /// The name Kroki uses, which is also the file extension.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiagramFormat::Svg => "svg",
+            DiagramFormat::Png => "png",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            DiagramFormat::Svg => "image/svg+xml",
+            DiagramFormat::Png => "image/png",
+        }
+    }
+}
+
+impl FromStr for DiagramFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "svg" => Ok(DiagramFormat::Svg),
+            "png" => Ok(DiagramFormat::Png),
+            other => Err(format!("Unknown diagram format '{}', expected svg or png", other)),
+        }
+    }
+}
+
+/// A language of fenced code blocks that are drawn as diagrams instead of
+/// being shown as code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagramLanguage {
+    /// The name Kroki knows the language by, e.g. `graphviz`.
+    pub name: String,
+    /// Other names for it in code blocks, e.g. `dot`.
+    pub aliases: Vec<String>,
+    pub format: DiagramFormat,
+}
+
+impl DiagramLanguage {
+    pub fn new(name: &str) -> Self {
+        DiagramLanguage {
+            name: name.to_string(),
+            aliases: Vec::new(),
+            format: DiagramFormat::default(),
+        }
+    }
+}
+
+/// The diagram languages a renderer draws. The default is
+/// [`DEFAULT_DIAGRAMS`] as SVG, with `dot` for Graphviz.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagramLanguages(Vec<DiagramLanguage>);
+
+impl DiagramLanguages {
+    pub fn new(languages: Vec<DiagramLanguage>) -> Self {
+        DiagramLanguages(languages)
+    }
+
+    /// The language a code block marked with `name` is written in, by its
+    /// name or one of its aliases.
+    pub fn find(&self, name: &str) -> Option<&DiagramLanguage> {
+        self.0
+            .iter()
+            .find(|language| language.name == name || language.aliases.iter().any(|alias| alias == name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DiagramLanguage> {
+        self.0.iter()
+    }
+
+    /// An empty diagram for a code block marked with `name`, if that is a
+    /// diagram language.
+    pub(crate) fn start_diagram(&self, name: &str) -> Option<Diagram> {
+        self.find(name).map(|language| Diagram {
+            kind: language.name.clone(),
+            source: String::new(),
+            format: language.format,
+        })
+    }
+}
+
+impl Default for DiagramLanguages {
+    fn default() -> Self {
+        DiagramLanguages(
+            DEFAULT_DIAGRAMS
+                .iter()
+                .map(|&name| DiagramLanguage {
+                    aliases: if name == "graphviz" { vec!["dot".to_string()] } else { Vec::new() },
+                    ..DiagramLanguage::new(name)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A diagram written as a fenced code block, e.g. ` ```mermaid `.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagram {
+    /// The name of the diagram language, never an alias.
+    pub kind: String,
+    pub source: String,
+    pub format: DiagramFormat,
+}
+
+impl Diagram {
+    /// URL under which a Kroki server renders the diagram.
+    pub fn kroki_url(&self, kroki_base: &str) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            kroki_base.trim_end_matches('/'),
+            self.kind,
+            self.format.as_str(),
+            self.encoded_source()
+        )
+    }
+
+    /// URL under which the backend at `server_base` serves the diagram. The
+    /// path only depends on the content, so the image can be cached
+    /// for good; the source is added for when the backend has not seen the
+    /// diagram before.
+    pub fn server_url(&self, server_base: &str) -> String {
+        format!(
+            "{}/{}/{}.{}?source={}",
+            server_base.trim_end_matches('/'),
+            self.kind,
+            self.hash(),
+            self.format.as_str(),
+            self.encoded_source()
+        )
+    }
+
+    /// SHA-256 of the language and the source, in hex. The format is left
+    /// out, the same diagram has the same hash as SVG and PNG.
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.kind.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.source.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The source as Kroki puts it in URLs: compressed with zlib and then
+    /// encoded as URL-safe base64.
+    pub fn encoded_source(&self) -> String {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        // Writing to a Vec cannot fail
+        encoder.write_all(self.source.as_bytes()).unwrap();
+        let compressed_bytes = encoder.finish().unwrap();
+        base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, compressed_bytes)
+    }
+
+    /// The diagram from a source in the form of [`Diagram::encoded_source`],
+    /// as SVG, or None if the source is not valid or longer than
+    /// `max_length` bytes once decompressed.
+    pub fn from_encoded(kind: &str, encoded_source: &str, max_length: usize) -> Option<Self> {
+        let compressed_bytes = base64::Engine::decode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            encoded_source.trim_end_matches('='),
+        )
+        .ok()?;
+        let mut source = String::new();
+        // A few bytes can inflate to gigabytes, so decompression stops right after the limit
+        ZlibDecoder::new(compressed_bytes.as_slice())
+            .take(max_length as u64 + 1)
+            .read_to_string(&mut source)
+            .ok()?;
+        if source.len() > max_length {
+            return None;
+        }
+        Some(Diagram {
+            kind: kind.to_string(),
+            source,
+            format: DiagramFormat::Svg,
+        })
+    }
+}
+
+/// All diagrams of a document in one of the `languages`, in the order they
+/// appear.
+pub fn find_diagrams(markdown: &str, languages: &DiagramLanguages) -> Vec<Diagram> {
+    let mut diagrams = Vec::new();
+    let mut current: Option<Diagram> = None;
+
+    for event in Parser::new_ext(markdown, Extensions::ALL.options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => current = languages.start_diagram(&lang),
+            Event::Text(text) => {
+                if let Some(diagram) = current.as_mut() {
+                    diagram.source.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => diagrams.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    diagrams
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::panic;
+
+use layout::backends::svg::SVGWriter;
+use layout::gv::parser::ast::{Graph, Stmt};
+use layout::gv::{DotParser, GraphBuilder};
+
+// The layout takes far more than linear time, so larger graphs are refused
+// before it starts
+const MAX_SOURCE_LENGTH: usize = 8 * 1024;
+const MAX_NODES: usize = 200;
+const MAX_EDGES: usize = 400;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid diagram: {0}")]
+pub struct DiagramError(String);
+
+/// Lays out a Graphviz (DOT) graph and draws it as SVG, without a diagram
+/// server.
+///
+/// Only the common parts of the DOT language are understood. The layout code
+/// panics on some graphs it cannot handle, which is reported as an error.
+/// Sources over 8 KiB and graphs with more than 200 nodes or 400 edges are
+/// refused.
+pub fn render_graphviz(source: &str) -> Result<String, DiagramError> {
+    if source.len() > MAX_SOURCE_LENGTH {
+        return Err(DiagramError(format!("the graph is longer than {} KiB", MAX_SOURCE_LENGTH / 1024)));
+    }
+    let graph = DotParser::new(source).process().map_err(DiagramError)?;
+    if count_edges(&graph) > MAX_EDGES {
+        return Err(DiagramError(format!("the graph has more than {} edges", MAX_EDGES)));
+    }
+    let mut nodes = HashSet::new();
+    collect_nodes(&graph, &mut nodes);
+    if nodes.len() > MAX_NODES {
+        return Err(DiagramError(format!("the graph has more than {} nodes", MAX_NODES)));
+    }
+
+    panic::catch_unwind(|| {
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual_graph = builder.get();
+
+        let mut svg = SVGWriter::new();
+        visual_graph.do_it(false, false, false, &mut svg);
+        svg.finalize()
+    })
+    .map_err(|_| DiagramError("the graph could not be laid out".to_string()))
+}
+
+/// The edges of a graph and its subgraphs, where `a -> b -> c` is two.
+fn count_edges(graph: &Graph) -> usize {
+    graph
+        .list
+        .list
+        .iter()
+        .map(|stmt| match stmt {
+            Stmt::Edge(edge) => edge.to.len(),
+            Stmt::SubGraph(subgraph) => count_edges(subgraph),
+            Stmt::Node(_) | Stmt::Attribute(_) => 0,
+        })
+        .sum()
+}
+
+/// The names of all nodes of a graph and its subgraphs, whether declared on
+/// their own or only used in edges.
+fn collect_nodes<'a>(graph: &'a Graph, nodes: &mut HashSet<&'a str>) {
+    for stmt in &graph.list.list {
+        match stmt {
+            Stmt::Node(node) => {
+                nodes.insert(&node.id.name);
+            }
+            Stmt::Edge(edge) => {
+                nodes.insert(&edge.from.name);
+                nodes.extend(edge.to.iter().map(|(to, _)| to.name.as_str()));
+            }
+            Stmt::SubGraph(subgraph) => collect_nodes(subgraph, nodes),
+            Stmt::Attribute(_) => {}
+        }
+    }
+}
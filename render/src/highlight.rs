@@ -0,0 +1,156 @@
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::html::escape_html;
+
+/// Prefix of the token classes, so they cannot clash with the page's own.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Themes for the token classes, which follow the user's light or dark
+/// setting.
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+
+/// Loading the syntaxes takes a while, so it only happens for the first
+/// code block.
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// The CSS that colors highlighted code: the light theme, and the dark one
+/// for pages where `<html>` has the `dark` class.
+pub fn highlight_stylesheet() -> String {
+    let themes = ThemeSet::load_defaults();
+    let mut css = String::new();
+    for (name, scope) in [(LIGHT_THEME, "html:not(.dark)"), (DARK_THEME, ".dark")] {
+        let Some(theme) = themes.themes.get(name) else { continue };
+        let Ok(theme_css) = css_for_theme_with_class_style(theme, CLASS_STYLE) else { continue };
+        for line in theme_css.lines() {
+            match line.strip_suffix(" {") {
+                Some(selectors) => {
+                    let scoped: Vec<String> =
+                        selectors.split(", ").map(|selector| format!("{scope} {selector}")).collect();
+                    css.push_str(&scoped.join(", "));
+                    css.push_str(" {");
+                }
+                None => css.push_str(line),
+            }
+            css.push('\n');
+        }
+    }
+    css
+}
+
+/// A fenced code block as HTML, with every line in its own `code-line` span
+/// so that they can be numbered. The info string is the language and
+/// optionally the lines to highlight, e.g. `rust {1,3-5}`.
+pub(crate) fn highlight_code(info: &str, code: &str) -> String {
+    let (language, highlighted) = parse_info(info);
+
+    let lines = language
+        .and_then(|language| SYNTAXES.find_syntax_by_token(language))
+        .and_then(|syntax| {
+            let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+            for line in LinesWithEndings::from(code) {
+                generator.parse_html_for_line_which_includes_newline(line).ok()?;
+            }
+            Some(split_lines(&generator.finalize()))
+        })
+        .unwrap_or_else(|| code.lines().map(escape_html).collect());
+
+    let mut html = String::from("<pre class=\"highlight\"><code");
+    if let Some(language) = language {
+        html.push_str(&format!(" class=\"language-{}\"", escape_html(language)));
+    }
+    html.push('>');
+    for (index, line) in lines.iter().enumerate() {
+        let number = index + 1;
+        if highlighted.iter().any(|range| range.contains(&number)) {
+            html.push_str("<span class=\"code-line highlighted\">");
+        } else {
+            html.push_str("<span class=\"code-line\">");
+        }
+        html.push_str(line);
+        html.push_str("</span>\n");
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// Splits the info string into the language, if there is one, and the
+/// lines to highlight. Parts of the line list that are not numbers are
+/// ignored.
+fn parse_info(info: &str) -> (Option<&str>, Vec<RangeInclusive<usize>>) {
+    let info = info.trim();
+    let (language, rest) = match info.find('{') {
+        Some(start) => (info[..start].trim(), &info[start..]),
+        None => (info.split_whitespace().next().unwrap_or_default(), ""),
+    };
+    let language = language.split_whitespace().next().filter(|language| !language.is_empty());
+
+    let lines = rest
+        .strip_prefix('{')
+        .and_then(|rest| rest.split_once('}'))
+        .map(|(list, _)| {
+            list.split(',')
+                .filter_map(|part| {
+                    let part = part.trim();
+                    match part.split_once('-') {
+                        Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+                        None => part.parse().ok().map(|line| line..=line),
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    (language, lines)
+}
+
+/// Splits highlighted HTML into lines that each close the spans they open
+/// and reopen the ones still open from the lines before.
+fn split_lines(html: &str) -> Vec<String> {
+    let mut open_spans: Vec<&str> = Vec::new();
+    let mut lines = Vec::new();
+    for segment in html.split('\n') {
+        let mut line: String = open_spans.concat();
+        line.push_str(segment);
+
+        let mut rest = segment;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start..];
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            if rest.starts_with("</") {
+                open_spans.pop();
+            } else {
+                open_spans.push(&rest[..end]);
+            }
+            rest = &rest[end..];
+        }
+
+        line.push_str(&"</span>".repeat(open_spans.len()));
+        lines.push(line);
+    }
+
+    // The code ends with a newline, after which only closing tags are left
+    if html.rsplit('\n').next().is_some_and(is_only_tags) {
+        lines.pop();
+    }
+    lines
+}
+
+fn is_only_tags(segment: &str) -> bool {
+    let mut in_tag = false;
+    segment.chars().all(|c| {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if in_tag => {}
+            _ => return false,
+        }
+        true
+    })
+}
@@ -0,0 +1,318 @@
+use std::borrow::Cow;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::diagram::{Diagram, DiagramLanguages};
+use crate::highlight::{highlight_code, highlight_stylesheet};
+use crate::math::math_html;
+
+/// Styles for documents rendered outside the web app, e.g. HTML exports.
+pub const STYLESHEET: &str = include_str!("document.css");
+
+/// Markdown syntax supported on top of CommonMark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extensions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub task_lists: bool,
+    /// Curly quotes, dashes and ellipses.
+    pub smart_punctuation: bool,
+    /// `# Heading {#id .class}`
+    pub heading_attributes: bool,
+    /// YAML (`---`) and TOML (`+++`) blocks at the start of a document,
+    /// which are left out of the output.
+    pub metadata_blocks: bool,
+    /// `$inline$` and `$$display$$` math.
+    pub math: bool,
+    /// GitHub style `> [!NOTE]` block quotes.
+    pub alerts: bool,
+    pub definition_lists: bool,
+    /// `^superscript^` and `~subscript~`.
+    pub super_and_subscript: bool,
+    /// `[[Page]]` links.
+    pub wikilinks: bool,
+}
+
+impl Extensions {
+    /// Plain CommonMark.
+    pub const NONE: Extensions = Extensions {
+        tables: false,
+        footnotes: false,
+        strikethrough: false,
+        task_lists: false,
+        smart_punctuation: false,
+        heading_attributes: false,
+        metadata_blocks: false,
+        math: false,
+        alerts: false,
+        definition_lists: false,
+        super_and_subscript: false,
+        wikilinks: false,
+    };
+
+    /// Every extension, which is what the editor uses.
+    pub const ALL: Extensions = Extensions {
+        tables: true,
+        footnotes: true,
+        strikethrough: true,
+        task_lists: true,
+        smart_punctuation: true,
+        heading_attributes: true,
+        metadata_blocks: true,
+        math: true,
+        alerts: true,
+        definition_lists: true,
+        super_and_subscript: true,
+        wikilinks: true,
+    };
+
+    pub(crate) fn options(&self) -> Options {
+        let flags = [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.task_lists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+            (
+                self.metadata_blocks,
+                Options::ENABLE_YAML_STYLE_METADATA_BLOCKS | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS,
+            ),
+            (self.math, Options::ENABLE_MATH),
+            (self.alerts, Options::ENABLE_GFM),
+            (self.definition_lists, Options::ENABLE_DEFINITION_LIST),
+            (
+                self.super_and_subscript,
+                Options::ENABLE_SUPERSCRIPT | Options::ENABLE_SUBSCRIPT,
+            ),
+            (self.wikilinks, Options::ENABLE_WIKILINKS),
+        ];
+
+        flags
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(Options::empty(), |options, (_, flag)| options | flag)
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions::ALL
+    }
+}
+
+/// What happens to fenced code blocks written in a diagram language.
+enum DiagramHandling<'a> {
+    /// Left alone, so they show up as code.
+    Code,
+    /// Images served by the Kroki server at the given URL.
+    Kroki(&'a str),
+    /// Images served by the backend's diagram cache at the given URL.
+    Server(&'a str),
+    /// Replaced with whatever HTML the function returns.
+    Custom(Box<dyn Fn(&Diagram) -> String + 'a>),
+}
+
+/// Markdown to HTML renderer. The defaults enable every extension, show
+/// diagrams as code, leave code blocks unhighlighted and math as LaTeX, and
+/// pass raw HTML through unchanged:
+///
+/// ```
+/// use dr_markdown_render::Renderer;
+///
+/// let html = Renderer::new()
+///     .kroki("https://kroki.io")
+///     .escape_raw_html(true)
+///     .render("# Hello <b>world</b>");
+/// assert_eq!(html, "<h1>Hello &lt;b&gt;world&lt;/b&gt;</h1>\n");
+/// ```
+pub struct Renderer<'a> {
+    extensions: Extensions,
+    diagram_languages: Cow<'a, DiagramLanguages>,
+    diagrams: DiagramHandling<'a>,
+    highlight_code: bool,
+    mathml: bool,
+    escape_raw_html: bool,
+    sanitizer: Option<Box<dyn Fn(String) -> String + 'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new() -> Self {
+        Renderer {
+            extensions: Extensions::default(),
+            diagram_languages: Cow::Owned(DiagramLanguages::default()),
+            diagrams: DiagramHandling::Code,
+            highlight_code: false,
+            mathml: false,
+            escape_raw_html: false,
+            sanitizer: None,
+        }
+    }
+
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// The code block languages that are diagrams, in place of the default
+    /// ones.
+    pub fn diagram_languages(mut self, languages: &'a DiagramLanguages) -> Self {
+        self.diagram_languages = Cow::Borrowed(languages);
+        self
+    }
+
+    /// Shows diagrams as images rendered by the Kroki server at `kroki_url`.
+    pub fn kroki(mut self, kroki_url: &'a str) -> Self {
+        self.diagrams = DiagramHandling::Kroki(kroki_url);
+        self
+    }
+
+    /// Shows diagrams as images from the backend's diagram cache at
+    /// `server_url`, which renders them itself or through Kroki.
+    pub fn diagram_server(mut self, server_url: &'a str) -> Self {
+        self.diagrams = DiagramHandling::Server(server_url);
+        self
+    }
+
+    /// Replaces every diagram with the HTML returned by `render_diagram`.
+    pub fn diagrams(mut self, render_diagram: impl Fn(&Diagram) -> String + 'a) -> Self {
+        self.diagrams = DiagramHandling::Custom(Box::new(render_diagram));
+        self
+    }
+
+    /// Highlights fenced code blocks and puts every line in a span of its
+    /// own, so they can be numbered. Lines listed after the language, as in
+    /// `rust {1,3-5}`, get the `highlighted` class. The colors come from
+    /// [`highlight_stylesheet`].
+    pub fn highlight_code(mut self, highlight: bool) -> Self {
+        self.highlight_code = highlight;
+        self
+    }
+
+    /// Typesets `$inline$` and `$$display$$` math as MathML. Formulas that
+    /// cannot be typeset are shown as written, in a `math-error` element
+    /// whose title says what is wrong.
+    pub fn mathml(mut self, mathml: bool) -> Self {
+        self.mathml = mathml;
+        self
+    }
+
+    /// Shows HTML written in the document as text instead of passing it
+    /// through.
+    pub fn escape_raw_html(mut self, escape: bool) -> Self {
+        self.escape_raw_html = escape;
+        self
+    }
+
+    /// Runs the finished HTML through `sanitize` before it is returned. The
+    /// sanitizer sees the complete output, including rendered diagrams.
+    pub fn sanitizer(mut self, sanitize: impl Fn(String) -> String + 'a) -> Self {
+        self.sanitizer = Some(Box::new(sanitize));
+        self
+    }
+
+    pub fn render(&self, markdown: &str) -> String {
+        let parser = Parser::new_ext(markdown, self.extensions.options());
+
+        let mut current_diagram: Option<Diagram> = None;
+        let mut current_code: Option<(String, String)> = None;
+
+        let transformed_events = parser.filter_map(|event| match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref lang)))
+                if !matches!(self.diagrams, DiagramHandling::Code) && self.diagram_languages.find(lang).is_some() =>
+            {
+                current_diagram = self.diagram_languages.start_diagram(lang);
+                None
+            }
+            Event::Text(text) if current_diagram.is_some() => {
+                if let Some(diagram) = current_diagram.as_mut() {
+                    diagram.source.push_str(&text);
+                }
+                None
+            }
+            Event::End(TagEnd::CodeBlock) if current_diagram.is_some() => current_diagram
+                .take()
+                .map(|diagram| Event::Html(format!("{}\n", self.render_diagram(&diagram)).into())),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if self.highlight_code => {
+                current_code = Some((info.into_string(), String::new()));
+                None
+            }
+            Event::Text(text) if current_code.is_some() => {
+                if let Some((_, code)) = current_code.as_mut() {
+                    code.push_str(&text);
+                }
+                None
+            }
+            Event::End(TagEnd::CodeBlock) if current_code.is_some() => current_code
+                .take()
+                .map(|(info, code)| Event::Html(highlight_code(&info, &code).into())),
+            Event::InlineMath(latex) if self.mathml => Some(Event::InlineHtml(math_html(&latex, false).into())),
+            Event::DisplayMath(latex) if self.mathml => Some(Event::InlineHtml(math_html(&latex, true).into())),
+            Event::Html(html) | Event::InlineHtml(html) if self.escape_raw_html => Some(Event::Text(html)),
+            _ => Some(event),
+        });
+
+        let mut html_output = String::new();
+        pulldown_cmark::html::push_html(&mut html_output, transformed_events);
+
+        match &self.sanitizer {
+            Some(sanitize) => sanitize(html_output),
+            None => html_output,
+        }
+    }
+
+    fn render_diagram(&self, diagram: &Diagram) -> String {
+        match &self.diagrams {
+            DiagramHandling::Code => String::new(),
+            DiagramHandling::Kroki(kroki_url) => diagram_image(&diagram.kroki_url(kroki_url), diagram),
+            DiagramHandling::Server(server_url) => diagram_image(&diagram.server_url(server_url), diagram),
+            DiagramHandling::Custom(render) => render(diagram),
+        }
+    }
+}
+
+impl Default for Renderer<'_> {
+    fn default() -> Self {
+        Renderer::new()
+    }
+}
+
+/// The `<img>` tag for a diagram served from `src`.
+pub fn diagram_image(src: &str, diagram: &Diagram) -> String {
+    format!(
+        "<img class=\"kroki-diagram\" src=\"{}\" alt=\"Diagram: {}\">",
+        escape_html(src),
+        escape_html(&diagram.kind)
+    )
+}
+
+/// Wraps rendered Markdown into a complete HTML page that needs nothing but
+/// itself to be displayed, including the colors of highlighted code.
+pub fn standalone_html(title: &str, body_html: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>\n{}{}</style>\n</head>\n\
+         <body>\n<article class=\"markdown-body\">\n{}</article>\n</body>\n</html>\n",
+        escape_html(title),
+        STYLESHEET,
+        highlight_stylesheet(),
+        body_html
+    )
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,30 @@
+//! Markdown rendering shared by the web frontend and the backend: HTML for
+//! previews and exports with highlighted code, math as MathML, diagrams
+//! through Kroki, with the `graphviz` feature Graphviz diagrams drawn
+//! in-process and, with the `pdf` feature, PDF documents.
+//!
+//! HTML is produced by a [`Renderer`], which is configured with the Markdown
+//! extensions to use, what to do with diagrams and how to sanitize the
+//! output.
+
+mod diagram;
+#[cfg(feature = "graphviz")]
+mod graphviz;
+mod highlight;
+mod html;
+mod math;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod sanitize;
+
+pub use diagram::{find_diagrams, Diagram, DiagramFormat, DiagramLanguage, DiagramLanguages, DEFAULT_DIAGRAMS};
+#[cfg(feature = "graphviz")]
+pub use graphviz::{render_graphviz, DiagramError};
+pub use highlight::highlight_stylesheet;
+pub use html::{
+    diagram_image, escape_html, standalone_html, Extensions, Renderer, STYLESHEET,
+};
+pub use math::{latex_to_mathml, MathError};
+#[cfg(feature = "pdf")]
+pub use pdf::{render_pdf, PdfError};
+pub use sanitize::SanitizePolicy;
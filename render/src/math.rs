@@ -0,0 +1,871 @@
+use crate::html::escape_html;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid formula: {0}")]
+pub struct MathError(String);
+
+/// Formulas nested deeper than this are refused instead of overflowing the
+/// stack.
+const MAX_DEPTH: usize = 64;
+
+/// Letters with a special look that are written as commands. Upper case
+/// Greek letters are upright in TeX, the lower case ones italic.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("epsilon", "ϵ"),
+    ("varepsilon", "ε"), ("zeta", "ζ"), ("eta", "η"), ("theta", "θ"), ("vartheta", "ϑ"),
+    ("iota", "ι"), ("kappa", "κ"), ("varkappa", "ϰ"), ("lambda", "λ"), ("mu", "μ"),
+    ("nu", "ν"), ("xi", "ξ"), ("omicron", "ο"), ("pi", "π"), ("varpi", "ϖ"), ("rho", "ρ"),
+    ("varrho", "ϱ"), ("sigma", "σ"), ("varsigma", "ς"), ("tau", "τ"), ("upsilon", "υ"),
+    ("phi", "ϕ"), ("varphi", "φ"), ("chi", "χ"), ("psi", "ψ"), ("omega", "ω"),
+    ("infty", "∞"), ("partial", "∂"), ("nabla", "∇"), ("emptyset", "∅"), ("varnothing", "∅"),
+    ("ell", "ℓ"), ("hbar", "ℏ"), ("hslash", "ℏ"), ("aleph", "ℵ"), ("beth", "ℶ"), ("Re", "ℜ"),
+    ("Im", "ℑ"), ("wp", "℘"), ("imath", "ı"), ("jmath", "ȷ"), ("top", "⊤"), ("bot", "⊥"),
+    ("angle", "∠"), ("triangle", "△"), ("clubsuit", "♣"), ("diamondsuit", "♢"),
+    ("heartsuit", "♡"), ("spadesuit", "♠"), ("flat", "♭"), ("natural", "♮"), ("sharp", "♯"),
+];
+
+const UPRIGHT_SYMBOLS: &[(&str, &str)] = &[
+    ("Gamma", "Γ"), ("Delta", "Δ"), ("Theta", "Θ"), ("Lambda", "Λ"), ("Xi", "Ξ"), ("Pi", "Π"),
+    ("Sigma", "Σ"), ("Upsilon", "Υ"), ("Phi", "Φ"), ("Psi", "Ψ"), ("Omega", "Ω"),
+];
+
+/// Binary operators, relations, arrows and punctuation.
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"), ("mp", "∓"), ("times", "×"), ("div", "÷"), ("cdot", "⋅"), ("cdotp", "⋅"),
+    ("ast", "∗"), ("star", "⋆"), ("circ", "∘"), ("bullet", "∙"), ("oplus", "⊕"),
+    ("ominus", "⊖"), ("otimes", "⊗"), ("oslash", "⊘"), ("odot", "⊙"), ("cup", "∪"),
+    ("cap", "∩"), ("sqcup", "⊔"), ("sqcap", "⊓"), ("vee", "∨"), ("lor", "∨"), ("wedge", "∧"),
+    ("land", "∧"), ("setminus", "∖"), ("wr", "≀"), ("amalg", "⨿"), ("uplus", "⊎"),
+    ("diamond", "⋄"), ("bigtriangleup", "△"), ("bigtriangledown", "▽"),
+    ("triangleleft", "◃"), ("triangleright", "▹"), ("dagger", "†"), ("ddagger", "‡"),
+    ("leq", "≤"), ("le", "≤"), ("geq", "≥"), ("ge", "≥"), ("neq", "≠"), ("ne", "≠"),
+    ("equiv", "≡"), ("approx", "≈"), ("approxeq", "≊"), ("sim", "∼"), ("simeq", "≃"),
+    ("cong", "≅"), ("propto", "∝"), ("ll", "≪"), ("gg", "≫"), ("prec", "≺"), ("succ", "≻"),
+    ("preceq", "⪯"), ("succeq", "⪰"), ("subset", "⊂"), ("supset", "⊃"), ("subseteq", "⊆"),
+    ("supseteq", "⊇"), ("subsetneq", "⊊"), ("supsetneq", "⊋"), ("sqsubseteq", "⊑"),
+    ("sqsupseteq", "⊒"), ("in", "∈"), ("ni", "∋"), ("notin", "∉"), ("mid", "∣"),
+    ("nmid", "∤"), ("parallel", "∥"), ("nparallel", "∦"), ("perp", "⟂"), ("models", "⊨"),
+    ("vdash", "⊢"), ("dashv", "⊣"), ("asymp", "≍"), ("doteq", "≐"), ("bowtie", "⋈"),
+    ("leqslant", "⩽"), ("geqslant", "⩾"), ("lesssim", "≲"), ("gtrsim", "≳"),
+    ("triangleq", "≜"), ("coloneqq", "≔"), ("to", "→"), ("rightarrow", "→"),
+    ("leftarrow", "←"), ("gets", "←"), ("leftrightarrow", "↔"), ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"), ("Leftrightarrow", "⇔"), ("iff", "⟺"), ("implies", "⟹"),
+    ("impliedby", "⟸"), ("longrightarrow", "⟶"), ("longleftarrow", "⟵"),
+    ("longleftrightarrow", "⟷"), ("Longrightarrow", "⟹"), ("Longleftarrow", "⟸"),
+    ("Longleftrightarrow", "⟺"), ("mapsto", "↦"), ("longmapsto", "⟼"),
+    ("hookrightarrow", "↪"), ("hookleftarrow", "↩"), ("uparrow", "↑"), ("downarrow", "↓"),
+    ("updownarrow", "↕"), ("Uparrow", "⇑"), ("Downarrow", "⇓"), ("nearrow", "↗"),
+    ("searrow", "↘"), ("swarrow", "↙"), ("nwarrow", "↖"), ("rightharpoonup", "⇀"),
+    ("leftharpoondown", "↽"), ("rightleftharpoons", "⇌"), ("leadsto", "⇝"),
+    ("forall", "∀"), ("exists", "∃"), ("nexists", "∄"), ("neg", "¬"), ("lnot", "¬"),
+    ("ldots", "…"), ("dots", "…"), ("cdots", "⋯"), ("vdots", "⋮"), ("ddots", "⋱"),
+    ("colon", ":"), ("prime", "′"), ("therefore", "∴"), ("because", "∵"),
+    ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"), ("lceil", "⌈"),
+    ("rceil", "⌉"), ("vert", "|"), ("Vert", "‖"), ("lvert", "|"), ("rvert", "|"),
+    ("lVert", "‖"), ("rVert", "‖"), ("backslash", "\\"), ("lbrace", "{"), ("rbrace", "}"),
+    ("lbrack", "["), ("rbrack", "]"), ("{", "{"), ("}", "}"), ("|", "‖"), ("%", "%"),
+    ("$", "$"), ("#", "#"), ("&", "&"), ("_", "_"),
+];
+
+/// Operators that grow in display math. The ones marked `true` take their
+/// scripts above and below.
+const LARGE_OPERATORS: &[(&str, &str, bool)] = &[
+    ("sum", "∑", true), ("prod", "∏", true), ("coprod", "∐", true), ("bigcup", "⋃", true),
+    ("bigcap", "⋂", true), ("bigsqcup", "⨆", true), ("bigvee", "⋁", true),
+    ("bigwedge", "⋀", true), ("bigoplus", "⨁", true), ("bigotimes", "⨂", true),
+    ("bigodot", "⨀", true), ("biguplus", "⨄", true), ("int", "∫", false), ("iint", "∬", false),
+    ("iiint", "∭", false), ("oint", "∮", false),
+];
+
+/// Named functions, written upright. The ones marked `true` take their
+/// scripts below in display math, like `\lim_{x \to 0}`.
+const FUNCTIONS: &[(&str, &str, bool)] = &[
+    ("arcsin", "arcsin", false), ("arccos", "arccos", false), ("arctan", "arctan", false),
+    ("arg", "arg", false), ("cos", "cos", false), ("cosh", "cosh", false), ("cot", "cot", false),
+    ("coth", "coth", false), ("csc", "csc", false), ("deg", "deg", false), ("dim", "dim", false),
+    ("exp", "exp", false), ("hom", "hom", false), ("ker", "ker", false), ("lg", "lg", false),
+    ("ln", "ln", false), ("log", "log", false), ("sec", "sec", false), ("sin", "sin", false),
+    ("sinh", "sinh", false), ("tan", "tan", false), ("tanh", "tanh", false),
+    ("det", "det", true), ("gcd", "gcd", true), ("inf", "inf", true), ("lim", "lim", true),
+    ("liminf", "lim inf", true), ("limsup", "lim sup", true), ("max", "max", true),
+    ("min", "min", true), ("Pr", "Pr", true), ("sup", "sup", true),
+];
+
+/// Accents over (`true`) or under their argument, and whether they stretch
+/// with it.
+const ACCENTS: &[(&str, &str, bool, bool)] = &[
+    ("hat", "^", true, false), ("widehat", "^", true, true), ("check", "ˇ", true, false),
+    ("tilde", "~", true, false), ("widetilde", "~", true, true), ("acute", "´", true, false),
+    ("grave", "`", true, false), ("dot", "˙", true, false), ("ddot", "¨", true, false),
+    ("breve", "˘", true, false), ("bar", "¯", true, false), ("vec", "→", true, false),
+    ("overrightarrow", "→", true, true), ("overleftarrow", "←", true, true),
+    ("overline", "‾", true, true), ("underline", "_", false, true),
+    ("overbrace", "⏞", true, true), ("underbrace", "⏟", false, true),
+];
+
+/// Horizontal spaces, in em.
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.167em"), ("thinspace", "0.167em"), (":", "0.222em"), (">", "0.222em"),
+    ("medspace", "0.222em"), (";", "0.278em"), ("thickspace", "0.278em"), (" ", "0.333em"),
+    ("quad", "1em"), ("qquad", "2em"),
+];
+
+/// Delimiters after `\left`, `\right`, `\big` and friends that are written
+/// as commands.
+const DELIMITERS: &[(&str, &str)] = &[
+    ("{", "{"), ("}", "}"), ("lbrace", "{"), ("rbrace", "}"), ("lbrack", "["), ("rbrack", "]"),
+    ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"), ("lceil", "⌈"),
+    ("rceil", "⌉"), ("|", "‖"), ("vert", "|"), ("Vert", "‖"), ("lvert", "|"), ("rvert", "|"),
+    ("lVert", "‖"), ("rVert", "‖"), ("backslash", "\\"), ("uparrow", "↑"),
+    ("downarrow", "↓"), ("updownarrow", "↕"), ("Uparrow", "⇑"), ("Downarrow", "⇓"),
+];
+
+/// Sizes of `\big`, `\Big`, `\bigg` and `\Bigg` delimiters.
+const DELIMITER_SIZES: &[(&str, &str)] =
+    &[("big", "1.2em"), ("Big", "1.623em"), ("bigg", "2.047em"), ("Bigg", "2.470em")];
+
+/// Characters that are fences in MathML, which would stretch with their
+/// content unless told otherwise. Only `\left` and `\right` make TeX grow
+/// them.
+const FENCES: &[char] = &['(', ')', '[', ']', '{', '}', '|', '‖', '⟨', '⟩', '⌊', '⌋', '⌈', '⌉'];
+
+/// Typesets LaTeX math as MathML, as a block for display math. The usual
+/// commands of LaTeX and `amsmath` are understood: fractions, roots,
+/// scripts, Greek letters and symbols, accents, fonts, `\left...\right`
+/// and matrix, `cases` and `aligned` environments. Anything else, and
+/// formulas that are not well-formed, are reported as an error.
+///
+/// ```
+/// use dr_markdown_render::latex_to_mathml;
+///
+/// assert_eq!(
+///     latex_to_mathml("x^2", false).unwrap(),
+///     "<math><msup><mi>x</mi><mn>2</mn></msup></math>"
+/// );
+/// assert!(latex_to_mathml("\\frac{1}{2", false).is_err());
+/// ```
+pub fn latex_to_mathml(latex: &str, display: bool) -> Result<String, MathError> {
+    let mut parser = Parser {
+        source: latex,
+        pos: 0,
+        font: Font::Default,
+        depth: 0,
+    };
+
+    // Display math may be split into lines with `\\`
+    let mut lines = Vec::new();
+    loop {
+        let (items, stop) = parser.parse_row()?;
+        lines.push(items.concat());
+        match stop {
+            Stop::Input => break,
+            Stop::Line => continue,
+            stop => return Err(stop.unexpected()),
+        }
+    }
+    if lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    let content = if lines.len() > 1 {
+        let rows: String = lines.iter().map(|line| format!("<mtr><mtd>{line}</mtd></mtr>")).collect();
+        format!("<mtable displaystyle=\"true\">{rows}</mtable>")
+    } else {
+        lines.concat()
+    };
+
+    if display {
+        Ok(format!("<math display=\"block\">{content}</math>"))
+    } else {
+        Ok(format!("<math>{content}</math>"))
+    }
+}
+
+/// The HTML for a formula, or the source marked as an error with the reason
+/// as its tooltip.
+pub(crate) fn math_html(latex: &str, display: bool) -> String {
+    latex_to_mathml(latex, display).unwrap_or_else(|error| {
+        let delimiter = if display { "$$" } else { "$" };
+        format!(
+            "<code class=\"math-error\" title=\"{}\">{delimiter}{}{delimiter}</code>",
+            escape_html(&error.to_string()),
+            escape_html(latex)
+        )
+    })
+}
+
+/// What ended a row of a formula.
+enum Stop {
+    Input,
+    Brace,
+    Right(String),
+    Cell,
+    Line,
+    End(String),
+}
+
+impl Stop {
+    fn unexpected(&self) -> MathError {
+        let token = match self {
+            Stop::Input => return MathError("unexpected end of formula".to_string()),
+            Stop::Brace => "}".to_string(),
+            Stop::Right(_) => "\\right".to_string(),
+            Stop::Cell => "&".to_string(),
+            Stop::Line => "\\\\".to_string(),
+            Stop::End(environment) => format!("\\end{{{environment}}}"),
+        };
+        MathError(format!("unexpected {token}"))
+    }
+}
+
+/// The font of letters and digits, set by `\mathbf` and friends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Default,
+    Upright,
+    Bold,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+/// A piece of a formula before its scripts are attached.
+struct Atom {
+    mathml: String,
+    /// Scripts go above and below instead of to the right.
+    limits: bool,
+    /// A named function, which is followed by a function application.
+    function: bool,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Self {
+        Atom {
+            mathml,
+            limits: false,
+            function: false,
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    font: Font,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skips white space and `%` comments, which do not matter in math.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '%' {
+                let rest = &self.source[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The name of a command, after its backslash: a run of letters or a
+    /// single other character.
+    fn command_name(&mut self) -> Result<String, MathError> {
+        let rest = &self.source[self.pos..];
+        let length = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let length = match length {
+            0 => rest.chars().next().map(char::len_utf8).ok_or_else(|| MathError("\\ at the end".to_string()))?,
+            length => length,
+        };
+        self.pos += length;
+        Ok(rest[..length].to_string())
+    }
+
+    /// Parses items until the end of the input, a `}`, `\right`, `&`, `\\`
+    /// or `\end` and returns them with what stopped the row.
+    fn parse_row(&mut self) -> Result<(Vec<String>, Stop), MathError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok((items, Stop::Input)),
+                Some('}') => {
+                    self.bump();
+                    return Ok((items, Stop::Brace));
+                }
+                Some('&') => {
+                    self.bump();
+                    return Ok((items, Stop::Cell));
+                }
+                Some('\\') => {
+                    let start = self.pos;
+                    self.bump();
+                    match self.command_name()?.as_str() {
+                        "right" => return Ok((items, Stop::Right(self.delimiter("\\right")?))),
+                        "\\" | "cr" => return Ok((items, Stop::Line)),
+                        "end" => return Ok((items, Stop::End(self.text_argument("\\end")?))),
+                        style @ ("displaystyle" | "textstyle") => {
+                            // The rest of the row is nested in the style, so it counts as a level
+                            self.depth += 1;
+                            if self.depth > MAX_DEPTH {
+                                return Err(MathError("the formula is nested too deeply".to_string()));
+                            }
+                            let (rest, stop) = self.parse_row()?;
+                            self.depth -= 1;
+                            items.push(format!(
+                                "<mstyle displaystyle=\"{}\">{}</mstyle>",
+                                style == "displaystyle",
+                                rest.concat()
+                            ));
+                            return Ok((items, stop));
+                        }
+                        _ => self.pos = start,
+                    }
+                }
+                Some(_) => {}
+            }
+            items.push(self.parse_scripted()?);
+        }
+    }
+
+    /// The rest of a `{...}` group, after the opening brace.
+    fn parse_group(&mut self) -> Result<String, MathError> {
+        match self.parse_row()? {
+            (items, Stop::Brace) => Ok(row(items)),
+            (_, Stop::Input) => Err(MathError("missing }".to_string())),
+            (_, stop) => Err(stop.unexpected()),
+        }
+    }
+
+    /// An atom with its sub- and superscripts.
+    fn parse_scripted(&mut self) -> Result<String, MathError> {
+        let mut atom = match self.peek() {
+            // Scripts without a base, as in `{}^{14}C`
+            Some('^' | '_') => Atom::new("<mrow></mrow>".to_string()),
+            _ => self.parse_atom()?,
+        };
+
+        let mut subscript = None;
+        let mut superscript = None;
+        let mut primes = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('^') => {
+                    self.bump();
+                    if superscript.is_some() {
+                        return Err(MathError("double superscript".to_string()));
+                    }
+                    superscript = Some(self.parse_argument("^")?);
+                }
+                Some('_') => {
+                    self.bump();
+                    if subscript.is_some() {
+                        return Err(MathError("double subscript".to_string()));
+                    }
+                    subscript = Some(self.parse_argument("_")?);
+                }
+                Some('\'') => {
+                    self.bump();
+                    primes += 1;
+                }
+                Some('\\') if self.source[self.pos..].starts_with("\\limits") => {
+                    self.pos += "\\limits".len();
+                    atom.limits = true;
+                }
+                Some('\\') if self.source[self.pos..].starts_with("\\nolimits") => {
+                    self.pos += "\\nolimits".len();
+                    atom.limits = false;
+                }
+                _ => break,
+            }
+        }
+
+        if primes > 0 {
+            let primes = format!("<mo>{}</mo>", "′".repeat(primes));
+            superscript = Some(match superscript {
+                Some(superscript) => format!("<mrow>{primes}{superscript}</mrow>"),
+                None => primes,
+            });
+        }
+
+        let base = atom.mathml;
+        let (under, over, both) = if atom.limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        let mut mathml = match (subscript, superscript) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{under}>{base}{sub}</{under}>"),
+            (None, Some(sup)) => format!("<{over}>{base}{sup}</{over}>"),
+            (Some(sub), Some(sup)) => format!("<{both}>{base}{sub}{sup}</{both}>"),
+        };
+        if atom.function {
+            mathml.push_str("<mo>\u{2061}</mo>");
+        }
+        Ok(mathml)
+    }
+
+    /// The argument of a command or script: a group, or a single character
+    /// or command.
+    fn parse_argument(&mut self, command: &str) -> Result<String, MathError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('}' | '&' | '^' | '_') => Err(MathError(format!("missing argument for {command}"))),
+            Some(c) if c.is_ascii_digit() => {
+                self.bump();
+                Ok(format!("<mn>{}</mn>", self.styled(c)))
+            }
+            Some(_) => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    /// An optional `[...]` argument, parsed as math.
+    fn parse_optional_argument(&mut self) -> Result<Option<String>, MathError> {
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return Ok(None);
+        }
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(']') => {
+                    self.bump();
+                    return Ok(Some(row(items)));
+                }
+                None => return Err(MathError("missing ]".to_string())),
+                Some(_) => items.push(self.parse_scripted()?),
+            }
+        }
+    }
+
+    /// A `{...}` argument taken as plain text, e.g. for `\text`.
+    fn text_argument(&mut self, command: &str) -> Result<String, MathError> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err(MathError(format!("missing argument for {command}")));
+        }
+        self.bump();
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    let text = &self.source[start..self.pos - 1];
+                    return Ok(unescape_text(text));
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        Err(MathError("missing }".to_string()))
+    }
+
+    fn parse_atom(&mut self) -> Result<Atom, MathError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(MathError("the formula is nested too deeply".to_string()));
+        }
+        let atom = self.parse_atom_inner();
+        self.depth -= 1;
+        atom
+    }
+
+    fn parse_atom_inner(&mut self) -> Result<Atom, MathError> {
+        self.skip_whitespace();
+        let Some(c) = self.bump() else {
+            return Err(MathError("unexpected end of formula".to_string()));
+        };
+        let mathml = match c {
+            '{' => self.parse_group()?,
+            '\\' => return self.parse_command(),
+            c if c.is_ascii_digit() || (c == '.' && self.peek().is_some_and(|c| c.is_ascii_digit())) => {
+                let start = self.pos - 1;
+                while let Some(c) = self.peek() {
+                    let rest = &self.source[self.pos + 1..];
+                    if c.is_ascii_digit() || (c == '.' && rest.starts_with(|c: char| c.is_ascii_digit())) {
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                let number: String = self.source[start..self.pos].chars().map(|c| self.styled(c)).collect();
+                format!("<mn>{number}</mn>")
+            }
+            c if c.is_alphabetic() => self.identifier(c),
+            '~' => space("0.333em"),
+            '\'' => "<mo>′</mo>".to_string(),
+            '-' => "<mo>−</mo>".to_string(),
+            '*' => "<mo>∗</mo>".to_string(),
+            c => operator(&c.to_string()),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    fn identifier(&self, c: char) -> String {
+        if self.font == Font::Upright {
+            format!("<mi mathvariant=\"normal\">{}</mi>", escape_html(&c.to_string()))
+        } else {
+            format!("<mi>{}</mi>", escape_html(&self.styled(c).to_string()))
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Atom, MathError> {
+        let name = self.command_name()?;
+        let name = name.as_str();
+
+        if let Some((_, symbol)) = SYMBOLS.iter().find(|(command, _)| *command == name) {
+            return Ok(Atom::new(format!("<mi>{symbol}</mi>")));
+        }
+        if let Some((_, symbol)) = UPRIGHT_SYMBOLS.iter().find(|(command, _)| *command == name) {
+            return Ok(Atom::new(format!("<mi mathvariant=\"normal\">{symbol}</mi>")));
+        }
+        if let Some((_, symbol)) = OPERATORS.iter().find(|(command, _)| *command == name) {
+            return Ok(Atom::new(operator(symbol)));
+        }
+        if let Some((_, symbol, limits)) = LARGE_OPERATORS.iter().find(|(command, ..)| *command == name) {
+            return Ok(Atom {
+                mathml: format!("<mo>{symbol}</mo>"),
+                limits: *limits,
+                function: false,
+            });
+        }
+        if let Some((_, function, limits)) = FUNCTIONS.iter().find(|(command, ..)| *command == name) {
+            return Ok(function_atom(function, *limits));
+        }
+        if let Some((_, width)) = SPACES.iter().find(|(command, _)| *command == name) {
+            return Ok(Atom::new(space(width)));
+        }
+        if let Some((_, accent, over, stretchy)) = ACCENTS.iter().find(|(command, ..)| *command == name) {
+            let base = self.parse_argument(&format!("\\{name}"))?;
+            let mathml = if *over {
+                format!("<mover accent=\"true\">{base}<mo stretchy=\"{stretchy}\">{accent}</mo></mover>")
+            } else {
+                format!("<munder accentunder=\"true\">{base}<mo stretchy=\"{stretchy}\">{accent}</mo></munder>")
+            };
+            // Braces take their labels above and below
+            return Ok(Atom {
+                mathml,
+                limits: name.ends_with("brace"),
+                function: false,
+            });
+        }
+        if let Some(size) = DELIMITER_SIZES
+            .iter()
+            .find(|(command, _)| name.strip_suffix(['l', 'r', 'm']).unwrap_or(name) == *command)
+            .map(|(_, size)| size)
+        {
+            let delimiter = self.delimiter(&format!("\\{name}"))?;
+            return Ok(Atom::new(format!(
+                "<mo stretchy=\"true\" minsize=\"{size}\" maxsize=\"{size}\">{}</mo>",
+                escape_html(&delimiter)
+            )));
+        }
+
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument(&format!("\\{name}"))?;
+                let denominator = self.parse_argument(&format!("\\{name}"))?;
+                let fraction = format!("<mfrac>{numerator}{denominator}</mfrac>");
+                match name {
+                    "dfrac" | "cfrac" => format!("<mstyle displaystyle=\"true\">{fraction}</mstyle>"),
+                    "tfrac" => format!("<mstyle displaystyle=\"false\">{fraction}</mstyle>"),
+                    _ => fraction,
+                }
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.parse_argument(&format!("\\{name}"))?;
+                let bottom = self.parse_argument(&format!("\\{name}"))?;
+                format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>")
+            }
+            "sqrt" => match self.parse_optional_argument()? {
+                Some(index) => {
+                    let base = self.parse_argument("\\sqrt")?;
+                    format!("<mroot>{base}{index}</mroot>")
+                }
+                None => format!("<msqrt>{}</msqrt>", self.parse_argument("\\sqrt")?),
+            },
+            "text" | "textrm" | "textnormal" | "textit" | "textbf" | "textsf" | "texttt" | "mbox" => {
+                let text = self.text_argument(&format!("\\{name}"))?;
+                format!("<mtext>{}</mtext>", escape_html(&text.replace(' ', "\u{a0}")))
+            }
+            "operatorname" => {
+                let limits = self.peek() == Some('*');
+                if limits {
+                    self.bump();
+                }
+                let function = self.text_argument("\\operatorname")?;
+                return Ok(function_atom(&function, limits));
+            }
+            "mathrm" | "mathbf" | "mathit" | "mathbb" | "mathcal" | "mathscr" | "mathfrak" | "mathsf" | "mathtt"
+            | "boldsymbol" | "bm" => {
+                let font = match name {
+                    "mathrm" => Font::Upright,
+                    "mathbf" | "boldsymbol" | "bm" => Font::Bold,
+                    "mathbb" => Font::DoubleStruck,
+                    "mathcal" | "mathscr" => Font::Script,
+                    "mathfrak" => Font::Fraktur,
+                    "mathsf" => Font::SansSerif,
+                    "mathtt" => Font::Monospace,
+                    _ => Font::Default,
+                };
+                let outer = self.font;
+                self.font = font;
+                let argument = self.parse_argument(&format!("\\{name}"));
+                self.font = outer;
+                argument?
+            }
+            "overset" | "stackrel" | "underset" => {
+                let script = self.parse_argument(&format!("\\{name}"))?;
+                let base = self.parse_argument(&format!("\\{name}"))?;
+                if name == "underset" {
+                    format!("<munder>{base}{script}</munder>")
+                } else {
+                    format!("<mover>{base}{script}</mover>")
+                }
+            }
+            "not" => {
+                let negated = self.parse_atom()?.mathml;
+                match negated.strip_suffix("</mo>") {
+                    Some(operator) => format!("{operator}\u{338}</mo>"),
+                    None => negated,
+                }
+            }
+            "left" => {
+                let open = self.delimiter("\\left")?;
+                let (items, stop) = self.parse_row()?;
+                let close = match stop {
+                    Stop::Right(close) => close,
+                    Stop::Input => return Err(MathError("\\left without \\right".to_string())),
+                    stop => return Err(stop.unexpected()),
+                };
+                format!("<mrow>{}{}{}</mrow>", fence(&open), items.concat(), fence(&close))
+            }
+            "middle" => fence(&self.delimiter("\\middle")?),
+            // MathML has no negative space
+            "!" | "negthinspace" => "<mrow></mrow>".to_string(),
+            "begin" => self.parse_environment()?,
+            "limits" | "nolimits" => return Err(MathError(format!("\\{name} is only allowed on operators"))),
+            _ => return Err(MathError(format!("unknown command \\{name}"))),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    /// The delimiter after `\left`, `\right` and the `\big` commands. `.`
+    /// stands for no delimiter.
+    fn delimiter(&mut self, command: &str) -> Result<String, MathError> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some('.') => Ok(String::new()),
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '/')) => Ok(c.to_string()),
+            Some('<') => Ok("⟨".to_string()),
+            Some('>') => Ok("⟩".to_string()),
+            Some('\\') => {
+                let name = self.command_name()?;
+                DELIMITERS
+                    .iter()
+                    .find(|(command, _)| *command == name)
+                    .map(|(_, delimiter)| delimiter.to_string())
+                    .ok_or_else(|| MathError(format!("\\{name} is not a delimiter")))
+            }
+            Some(c) => Err(MathError(format!("{c} is not a delimiter"))),
+            None => Err(MathError(format!("missing delimiter after {command}"))),
+        }
+    }
+
+    /// The rest of an environment after `\begin`, as a table.
+    fn parse_environment(&mut self) -> Result<String, MathError> {
+        let name = self.text_argument("\\begin")?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", String::new()),
+            "pmatrix" => ("(", ")", String::new()),
+            "bmatrix" => ("[", "]", String::new()),
+            "Bmatrix" => ("{", "}", String::new()),
+            "vmatrix" => ("|", "|", String::new()),
+            "Vmatrix" => ("‖", "‖", String::new()),
+            "cases" => ("{", "", "left left".to_string()),
+            "aligned" | "align" | "align*" | "split" => ("", "", "right left right left right left".to_string()),
+            "gathered" | "gather" | "gather*" => ("", "", String::new()),
+            "array" => {
+                let columns = self.text_argument("\\begin{array}")?;
+                let align: Vec<&str> = columns
+                    .chars()
+                    .filter_map(|column| match column {
+                        'l' => Some("left"),
+                        'c' => Some("center"),
+                        'r' => Some("right"),
+                        _ => None,
+                    })
+                    .collect();
+                ("", "", align.join(" "))
+            }
+            _ => return Err(MathError(format!("unknown environment {name}"))),
+        };
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new()];
+        loop {
+            let (items, stop) = self.parse_row()?;
+            if let Some(cells) = rows.last_mut() {
+                cells.push(items.concat());
+            }
+            match stop {
+                Stop::Cell => {}
+                Stop::Line => rows.push(Vec::new()),
+                Stop::End(end) if end == name => break,
+                Stop::Input => return Err(MathError(format!("\\begin{{{name}}} without \\end{{{name}}}"))),
+                stop => return Err(stop.unexpected()),
+            }
+        }
+        // A `\\` before `\end` does not start another row
+        if rows.len() > 1 && rows.last().is_some_and(|cells| cells.iter().all(String::is_empty)) {
+            rows.pop();
+        }
+
+        let mut table = String::from("<mtable");
+        if !align.is_empty() {
+            table.push_str(&format!(" columnalign=\"{align}\""));
+        }
+        table.push('>');
+        for cells in rows {
+            table.push_str("<mtr>");
+            for cell in cells {
+                table.push_str(&format!("<mtd>{cell}</mtd>"));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        if open.is_empty() && close.is_empty() {
+            Ok(table)
+        } else {
+            Ok(format!("<mrow>{}{table}{}</mrow>", fence(open), fence(close)))
+        }
+    }
+
+    /// A letter or digit in the current font.
+    fn styled(&self, c: char) -> char {
+        let (upper, lower, digits, holes): (u32, u32, Option<u32>, &[(char, char)]) = match self.font {
+            Font::Default | Font::Upright => return c,
+            Font::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE), &[]),
+            Font::Script => (
+                0x1D49C,
+                0x1D4B6,
+                None,
+                &[
+                    ('B', 'ℬ'), ('E', 'ℰ'), ('F', 'ℱ'), ('H', 'ℋ'), ('I', 'ℐ'), ('L', 'ℒ'),
+                    ('M', 'ℳ'), ('R', 'ℛ'), ('e', 'ℯ'), ('g', 'ℊ'), ('o', 'ℴ'),
+                ],
+            ),
+            Font::Fraktur => (
+                0x1D504,
+                0x1D51E,
+                None,
+                &[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+            ),
+            Font::DoubleStruck => (
+                0x1D538,
+                0x1D552,
+                Some(0x1D7D8),
+                &[('C', 'ℂ'), ('H', 'ℍ'), ('N', 'ℕ'), ('P', 'ℙ'), ('Q', 'ℚ'), ('R', 'ℝ'), ('Z', 'ℤ')],
+            ),
+            Font::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2), &[]),
+            Font::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6), &[]),
+        };
+
+        // Letters that were in Unicode before the others have their old
+        // code points
+        if let Some((_, styled)) = holes.iter().find(|(letter, _)| *letter == c) {
+            return *styled;
+        }
+        let code = match c {
+            'A'..='Z' => upper + (c as u32 - 'A' as u32),
+            'a'..='z' => lower + (c as u32 - 'a' as u32),
+            '0'..='9' => match digits {
+                Some(zero) => zero + (c as u32 - '0' as u32),
+                None => return c,
+            },
+            _ => return c,
+        };
+        char::from_u32(code).unwrap_or(c)
+    }
+}
+
+/// Several items as one, wrapped in `<mrow>` unless there is just one.
+fn row(mut items: Vec<String>) -> String {
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        format!("<mrow>{}</mrow>", items.concat())
+    }
+}
+
+fn operator(symbol: &str) -> String {
+    let escaped = escape_html(symbol);
+    if symbol.chars().count() == 1 && symbol.starts_with(FENCES) {
+        format!("<mo stretchy=\"false\">{escaped}</mo>")
+    } else {
+        format!("<mo>{escaped}</mo>")
+    }
+}
+
+/// A delimiter that grows with what it encloses, or nothing for `.`.
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        String::new()
+    } else {
+        format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape_html(delimiter))
+    }
+}
+
+fn function_atom(name: &str, limits: bool) -> Atom {
+    let name = escape_html(name);
+    Atom {
+        mathml: if limits {
+            format!("<mo movablelimits=\"true\">{name}</mo>")
+        } else {
+            format!("<mi>{name}</mi>")
+        },
+        limits,
+        function: !limits,
+    }
+}
+
+fn space(width: &str) -> String {
+    format!("<mspace width=\"{width}\"></mspace>")
+}
+
+/// Text in `\text{...}` with `\{`, `\}` and the like unescaped.
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,747 @@
+use printpdf::{
+    path::PaintMode, BuiltinFont, Color, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Pt, Rect, Rgb,
+};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
+
+use crate::diagram::DiagramLanguages;
+use crate::html::Extensions;
+
+// A4 in points
+const PAGE_WIDTH: f32 = 595.28;
+const PAGE_HEIGHT: f32 = 841.89;
+const MARGIN: f32 = 56.69;
+const CONTENT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.0;
+const LINE_HEIGHT: f32 = 1.45;
+const LIST_INDENT: f32 = 18.0;
+const QUOTE_INDENT: f32 = 14.0;
+const CELL_PADDING: f32 = 4.0;
+// Deeply nested lists and quotes stop indenting here, so that the text keeps
+// at least half of the page
+const MAX_INDENT: f32 = CONTENT_WIDTH / 2.0;
+// Cells after the last column are joined into it
+const MAX_COLUMNS: usize = 12;
+const BULLET: &str = "\u{2022}";
+
+const TEXT_COLOR: (f32, f32, f32) = (0.12, 0.16, 0.22);
+const MUTED_COLOR: (f32, f32, f32) = (0.42, 0.45, 0.5);
+const LINK_COLOR: (f32, f32, f32) = (0.15, 0.39, 0.92);
+const RULE_COLOR: (f32, f32, f32) = (0.82, 0.84, 0.86);
+const CODE_BACKGROUND: (f32, f32, f32) = (0.95, 0.96, 0.96);
+
+#[derive(Debug, thiserror::Error)]
+#[error("PDF rendering failed: {0}")]
+pub struct PdfError(String);
+
+impl From<printpdf::Error> for PdfError {
+    fn from(error: printpdf::Error) -> Self {
+        PdfError(error.to_string())
+    }
+}
+
+/// Renders Markdown to a PDF document with the built-in PDF fonts.
+///
+/// The built-in fonts only cover the Latin-1 range, so other characters are
+/// replaced. Diagrams in one of the `diagram_languages` are shown as their
+/// source, since drawing them would need a diagram server.
+pub fn render_pdf(title: &str, markdown: &str, diagram_languages: &DiagramLanguages) -> Result<Vec<u8>, PdfError> {
+    let (doc, page, layer) = PdfDocument::new(title, Mm::from(Pt(PAGE_WIDTH)), Mm::from(Pt(PAGE_HEIGHT)), "Content");
+    let fonts = Fonts {
+        regular: doc.add_builtin_font(BuiltinFont::Helvetica)?,
+        bold: doc.add_builtin_font(BuiltinFont::HelveticaBold)?,
+        italic: doc.add_builtin_font(BuiltinFont::HelveticaOblique)?,
+        bold_italic: doc.add_builtin_font(BuiltinFont::HelveticaBoldOblique)?,
+        mono: doc.add_builtin_font(BuiltinFont::Courier)?,
+    };
+    let first_page = doc.get_page(page).get_layer(layer);
+
+    let mut writer = Writer {
+        doc,
+        fonts,
+        pages: vec![first_page],
+        y: MARGIN,
+        indent: 0.0,
+        quote_depth: 0,
+    };
+    let mut state = BlockState {
+        diagram_languages: diagram_languages.clone(),
+        ..BlockState::default()
+    };
+
+    for event in Parser::new_ext(markdown, Extensions::ALL.options()) {
+        state.handle(&mut writer, event);
+    }
+    state.flush(&mut writer, BODY_SIZE, false);
+
+    writer.number_pages();
+    Ok(writer.doc.save_to_bytes()?)
+}
+
+struct Fonts {
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    italic: IndirectFontRef,
+    bold_italic: IndirectFontRef,
+    mono: IndirectFontRef,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    code: bool,
+    link: bool,
+}
+
+/// A piece of inline text with a single style.
+#[derive(Clone)]
+struct Run {
+    text: String,
+    style: Style,
+}
+
+/// A word (or part of one) placed on a line, with the space that follows it.
+struct Piece {
+    text: String,
+    style: Style,
+    space_after: bool,
+}
+
+#[derive(Default)]
+struct BlockState {
+    runs: Vec<Run>,
+    style: Style,
+    heading: Option<HeadingLevel>,
+    lists: Vec<Option<u64>>,
+    // Bullet or number waiting for the first line of a list item
+    pending_marker: Option<String>,
+    code_block: Option<String>,
+    diagram: Option<String>,
+    diagram_languages: DiagramLanguages,
+    table: Option<Table>,
+    in_metadata: bool,
+}
+
+#[derive(Default)]
+struct Table {
+    // Each row with whether it is the header row
+    rows: Vec<(bool, Vec<Vec<Run>>)>,
+}
+
+impl BlockState {
+    fn handle(&mut self, writer: &mut Writer, event: Event) {
+        if self.in_metadata {
+            if let Event::End(TagEnd::MetadataBlock(_)) = event {
+                self.in_metadata = false;
+            }
+            return;
+        }
+
+        if let Some(code) = self.code_block.as_mut() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => {
+                    let code = self.code_block.take().unwrap_or_default();
+                    if let Some(kind) = self.diagram.take() {
+                        writer.caption(&format!("{} diagram", kind));
+                    }
+                    writer.code_block(&code);
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(writer, tag),
+            Event::End(tag) => self.end(writer, tag),
+            Event::Text(text) => self.push(&text),
+            Event::Code(text) => {
+                let style = Style { code: true, ..self.style };
+                self.runs.push(Run { text: text.into_string(), style });
+            }
+            Event::InlineMath(text) => {
+                let style = Style { code: true, ..self.style };
+                self.runs.push(Run { text: text.into_string(), style });
+            }
+            Event::DisplayMath(text) => {
+                self.flush(writer, BODY_SIZE, true);
+                writer.code_block(&text);
+            }
+            Event::SoftBreak => self.push(" "),
+            Event::HardBreak => self.push("\n"),
+            Event::Rule => {
+                self.flush(writer, BODY_SIZE, true);
+                writer.rule();
+            }
+            Event::TaskListMarker(checked) => self.push(if checked { "[x] " } else { "[ ] " }),
+            Event::FootnoteReference(label) => self.push(&format!("[{}]", label)),
+            Event::Html(_) | Event::InlineHtml(_) => {}
+        }
+    }
+
+    fn start(&mut self, writer: &mut Writer, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Heading { level, .. } => {
+                self.flush(writer, BODY_SIZE, true);
+                self.heading = Some(level);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush(writer, BODY_SIZE, true);
+                writer.quote_depth += 1;
+                writer.indent += QUOTE_INDENT;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush(writer, BODY_SIZE, true);
+                if let CodeBlockKind::Fenced(lang) = &kind {
+                    if let Some(language) = self.diagram_languages.find(lang) {
+                        self.diagram = Some(language.name.clone());
+                    }
+                }
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush(writer, BODY_SIZE, self.lists.is_empty());
+                self.lists.push(start);
+                writer.indent += LIST_INDENT;
+            }
+            Tag::Item => {
+                self.flush(writer, BODY_SIZE, false);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => BULLET.to_string(),
+                };
+                self.pending_marker = Some(marker);
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush(writer, BODY_SIZE, true);
+                self.push(&format!("[{}] ", label));
+            }
+            Tag::Table(_) => {
+                self.flush(writer, BODY_SIZE, true);
+                self.table = Some(Table::default());
+            }
+            Tag::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push((true, Vec::new()));
+                }
+            }
+            Tag::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push((false, Vec::new()));
+                }
+            }
+            Tag::TableCell => self.runs.clear(),
+            Tag::Emphasis => self.style.italic = true,
+            Tag::Strong => self.style.bold = true,
+            Tag::Link { .. } => self.style.link = true,
+            Tag::Image { .. } => {
+                self.style.italic = true;
+                self.push("[Image: ");
+            }
+            Tag::MetadataBlock(_) => self.in_metadata = true,
+            Tag::Strikethrough
+            | Tag::Superscript
+            | Tag::Subscript
+            | Tag::HtmlBlock
+            | Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition => {}
+        }
+    }
+
+    fn end(&mut self, writer: &mut Writer, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.flush(writer, BODY_SIZE, true),
+            TagEnd::Heading(level) => {
+                let size = match level {
+                    HeadingLevel::H1 => 22.0,
+                    HeadingLevel::H2 => 18.0,
+                    HeadingLevel::H3 => 15.0,
+                    HeadingLevel::H4 => 13.0,
+                    _ => 12.0,
+                };
+                writer.space_before(size * 0.6);
+                self.flush(writer, size, true);
+                self.heading = None;
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush(writer, BODY_SIZE, true);
+                writer.quote_depth -= 1;
+                writer.indent -= QUOTE_INDENT;
+            }
+            TagEnd::List(_) => {
+                self.flush(writer, BODY_SIZE, false);
+                self.lists.pop();
+                writer.indent -= LIST_INDENT;
+                if self.lists.is_empty() {
+                    writer.space_before(BODY_SIZE * 0.6);
+                }
+            }
+            TagEnd::Item => self.flush(writer, BODY_SIZE, false),
+            TagEnd::FootnoteDefinition => self.flush(writer, BODY_SIZE, true),
+            TagEnd::TableCell => {
+                let runs = std::mem::take(&mut self.runs);
+                if let Some((_, cells)) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    cells.push(runs);
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    writer.table(&table.rows);
+                }
+            }
+            TagEnd::Emphasis => self.style.italic = false,
+            TagEnd::Strong => self.style.bold = false,
+            TagEnd::Link => self.style.link = false,
+            TagEnd::Image => {
+                self.push("]");
+                self.style.italic = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        let style = Style {
+            bold: self.style.bold || self.heading.is_some(),
+            ..self.style
+        };
+        self.runs.push(Run { text: text.to_string(), style });
+    }
+
+    /// Writes the collected inline text as one block.
+    fn flush(&mut self, writer: &mut Writer, size: f32, spacing: bool) {
+        // Table cells are written when the table ends
+        if self.table.is_some() {
+            return;
+        }
+        let runs = std::mem::take(&mut self.runs);
+        let marker = self.pending_marker.take();
+        if runs.iter().all(|run| run.text.trim().is_empty()) {
+            // Keep the marker for the first text of an item that starts with a nested block
+            self.pending_marker = marker;
+            return;
+        }
+        writer.paragraph(&runs, size, marker.as_deref());
+        if spacing {
+            writer.space_before(size * 0.7);
+        }
+    }
+}
+
+struct Writer {
+    doc: PdfDocumentReference,
+    fonts: Fonts,
+    pages: Vec<PdfLayerReference>,
+    // Distance of the next line from the top of the page
+    y: f32,
+    indent: f32,
+    quote_depth: usize,
+}
+
+impl Writer {
+    /// The indentation of the current block, limited to `MAX_INDENT`.
+    fn indent(&self) -> f32 {
+        self.indent.min(MAX_INDENT)
+    }
+
+    fn layer(&self) -> &PdfLayerReference {
+        self.pages.last().expect("the document always has a page")
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(Mm::from(Pt(PAGE_WIDTH)), Mm::from(Pt(PAGE_HEIGHT)), "Content");
+        self.pages.push(self.doc.get_page(page).get_layer(layer));
+        self.y = MARGIN;
+    }
+
+    /// Starts a new page unless `height` still fits on the current one.
+    fn reserve(&mut self, height: f32) {
+        if self.y + height > PAGE_HEIGHT - MARGIN && self.y > MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn space_before(&mut self, space: f32) {
+        if self.y > MARGIN {
+            self.y += space;
+        }
+    }
+
+    fn font(&self, style: Style) -> &IndirectFontRef {
+        match (style.code, style.bold, style.italic) {
+            (true, _, _) => &self.fonts.mono,
+            (false, true, true) => &self.fonts.bold_italic,
+            (false, true, false) => &self.fonts.bold,
+            (false, false, true) => &self.fonts.italic,
+            (false, false, false) => &self.fonts.regular,
+        }
+    }
+
+    fn paragraph(&mut self, runs: &[Run], size: f32, marker: Option<&str>) {
+        let left = MARGIN + self.indent();
+        let lines = wrap(runs, size, CONTENT_WIDTH - self.indent());
+        let line_height = size * LINE_HEIGHT;
+
+        for (index, line) in lines.iter().enumerate() {
+            self.reserve(line_height);
+            let baseline = self.y + size;
+
+            match marker.filter(|_| index == 0) {
+                // The built-in fonts have no bullet character that survives
+                // the encoding, so bullets are drawn
+                Some(BULLET) => {
+                    let bullet = size * 0.3;
+                    let x = left - bullet - 7.0;
+                    self.fill_rect(x, baseline - size * 0.35 - bullet / 2.0, bullet, bullet, TEXT_COLOR);
+                }
+                Some(marker) => {
+                    let marker_style = Style::default();
+                    let x = left - text_width(marker, marker_style, size) - 5.0;
+                    self.text(marker, marker_style, size, x, baseline);
+                }
+                None => {}
+            }
+
+            self.draw_line(line, size, left, baseline);
+
+            self.quote_bar(line_height);
+            self.y += line_height;
+        }
+    }
+
+    fn code_block(&mut self, code: &str) {
+        let left = MARGIN + self.indent();
+        let line_height = CODE_SIZE * LINE_HEIGHT;
+        let char_width = text_width(" ", Style { code: true, ..Style::default() }, CODE_SIZE);
+        let max_chars = (((CONTENT_WIDTH - self.indent() - 2.0 * CELL_PADDING) / char_width) as usize).max(1);
+
+        let mut lines = Vec::new();
+        for line in code.trim_end_matches('\n').split('\n') {
+            let chars: Vec<char> = line.replace('\t', "    ").chars().collect();
+            if chars.is_empty() {
+                lines.push(String::new());
+            }
+            for chunk in chars.chunks(max_chars) {
+                lines.push(chunk.iter().collect::<String>());
+            }
+        }
+
+        self.space_before(2.0);
+        for line in lines {
+            self.reserve(line_height);
+            self.fill_rect(left, self.y, CONTENT_WIDTH - self.indent(), line_height, CODE_BACKGROUND);
+            let style = Style { code: true, ..Style::default() };
+            self.text(&line, style, CODE_SIZE, left + CELL_PADDING, self.y + CODE_SIZE + 1.0);
+            self.quote_bar(line_height);
+            self.y += line_height;
+        }
+        self.space_before(BODY_SIZE * 0.7);
+    }
+
+    fn caption(&mut self, text: &str) {
+        let runs = [Run {
+            text: text.to_string(),
+            style: Style { italic: true, ..Style::default() },
+        }];
+        self.paragraph(&runs, CODE_SIZE, None);
+    }
+
+    fn rule(&mut self) {
+        self.reserve(BODY_SIZE);
+        let y = self.y + BODY_SIZE / 2.0;
+        self.line(MARGIN + self.indent(), y, PAGE_WIDTH - MARGIN, y, RULE_COLOR);
+        self.y += BODY_SIZE * 1.5;
+    }
+
+    fn table(&mut self, rows: &[(bool, Vec<Vec<Run>>)]) {
+        let columns = rows.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0).min(MAX_COLUMNS);
+        if columns == 0 {
+            return;
+        }
+        let left = MARGIN + self.indent();
+        let width = CONTENT_WIDTH - self.indent();
+        let column_width = width / columns as f32;
+        let line_height = BODY_SIZE * LINE_HEIGHT;
+
+        for (header, cells) in rows {
+            let wrapped: Vec<Vec<Vec<Piece>>> = join_extra_cells(cells)
+                .iter()
+                .map(|runs| {
+                    let runs: Vec<Run> = runs
+                        .iter()
+                        .map(|run| Run {
+                            text: run.text.clone(),
+                            style: Style { bold: run.style.bold || *header, ..run.style },
+                        })
+                        .collect();
+                    wrap(&runs, BODY_SIZE, column_width - 2.0 * CELL_PADDING)
+                })
+                .collect();
+            let line_count = wrapped.iter().map(Vec::len).max().unwrap_or(1).max(1);
+            let row_height = line_count as f32 * line_height + 2.0 * CELL_PADDING;
+
+            self.reserve(row_height);
+            let top = self.y;
+            for (column, lines) in wrapped.iter().enumerate() {
+                let cell_left = left + column as f32 * column_width + CELL_PADDING;
+                for (index, line) in lines.iter().enumerate() {
+                    let baseline = top + CELL_PADDING + index as f32 * line_height + BODY_SIZE;
+                    self.draw_line(line, BODY_SIZE, cell_left, baseline);
+                }
+            }
+
+            // Cell borders
+            let bottom = top + row_height;
+            self.line(left, top, left + width, top, RULE_COLOR);
+            self.line(left, bottom, left + width, bottom, RULE_COLOR);
+            for column in 0..=columns {
+                let x = left + column as f32 * column_width;
+                self.line(x, top, x, bottom, RULE_COLOR);
+            }
+            self.y = bottom;
+        }
+        self.space_before(BODY_SIZE * 0.7);
+    }
+
+    /// Writes one wrapped line, joining pieces of the same style so that the
+    /// text can be copied out of the PDF with its spaces.
+    fn draw_line(&self, line: &[Piece], size: f32, left: f32, baseline: f32) {
+        let mut x = left;
+        let mut start = x;
+        let mut text = String::new();
+        for (index, piece) in line.iter().enumerate() {
+            text.push_str(&piece.text);
+            x += text_width(&piece.text, piece.style, size);
+            let next_style = line.get(index + 1).map(|next| next.style);
+            if piece.space_after {
+                if next_style == Some(piece.style) {
+                    text.push(' ');
+                }
+                x += text_width(" ", piece.style, size);
+            }
+            if next_style != Some(piece.style) {
+                self.text(&text, piece.style, size, start, baseline);
+                text.clear();
+                start = x;
+            }
+        }
+    }
+
+    /// The bar left of block quotes, drawn line by line so that it follows
+    /// page breaks.
+    fn quote_bar(&self, height: f32) {
+        for depth in 0..self.quote_depth {
+            let x = MARGIN + self.indent() - QUOTE_INDENT * (depth as f32 + 1.0) + 3.0;
+            // Bars of quotes nested deeper than `MAX_INDENT` would be left of the margin
+            if x < MARGIN {
+                break;
+            }
+            self.line(x, self.y, x, self.y + height, RULE_COLOR);
+        }
+    }
+
+    fn text(&self, text: &str, style: Style, size: f32, x: f32, baseline: f32) {
+        if text.is_empty() {
+            return;
+        }
+        let color = if style.link {
+            LINK_COLOR
+        } else if self.quote_depth > 0 {
+            MUTED_COLOR
+        } else {
+            TEXT_COLOR
+        };
+        let layer = self.layer();
+        layer.set_fill_color(rgb(color));
+        layer.use_text(
+            text,
+            size,
+            Mm::from(Pt(x)),
+            Mm::from(Pt(PAGE_HEIGHT - baseline)),
+            self.font(style),
+        );
+    }
+
+    fn line(&self, x1: f32, y1: f32, x2: f32, y2: f32, color: (f32, f32, f32)) {
+        let layer = self.layer();
+        layer.set_outline_color(rgb(color));
+        layer.set_outline_thickness(0.5);
+        layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm::from(Pt(x1)), Mm::from(Pt(PAGE_HEIGHT - y1))), false),
+                (Point::new(Mm::from(Pt(x2)), Mm::from(Pt(PAGE_HEIGHT - y2))), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn fill_rect(&self, x: f32, y: f32, width: f32, height: f32, color: (f32, f32, f32)) {
+        let layer = self.layer();
+        layer.set_fill_color(rgb(color));
+        layer.add_rect(
+            Rect::new(
+                Mm::from(Pt(x)),
+                Mm::from(Pt(PAGE_HEIGHT - y - height)),
+                Mm::from(Pt(x + width)),
+                Mm::from(Pt(PAGE_HEIGHT - y)),
+            )
+            .with_mode(PaintMode::Fill),
+        );
+    }
+
+    fn number_pages(&self) {
+        let total = self.pages.len();
+        let style = Style::default();
+        for (index, layer) in self.pages.iter().enumerate() {
+            let label = format!("{} / {}", index + 1, total);
+            let x = (PAGE_WIDTH - text_width(&label, style, CODE_SIZE)) / 2.0;
+            layer.set_fill_color(rgb(MUTED_COLOR));
+            layer.use_text(
+                label,
+                CODE_SIZE,
+                Mm::from(Pt(x)),
+                Mm::from(Pt(MARGIN / 2.0)),
+                &self.fonts.regular,
+            );
+        }
+    }
+}
+
+/// The cells of a row with those after `MAX_COLUMNS` joined into the last
+/// column.
+fn join_extra_cells(cells: &[Vec<Run>]) -> Vec<Vec<Run>> {
+    let mut cells = cells.to_vec();
+    if cells.len() > MAX_COLUMNS {
+        let extra = cells.split_off(MAX_COLUMNS);
+        let last = cells.last_mut().expect("MAX_COLUMNS is not zero");
+        for cell in extra {
+            last.push(Run { text: " | ".to_string(), style: Style::default() });
+            last.extend(cell);
+        }
+    }
+    cells
+}
+
+fn rgb((r, g, b): (f32, f32, f32)) -> Color {
+    Color::Rgb(Rgb::new(r, g, b, None))
+}
+
+/// Breaks styled text into lines that fit into `width`. Newlines in the
+/// text force a line break. Lines are at least one character wide.
+fn wrap(runs: &[Run], size: f32, width: f32) -> Vec<Vec<Piece>> {
+    let width = width.max(size);
+    let mut lines = Vec::new();
+    let mut line: Vec<Piece> = Vec::new();
+    let mut line_width = 0.0;
+
+    for run in runs {
+        for (segment_index, segment) in run.text.split('\n').enumerate() {
+            if segment_index > 0 {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0.0;
+            }
+
+            let mut rest = segment;
+            while !rest.is_empty() {
+                let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let (word, after) = rest.split_at(word_end);
+                let space_after = !after.is_empty();
+                rest = after.trim_start();
+
+                if word.is_empty() {
+                    // Leading whitespace belongs to the previous piece
+                    if let Some(last) = line.last_mut() {
+                        last.space_after = true;
+                    }
+                    continue;
+                }
+
+                let space = text_width(" ", run.style, size);
+                let mut word = word.to_string();
+                while !word.is_empty() {
+                    let word_width = text_width(&word, run.style, size);
+                    if line_width + word_width <= width {
+                        line_width += word_width + if space_after { space } else { 0.0 };
+                        line.push(Piece { text: word, style: run.style, space_after });
+                        break;
+                    }
+                    if !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                        line_width = 0.0;
+                        continue;
+                    }
+                    // A single word wider than the line is split
+                    let (head, tail) = split_to_width(&word, run.style, size, width);
+                    line.push(Piece { text: head, style: run.style, space_after: false });
+                    lines.push(std::mem::take(&mut line));
+                    word = tail;
+                }
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Splits off the head of `word` that fits into `width`, but at least its
+/// first character.
+fn split_to_width(word: &str, style: Style, size: f32, width: f32) -> (String, String) {
+    let mut used = 0.0;
+    let mut split = word.len();
+    for (index, c) in word.char_indices() {
+        used += char_width(c, style) * size / 1000.0;
+        if used > width && index > 0 {
+            split = index;
+            break;
+        }
+    }
+    let (head, tail) = word.split_at(split);
+    (head.to_string(), tail.to_string())
+}
+
+fn text_width(text: &str, style: Style, size: f32) -> f32 {
+    text.chars().map(|c| char_width(c, style)).sum::<f32>() * size / 1000.0
+}
+
+// Advance widths of the printable ASCII characters (32 to 126) in the
+// built-in fonts, in thousandths of the font size. The oblique variants use
+// the same widths as the upright ones.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+fn char_width(c: char, style: Style) -> f32 {
+    if style.code {
+        return 600.0;
+    }
+    let table = if style.bold { &HELVETICA_BOLD_WIDTHS } else { &HELVETICA_WIDTHS };
+    match c as u32 {
+        code @ 32..=126 => table[(code - 32) as usize] as f32,
+        // Close enough for the Latin-1 letters that make up most of the rest
+        _ => 556.0,
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+/// Tags added to ammonia's default allow-list, for what Markdown produces.
+const MARKDOWN_TAGS: &[&str] = &["input"];
+
+/// MathML produced for formulas, with the attributes each tag may have.
+const MATHML_TAGS: &[(&str, &[&str])] = &[
+    ("math", &["display"]),
+    ("mrow", &[]),
+    ("mi", &["mathvariant"]),
+    ("mn", &[]),
+    ("mo", &["stretchy", "fence", "minsize", "maxsize", "movablelimits"]),
+    ("mtext", &[]),
+    ("mspace", &["width"]),
+    ("msub", &[]),
+    ("msup", &[]),
+    ("msubsup", &[]),
+    ("munder", &["accentunder"]),
+    ("mover", &["accent"]),
+    ("munderover", &[]),
+    ("mfrac", &["linethickness"]),
+    ("msqrt", &[]),
+    ("mroot", &[]),
+    ("mstyle", &["displaystyle"]),
+    ("mtable", &["columnalign", "displaystyle"]),
+    ("mtr", &[]),
+    ("mtd", &[]),
+];
+
+/// Put in front of every `id`, so that names like footnote labels and
+/// heading attributes cannot shadow globals of the page (DOM clobbering).
+const ID_PREFIX: &str = "user-content-";
+
+/// Tags that are removed together with their content.
+const NEVER_ALLOWED_TAGS: &[&str] = &["script", "style"];
+
+/// Class names produced by the renderer. A class is kept if it starts with
+/// one of these, all others are removed.
+const MARKDOWN_CLASS_PREFIXES: &[&str] = &[
+    "language-",
+    "highlight",
+    "code-line",
+    "hl-",
+    "math",
+    "footnote-",
+    "markdown-alert",
+    "kroki-diagram",
+    "diagram-error",
+];
+
+/// Allow-list of the HTML that may end up in the page. Everything else is
+/// removed: unknown tags (their text is kept), event handlers, `style`
+/// except table alignment, and URLs with schemes other than `http`,
+/// `https`, `mailto` and a few more harmless ones.
+///
+/// The lists can be extended per deployment. Each method takes a comma
+/// separated list, so that they can be fed straight from configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanitizePolicy {
+    extra_tags: Vec<String>,
+    denied_tags: Vec<String>,
+    extra_attributes: Vec<String>,
+    extra_url_schemes: Vec<String>,
+    extra_class_prefixes: Vec<String>,
+    data_images: bool,
+    blob_images: bool,
+}
+
+impl SanitizePolicy {
+    pub fn new() -> Self {
+        SanitizePolicy::default()
+    }
+
+    /// Allows more tags, e.g. `"iframe, video"`. `script` and `style` are
+    /// never allowed.
+    pub fn allow_tags(mut self, tags: &str) -> Self {
+        self.extra_tags
+            .extend(split_list(tags).filter(|tag| !NEVER_ALLOWED_TAGS.contains(&tag.as_str())));
+        self
+    }
+
+    /// Removes tags from the allow-list, e.g. `"img"` to not show images
+    /// from other servers.
+    pub fn deny_tags(mut self, tags: &str) -> Self {
+        self.denied_tags.extend(split_list(tags));
+        self
+    }
+
+    /// Allows attributes on all tags. Event handlers (`on...`), `style` and
+    /// `rel` (set on every link) are never allowed.
+    pub fn allow_attributes(mut self, attributes: &str) -> Self {
+        self.extra_attributes.extend(
+            split_list(attributes).filter(|name| !name.starts_with("on") && name != "style" && name != "rel"),
+        );
+        self
+    }
+
+    /// Allows more URL schemes in links and images, e.g. `"ftp"`.
+    pub fn allow_url_schemes(mut self, schemes: &str) -> Self {
+        self.extra_url_schemes.extend(
+            split_list(schemes).filter(|scheme| scheme != "javascript" && scheme != "vbscript"),
+        );
+        self
+    }
+
+    /// Keeps classes starting with any of the given prefixes.
+    pub fn allow_class_prefixes(mut self, prefixes: &str) -> Self {
+        self.extra_class_prefixes.extend(split_list(prefixes));
+        self
+    }
+
+    /// Allows images whose source is a `data:image/...` URL, which exports
+    /// use to embed diagrams.
+    pub fn allow_data_images(mut self, allow: bool) -> Self {
+        self.data_images = allow;
+        self
+    }
+
+    /// Allows images whose source is a `blob:` URL, which the editor uses
+    /// for diagrams it fetched with the login.
+    pub fn allow_blob_images(mut self, allow: bool) -> Self {
+        self.blob_images = allow;
+        self
+    }
+
+    /// Removes everything from `html` that the policy does not allow.
+    pub fn clean(&self, html: &str) -> String {
+        let mut builder = ammonia::Builder::default();
+
+        builder
+            .add_tags(MARKDOWN_TAGS)
+            .add_tags(MATHML_TAGS.iter().map(|(tag, _)| *tag))
+            .add_tags(self.extra_tags.iter().map(String::as_str))
+            .rm_tags(self.denied_tags.iter().map(String::as_str))
+            .add_generic_attributes(["id", "class"])
+            .id_prefix(Some(ID_PREFIX))
+            .add_generic_attributes(self.extra_attributes.iter().map(String::as_str))
+            .add_url_schemes(self.extra_url_schemes.iter().map(String::as_str))
+            .add_tag_attributes("input", ["checked"])
+            .add_tag_attributes("th", ["style"])
+            .add_tag_attributes("td", ["style"])
+            .filter_style_properties(HashSet::from(["text-align"]))
+            // Task list checkboxes are the only inputs Markdown produces
+            .tag_attribute_values(HashMap::from([(
+                "input",
+                HashMap::from([("type", HashSet::from(["checkbox"]))]),
+            )]))
+            .set_tag_attribute_value("input", "disabled", "");
+
+        for (tag, attributes) in MATHML_TAGS {
+            builder.add_tag_attributes(tag, attributes.iter().copied());
+        }
+
+        if self.data_images {
+            builder.add_url_schemes(["data"]);
+        }
+        if self.blob_images {
+            builder.add_url_schemes(["blob"]);
+        }
+
+        let class_prefixes: Vec<String> = MARKDOWN_CLASS_PREFIXES
+            .iter()
+            .map(|prefix| prefix.to_string())
+            .chain(self.extra_class_prefixes.iter().cloned())
+            .collect();
+        let data_images = self.data_images;
+        let blob_images = self.blob_images;
+
+        builder.attribute_filter(move |element, attribute, value| {
+            if attribute == "class" {
+                let classes: Vec<&str> = value
+                    .split_whitespace()
+                    .filter(|class| class_prefixes.iter().any(|prefix| class.starts_with(prefix.as_str())))
+                    .collect();
+                return (!classes.is_empty()).then(|| classes.join(" ").into());
+            }
+            // Links within the page, e.g. to footnotes, have to point to
+            // the prefixed ids
+            if attribute == "href" {
+                if let Some(fragment) = value.strip_prefix('#').filter(|f| !f.starts_with(ID_PREFIX)) {
+                    return Some(format!("#{}{}", ID_PREFIX, fragment).into());
+                }
+            }
+            // With `data:` URLs allowed for images they must not show up
+            // anywhere else, e.g. as link targets
+            if data_images && is_data_url(value) {
+                return (element == "img" && attribute == "src" && is_data_image(value)).then_some(value.into());
+            }
+            if blob_images && has_scheme(value, "blob:") {
+                return (element == "img" && attribute == "src").then_some(value.into());
+            }
+            Some(value.into())
+        });
+
+        builder.clean(html).to_string()
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split(',')
+        .map(|item| item.trim().to_ascii_lowercase())
+        .filter(|item| !item.is_empty())
+}
+
+fn is_data_url(value: &str) -> bool {
+    has_scheme(value, "data:")
+}
+
+fn has_scheme(value: &str, scheme: &str) -> bool {
+    value
+        .trim_start()
+        .get(..scheme.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+}
+
+fn is_data_image(value: &str) -> bool {
+    value.trim_start().get(..11).is_some_and(|prefix| prefix.eq_ignore_ascii_case("data:image/"))
+}
@@ -0,0 +1,168 @@
+use dr_markdown_render::{
+    find_diagrams, Diagram, DiagramFormat, DiagramLanguage, DiagramLanguages, Renderer, DEFAULT_DIAGRAMS,
+};
+
+#[test]
+fn encoded_source_round_trips() {
+    let diagram = Diagram {
+        kind: "graphviz".to_string(),
+        source: "digraph { a -> \"ü\" }\n".to_string(),
+        format: DiagramFormat::Svg,
+    };
+    let encoded = diagram.encoded_source();
+    assert!(diagram.kroki_url("https://kroki.example/").ends_with(&format!("/graphviz/svg/{}", encoded)));
+    assert_eq!(Diagram::from_encoded("graphviz", &encoded, 1024), Some(diagram));
+}
+
+#[test]
+fn server_urls_are_content_addressed() {
+    let diagram = Diagram {
+        kind: "plantuml".to_string(),
+        source: "Alice -> Bob".to_string(),
+        format: DiagramFormat::Svg,
+    };
+    let other = Diagram {
+        kind: "mermaid".to_string(),
+        ..diagram.clone()
+    };
+    let png = Diagram {
+        format: DiagramFormat::Png,
+        ..diagram.clone()
+    };
+    assert_eq!(diagram.hash().len(), 64);
+    assert_ne!(diagram.hash(), other.hash());
+    assert_eq!(diagram.hash(), png.hash());
+    assert_eq!(
+        diagram.server_url("/api/diagrams/"),
+        format!("/api/diagrams/plantuml/{}.svg?source={}", diagram.hash(), diagram.encoded_source())
+    );
+    assert!(png.server_url("/api/diagrams").contains(&format!("/{}.png?", png.hash())));
+    assert!(png.kroki_url("https://kroki.example").starts_with("https://kroki.example/plantuml/png/"));
+}
+
+#[test]
+fn invalid_encoded_source() {
+    assert_eq!(Diagram::from_encoded("graphviz", "not base64!", 1024), None);
+    assert_eq!(Diagram::from_encoded("graphviz", "bm90IHpsaWI", 1024), None);
+}
+
+#[test]
+fn encoded_source_is_limited() {
+    let diagram = Diagram {
+        kind: "graphviz".to_string(),
+        source: "a".repeat(1_000_000),
+        format: DiagramFormat::Svg,
+    };
+    let encoded = diagram.encoded_source();
+    assert!(encoded.len() < 2000);
+    assert_eq!(Diagram::from_encoded("graphviz", &encoded, 1024), None);
+    assert_eq!(Diagram::from_encoded("graphviz", &encoded, 1_000_000), Some(diagram));
+}
+
+#[test]
+fn finds_diagrams_in_order() {
+    let markdown = "```graphviz\ndigraph { a }\n```\n\n```rust\nfn main() {}\n```\n\n```mermaid\ngraph TD\n```\n";
+    let diagrams = find_diagrams(markdown, &DiagramLanguages::default());
+    let kinds: Vec<_> = diagrams.iter().map(|diagram| diagram.kind.as_str()).collect();
+    assert_eq!(kinds, ["graphviz", "mermaid"]);
+    assert_eq!(diagrams[0].source, "digraph { a }\n");
+}
+
+#[test]
+fn default_languages() {
+    let languages = DiagramLanguages::default();
+    let names: Vec<_> = languages.iter().map(|language| language.name.as_str()).collect();
+    assert_eq!(names, DEFAULT_DIAGRAMS);
+    assert_eq!(languages.find("dot").map(|language| language.name.as_str()), Some("graphviz"));
+    assert_eq!(languages.find("d2"), None);
+}
+
+#[test]
+fn configured_languages_and_aliases() {
+    let languages = DiagramLanguages::new(vec![
+        DiagramLanguage {
+            aliases: vec!["dot".to_string()],
+            ..DiagramLanguage::new("graphviz")
+        },
+        DiagramLanguage {
+            format: DiagramFormat::Png,
+            ..DiagramLanguage::new("d2")
+        },
+    ]);
+    let markdown = "```dot\ndigraph { a }\n```\n\n```d2\na -> b\n```\n\n```mermaid\ngraph TD\n```\n";
+
+    let diagrams = find_diagrams(markdown, &languages);
+    assert_eq!(diagrams.len(), 2);
+    assert_eq!((diagrams[0].kind.as_str(), diagrams[0].format), ("graphviz", DiagramFormat::Svg));
+    assert_eq!((diagrams[1].kind.as_str(), diagrams[1].format), ("d2", DiagramFormat::Png));
+
+    let html = Renderer::new()
+        .diagram_languages(&languages)
+        .diagram_server("/api/diagrams")
+        .render(markdown);
+    assert!(html.contains("src=\"/api/diagrams/graphviz/"));
+    assert!(html.contains(&format!("/api/diagrams/d2/{}.png?source=", diagrams[1].hash())));
+    assert!(html.contains("<code class=\"language-mermaid\">"));
+}
+
+#[test]
+fn diagram_formats() {
+    assert_eq!(" PNG".parse(), Ok(DiagramFormat::Png));
+    assert_eq!("svg".parse(), Ok(DiagramFormat::Svg));
+    assert!("gif".parse::<DiagramFormat>().is_err());
+    assert_eq!(DiagramFormat::Png.mime_type(), "image/png");
+}
+
+#[cfg(feature = "graphviz")]
+mod graphviz {
+    use dr_markdown_render::render_graphviz;
+
+    #[test]
+    fn draws_graphs() {
+        let svg = render_graphviz("digraph { rankdir=LR; client -> server [label=\"request\"]; server -> db }").unwrap();
+        assert!(svg.contains("<svg"));
+        for label in ["client", "server", "db", "request"] {
+            assert!(svg.contains(label), "{} is missing", label);
+        }
+    }
+
+    #[test]
+    fn escapes_labels() {
+        let svg = render_graphviz("digraph { a [label=\"<script>alert(1)</script>\"] }").unwrap();
+        assert!(!svg.contains("<script>"));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(render_graphviz("digraph { a -> }").is_err());
+        assert!(render_graphviz("not a graph").is_err());
+    }
+
+    #[test]
+    fn refuses_large_graphs() {
+        let nodes: String = (0..201).map(|node| format!("n{}; ", node)).collect();
+        assert_eq!(
+            render_graphviz(&format!("digraph {{ {} }}", nodes)).unwrap_err().to_string(),
+            "Invalid diagram: the graph has more than 200 nodes"
+        );
+
+        let edges: String = (0..401).map(|edge| format!("a -> b{}; ", edge % 10)).collect();
+        assert_eq!(
+            render_graphviz(&format!("digraph {{ {} }}", edges)).unwrap_err().to_string(),
+            "Invalid diagram: the graph has more than 400 edges"
+        );
+
+        // Nodes that only appear in edges count too
+        let chain: String = (0..200).map(|node| format!("n{} -> n{}; ", node, node + 1)).collect();
+        assert_eq!(
+            render_graphviz(&format!("digraph {{ {} }}", chain)).unwrap_err().to_string(),
+            "Invalid diagram: the graph has more than 200 nodes"
+        );
+
+        let label = "x".repeat(8 * 1024);
+        assert_eq!(
+            render_graphviz(&format!("digraph {{ a [label=\"{}\"] }}", label)).unwrap_err().to_string(),
+            "Invalid diagram: the graph is longer than 8 KiB"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use dr_markdown_render::{latex_to_mathml, Renderer, SanitizePolicy};
+
+fn inline(latex: &str) -> String {
+    let mathml = latex_to_mathml(latex, false).unwrap();
+    mathml
+        .strip_prefix("<math>")
+        .and_then(|mathml| mathml.strip_suffix("</math>"))
+        .unwrap()
+        .to_string()
+}
+
+fn error(latex: &str) -> String {
+    latex_to_mathml(latex, false).unwrap_err().to_string()
+}
+
+#[test]
+fn identifiers_numbers_and_operators() {
+    assert_eq!(inline("x + 1.5"), "<mi>x</mi><mo>+</mo><mn>1.5</mn>");
+    assert_eq!(inline("a-b<c"), "<mi>a</mi><mo>−</mo><mi>b</mi><mo>&lt;</mo><mi>c</mi>");
+    assert_eq!(inline("\\alpha \\Gamma"), "<mi>α</mi><mi mathvariant=\"normal\">Γ</mi>");
+    assert_eq!(inline("a \\leq b"), "<mi>a</mi><mo>≤</mo><mi>b</mi>");
+    assert_eq!(inline("f(x)"), "<mi>f</mi><mo stretchy=\"false\">(</mo><mi>x</mi><mo stretchy=\"false\">)</mo>");
+}
+
+#[test]
+fn display_math_is_a_block() {
+    assert_eq!(latex_to_mathml("x", true).unwrap(), "<math display=\"block\"><mi>x</mi></math>");
+}
+
+#[test]
+fn scripts() {
+    assert_eq!(inline("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+    assert_eq!(inline("x_i^{n+1}"), "<msubsup><mi>x</mi><mi>i</mi><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msubsup>");
+    assert_eq!(inline("x^23"), "<msup><mi>x</mi><mn>2</mn></msup><mn>3</mn>");
+    assert_eq!(inline("f'"), "<msup><mi>f</mi><mo>′</mo></msup>");
+    assert_eq!(inline("{}^{14}C"), "<msup><mrow></mrow><mn>14</mn></msup><mi>C</mi>");
+}
+
+#[test]
+fn large_operators_and_functions() {
+    assert_eq!(
+        inline("\\sum_{i=1}^n i"),
+        "<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mi>i</mi>"
+    );
+    assert_eq!(inline("\\int_0^1"), "<msubsup><mo>∫</mo><mn>0</mn><mn>1</mn></msubsup>");
+    assert_eq!(inline("\\int\\limits_0"), "<munder><mo>∫</mo><mn>0</mn></munder>");
+    assert_eq!(inline("\\sin x"), "<mi>sin</mi><mo>\u{2061}</mo><mi>x</mi>");
+    assert_eq!(
+        inline("\\lim_{n}"),
+        "<munder><mo movablelimits=\"true\">lim</mo><mi>n</mi></munder>"
+    );
+    assert_eq!(inline("\\operatorname{rank} A"), "<mi>rank</mi><mo>\u{2061}</mo><mi>A</mi>");
+}
+
+#[test]
+fn fractions_roots_and_text() {
+    assert_eq!(inline("\\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+    assert_eq!(inline("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+    assert_eq!(inline("\\sqrt{x}"), "<msqrt><mi>x</mi></msqrt>");
+    assert_eq!(inline("\\sqrt[3]{x}"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+    assert_eq!(inline("\\text{if } x"), "<mtext>if\u{a0}</mtext><mi>x</mi>");
+    assert_eq!(
+        inline("\\binom{n}{k}"),
+        "<mrow><mo>(</mo><mfrac linethickness=\"0\"><mi>n</mi><mi>k</mi></mfrac><mo>)</mo></mrow>"
+    );
+}
+
+#[test]
+fn accents_and_fonts() {
+    assert_eq!(inline("\\hat{x}"), "<mover accent=\"true\"><mi>x</mi><mo stretchy=\"false\">^</mo></mover>");
+    assert_eq!(inline("\\mathbb{R}^n"), "<msup><mi>ℝ</mi><mi>n</mi></msup>");
+    assert_eq!(inline("\\mathbf{v}"), "<mi>𝐯</mi>");
+    assert_eq!(inline("\\mathcal{L}"), "<mi>ℒ</mi>");
+    assert_eq!(inline("\\mathrm{d}x"), "<mi mathvariant=\"normal\">d</mi><mi>x</mi>");
+}
+
+#[test]
+fn delimiters_and_environments() {
+    assert_eq!(
+        inline("\\left( x \\right)"),
+        "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+    );
+    assert_eq!(
+        inline("\\left. x \\right|"),
+        "<mrow><mi>x</mi><mo fence=\"true\" stretchy=\"true\">|</mo></mrow>"
+    );
+    assert_eq!(
+        inline("\\begin{matrix} a & b \\\\ c & d \\\\ \\end{matrix}"),
+        "<mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>"
+    );
+    assert!(inline("\\begin{pmatrix} 1 \\end{pmatrix}").starts_with("<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable>"));
+    assert!(inline("\\begin{cases} 0 & x < 0 \\\\ 1 & x \\geq 0 \\end{cases}").contains("columnalign=\"left left\""));
+}
+
+#[test]
+fn lines_in_display_math() {
+    assert_eq!(
+        latex_to_mathml("a \\\\ b", true).unwrap(),
+        "<math display=\"block\"><mtable displaystyle=\"true\"><mtr><mtd><mi>a</mi></mtd></mtr><mtr><mtd><mi>b</mi></mtd></mtr></mtable></math>"
+    );
+}
+
+#[test]
+fn style_switches() {
+    assert_eq!(
+        inline("a \\displaystyle \\frac12"),
+        "<mi>a</mi><mstyle displaystyle=\"true\"><mfrac><mn>1</mn><mn>2</mn></mfrac></mstyle>"
+    );
+}
+
+#[test]
+fn malformed_formulas_are_errors() {
+    assert_eq!(error("\\frac{1}{2"), "Invalid formula: missing }");
+    assert_eq!(error("x}"), "Invalid formula: unexpected }");
+    assert_eq!(error("\\foo"), "Invalid formula: unknown command \\foo");
+    assert_eq!(error("x^"), "Invalid formula: missing argument for ^");
+    assert_eq!(error("x^1^2"), "Invalid formula: double superscript");
+    assert_eq!(error("\\left( x"), "Invalid formula: \\left without \\right");
+    assert_eq!(error("x \\right)"), "Invalid formula: unexpected \\right");
+    assert_eq!(error("\\begin{matrix} a"), "Invalid formula: \\begin{matrix} without \\end{matrix}");
+    assert_eq!(error("\\begin{foo}"), "Invalid formula: unknown environment foo");
+    assert_eq!(error("a & b"), "Invalid formula: unexpected &");
+    assert_eq!(error(&"{".repeat(1000)), "Invalid formula: the formula is nested too deeply");
+    assert_eq!(
+        latex_to_mathml(&"\\textstyle ".repeat(50_000), true).unwrap_err().to_string(),
+        "Invalid formula: the formula is nested too deeply"
+    );
+}
+
+#[test]
+fn renderer_marks_malformed_formulas() {
+    let html = Renderer::new().mathml(true).render("$x^2$ and $\\frac{1}$\n\n$$\\sqrt{2}$$\n");
+    assert_eq!(
+        html,
+        "<p><math><msup><mi>x</mi><mn>2</mn></msup></math> and \
+         <code class=\"math-error\" title=\"Invalid formula: missing argument for \\frac\">$\\frac{1}$</code></p>\n\
+         <p><math display=\"block\"><msqrt><mn>2</mn></msqrt></math></p>\n"
+    );
+}
+
+#[test]
+fn sanitizer_keeps_mathml() {
+    let policy = SanitizePolicy::new();
+    let html = Renderer::new()
+        .mathml(true)
+        .render("$\\left( \\hat{x}_i^2 \\right) \\leq \\mathbb{R} \\, \\frac{a}{b} \\binom{n}{k} \\lim_{n} \\big|$\n\n$$\\begin{cases} 1 & \\text{yes} \\end{cases}$$\n\n$\\frac{1}$\n");
+    assert_eq!(policy.clean(&html), html);
+}
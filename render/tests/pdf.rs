@@ -0,0 +1,33 @@
+#![cfg(feature = "pdf")]
+
+use dr_markdown_render::{render_pdf, DiagramLanguages};
+
+fn pdf(markdown: &str) -> Vec<u8> {
+    render_pdf("Test", markdown, &DiagramLanguages::default()).unwrap()
+}
+
+#[test]
+fn renders_a_document() {
+    let bytes = pdf("# Title\n\nSome *text* with `code`.\n\n- one\n- two\n\n> quoted\n");
+    assert!(bytes.starts_with(b"%PDF"));
+}
+
+#[test]
+fn wide_tables_are_rendered() {
+    let header = format!("|{}\n", " head |".repeat(80));
+    let separator = format!("|{}\n", " --- |".repeat(80));
+    let row = format!("|{}\n", " a_rather_long_cell_value |".repeat(80));
+    assert!(pdf(&format!("{header}{separator}{row}")).starts_with(b"%PDF"));
+}
+
+#[test]
+fn deeply_nested_lists_are_rendered() {
+    let list: String = (0..40).map(|depth| format!("{}- item {depth}\n", "  ".repeat(depth))).collect();
+    assert!(pdf(&list).starts_with(b"%PDF"));
+}
+
+#[test]
+fn deeply_nested_quotes_are_rendered() {
+    let quote = format!("{} a quote with several words in it\n", ">".repeat(40));
+    assert!(pdf(&quote).starts_with(b"%PDF"));
+}
@@ -0,0 +1,228 @@
+use std::fs;
+
+use dr_markdown_render::{Renderer, SanitizePolicy};
+
+fn clean(html: &str) -> String {
+    SanitizePolicy::new().clean(html)
+}
+
+fn render(markdown: &str) -> String {
+    let policy = SanitizePolicy::new();
+    Renderer::new()
+        .kroki("https://kroki.example")
+        .sanitizer(move |html| policy.clean(&html))
+        .render(markdown)
+}
+
+#[test]
+fn removes_scripts() {
+    assert_eq!(clean("<p>a</p><script>alert(1)</script>"), "<p>a</p>");
+    assert_eq!(clean("<SCRIPT SRC=//evil.example/x.js></SCRIPT>b"), "b");
+    assert_eq!(clean("<scr<script>ipt>alert(1)</script>"), "ipt&gt;alert(1)");
+    assert_eq!(
+        clean("<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\">"),
+        "&lt;p title=\"<img src=\"x\">\"&gt;"
+    );
+    assert_eq!(clean("<style>body { display: none }</style>text"), "text");
+    assert_eq!(clean("<iframe src=\"https://evil.example\"></iframe>"), "");
+    assert_eq!(clean("<object data=\"x.swf\"></object><embed src=\"x.swf\">"), "");
+}
+
+#[test]
+fn removes_event_handlers() {
+    assert_eq!(clean("<img src=\"a.png\" onerror=\"alert(1)\">"), "<img src=\"a.png\">");
+    assert_eq!(clean("<p onclick=\"alert(1)\" ONMOUSEOVER=alert(2)>x</p>"), "<p>x</p>");
+    assert_eq!(clean("<details open ontoggle=alert(1)>x</details>"), "<details>x</details>");
+    assert_eq!(clean("<a href=\"https://example.com\" onfocus=\"alert(1)\" autofocus>x</a>"), "<a href=\"https://example.com\" rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<body onload=alert(1)>x</body>"), "x");
+}
+
+#[test]
+fn removes_javascript_urls() {
+    assert_eq!(clean("<a href=\"javascript:alert(1)\">x</a>"), "<a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<a href=\" JaVaScRiPt:alert(1)\">x</a>"), "<a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<a href=\"java&#x09;script:alert(1)\">x</a>"), "<a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<a href=\"vbscript:msgbox(1)\">x</a>"), "<a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<a href=\"data:text/html,<script>alert(1)</script>\">x</a>"), "<a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(clean("<img src=\"javascript:alert(1)\">"), "<img>");
+    assert_eq!(clean("<form action=\"javascript:alert(1)\"><button>x</button></form>"), "x");
+}
+
+#[test]
+fn removes_javascript_urls_written_in_markdown() {
+    let html = render("[click](javascript:alert(1)) ![img](javascript:alert(2))\n\n[ref]\n\n[ref]: javascript:alert(3)");
+    assert!(!html.contains("javascript"), "{}", html);
+    assert!(html.contains(">click</a>"), "{}", html);
+}
+
+#[test]
+fn removes_svg_payloads() {
+    assert_eq!(clean("<svg onload=alert(1)><circle r=\"5\"></circle></svg>"), "");
+    assert_eq!(clean("<svg><script>alert(1)</script></svg>"), "");
+    assert_eq!(clean("<svg><a xlink:href=\"javascript:alert(1)\"><text>x</text></a></svg>"), "");
+    assert_eq!(
+        clean("<svg><animate attributeName=\"href\" values=\"javascript:alert(1)\"></animate></svg>"),
+        ""
+    );
+    assert_eq!(clean("<svg><foreignObject><img src=x onerror=alert(1)></foreignObject></svg>"), "");
+    // MathML is allowed for formulas, the event handler is not
+    assert_eq!(
+        clean("<math><mtext><img src=x onerror=alert(1)></mtext></math>"),
+        "<math><mtext><img src=\"x\"></mtext></math>"
+    );
+}
+
+#[test]
+fn svg_stays_safe_when_allowed() {
+    let policy = SanitizePolicy::new().allow_tags("svg, circle");
+    assert_eq!(
+        policy.clean("<svg onload=alert(1)><circle onclick=alert(2)></circle><script>alert(3)</script></svg>"),
+        "<svg><circle></circle></svg>"
+    );
+}
+
+#[test]
+fn removes_styles_except_table_alignment() {
+    assert_eq!(clean("<p style=\"position: fixed; inset: 0\">x</p>"), "<p>x</p>");
+    assert_eq!(
+        clean("<table><tr><td style=\"text-align: center; background: url(x)\">x</td></tr></table>"),
+        "<table><tbody><tr><td style=\"text-align:center\">x</td></tr></tbody></table>"
+    );
+}
+
+#[test]
+fn keeps_only_renderer_classes() {
+    assert_eq!(
+        clean("<code class=\"language-rust fixed inset-0\">x</code>"),
+        "<code class=\"language-rust\">x</code>"
+    );
+    assert_eq!(clean("<div class=\"fixed inset-0 z-50\">x</div>"), "<div>x</div>");
+}
+
+#[test]
+fn keeps_markdown_output() {
+    for path in fs::read_dir("tests/corpus").unwrap() {
+        let markdown = fs::read_to_string(path.unwrap().path()).unwrap();
+        let unsanitized = Renderer::new().kroki("https://kroki.example").render(&markdown);
+        let sanitized = render(&markdown);
+
+        // Everything but raw HTML and classes picked by the author survives,
+        // only links gain `rel` and ids a prefix
+        if !markdown.contains("<script>") && !markdown.contains("<!--") {
+            let unsanitized = unsanitized
+                .replace("<a href", "<a rel=\"noopener noreferrer\" href")
+                .replace("href=\"#", "href=\"#user-content-")
+                .replace(" id=\"", " id=\"user-content-")
+                .replace(" class=\"special\"", "")
+                .replace("text-align: ", "text-align:")
+                .replace(" />", ">")
+                .replace("/>", ">");
+            assert_eq!(
+                normalize(&sanitized),
+                normalize(&unsanitized),
+                "output of the renderer should not be changed"
+            );
+        }
+    }
+}
+
+#[test]
+fn keeps_diagram_server_images() {
+    let markdown = fs::read_to_string("tests/corpus/diagrams.md").unwrap();
+    let renderer = Renderer::new().diagram_server("/api/diagrams");
+    let unsanitized = renderer.render(&markdown);
+    assert_eq!(clean(&unsanitized), unsanitized);
+}
+
+#[test]
+fn keeps_highlighted_code() {
+    let markdown = fs::read_to_string("tests/corpus/code.md").unwrap();
+    let renderer = Renderer::new().highlight_code(true);
+    let unsanitized = renderer.render(&format!("{markdown}\n```rust {{2}}\nlet a = 1;\nlet b = 2;\n```\n"));
+
+    // Quotes in text come back unescaped
+    assert_eq!(clean(&unsanitized), unsanitized.replace("&quot;", "\""));
+}
+
+#[test]
+fn task_lists_are_read_only_checkboxes() {
+    assert_eq!(
+        render("- [x] done"),
+        "<ul>\n<li><input type=\"checkbox\" checked=\"\" disabled=\"\">\ndone</li>\n</ul>\n"
+    );
+    assert_eq!(clean("<input type=\"text\" value=\"x\" autofocus onfocus=alert(1)>"), "<input disabled=\"\">");
+}
+
+#[test]
+fn deployment_can_extend_and_restrict() {
+    let policy = SanitizePolicy::new()
+        .allow_tags("video, script")
+        .deny_tags("img")
+        .allow_attributes("controls, onerror, data-note")
+        .allow_url_schemes("dat, javascript")
+        .allow_class_prefixes("callout-");
+
+    assert_eq!(
+        policy.clean("<video controls onerror=alert(1) data-note=\"n\"></video><script>alert(2)</script><img src=\"a.png\">"),
+        "<video controls=\"\" data-note=\"n\"></video>"
+    );
+    assert_eq!(
+        policy.clean("<a href=\"dat://example\">x</a><a href=\"javascript:alert(1)\">y</a>"),
+        "<a href=\"dat://example\" rel=\"noopener noreferrer\">x</a><a rel=\"noopener noreferrer\">y</a>"
+    );
+    assert_eq!(
+        policy.clean("<div class=\"callout-warning fixed\">x</div>"),
+        "<div class=\"callout-warning\">x</div>"
+    );
+}
+
+#[test]
+fn data_urls_only_for_images() {
+    let svg = "data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=";
+    let html = format!("<img src=\"{svg}\"><a href=\"{svg}\">x</a><img src=\"data:text/html,x\">");
+
+    assert_eq!(clean(&html), "<img><a rel=\"noopener noreferrer\">x</a><img>");
+    assert_eq!(
+        SanitizePolicy::new().allow_data_images(true).clean(&html),
+        format!("<img src=\"{svg}\"><a rel=\"noopener noreferrer\">x</a><img>")
+    );
+}
+
+#[test]
+fn blob_urls_only_for_images() {
+    let blob = "blob:http://localhost/0b5c3f3e-8c39-4a51-9c1e-7f8c0b8e6d1a";
+    let html = format!("<img src=\"{blob}\"><a href=\"{blob}\">x</a>");
+
+    assert_eq!(clean(&html), "<img><a rel=\"noopener noreferrer\">x</a>");
+    assert_eq!(
+        SanitizePolicy::new().allow_blob_images(true).clean(&html),
+        format!("<img src=\"{blob}\"><a rel=\"noopener noreferrer\">x</a>")
+    );
+}
+
+#[test]
+fn ids_are_prefixed() {
+    assert_eq!(
+        clean("<img id=\"currentUser\" src=\"a.png\"><form id=\"config\"></form>"),
+        "<img id=\"user-content-currentUser\" src=\"a.png\">"
+    );
+
+    let html = render("A note[^note].\n\n[^note]: Text\n\n# Heading {#intro}\n\n[Back](#intro)");
+    assert!(html.contains("<a href=\"#user-content-note\""), "{}", html);
+    assert!(html.contains("id=\"user-content-note\""), "{}", html);
+    assert!(html.contains("<h1 id=\"user-content-intro\">"), "{}", html);
+    assert!(html.contains("<a href=\"#user-content-intro\""), "{}", html);
+}
+
+// Attribute order and self-closing tags differ between the renderer and the
+// sanitizer, so the outputs are compared tag by tag with sorted attributes
+fn normalize(html: &str) -> Vec<String> {
+    html.split('<')
+        .map(|part| {
+            let (tag, rest) = part.split_once('>').unwrap_or((part, ""));
+            let mut attributes: Vec<&str> = tag.split(' ').collect();
+            attributes[1..].sort();
+            format!("{}>{}", attributes.join(" "), rest)
+        })
+        .collect()
+}
@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::fs;
+
+use dr_markdown_render::{Extensions, Renderer};
+
+const KROKI_URL: &str = "https://kroki.example";
+
+/// Every file in `tests/corpus` rendered the way the editor preview does.
+#[test]
+fn corpus() {
+    let renderer = Renderer::new().kroki(KROKI_URL);
+    insta::glob!("corpus/*.md", |path| {
+        let markdown = fs::read_to_string(path).unwrap();
+        insta::assert_snapshot!(renderer.render(&markdown));
+    });
+}
+
+#[test]
+fn commonmark_only() {
+    let markdown = fs::read_to_string("tests/corpus/extensions.md").unwrap();
+    let html = Renderer::new().extensions(Extensions::NONE).render(&markdown);
+    insta::assert_snapshot!(html);
+}
+
+#[test]
+fn single_extension() {
+    let extensions = Extensions {
+        tables: true,
+        ..Extensions::NONE
+    };
+    let html = Renderer::new()
+        .extensions(extensions)
+        .render("| a |\n|---|\n| b |\n\n~~not struck~~");
+    insta::assert_snapshot!(html);
+}
+
+#[test]
+fn diagrams_as_code_by_default() {
+    let markdown = fs::read_to_string("tests/corpus/diagrams.md").unwrap();
+    insta::assert_snapshot!(Renderer::new().render(&markdown));
+}
+
+#[test]
+fn diagram_server() {
+    let markdown = fs::read_to_string("tests/corpus/diagrams.md").unwrap();
+    insta::assert_snapshot!(Renderer::new().diagram_server("/api/diagrams").render(&markdown));
+}
+
+#[test]
+fn custom_diagrams() {
+    let markdown = fs::read_to_string("tests/corpus/diagrams.md").unwrap();
+    let seen = RefCell::new(Vec::new());
+    let html = Renderer::new()
+        .diagrams(|diagram| {
+            seen.borrow_mut().push(diagram.clone());
+            format!("<figure data-kind=\"{}\"></figure>", diagram.kind)
+        })
+        .render(&markdown);
+
+    insta::assert_snapshot!(html);
+    insta::assert_debug_snapshot!("custom_diagrams_found", seen.into_inner());
+}
+
+#[test]
+fn highlighted_code() {
+    let markdown = fs::read_to_string("tests/corpus/code.md").unwrap();
+    insta::assert_snapshot!(Renderer::new().highlight_code(true).render(&markdown));
+}
+
+#[test]
+fn highlighted_lines() {
+    let html = Renderer::new()
+        .highlight_code(true)
+        .render("```js {1, 3-4}\n/* one\ntwo */\nlet x = 1;\nx;\n```\n\n``` {2}\nplain\n<text>\n```\n");
+    insta::assert_snapshot!(html);
+}
+
+#[test]
+fn math_as_mathml() {
+    let markdown = fs::read_to_string("tests/corpus/extensions.md").unwrap();
+    insta::assert_snapshot!(Renderer::new().mathml(true).render(&markdown));
+}
+
+#[test]
+fn escaped_raw_html() {
+    let markdown = fs::read_to_string("tests/corpus/html.md").unwrap();
+    let html = Renderer::new().escape_raw_html(true).render(&markdown);
+    insta::assert_snapshot!(html);
+}
+
+#[test]
+fn escaping_raw_html_keeps_diagrams() {
+    let html = Renderer::new()
+        .kroki(KROKI_URL)
+        .escape_raw_html(true)
+        .render("<b>bold</b>\n\n```graphviz\ndigraph { a -> b }\n```\n");
+    insta::assert_snapshot!(html);
+}
+
+#[test]
+fn sanitizer_sees_complete_output() {
+    let input = RefCell::new(String::new());
+    let html = Renderer::new()
+        .kroki(KROKI_URL)
+        .sanitizer(|html| {
+            *input.borrow_mut() = html.clone();
+            html.replace("<script>", "").replace("</script>", "")
+        })
+        .render("# Title\n\n<script>alert(1)</script>\n\n```mermaid\ngraph TD\n```\n");
+
+    let input = input.into_inner();
+    assert!(input.contains("<h1>Title</h1>"));
+    assert!(input.contains("<script>"));
+    assert!(input.contains("class=\"kroki-diagram\""));
+    insta::assert_snapshot!(html);
+}